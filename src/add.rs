@@ -2,59 +2,123 @@ use std::{fs, io::Write as _, path::Path};
 
 use anyhow::{Context as _, Result};
 
-use crate::{cli, known};
+use crate::{cli, cmd, config, known};
 
-fn gen_tool(options: &cli::Add) -> Result<String, anyhow::Error> {
+/// A tool that isn't known, from `--custom`
+fn custom_tool(options: &cli::Add) -> Result<config::Tool> {
+    let cmd = options.cmd.clone().context("`--custom` requires `--cmd`")?;
+    check_on_path(&cmd)?;
+    Ok(config::Tool {
+        name: Some(options.tool.clone()),
+        cmd,
+        files: options.files.clone(),
+        ignore: Vec::new(),
+        granularity: config::Granularity::default(),
+        configs: Vec::new(),
+        env: Vec::new(),
+        env_stamp: config::EnvStamp::Auto,
+        cd: None,
+        cache_failures: None,
+        dedupe_group: None,
+        stdin: false,
+        min_version: None,
+        version: None,
+        version_cmd: None,
+        enabled_if: None,
+        platforms: Vec::new(),
+        max_file_size: None,
+        binary: false,
+        batch_size: None,
+        files_via: None,
+        argfile: None,
+        no_capture: false,
+        absolute_paths: false,
+        before: None,
+        after: None,
+        problem_matcher: None,
+        warns: config::WarnCfg::default(),
+    })
+}
+
+/// Check that the program that `cmd` runs exists
+fn check_on_path(cmd: &str) -> Result<()> {
+    let program = cmd.split_whitespace().next().context("Empty `--cmd`")?;
+    if !cmd::on_path(program, None) {
+        anyhow::bail!("Command not found: {program}");
+    }
+    Ok(())
+}
+
+/// Apply the overrides that are common to linters and formatters
+fn override_tool(tool: &mut config::Tool, options: &cli::Add) {
+    if let Some(name) = &options.name {
+        tool.name = Some(name.clone());
+    }
+    if let Some(cmd) = &options.cmd {
+        tool.cmd = cmd.clone();
+    }
+    if !options.files.is_empty() {
+        tool.files = options.files.clone();
+    }
+    if let Some(granularity) = options.granularity {
+        tool.granularity = granularity;
+    }
+    if let Some(config_path) = &options.config {
+        tool.configs = vec![config_path.clone()];
+    }
+}
+
+fn gen_tool(options: &cli::Add, registry: &known::Registry) -> Result<String, anyhow::Error> {
     let is_formatter = if let Some(formatter) = options.formatter {
         formatter
+    } else if options.custom {
+        options.check.is_some()
     } else {
         // Try to determine from known tools
-        known::known_formatter_by_name(&options.tool).is_some()
+        registry.formatter_by_name(&options.tool).is_some()
     };
 
     if is_formatter {
-        let mut formatter = known::known_formatter_by_name(&options.tool)
-            .ok_or_else(|| anyhow::anyhow!("Unknown formatter: {}", options.tool))?;
-        if let Some(name) = &options.name {
-            formatter.tool.name = Some(name.clone());
-        }
-        if let Some(cmd) = &options.cmd {
-            formatter.tool.cmd = cmd.clone();
-        }
-        if let Some(files) = &options.files {
-            formatter.tool.files = vec![files.clone()];
-        }
+        let mut formatter = if options.custom {
+            config::Formatter {
+                tool: custom_tool(options)?,
+                check: None,
+                write_back: false,
+            }
+        } else {
+            registry
+                .formatter_by_name(&options.tool)
+                .ok_or_else(|| anyhow::anyhow!("Unknown formatter: {}", options.tool))?
+        };
+        override_tool(&mut formatter.tool, options);
         if let Some(check) = &options.check {
             formatter.check = Some(check.clone());
         }
-        if let Some(config_path) = &options.config {
-            formatter.tool.configs = vec![config_path.clone()];
-        }
         let toml =
             toml::to_string_pretty(&formatter).context("Failed to serialize formatter to TOML")?;
         Ok(format!("[[formatter]]\n{toml}"))
     } else {
-        let mut linter = known::known_linter_by_name(&options.tool)
-            .ok_or_else(|| anyhow::anyhow!("Unknown linter: {}", options.tool))?;
-        if let Some(name) = &options.name {
-            linter.tool.name = Some(name.clone());
-        }
-        if let Some(cmd) = &options.cmd {
-            linter.tool.cmd = cmd.clone();
-        }
-        if let Some(files) = &options.files {
-            linter.tool.files = vec![files.clone()];
-        }
-        if let Some(config_path) = &options.config {
-            linter.tool.configs = vec![config_path.clone()];
+        let mut linter = if options.custom {
+            config::Linter {
+                tool: custom_tool(options)?,
+                fix: None,
+            }
+        } else {
+            registry
+                .linter_by_name(&options.tool)
+                .ok_or_else(|| anyhow::anyhow!("Unknown linter: {}", options.tool))?
+        };
+        override_tool(&mut linter.tool, options);
+        if let Some(fix) = &options.fix {
+            linter.fix = Some(fix.clone());
         }
         let toml = toml::to_string_pretty(&linter).context("Failed to serialize linter to TOML")?;
         Ok(format!("[[linter]]\n{toml}"))
     }
 }
 
-pub(crate) fn go(config_path: &Path, options: &cli::Add) -> Result<()> {
-    let toml = gen_tool(options)?;
+pub(crate) fn go(config_path: &Path, options: &cli::Add, registry: &known::Registry) -> Result<()> {
+    let toml = gen_tool(options, registry)?;
     let mut file = fs::OpenOptions::new()
         .append(true)
         .open(config_path)
@@ -64,3 +128,72 @@ pub(crate) fn go(config_path: &Path, options: &cli::Add) -> Result<()> {
     writeln!(file)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser as _;
+    use expect_test::expect;
+
+    use super::*;
+
+    fn add(args: &[&str]) -> Result<String> {
+        let options = cli::Add::try_parse_from(["add"].iter().chain(args)).unwrap();
+        gen_tool(&options, &known::Registry::default())
+    }
+
+    #[test]
+    fn custom() {
+        let args = [
+            "lint-sh",
+            "--custom",
+            "--cmd",
+            "sh -n",
+            "--files",
+            "*.sh",
+            "--files",
+            "*.bash",
+            "--granularity",
+            "batch",
+        ];
+        expect![[r#"
+            [[linter]]
+            name = "lint-sh"
+            cmd = "sh -n"
+            files = [
+                "*.sh",
+                "*.bash",
+            ]
+            granularity = "batch"
+        "#]]
+        .assert_eq(&add(&args).unwrap());
+    }
+
+    #[test]
+    fn custom_formatter() {
+        let args = [
+            "fmt-sh",
+            "--custom",
+            "--cmd",
+            "sh -c true",
+            "--check",
+            "sh -c false",
+            "--files",
+            "*.sh",
+        ];
+        expect![[r#"
+            [[formatter]]
+            name = "fmt-sh"
+            cmd = "sh -c true"
+            files = ["*.sh"]
+            check = "sh -c false"
+        "#]]
+        .assert_eq(&add(&args).unwrap());
+    }
+
+    #[test]
+    fn custom_not_found() {
+        let args = ["x", "--custom", "--cmd", "lun-no-such-tool", "--files", "*"];
+        let err = add(&args).unwrap_err();
+        assert_eq!(err.to_string(), "Command not found: lun-no-such-tool");
+    }
+}
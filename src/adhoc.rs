@@ -0,0 +1,80 @@
+//! `lun exec`: run a command that isn't in the config file as if it were a
+//! linter, e.g., to try it out before adding it.
+
+use anyhow::{Context as _, Result};
+
+use crate::{cli, config};
+
+fn tool(exec: &cli::Exec) -> Result<config::Tool> {
+    if let Some(arg) = exec.cmd.iter().find(|a| a.contains(char::is_whitespace)) {
+        // Commands are split on whitespace, see `cmd.rs`
+        anyhow::bail!("Arguments can't contain whitespace: {arg:?}");
+    }
+    let program = exec.cmd.first().context("Empty command")?;
+    Ok(config::Tool {
+        name: Some(exec.name.clone().unwrap_or_else(|| program.clone())),
+        cmd: exec.cmd.join(" "),
+        files: exec.files.clone(),
+        ignore: Vec::new(),
+        granularity: exec.granularity.unwrap_or_default(),
+        configs: Vec::new(),
+        env: Vec::new(),
+        env_stamp: config::EnvStamp::Auto,
+        cd: None,
+        cache_failures: None,
+        dedupe_group: None,
+        stdin: false,
+        min_version: None,
+        version: None,
+        version_cmd: None,
+        enabled_if: None,
+        platforms: Vec::new(),
+        max_file_size: None,
+        binary: false,
+        batch_size: None,
+        files_via: None,
+        argfile: None,
+        no_capture: false,
+        absolute_paths: false,
+        before: None,
+        after: None,
+        problem_matcher: None,
+        warns: config::WarnCfg::default(),
+    })
+}
+
+/// The config file (if any) with the command as its only tool
+pub(crate) fn config(exec: &cli::Exec, base: Option<config::Config>) -> Result<config::Config> {
+    let mut config = match base {
+        Some(config) => config,
+        None => toml::from_str("").context("Failed to create default config")?,
+    };
+    config.linter = vec![config::Linter {
+        tool: tool(exec)?,
+        fix: None,
+    }];
+    config.formatter.clear();
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adhoc_tool() {
+        let parse = |args: &[&str]| {
+            let cli = cli::Cli::try_parse_from(args).unwrap();
+            let cli::Command::Exec(exec) = cli.command else {
+                unreachable!()
+            };
+            config(&exec, None)
+        };
+        let config = parse(&["lun", "exec", "--files", "*.proto", "--", "buf", "lint"]).unwrap();
+        let tool = &config.linter[0].tool;
+        assert_eq!(tool.name.as_deref(), Some("buf"));
+        assert_eq!(tool.cmd, "buf lint");
+        assert_eq!(tool.files, ["*.proto"]);
+        assert!(parse(&["lun", "exec", "--files", "*", "--", "echo", "a b"]).is_err());
+    }
+}
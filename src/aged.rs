@@ -0,0 +1,182 @@
+//! Files of records keyed by hashes, each with an age, i.e., the number of
+//! runs since it was last used. Old records are dropped on flush. Used by
+//! `index.rs` and `timings.rs`.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use tracing::{debug, warn};
+
+use crate::atomic;
+
+/// The kind of records in a [`Store`], and how they are written
+pub(crate) trait Format {
+    /// For messages, e.g., "index"
+    const NAME: &'static str;
+    const MAGIC: &'static [u8; 4];
+    /// Bump this when the layout of records or the way that keys are computed
+    /// changes.
+    const VERSION: u16;
+    /// Entries that haven't been used in this many runs are dropped on flush
+    const MAX_AGE: u16;
+    type Value: Value;
+}
+
+pub(crate) trait Value: Copy + fmt::Debug {
+    const SIZE: usize;
+    fn read(bytes: &[u8]) -> Self;
+    fn write(self, out: &mut Vec<u8>);
+}
+
+impl Value for u64 {
+    const SIZE: usize = size_of::<u64>();
+
+    #[allow(clippy::unwrap_used)]
+    fn read(bytes: &[u8]) -> Self {
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Value for u128 {
+    const SIZE: usize = size_of::<u128>();
+
+    #[allow(clippy::unwrap_used)]
+    fn read(bytes: &[u8]) -> Self {
+        u128::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+const HEADER_SIZE: usize = 4 + size_of::<u16>();
+
+#[derive(Debug)]
+pub(crate) struct Store<F: Format> {
+    /// Map from keys to values and their age
+    entries: HashMap<u128, (F::Value, u16)>,
+    /// Where to write the records on flush, if anywhere
+    file: Option<PathBuf>,
+    /// Flush writes to disk before returning, see [`atomic::write`]
+    pub(crate) fsync: bool,
+    format: PhantomData<F>,
+}
+
+impl<F: Format> Default for Store<F> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            file: None,
+            fsync: false,
+            format: PhantomData,
+        }
+    }
+}
+
+impl<F: Format> Store<F> {
+    /// Age, key, and value
+    const RECORD_SIZE: usize = size_of::<u16>() + size_of::<u128>() + F::Value::SIZE;
+
+    /// Load records from `file`, starting afresh if it is missing or corrupt
+    pub(crate) fn from_file(file: &Path) -> Result<Self> {
+        let mut store = Self {
+            file: Some(file.to_path_buf()),
+            ..Self::default()
+        };
+        if !file.exists() {
+            debug!("No {} at {}", F::NAME, file.display());
+            return Ok(store);
+        }
+        let contents = fs::read(file)
+            .with_context(|| format!("Failed to read {} file: {}", F::NAME, file.display()))?;
+        let ok = contents.len() >= HEADER_SIZE
+            && contents[..F::MAGIC.len()] == *F::MAGIC
+            && contents[F::MAGIC.len()..HEADER_SIZE] == F::VERSION.to_le_bytes()
+            && (contents.len() - HEADER_SIZE).is_multiple_of(Self::RECORD_SIZE);
+        if !ok {
+            warn!("Corrupted {} at {}", F::NAME, file.display());
+            drop(fs::remove_file(file));
+            return Ok(store);
+        }
+        #[allow(clippy::unwrap_used)]
+        for chunk in contents[HEADER_SIZE..].chunks_exact(Self::RECORD_SIZE) {
+            let (age, rest) = chunk.split_at(size_of::<u16>());
+            let (key, value) = rest.split_at(size_of::<u128>());
+            store.entries.insert(
+                u128::from_le_bytes(key.try_into().unwrap()),
+                (
+                    F::Value::read(value),
+                    u16::from_le_bytes(age.try_into().unwrap()),
+                ),
+            );
+        }
+        debug!("Loaded {} {} entries", store.len(), F::NAME);
+        Ok(store)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn value(&self, key: u128) -> Option<F::Value> {
+        self.entries.get(&key).map(|(value, _)| *value)
+    }
+
+    /// Like [`Store::value`], and keep the entry for another [`Format::MAX_AGE`]
+    /// runs
+    pub(crate) fn touch(&mut self, key: u128) -> Option<F::Value> {
+        self.entries.get_mut(&key).map(|(value, age)| {
+            *age = 0;
+            *value
+        })
+    }
+
+    pub(crate) fn insert(&mut self, key: u128, value: F::Value) {
+        self.entries.insert(key, (value, 0));
+    }
+
+    /// Prepare the records for another run after a flush, as if they had been
+    /// loaded again (i.e., by `lun daemon`)
+    pub(crate) fn next_run(&mut self) {
+        self.entries.retain(|_, (_, age)| *age < F::MAX_AGE);
+        for (_, age) in self.entries.values_mut() {
+            *age = age.saturating_add(1);
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        self.entries.retain(|_, (_, age)| *age < F::MAX_AGE);
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        // For determinism
+        entries.sort_by_key(|(key, _)| **key);
+        debug!(
+            "Flushing {} of size {} to {}",
+            F::NAME,
+            entries.len() * Self::RECORD_SIZE,
+            file.display()
+        );
+        let mut content = Vec::with_capacity(HEADER_SIZE + entries.len() * Self::RECORD_SIZE);
+        content.extend_from_slice(F::MAGIC);
+        content.extend_from_slice(&F::VERSION.to_le_bytes());
+        for (key, (value, age)) in entries {
+            content.extend_from_slice(&age.saturating_add(1).to_le_bytes());
+            content.extend_from_slice(&key.to_le_bytes());
+            value.write(&mut content);
+        }
+        atomic::write(file, &content, self.fsync)
+            .with_context(|| format!("Failed to write {} file: {}", F::NAME, file.display()))
+    }
+}
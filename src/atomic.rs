@@ -0,0 +1,71 @@
+//! Crash-safe file writes.
+
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".tmp.{}", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Write `contents` to `path` so that readers see either the old or the new
+/// contents, but never a partial write.
+///
+/// With `fsync`, the data and the directory entry are also flushed to disk
+/// before returning, so that the write survives a power loss.
+pub(crate) fn write(path: &Path, contents: &[u8], fsync: bool) -> io::Result<()> {
+    let tmp = temp_path(path);
+    let result = (|| {
+        let mut f = fs::File::create(&tmp)?;
+        f.write_all(contents)?;
+        if fsync {
+            f.sync_all()?;
+        }
+        drop(f);
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        drop(fs::remove_file(&tmp));
+        return result;
+    }
+    #[cfg(unix)]
+    if fsync && let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f");
+        write(&path, b"old", false).unwrap();
+        write(&path, b"new", true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn failed_write_leaves_no_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("d");
+        // Renaming a file over a non-empty directory fails
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("f"), b"").unwrap();
+        assert!(write(&path, b"new", false).is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
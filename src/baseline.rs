@@ -0,0 +1,155 @@
+//! `.lun-baseline.json`: diagnostics that existed when `lun baseline create`
+//! was run, and that `lun run` doesn't report.
+//!
+//! Diagnostics are matched by tool, file, code, and message, but not by line,
+//! so that they still match after unrelated edits. Each entry suppresses one
+//! diagnostic, so new copies of an old diagnostic are still reported.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+
+use crate::{atomic, diag::Problem};
+
+pub(crate) const FILE: &str = ".lun-baseline.json";
+const VERSION: u32 = 1;
+
+#[derive(
+    Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+pub(crate) struct Entry {
+    pub(crate) tool: String,
+    pub(crate) file: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) code: Option<String>,
+    pub(crate) message: String,
+}
+
+impl Entry {
+    pub(crate) fn new(tool: &str, problem: &Problem) -> Self {
+        Self {
+            tool: tool.to_string(),
+            file: problem.diag.file.clone(),
+            code: problem.diag.code.clone(),
+            message: problem.message.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Contents {
+    version: u32,
+    diagnostics: Vec<Entry>,
+}
+
+/// How many of each diagnostic are suppressed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Baseline(HashMap<Entry, usize>);
+
+impl Baseline {
+    /// Load the baseline, if there is one
+    pub(crate) fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read baseline: {}", path.display()));
+            }
+        };
+        let contents = serde_json::from_slice::<Contents>(&contents)
+            .with_context(|| format!("Failed to parse baseline: {}", path.display()))?;
+        if contents.version != VERSION {
+            anyhow::bail!(
+                "Unsupported baseline version {} in {}, run `lun baseline create`",
+                contents.version,
+                path.display()
+            );
+        }
+        let mut baseline = Self::default();
+        for entry in contents.diagnostics {
+            *baseline.0.entry(entry).or_default() += 1;
+        }
+        Ok(Some(baseline))
+    }
+
+    /// The diagnostics of `tool` that aren't in the baseline
+    pub(crate) fn filter(&self, tool: &str, problems: Vec<Problem>) -> Vec<Problem> {
+        let mut remaining = HashMap::<Entry, usize>::new();
+        problems
+            .into_iter()
+            .filter(|problem| {
+                let entry = Entry::new(tool, problem);
+                let Some(count) = self.0.get(&entry) else {
+                    return true;
+                };
+                let used = remaining.entry(entry).or_default();
+                *used += 1;
+                *used > *count
+            })
+            .collect()
+    }
+
+    /// Whether all of the diagnostics in the output of a failed command are in
+    /// the baseline, so that it passes. Output without diagnostics fails.
+    pub(crate) fn covers(&self, tool: &str, problems: Vec<Problem>) -> bool {
+        !problems.is_empty() && self.filter(tool, problems).is_empty()
+    }
+}
+
+/// Write the baseline, sorted so that it diffs well
+pub(crate) fn write(path: &Path, mut diagnostics: Vec<Entry>) -> Result<()> {
+    diagnostics.sort();
+    let contents = Contents {
+        version: VERSION,
+        diagnostics,
+    };
+    let mut json = serde_json::to_vec_pretty(&contents).context("Failed to serialize baseline")?;
+    json.push(b'\n');
+    atomic::write(path, &json, false)
+        .with_context(|| format!("Failed to write baseline: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diag::{Diagnostic, Severity};
+
+    fn problem(file: &str, line: u32, message: &str) -> Problem {
+        Problem {
+            diag: Diagnostic {
+                file: PathBuf::from(file),
+                line,
+                col: None,
+                code: Some("F401".to_string()),
+            },
+            message: message.to_string(),
+            severity: Severity::Error,
+        }
+    }
+
+    #[test]
+    fn roundtrip_and_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE);
+        assert_eq!(Baseline::load(&path).unwrap(), None);
+        let old = problem("a.py", 1, "`os` imported but unused");
+        write(&path, vec![Entry::new("ruff", &old)]).unwrap();
+        let baseline = Baseline::load(&path).unwrap().unwrap();
+
+        // Lines may move
+        assert!(baseline.covers("ruff", vec![problem("a.py", 5, &old.message)]));
+        assert!(!baseline.covers("ruff", Vec::new()));
+        assert!(!baseline.covers("mypy", vec![old.clone()]));
+        // Only as many as were recorded
+        let new = baseline.filter("ruff", vec![old.clone(), old.clone()]);
+        assert_eq!(new.len(), 1);
+        let new = baseline.filter("ruff", vec![old, problem("b.py", 1, "x")]);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].diag.file, PathBuf::from("b.py"));
+    }
+}
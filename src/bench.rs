@@ -0,0 +1,158 @@
+//! `lun bench`: time each tool on all of the files without the cache, and
+//! compare to the previous benchmark, which is stored in the cache directory.
+
+use std::{collections::BTreeMap, fmt::Write as _, fs, io, path::Path, time::Duration};
+
+use anyhow::{Context as _, Result};
+use tracing::warn;
+
+use crate::{atomic, cli, config, run, warn::warns::Warns};
+
+/// Wall-clock times of the runs of a tool, in microseconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct Stats {
+    min: u64,
+    mean: u64,
+    max: u64,
+}
+
+impl Stats {
+    fn new(times: &[Duration]) -> Option<Self> {
+        let micros = times
+            .iter()
+            .map(|t| u64::try_from(t.as_micros()).unwrap_or(u64::MAX))
+            .collect::<Vec<_>>();
+        let n = u64::try_from(micros.len()).ok().filter(|n| *n > 0)?;
+        Some(Self {
+            min: *micros.iter().min()?,
+            mean: micros.iter().sum::<u64>() / n,
+            max: *micros.iter().max()?,
+        })
+    }
+}
+
+pub(crate) fn go(
+    cli: &cli::Cli,
+    bench: &cli::Bench,
+    config: &config::Config,
+    lints: &Warns,
+) -> Result<()> {
+    let times = run::time_tools(cli, config, lints, bench.iterations)?;
+    let stats = times
+        .iter()
+        .filter_map(|(tool, times)| Some((tool.clone(), Stats::new(times)?)))
+        .collect::<BTreeMap<_, _>>();
+    let path = cli.cache.join("bench.json");
+    let baseline = load(&path);
+    print!("{}", render(&stats, &baseline));
+    let json = serde_json::to_vec_pretty(&stats).context("Failed to serialize benchmark")?;
+    atomic::write(&path, &json, false)
+        .with_context(|| format!("Failed to write benchmark: {}", path.display()))
+}
+
+/// The previous benchmark, if any
+fn load(path: &Path) -> BTreeMap<String, Stats> {
+    match fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+            warn!("Ignoring corrupted benchmark at {}: {e}", path.display());
+            BTreeMap::new()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            warn!("Failed to read benchmark at {}: {e}", path.display());
+            BTreeMap::new()
+        }
+    }
+}
+
+fn millis(micros: u64) -> String {
+    #[allow(clippy::cast_precision_loss)]
+    let ms = micros as f64 / 1000.0;
+    format!("{ms:.1}ms")
+}
+
+fn render(stats: &BTreeMap<String, Stats>, baseline: &BTreeMap<String, Stats>) -> String {
+    let width = stats.keys().map(String::len).max().unwrap_or(0).max(4);
+    let mut out = format!(
+        "{:width$}  {:>10}  {:>10}  {:>10}  {:>8}\n",
+        "tool", "min", "mean", "max", "change"
+    );
+    for (tool, s) in stats {
+        // Relative to the previous mean
+        let change = match baseline.get(tool) {
+            Some(prev) if prev.mean > 0 => {
+                #[allow(clippy::cast_precision_loss)]
+                let pct = (s.mean as f64 / prev.mean as f64 - 1.0) * 100.0;
+                format!("{pct:+.1}%")
+            }
+            _ => "-".to_string(),
+        };
+        #[allow(clippy::unwrap_used)]
+        writeln!(
+            out,
+            "{tool:width$}  {:>10}  {:>10}  {:>10}  {change:>8}",
+            millis(s.min),
+            millis(s.mean),
+            millis(s.max)
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let times = [10, 30, 20].map(Duration::from_millis);
+        assert_eq!(
+            Stats::new(&times),
+            Some(Stats {
+                min: 10_000,
+                mean: 20_000,
+                max: 30_000,
+            })
+        );
+        assert_eq!(Stats::new(&[]), None);
+    }
+
+    #[test]
+    fn render_with_baseline() {
+        let stats = BTreeMap::from([
+            (
+                "ruff".to_string(),
+                Stats {
+                    min: 1_000,
+                    mean: 1_500,
+                    max: 2_000,
+                },
+            ),
+            (
+                "shellcheck".to_string(),
+                Stats {
+                    min: 120_000,
+                    mean: 125_000,
+                    max: 130_500,
+                },
+            ),
+        ]);
+        let baseline = BTreeMap::from([(
+            "ruff".to_string(),
+            Stats {
+                min: 1_000,
+                mean: 1_000,
+                max: 1_000,
+            },
+        )]);
+        expect![[r#"
+            tool               min        mean         max    change
+            ruff             1.0ms       1.5ms       2.0ms    +50.0%
+            shellcheck     120.0ms     125.0ms     130.5ms         -
+        "#]]
+        .assert_eq(&render(&stats, &baseline));
+    }
+}
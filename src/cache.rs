@@ -1,15 +1,21 @@
 use std::{
     collections::HashMap,
+    ffi::OsString,
     fs,
     hash::Hash as _,
+    io::{Read as _, Write as _},
     mem::size_of,
+    num::NonZeroU16,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
 use tracing::{debug, info, warn};
 use xxhash_rust::xxh3::Xxh3;
 
+use crate::atomic;
 use crate::file;
 use crate::tool;
 
@@ -55,6 +61,107 @@ pub(crate) trait CacheWriter {
     fn done(&mut self, key: &Key);
     fn done_hash(&mut self, hash: KeyHash);
     fn flush(&mut self) -> Result<bool>;
+    /// Where to record entries as soon as commands complete, if anywhere
+    fn journal(&self) -> Option<Arc<Journal>> {
+        None
+    }
+}
+
+/// Append-only log of entries added since the cache was last flushed, so that
+/// they survive if Lūn is killed. Loaded along with the cache, and removed on
+/// flush.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    /// Opened on the first append
+    file: Mutex<Option<fs::File>>,
+}
+
+const JOURNAL_MAGIC: &[u8; 4] = b"lunj";
+
+impl Journal {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Append entries. Failures are only logged, since the entries will still
+    /// be flushed to the cache at the end of the run.
+    pub(crate) fn append(&self, hashes: &[KeyHash]) {
+        if hashes.is_empty() {
+            return;
+        }
+        let mut records = Vec::with_capacity(hashes.len() * RECORD_SIZE);
+        for hash in hashes {
+            records.extend_from_slice(&0u16.to_le_bytes());
+            records.extend_from_slice(&hash.0.0.to_le_bytes());
+        }
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = self.write(&mut file, &records) {
+            warn!("Failed to write cache journal {}: {e}", self.path.display());
+        }
+    }
+
+    fn write(&self, file: &mut Option<fs::File>, records: &[u8]) -> std::io::Result<()> {
+        let file = match file {
+            Some(file) => file,
+            None => {
+                let mut opened = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                let len = usize::try_from(opened.metadata()?.len()).unwrap_or(usize::MAX);
+                if len < HEADER_SIZE {
+                    opened.set_len(0)?;
+                    opened.write_all(JOURNAL_MAGIC)?;
+                    opened.write_all(&FORMAT_VERSION.to_le_bytes())?;
+                } else if !(len - HEADER_SIZE).is_multiple_of(RECORD_SIZE) {
+                    // Drop a partial record left by a killed process, so that
+                    // the new records are aligned
+                    let records = (len - HEADER_SIZE) / RECORD_SIZE;
+                    opened.set_len((HEADER_SIZE + records * RECORD_SIZE) as u64)?;
+                }
+                file.insert(opened)
+            }
+        };
+        // A single write, so that a killed process leaves at most one
+        // partial record
+        file.write_all(records)
+    }
+
+    /// Entries in the journal at `path`, if any
+    fn load(path: &Path) -> Vec<KeyHash> {
+        let Ok(contents) = fs::read(path) else {
+            return Vec::new();
+        };
+        let ok = contents.len() >= HEADER_SIZE
+            && contents[..JOURNAL_MAGIC.len()] == *JOURNAL_MAGIC
+            && contents[JOURNAL_MAGIC.len()..HEADER_SIZE] == FORMAT_VERSION.to_le_bytes();
+        if !ok {
+            warn!("Corrupted cache journal at {}", path.display());
+            return Vec::new();
+        }
+        // Ignore a trailing partial record
+        #[allow(clippy::unwrap_used)]
+        let hashes = contents[HEADER_SIZE..]
+            .chunks_exact(RECORD_SIZE)
+            .map(|chunk| {
+                KeyHash(file::Xxhash(u128::from_le_bytes(
+                    chunk[size_of::<u16>()..].try_into().unwrap(),
+                )))
+            })
+            .collect::<Vec<_>>();
+        debug!("Loaded {} entries from {}", hashes.len(), path.display());
+        hashes
+    }
+
+    /// Remove the journal, once its entries are in the cache
+    fn clear(&self) {
+        self.file.lock().unwrap_or_else(|e| e.into_inner()).take();
+        drop(fs::remove_file(&self.path));
+    }
 }
 
 pub(crate) trait Cache: CacheWriter {
@@ -62,14 +169,29 @@ pub(crate) trait Cache: CacheWriter {
 }
 
 pub(crate) struct HashCache {
+    /// Map from key hashes to their age, i.e., the number of runs since they
+    /// were last used
     pub(crate) hashes: HashMap<KeyHash, u16>,
     file: PathBuf,
     pub(crate) max_entries: usize,
+    /// Entries older than this (in runs) are dropped on flush
+    pub(crate) max_age: Option<NonZeroU16>,
+    /// Flush writes to disk before returning, see [`atomic::write`]
+    pub(crate) fsync: bool,
     pub(crate) entries_added: usize, // used in warnings
+    entries_dropped: usize,          // on load, used in warnings
+    journal: Option<Arc<Journal>>,
 }
 
-// Header format: 2 bytes (major) + 2 bytes (minor) + 2 bytes (patch) = 6 bytes total
+// Header format: 4 bytes (magic) + 2 bytes (format version) = 6 bytes total
 const HEADER_SIZE: usize = 6;
+const MAGIC: &[u8; 4] = b"lunc";
+/// Version of the cache format. Bump this when the layout of records or the
+/// way that keys are computed changes, but not otherwise.
+const FORMAT_VERSION: u16 = 1;
+/// Releases (before the format version was introduced) that wrote caches in
+/// format version 1. Their headers held the version of Lūn instead.
+const LEGACY_VERSIONS: &[(u16, u16, u16)] = &[(0, 6, 0)];
 const RECORD_SIZE: usize = size_of::<u16>() + size_of::<KeyHash>(); // 2 bytes (u16 counter) + 16 bytes (u128 hash)
 // For reference rust-lang/rust has 32000 (~ 2^15) .rs files
 // 2^17 * 18 bytes is ~ 2.25 MiB
@@ -81,34 +203,35 @@ pub(crate) fn max_entries_from_bytes(bytes: usize) -> usize {
     bytes / RECORD_SIZE
 }
 
-#[allow(clippy::unwrap_used)]
-fn current_version() -> (u16, u16, u16) {
-    (
-        const { u16::from_str_radix(env!("CARGO_PKG_VERSION_MAJOR"), 10) }.unwrap(),
-        const { u16::from_str_radix(env!("CARGO_PKG_VERSION_MINOR"), 10) }.unwrap(),
-        const { u16::from_str_radix(env!("CARGO_PKG_VERSION_PATCH"), 10) }.unwrap(),
-    )
+#[derive(Debug, PartialEq, Eq)]
+enum Header {
+    Format(u16),
+    /// Version of Lūn that wrote the cache
+    Legacy(u16, u16, u16),
 }
 
-fn serialize_version_header(major: u16, minor: u16, patch: u16) -> [u8; HEADER_SIZE] {
+fn serialize_header() -> [u8; HEADER_SIZE] {
     let mut header = [0u8; HEADER_SIZE];
-    header[0..2].copy_from_slice(&major.to_le_bytes());
-    header[2..4].copy_from_slice(&minor.to_le_bytes());
-    header[4..6].copy_from_slice(&patch.to_le_bytes());
+    header[0..4].copy_from_slice(MAGIC);
+    header[4..6].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
     header
 }
 
-fn deserialize_version_header(header: &[u8]) -> Option<(u16, u16, u16)> {
+fn deserialize_header(header: &[u8]) -> Option<Header> {
     if header.len() < HEADER_SIZE {
         return None;
     }
+    if &header[0..4] == MAGIC {
+        let version_bytes: [u8; 2] = header[4..6].try_into().ok()?;
+        return Some(Header::Format(u16::from_le_bytes(version_bytes)));
+    }
     let major_bytes: [u8; 2] = header[0..2].try_into().ok()?;
     let minor_bytes: [u8; 2] = header[2..4].try_into().ok()?;
     let patch_bytes: [u8; 2] = header[4..6].try_into().ok()?;
     let major = u16::from_le_bytes(major_bytes);
     let minor = u16::from_le_bytes(minor_bytes);
     let patch = u16::from_le_bytes(patch_bytes);
-    Some((major, minor, patch))
+    Some(Header::Legacy(major, minor, patch))
 }
 
 impl HashCache {
@@ -118,7 +241,11 @@ impl HashCache {
             hashes: HashMap::new(),
             file,
             max_entries: max_size_entries,
+            max_age: None,
+            fsync: false,
             entries_added: 0,
+            entries_dropped: 0,
+            journal: None,
         }
     }
 
@@ -128,14 +255,33 @@ impl HashCache {
             max_entries_from_bytes,
         );
         let mut cache = Self::new(file.to_path_buf(), max_size_entries);
-        if !file.exists() {
+        if file.exists() {
+            cache.load(file)?;
+        } else {
             debug!("No cache at {}", file.display());
-            return Ok(cache);
         }
-        cache.load(file)?;
+        let journal = file.with_extension("journal");
+        for hash in Journal::load(&journal) {
+            cache.hashes.insert(hash, 0);
+        }
+        cache.journal = Some(Arc::new(Journal::new(journal)));
         Ok(cache)
     }
 
+    /// Prepare the cache for another run after a flush, as if it had been
+    /// loaded again (i.e., by `lun daemon`)
+    pub(crate) fn next_run(&mut self) {
+        if let Some(max_age) = self.max_age {
+            self.expire(max_age);
+        }
+        for age in self.hashes.values_mut() {
+            *age = age.saturating_add(1);
+        }
+        self.compact();
+        self.entries_added = 0;
+        self.entries_dropped = 0;
+    }
+
     fn cache_ok(file: &Path, contents: &[u8]) -> bool {
         if contents.len() < HEADER_SIZE {
             warn!(
@@ -146,28 +292,43 @@ impl HashCache {
             return false;
         }
 
-        let Some((cached_major, cached_minor, cached_patch)) =
-            deserialize_version_header(&contents[0..HEADER_SIZE])
-        else {
+        let Some(header) = deserialize_header(&contents[0..HEADER_SIZE]) else {
             warn!("Corrupted cache header at {}", file.display(),);
             return false;
         };
 
-        let (current_major, current_minor, current_patch) = current_version();
-        if (cached_major, cached_minor, cached_patch)
-            != (current_major, current_minor, current_patch)
-        {
-            info!(
-                "Cache version mismatch at {} (lun: {}.{}.{}, cache: {}.{}.{})",
-                file.display(),
-                current_major,
-                current_minor,
-                current_patch,
-                cached_major,
-                cached_minor,
-                cached_patch,
-            );
-            return false;
+        match header {
+            Header::Format(FORMAT_VERSION) => (),
+            Header::Format(version) => {
+                info!(
+                    "Cache format mismatch at {} (lun: {}, cache: {})",
+                    file.display(),
+                    FORMAT_VERSION,
+                    version,
+                );
+                return false;
+            }
+            Header::Legacy(major, minor, patch)
+                if LEGACY_VERSIONS.contains(&(major, minor, patch)) =>
+            {
+                debug!(
+                    "Migrating cache at {} from lun {}.{}.{}",
+                    file.display(),
+                    major,
+                    minor,
+                    patch,
+                );
+            }
+            Header::Legacy(major, minor, patch) => {
+                info!(
+                    "Cache version mismatch at {} (cache: lun {}.{}.{})",
+                    file.display(),
+                    major,
+                    minor,
+                    patch,
+                );
+                return false;
+            }
         }
 
         if !(contents.len() - HEADER_SIZE).is_multiple_of(RECORD_SIZE) {
@@ -193,9 +354,36 @@ impl HashCache {
         let records_data = &contents[HEADER_SIZE..];
         self.load_records(records_data);
         debug!("Loaded {} hashes", self.hashes.len());
+        if self.hashes.len() > self.max_entries {
+            self.compact();
+        }
         Ok(())
     }
 
+    /// Drop the oldest entries until the cache is within its size limit
+    fn compact(&mut self) {
+        let excess = self.hashes.len().saturating_sub(self.max_entries);
+        if excess == 0 {
+            return;
+        }
+        let mut entries: Vec<(u16, KeyHash)> =
+            self.hashes.iter().map(|(h, &age)| (age, *h)).collect();
+        // Oldest first, then by hash for determinism
+        entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.0.0.cmp(&b.1.0.0)));
+        for (_, hash) in entries.into_iter().take(excess) {
+            self.hashes.remove(&hash);
+        }
+        debug!("Dropped {excess} old cache entries");
+        self.entries_dropped += excess;
+    }
+
+    /// Drop entries that haven't been used in more than `max_age` runs
+    fn expire(&mut self, max_age: NonZeroU16) {
+        let before = self.hashes.len();
+        self.hashes.retain(|_, age| *age < max_age.get());
+        debug!("Expired {} cache entries", before - self.hashes.len());
+    }
+
     fn load_records(&mut self, contents: &[u8]) {
         assert_eq!(contents.len() % RECORD_SIZE, 0); // cache_ok
         self.hashes.reserve(contents.len() / RECORD_SIZE);
@@ -219,6 +407,9 @@ impl HashCache {
             self.file.display(),
         );
 
+        if let Some(max_age) = self.max_age {
+            self.expire(max_age);
+        }
         let mut entries: Vec<(u16, u128)> = self
             .hashes
             .iter()
@@ -230,12 +421,11 @@ impl HashCache {
         let initial_count = entries.len();
         let to_keep = entries.len().min(self.max_entries);
         let removed_count = initial_count.saturating_sub(to_keep);
-        let cache_full = removed_count > 0;
+        let cache_full = removed_count > 0 || self.entries_dropped > 0;
         debug!("Dropping {} old cache entries", removed_count);
 
         let mut content = Vec::with_capacity(HEADER_SIZE + to_keep * RECORD_SIZE);
-        let (major, minor, patch) = current_version();
-        content.extend_from_slice(&serialize_version_header(major, minor, patch));
+        content.extend_from_slice(&serialize_header());
 
         for (counter, hash_value) in entries.into_iter().take(to_keep) {
             debug_assert_eq!(
@@ -252,23 +442,30 @@ impl HashCache {
 impl CacheWriter for HashCache {
     #[inline]
     fn done_hash(&mut self, hash: KeyHash) {
-        let was_new = self.hashes.insert(hash, 0).is_none();
-        debug_assert!(was_new);
-        self.entries_added += 1;
+        // Entries may already be present for tools run with `--fresh-tool`
+        if self.hashes.insert(hash, 0).is_none() {
+            self.entries_added += 1;
+        }
     }
 
     #[inline]
     fn done(&mut self, key: &Key) {
-        debug_assert!(self.needed(key));
         self.done_hash(KeyHash::from(key));
     }
 
     fn flush(&mut self) -> Result<bool> {
         let (content, cache_full) = self.serialize();
-        fs::write(&self.file, content)
+        atomic::write(&self.file, &content, self.fsync)
             .with_context(|| format!("Failed to write cache file: {}", self.file.display()))?;
+        if let Some(journal) = &self.journal {
+            journal.clear();
+        }
         Ok(cache_full)
     }
+
+    fn journal(&self) -> Option<Arc<Journal>> {
+        self.journal.clone()
+    }
 }
 
 impl Cache for HashCache {
@@ -280,6 +477,51 @@ impl Cache for HashCache {
     }
 }
 
+/// Default cache directory, relative to the repository
+pub(crate) const DEFAULT_DIR: &str = ".lun";
+
+/// Where the cache lives, if not given with `--cache`: `$LUN_CACHE_DIR`, then
+/// `cache_dir` from the config file, then `.lun` if the current directory is
+/// writable, and otherwise a directory per repository under
+/// `$XDG_CACHE_HOME/lun`.
+pub(crate) fn resolve_dir(
+    env: impl Fn(&str) -> Option<OsString>,
+    config_dir: Option<&Path>,
+    cwd: &Path,
+) -> PathBuf {
+    if let Some(dir) = env("LUN_CACHE_DIR").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = config_dir {
+        return dir.to_path_buf();
+    }
+    let default = Path::new(DEFAULT_DIR);
+    if cwd.join(default).exists() || writable(cwd) {
+        return default.to_path_buf();
+    }
+    let user_cache = env("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".cache")));
+    let Some(user_cache) = user_cache else {
+        return default.to_path_buf();
+    };
+    let mut hasher = Xxh3::new();
+    hasher.update(cwd.as_os_str().as_encoded_bytes());
+    let dir = user_cache
+        .join("lun")
+        .join(format!("{:032x}", hasher.digest128()));
+    debug!("{} is read-only, using {}", cwd.display(), dir.display());
+    dir
+}
+
+/// Whether a directory can be created in `dir`, without creating one (which
+/// file watchers would see). Also false on read-only filesystems.
+fn writable(dir: &Path) -> bool {
+    let access = rustix::fs::Access::WRITE_OK | rustix::fs::Access::EXEC_OK;
+    rustix::fs::access(dir, access).is_ok()
+}
+
 pub(crate) fn rm(path: &Path) -> Result<(), anyhow::Error> {
     if path.exists() {
         fs::remove_dir_all(path)
@@ -289,13 +531,23 @@ pub(crate) fn rm(path: &Path) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-pub(crate) fn gc(cache_file: &Path, max_size_bytes: Option<usize>) -> Result<(), anyhow::Error> {
+pub(crate) fn gc(
+    cache_file: &Path,
+    max_size_bytes: Option<usize>,
+    max_age: Option<NonZeroU16>,
+) -> Result<(), anyhow::Error> {
     if !cache_file.exists() {
         info!("No cache file at {}", cache_file.display());
         return Ok(());
     }
     let max_size_bytes = max_size_bytes.unwrap_or(DEFAULT_MAX_CACHE_SIZE_BYTES);
     let mut cache = HashCache::from_file(cache_file, Some(max_size_bytes))?;
+    if let Some(max_age) = max_age {
+        let before = cache.hashes.len();
+        cache.expire(max_age);
+        let expired = before - cache.hashes.len();
+        info!("Removed {expired} entries unused in the last {max_age} runs");
+    }
     let cache_full = cache.flush()?;
     if cache_full {
         info!("Cache reduced to {} bytes", max_size_bytes);
@@ -305,6 +557,95 @@ pub(crate) fn gc(cache_file: &Path, max_size_bytes: Option<usize>) -> Result<(),
     Ok(())
 }
 
+const EXPORT_MAGIC: &[u8; 4] = b"lunx";
+
+/// Write the cache to `path` in a portable format, see `doc/cache.md`
+pub(crate) fn export(cache_file: &Path, path: &Path) -> Result<(), anyhow::Error> {
+    let cache = HashCache::from_file(cache_file, Some(usize::MAX))?;
+    let mut entries: Vec<(u128, u16)> = cache.hashes.iter().map(|(h, &a)| (h.0.0, a)).collect();
+    // Sort by hash so that the output only depends on the entries
+    entries.sort_unstable();
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(EXPORT_MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    let mut encoder = ZlibEncoder::new(header, Compression::best());
+    // The counters are mostly the same, and sorted hashes share their leading
+    // bytes, so they compress better apart and big-endian
+    let compress = |encoder: &mut ZlibEncoder<Vec<u8>>| -> std::io::Result<()> {
+        for (_, counter) in &entries {
+            encoder.write_all(&counter.to_le_bytes())?;
+        }
+        for (hash_value, _) in &entries {
+            encoder.write_all(&hash_value.to_be_bytes())?;
+        }
+        Ok(())
+    };
+    compress(&mut encoder).context("Failed to compress cache export")?;
+    let content = encoder
+        .finish()
+        .context("Failed to compress cache export")?;
+    atomic::write(path, &content, false)
+        .with_context(|| format!("Failed to write cache export: {}", path.display()))?;
+    info!("Exported {} entries to {}", entries.len(), path.display());
+    Ok(())
+}
+
+/// Merge entries exported with [`export`] into the cache
+pub(crate) fn import(
+    cache_file: &Path,
+    path: &Path,
+    max_size_bytes: Option<usize>,
+) -> Result<(), anyhow::Error> {
+    let content = fs::read(path)
+        .with_context(|| format!("Failed to read cache export: {}", path.display()))?;
+    if content.len() < HEADER_SIZE || &content[0..4] != EXPORT_MAGIC {
+        bail!("Not a cache export: {}", path.display());
+    }
+    #[allow(clippy::unwrap_used)]
+    let version = u16::from_le_bytes(content[4..6].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        bail!(
+            "Cache export {} has format {version}, expected {FORMAT_VERSION}",
+            path.display()
+        );
+    }
+    let mut records = Vec::new();
+    ZlibDecoder::new(&content[HEADER_SIZE..])
+        .read_to_end(&mut records)
+        .with_context(|| format!("Corrupted cache export: {}", path.display()))?;
+    if !records.len().is_multiple_of(RECORD_SIZE) {
+        bail!("Corrupted cache export: {}", path.display());
+    }
+
+    // See `export`
+    let (counters, hashes) = records.split_at(records.len() / RECORD_SIZE * size_of::<u16>());
+    #[allow(clippy::unwrap_used)]
+    let imported = counters
+        .chunks_exact(size_of::<u16>())
+        .zip(hashes.chunks_exact(size_of::<KeyHash>()))
+        .map(|(counter, hash)| {
+            let hash = u128::from_be_bytes(hash.try_into().unwrap());
+            (
+                KeyHash(file::Xxhash(hash)),
+                u16::from_le_bytes(counter.try_into().unwrap()),
+            )
+        });
+    let mut cache = HashCache::from_file(cache_file, max_size_bytes)?;
+    let before = cache.hashes.len();
+    for (hash, age) in imported {
+        cache
+            .hashes
+            .entry(hash)
+            .and_modify(|a| *a = (*a).min(age))
+            .or_insert(age);
+    }
+    let added = cache.hashes.len() - before;
+    cache.compact();
+    cache.flush()?;
+    info!("Imported {added} new entries from {}", path.display());
+    Ok(())
+}
+
 pub(crate) fn stats(cache_file: &Path) -> Result<(), anyhow::Error> {
     const KIBI: usize = 1024;
     const TWO_KIBI: usize = 2 * KIBI;
@@ -317,11 +658,7 @@ pub(crate) fn stats(cache_file: &Path) -> Result<(), anyhow::Error> {
     let total_size_bytes = HEADER_SIZE + records * RECORD_SIZE;
     let max_records = cache.max_entries;
     let max_size_bytes = HEADER_SIZE + max_records * RECORD_SIZE;
-    let percentage_used = if max_records > 0 {
-        (records * 100) / max_records
-    } else {
-        0
-    };
+    let percentage_used = (records * 100).checked_div(max_records).unwrap_or(0);
 
     // Calculate records in most recent run (counter == 0)
     let records_most_recent_run = cache
@@ -338,11 +675,7 @@ pub(crate) fn stats(cache_file: &Path) -> Result<(), anyhow::Error> {
     } else {
         0
     };
-    let avg_records_per_run = if runs_represented > 0 {
-        records / runs_represented
-    } else {
-        0
-    };
+    let avg_records_per_run = records.checked_div(runs_represented).unwrap_or(0);
 
     info!("Number of runs: {runs_represented}");
     info!("Records: {records}");
@@ -365,6 +698,37 @@ pub(crate) fn stats(cache_file: &Path) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+pub(crate) fn key(cache_file: &Path, tool: &tool::Tool, path: &Path) -> Result<(), anyhow::Error> {
+    let mut file = file::File::new(path.to_path_buf())?;
+    file.fill_content_stamp()?;
+    let cache = HashCache::from_file(cache_file, None)?;
+    let cached = |key: &Key| {
+        if cache.hashes.contains_key(&KeyHash::from(key)) {
+            "hit"
+        } else {
+            "miss"
+        }
+    };
+    let mtime_key = Key::from_mtime(&file, tool);
+    let content_key = Key::from_content(&file, tool);
+    println!("tool:          {}", tool.display_name());
+    println!("command:       {}", tool.cmd);
+    println!("tool stamp:    {:032x}", tool.stamp.0.0);
+    println!("mtime stamp:   {:032x}", mtime_key.stamp.0.0);
+    println!("content stamp: {:032x}", content_key.stamp.0.0);
+    println!(
+        "mtime key:     {:032x} ({})",
+        KeyHash::from(&mtime_key).0.0,
+        cached(&mtime_key)
+    );
+    println!(
+        "content key:   {:032x} ({})",
+        KeyHash::from(&content_key).0.0,
+        cached(&content_key)
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -388,6 +752,23 @@ mod tests {
         assert_eq!(cache.file, temp_file.path());
     }
 
+    #[test]
+    fn next_run_matches_reload() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut cache = HashCache::new(temp_file.path().to_path_buf(), 1000);
+        cache.max_age = NonZeroU16::new(2);
+        cache.done(&create_test_key("a.rs", "cargo fmt"));
+        cache.flush().unwrap();
+        let reloaded = HashCache::from_file(temp_file.path(), None).unwrap();
+        cache.next_run();
+        assert_eq!(reloaded.hashes, cache.hashes);
+        cache.next_run();
+        assert!(!cache.hashes.is_empty());
+        cache.next_run();
+        assert!(cache.hashes.is_empty());
+        assert_eq!(cache.entries_added, 0);
+    }
+
     #[test]
     fn load_nonexistent_file() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -407,6 +788,179 @@ mod tests {
         assert!(cache.hashes.is_empty());
     }
 
+    #[test]
+    fn load_truncated_file() {
+        let temp_file = NamedTempFile::new().unwrap();
+        {
+            let mut cache = HashCache::new(temp_file.path().to_path_buf(), 1000);
+            cache.done(&create_test_key("test.rs", "cargo fmt"));
+            cache.flush().unwrap();
+        }
+        let content = fs::read(temp_file.path()).unwrap();
+        fs::write(temp_file.path(), &content[..content.len() - 1]).unwrap();
+
+        let cache = HashCache::from_file(temp_file.path(), None).unwrap();
+        assert!(cache.hashes.is_empty());
+        assert!(!temp_file.path().exists());
+    }
+
+    fn legacy_cache(path: &Path, version: (u16, u16, u16), key: &Key) {
+        let mut content = Vec::new();
+        content.extend_from_slice(&version.0.to_le_bytes());
+        content.extend_from_slice(&version.1.to_le_bytes());
+        content.extend_from_slice(&version.2.to_le_bytes());
+        content.extend_from_slice(&0u16.to_le_bytes());
+        content.extend_from_slice(&KeyHash::from(key).0.0.to_le_bytes());
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn migrate_legacy() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let key = create_test_key("test.rs", "cargo fmt");
+        legacy_cache(temp_file.path(), (0, 6, 0), &key);
+        {
+            let mut cache = HashCache::from_file(temp_file.path(), None).unwrap();
+            assert!(!cache.needed(&key));
+            cache.flush().unwrap();
+        }
+        let content = fs::read(temp_file.path()).unwrap();
+        assert_eq!(
+            deserialize_header(&content),
+            Some(Header::Format(FORMAT_VERSION))
+        );
+        let mut cache = HashCache::from_file(temp_file.path(), None).unwrap();
+        assert!(!cache.needed(&key));
+    }
+
+    #[test]
+    fn discard_old_legacy() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let key = create_test_key("test.rs", "cargo fmt");
+        legacy_cache(temp_file.path(), (0, 5, 0), &key);
+        let cache = HashCache::from_file(temp_file.path(), None).unwrap();
+        assert!(cache.hashes.is_empty());
+    }
+
+    #[test]
+    fn discard_other_format() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut content = serialize_header().to_vec();
+        content[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        fs::write(temp_file.path(), content).unwrap();
+        let cache = HashCache::from_file(temp_file.path(), None).unwrap();
+        assert!(cache.hashes.is_empty());
+        assert!(!temp_file.path().exists());
+    }
+
+    #[test]
+    fn export_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let ci = dir.path().join("ci");
+        let local = dir.path().join("local");
+        let export_file = dir.path().join("export");
+        let shared = create_test_key("shared.rs", "cargo fmt");
+        let remote = create_test_key("theirs.rs.bak", "cargo fmt");
+        let mine = create_test_key("mine.rs", "cargo fmt");
+        {
+            let mut cache = HashCache::new(ci.clone(), 1000);
+            cache.done(&shared);
+            cache.done(&remote);
+            cache.flush().unwrap();
+            let mut cache = HashCache::new(local.clone(), 1000);
+            cache.done(&shared);
+            cache.done(&mine);
+            cache.flush().unwrap();
+        }
+        export(&ci, &export_file).unwrap();
+        let exported = fs::read(&export_file).unwrap();
+        export(&ci, &export_file).unwrap();
+        assert_eq!(fs::read(&export_file).unwrap(), exported);
+
+        import(&local, &export_file, None).unwrap();
+        let mut cache = HashCache::from_file(&local, None).unwrap();
+        assert_eq!(cache.hashes.len(), 3);
+        assert!(!cache.needed(&shared));
+        assert!(!cache.needed(&remote));
+        assert!(!cache.needed(&mine));
+    }
+
+    #[test]
+    fn import_rejects_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = dir.path().join("local");
+        let mut cache = HashCache::new(local.clone(), 1000);
+        cache.done(&create_test_key("test.rs", "cargo fmt"));
+        cache.flush().unwrap();
+        assert!(import(&dir.path().join("other"), &local, None).is_err());
+    }
+
+    #[test]
+    fn export_compressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let ci = dir.path().join("ci");
+        let export_file = dir.path().join("export");
+        let mut cache = HashCache::new(ci.clone(), 100_000);
+        for i in 0..1000 {
+            cache.done(&create_test_key(&"a".repeat(i + 1), "cargo fmt"));
+        }
+        cache.flush().unwrap();
+        export(&ci, &export_file).unwrap();
+        let exported = fs::read(&export_file).unwrap();
+        assert!(exported.len() < HEADER_SIZE + 1000 * RECORD_SIZE);
+
+        let local = dir.path().join("local");
+        import(&local, &export_file, None).unwrap();
+        assert_eq!(
+            HashCache::from_file(&local, None).unwrap().hashes.len(),
+            1000
+        );
+        // Truncated
+        fs::write(&export_file, &exported[..exported.len() - 8]).unwrap();
+        assert!(import(&local, &export_file, None).is_err());
+    }
+
+    #[test]
+    fn compact_on_load() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let old = create_test_key("old.rs", "cargo fmt");
+        let new = create_test_key("newer.rs", "cargo fmt");
+        {
+            let mut cache = HashCache::new(temp_file.path().to_path_buf(), 1000);
+            cache.done(&old);
+            cache.flush().unwrap();
+        }
+        {
+            let mut cache = HashCache::from_file(temp_file.path(), None).unwrap();
+            cache.done(&new);
+            cache.flush().unwrap();
+        }
+        let mut cache = HashCache::from_file(temp_file.path(), Some(RECORD_SIZE)).unwrap();
+        assert_eq!(cache.hashes.len(), 1);
+        assert!(cache.needed(&old));
+        assert!(!cache.needed(&new));
+        assert!(cache.flush().unwrap());
+    }
+
+    #[test]
+    fn max_age() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let key = create_test_key("test.rs", "cargo fmt");
+        {
+            let mut cache = HashCache::new(temp_file.path().to_path_buf(), 1000);
+            cache.done(&key);
+            cache.flush().unwrap();
+        }
+        for _ in 0..2 {
+            let mut cache = HashCache::from_file(temp_file.path(), None).unwrap();
+            cache.max_age = NonZeroU16::new(2);
+            assert!(!cache.hashes.is_empty());
+            cache.flush().unwrap();
+        }
+        let cache = HashCache::from_file(temp_file.path(), None).unwrap();
+        assert!(cache.hashes.is_empty());
+    }
+
     #[test]
     fn persistence() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -421,4 +975,70 @@ mod tests {
             assert!(!cache.needed(&key));
         }
     }
+
+    #[test]
+    fn journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("cache");
+        let key = create_test_key("test.rs", "cargo fmt");
+        {
+            // Killed before flushing
+            let cache = HashCache::from_file(&file, None).unwrap();
+            cache.journal().unwrap().append(&[KeyHash::from(&key)]);
+        }
+        // A partial record, e.g., from a write that was interrupted
+        let journal = dir.path().join("cache.journal");
+        let mut f = fs::OpenOptions::new().append(true).open(&journal).unwrap();
+        f.write_all(&[0; 3]).unwrap();
+        let key2 = create_test_key("test2.rs", "cargo fmt");
+        {
+            // Killed again, after appending after the partial record
+            let mut cache = HashCache::from_file(&file, None).unwrap();
+            assert!(!cache.needed(&key));
+            cache.journal().unwrap().append(&[KeyHash::from(&key2)]);
+        }
+        let mut cache = HashCache::from_file(&file, None).unwrap();
+        assert!(!cache.needed(&key));
+        assert!(!cache.needed(&key2));
+        cache.flush().unwrap();
+        assert!(!journal.exists());
+        let mut cache = HashCache::from_file(&file, None).unwrap();
+        assert!(!cache.needed(&key));
+    }
+
+    #[test]
+    fn resolve_dir_precedence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |var: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == var)
+                    .map(|(_, v)| OsString::from(v))
+            }
+        };
+        let vars = &[("LUN_CACHE_DIR", "/env"), ("XDG_CACHE_HOME", "/xdg")];
+        let config = Some(Path::new("/config"));
+        assert_eq!(
+            resolve_dir(env(vars), config, tmp.path()),
+            PathBuf::from("/env")
+        );
+        let vars = &[("LUN_CACHE_DIR", ""), ("XDG_CACHE_HOME", "/xdg")];
+        assert_eq!(
+            resolve_dir(env(vars), config, tmp.path()),
+            PathBuf::from("/config")
+        );
+        assert_eq!(
+            resolve_dir(env(vars), None, tmp.path()),
+            PathBuf::from(DEFAULT_DIR)
+        );
+        // Checking doesn't touch the directory
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+        // Nothing can be created in a missing directory
+        let read_only = tmp.path().join("missing");
+        let dir = resolve_dir(env(vars), None, &read_only);
+        assert!(dir.starts_with("/xdg/lun"));
+        let vars = &[("HOME", "/home/user")];
+        let dir = resolve_dir(env(vars), None, &read_only);
+        assert!(dir.starts_with("/home/user/.cache/lun"));
+    }
 }
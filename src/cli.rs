@@ -1,25 +1,137 @@
-use std::{num::NonZeroUsize, path::PathBuf};
+use std::{
+    ffi::OsString,
+    num::{NonZeroU16, NonZeroUsize},
+    path::PathBuf,
+};
+
+use clap::{CommandFactory as _, FromArgMatches as _, builder::Resettable, parser::ValueSource};
+
+use crate::{cache, config};
 
 pub(crate) mod log;
 pub(crate) mod warn;
 
+#[derive(Debug)]
+pub(crate) struct Cli {
+    /// See [`Cli::resolve_cache`]
+    pub(crate) cache: PathBuf,
+    /// `--cache` was given
+    pub(crate) cache_given: bool,
+    pub(crate) config: PathBuf,
+    /// `--config` was given
+    pub(crate) config_given: bool,
+    /// Merged on top of `config`, see [`config::Config::load`]
+    pub(crate) config_overlays: Vec<PathBuf>,
+    /// See `root.rs`
+    pub(crate) project_root: Option<PathBuf>,
+    pub(crate) log: log::LogOptions,
+    pub(crate) warn: warn::WarnOpts,
+    pub(crate) command: Command,
+}
+
 #[derive(Debug, clap::Parser)]
 #[command(name = "lun")]
 #[command(about = "Run linters fast")]
 #[command(version)]
-pub(crate) struct Cli {
-    /// Path to the cache directory
-    #[arg(long, default_value = ".lun")]
-    pub(crate) cache: PathBuf,
-    /// Path to the configuration file
-    #[arg(short, long, default_value = "lun.toml")]
-    pub(crate) config: PathBuf,
+struct Args {
+    /// Path to the cache directory [default: $LUN_CACHE_DIR, `cache_dir`, or .lun]
+    #[arg(long)]
+    cache: Option<PathBuf>,
+    /// Path to the configuration file [default: lun.toml in the project root]
+    ///
+    /// When given several times, later files are merged on top of earlier ones.
+    #[arg(short, long, action = clap::ArgAction::Append)]
+    config: Vec<PathBuf>,
+    /// Merge `lun.NAME.toml` on top of the configuration file
+    #[arg(long, value_name = "NAME")]
+    config_name: Option<String>,
+    /// Run in this directory, rather than the closest one with a config file
+    #[arg(long, value_name = "DIR")]
+    project_root: Option<PathBuf>,
     #[command(flatten)]
-    pub(crate) log: log::LogOptions,
+    log: log::LogOptions,
     #[command(flatten)]
-    pub(crate) warn: warn::WarnOpts,
+    warn: warn::WarnOpts,
+    #[command(flatten, next_help_heading = "Run options (without a subcommand)")]
+    run: Run,
     #[command(subcommand)]
-    pub(crate) command: Command,
+    command: Option<Command>,
+}
+
+impl Cli {
+    pub(crate) fn parse() -> Self {
+        Self::try_parse_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse arguments. Without a subcommand, this is equivalent to `lun run`.
+    pub(crate) fn try_parse_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut cmd = Args::command();
+        let matches = cmd.try_get_matches_from_mut(args)?;
+        let args = Args::from_arg_matches(&matches).map_err(|e| e.format(&mut cmd))?;
+        let command = match args.command {
+            None => Command::Run(args.run),
+            Some(command) => {
+                let run_cmd = Run::command();
+                let given = run_cmd.get_arguments().find(|arg| {
+                    matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+                });
+                if let Some(arg) = given {
+                    let name = arg.get_long().map_or_else(
+                        || format!("-{}", arg.get_short().unwrap_or_default()),
+                        |l| format!("--{l}"),
+                    );
+                    let msg = if matches!(command, Command::Run(_)) {
+                        format!("the argument '{name}' must come after `run`")
+                    } else {
+                        format!("the argument '{name}' can only be used with `lun run`")
+                    };
+                    return Err(cmd.error(clap::error::ErrorKind::ArgumentConflict, msg));
+                }
+                command
+            }
+        };
+        let config_given = !args.config.is_empty();
+        let mut configs = args.config.into_iter();
+        let config = configs
+            .next()
+            .unwrap_or_else(|| PathBuf::from(config::DEFAULT_PATH));
+        let mut config_overlays = configs.collect::<Vec<_>>();
+        if let Some(name) = &args.config_name {
+            let stem = config.file_stem().unwrap_or_default().to_string_lossy();
+            config_overlays.push(config.with_file_name(format!("{stem}.{name}.toml")));
+        }
+        Ok(Self {
+            cache_given: args.cache.is_some(),
+            cache: args
+                .cache
+                .unwrap_or_else(|| PathBuf::from(cache::DEFAULT_DIR)),
+            config_given,
+            config,
+            config_overlays,
+            project_root: args.project_root,
+            log: args.log,
+            warn: args.warn,
+            command,
+        })
+    }
+
+    /// Pick the cache directory, unless it was given with `--cache`. See
+    /// [`cache::resolve_dir`].
+    pub(crate) fn resolve_cache(&mut self, config: Option<&config::Config>) {
+        if self.cache_given {
+            return;
+        }
+        let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        self.cache = cache::resolve_dir(
+            |var| std::env::var_os(var),
+            config.and_then(|c| c.cache_dir.as_deref()),
+            &cwd,
+        );
+    }
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -29,6 +141,19 @@ pub(crate) enum Command {
     Cache(Cache),
     Init(Init),
     Add(Add),
+    /// Config file commands
+    Config(Config),
+    /// Git hook commands
+    Hook(Hook),
+    /// Run a language server that reports the diagnostics of linters
+    Lsp,
+    /// Serve `lun run --daemon`, keeping files and the cache in memory
+    Daemon,
+    Bench(Bench),
+    /// Baseline commands
+    Baseline(Baseline),
+    Exec(Exec),
+    Plan(Plan),
     /// Show available warnings
     Warns {
         /// Show documentation for a specific warnings
@@ -53,11 +178,92 @@ pub(crate) enum CacheCommand {
         /// Maximum cache size in bytes (defaults to default max cache size)
         #[arg(long, value_name = "BYTES")]
         size: Option<usize>,
+        /// Remove entries that haven't been used in this many runs
+        #[arg(long, value_name = "RUNS")]
+        max_age: Option<NonZeroU16>,
     },
     /// Print cache statistics
     Stats,
+    /// Write the cache to a file (e.g., for CI caches)
+    Export {
+        /// Path to write to
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Merge a file written by `lun cache export` into the cache
+    Import {
+        /// Path to read from
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
     /// Create, query, and remove freeform cache entries
     Entry(CacheEntry),
+    /// Print the cache keys for a tool and file
+    Key {
+        /// Name of the tool
+        #[arg(value_name = "TOOL")]
+        tool: String,
+        /// File to compute keys for
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Include tool versions in cache keys (`--careful=binary`: their executables)
+        #[arg(
+            long,
+            value_name = "WHAT",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "version"
+        )]
+        careful: Option<config::Careful>,
+        /// Use the "check" command of formatters
+        #[arg(long, conflicts_with = "fix")]
+        check: bool,
+        /// Use the "fix" command of linters
+        #[arg(short = 'x', long)]
+        fix: bool,
+    },
+}
+
+/// Config file commands
+#[derive(Debug, clap::Parser)]
+pub(crate) struct Config {
+    #[command(subcommand)]
+    pub(crate) command: ConfigCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum ConfigCommand {
+    /// Print the config in the format of another tool
+    Export {
+        #[arg(long)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// `treefmt.toml` with the formatters
+    Treefmt,
+}
+
+/// Git hook commands
+#[derive(Debug, clap::Parser)]
+pub(crate) struct Hook {
+    #[command(subcommand)]
+    pub(crate) command: HookCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum HookCommand {
+    /// Install a git hook that runs `lun run --check`
+    Install {
+        /// Hook to install
+        #[arg(value_name = "HOOK", default_value = "pre-commit", value_parser = clap::builder::PossibleValuesParser::new(crate::lefthook::HOOKS))]
+        hook: String,
+        /// Overwrite an existing hook
+        #[arg(short, long)]
+        force: bool,
+    },
 }
 
 /// Cache entry management commands
@@ -86,9 +292,15 @@ pub(crate) enum CacheEntryCommand {
         /// Files to check entries for
         #[arg(value_name = "FILE", required = true)]
         files: Vec<PathBuf>,
-        /// Use null-separated output
-        #[arg(short = '0')]
+        /// Use null-separated output (e.g., for `xargs -0`)
+        #[arg(short = '0', short_alias = 'z', long = "print0")]
         null_separated: bool,
+        /// Print the files with entries, rather than `true` or `false` for each
+        #[arg(long, conflicts_with = "missing")]
+        present: bool,
+        /// Print the files without entries, rather than `true` or `false` for each
+        #[arg(long)]
+        missing: bool,
     },
     /// Remove entries from the cache
     Rm {
@@ -98,53 +310,138 @@ pub(crate) enum CacheEntryCommand {
         /// Files to remove entries for
         #[arg(value_name = "FILE", required = true)]
         files: Vec<PathBuf>,
+        /// Use null-separated output (e.g., for `xargs -0`)
+        #[arg(short = '0', short_alias = 'z', long = "print0")]
+        null_separated: bool,
+        /// Print the files whose entries were removed, rather than `true` or
+        /// `false` for each
+        #[arg(long)]
+        removed: bool,
     },
 }
 
 /// Run linters and formatters
-#[derive(Debug, clap::Parser)]
+#[derive(Clone, Debug, clap::Parser)]
 pub(crate) struct Run {
+    /// Store output of failed commands and replay it until files change
+    #[arg(long)]
+    pub(crate) cache_failures: bool,
+    /// Drop cache entries unused in this many runs (overrides config file value)
+    #[arg(long, value_name = "RUNS")]
+    pub(crate) cache_max_age: Option<NonZeroU16>,
     /// Maximum cache size in bytes (overrides config file value)
     #[arg(long, value_name = "BYTES")]
     pub(crate) cache_size: Option<usize>,
-    /// Include tool version in cache keys
-    #[arg(long)]
-    pub(crate) careful: bool,
+    /// Include tool versions in cache keys (`--careful=binary`: their executables)
+    #[arg(
+        long,
+        value_name = "WHAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "version"
+    )]
+    pub(crate) careful: Option<config::Careful>,
     /// Run linters, run formatters in "check" mode (i.e., in CI)
     #[arg(long)]
     pub(crate) check: bool,
+    /// With --check, print diffs of the changes that formatters would make
+    #[arg(long, requires = "check", conflicts_with = "ninja")]
+    pub(crate) diff: bool,
+    /// Send the run to `lun daemon`, if it is running
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "dry_run",
+            "else",
+            "explain_cache",
+            "files_from",
+            "fix_and_check",
+            "ninja",
+            "no_capture",
+            "output",
+            "output_format",
+            "then",
+            "timings",
+            "watch",
+        ]
+    )]
+    pub(crate) daemon: bool,
     /// Don't execute any commands
     #[arg(short = 'n', long)]
     pub(crate) dry_run: bool,
+    /// Explain why each (tool, file) pair was skipped or scheduled
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text")]
+    pub(crate) explain_cache: Option<ExplainFormat>,
     /// Command to run on failure (see also --then)
     #[arg(short, long)]
     pub(crate) r#else: Option<String>,
+    /// Write diagnostics of failed commands to this file, as `file:line:col: message`
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["ninja", "no_capture", "output"])]
+    pub(crate) error_file: Option<PathBuf>,
+    /// How to show the output of tools
+    #[arg(long, value_name = "MODE", default_value = "captured")]
+    pub(crate) output: OutputMode,
+    /// Format of the results on stdout
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "human",
+        conflicts_with_all = ["ninja", "no_capture", "output"]
+    )]
+    pub(crate) output_format: OutputFormat,
+    /// Only run on files listed in this file (`-` for stdin, see also `-z`)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["staged", "only_dirty"])]
+    pub(crate) files_from: Option<PathBuf>,
     /// Run tools in fix mode (that have them)
     #[arg(short = 'x', long)]
     pub(crate) fix: bool,
+    /// Run tools in fix mode, then check the files that changed or still fail
+    #[arg(long, conflicts_with_all = ["check", "fix", "watch"])]
+    pub(crate) fix_and_check: bool,
     /// Only run formatters
     #[arg(short, long = "format")]
     pub(crate) format: bool,
     /// Equivalent to `--no-cache --no-refs`
     #[arg(short = 'F', long)]
     pub(crate) fresh: bool,
+    /// Ignore cache hits for tools with the given name (can be used multiple times)
+    #[arg(long, action = clap::ArgAction::Append, value_name = "TOOL")]
+    pub(crate) fresh_tool: Vec<String>,
+    /// Run on files marked as generated in `.gitattributes` (overrides config file value)
+    #[arg(long)]
+    pub(crate) include_generated: bool,
     /// Number of parallel jobs (overrides config file value)
     #[arg(short, long = "jobs")]
     pub(crate) jobs: Option<NonZeroUsize>,
-    /// Continue running commands even after one fails
-    #[arg(long)]
-    pub(crate) keep_going: bool,
+    /// Continue running commands until N fail (default: never stop)
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0"
+    )]
+    pub(crate) keep_going: Option<usize>,
+    /// Only run on the first N files that need it, e.g., to try out a new tool
+    #[arg(long, value_name = "N", conflicts_with = "sample")]
+    pub(crate) limit: Option<usize>,
+    /// Run linters with read-only access to the repository and no network
+    #[arg(long, conflicts_with = "ninja")]
+    pub(crate) sandbox: bool,
     /// Use Ninja to run commands (overrides config file value)
     #[arg(short = 'N', long)]
     pub(crate) ninja: bool,
+    /// Report diagnostics in the baseline file, too (see `lun baseline`)
+    #[arg(long)]
+    pub(crate) no_baseline: bool,
     /// Skip batching jobs (run one command per file)
     #[arg(long)]
     pub(crate) no_batch: bool,
     /// Disable reading from and writing to the cache
     #[arg(long)]
     pub(crate) no_cache: bool,
-    /// Don't capture output (stream directly to terminal)
-    #[arg(long)]
+    /// Don't capture output (stream directly to terminal), like `--output raw`
+    #[arg(long, conflicts_with = "output")]
     pub(crate) no_capture: bool,
     /// Disable use of mtime in cache entries (overrides config file value)
     #[arg(long)]
@@ -152,6 +449,15 @@ pub(crate) struct Run {
     /// Ignore any refs from CLI or config file
     #[arg(long)]
     pub(crate) no_refs: bool,
+    /// Send a desktop notification when runs start failing or passing again
+    #[arg(long, requires = "watch")]
+    pub(crate) notify: bool,
+    /// Paths in `--files-from` are NUL-separated rather than newline-separated
+    #[arg(short = 'z', long, requires = "files_from")]
+    pub(crate) null: bool,
+    /// Only run on files with uncommitted changes, including untracked files
+    #[arg(long, conflicts_with = "staged")]
+    pub(crate) only_dirty: bool,
     /// Only run tools with the given name (can be used multiple times)
     #[arg(long, action = clap::ArgAction::Append, value_name = "TOOL")]
     pub(crate) only_tool: Vec<String>,
@@ -164,12 +470,28 @@ pub(crate) struct Run {
     /// Skip matching files (can be used multiple times)
     #[arg(long, action = clap::ArgAction::Append, value_name = "GLOB")]
     pub(crate) skip_files: Vec<String>,
+    /// Don't walk matching paths, in addition to `walk.ignore` (can be used multiple times)
+    #[arg(long, action = clap::ArgAction::Append, value_name = "GLOB")]
+    pub(crate) walk_ignore: Vec<String>,
+    /// Seed for `--shuffle` and `--sample random:N` (default: random)
+    #[arg(long, value_name = "N")]
+    pub(crate) seed: Option<u64>,
+    /// Run commands and pass files to them in a random order, to find tools
+    /// that depend on it (see also `--seed`)
+    #[arg(long)]
+    pub(crate) shuffle: bool,
+    /// Only run on some of the files that need it: `first:N` or `random:N`
+    #[arg(long, value_name = "HOW")]
+    pub(crate) sample: Option<Sample>,
     /// Only run on staged files (useful in pre-commit hooks)
     #[arg(long)]
     pub(crate) staged: bool,
-    /// Command to run failure (useful with --watch)
+    /// Command to run on success (useful with --watch)
     #[arg(short, long)]
     pub(crate) then: Option<String>,
+    /// Report how long walking, hashing, the cache, planning, and running took
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "table")]
+    pub(crate) timings: Option<TimingsFormat>,
     /// Git refs assumed to be good (can be used multiple times)
     #[arg(long, action = clap::ArgAction::Append)]
     pub(crate) refs: Vec<String>,
@@ -178,21 +500,179 @@ pub(crate) struct Run {
     pub(crate) watch: bool,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum OutputMode {
+    /// Show the output of commands that fail, once they finish
+    #[default]
+    Captured,
+    /// Stream output directly to the terminal, like `--no-capture`
+    Raw,
+    /// Stream output, prefixing each line with the name of the tool
+    Prefixed,
+}
+
+/// Which of the files that need running to run on, see `--limit` and
+/// `--sample`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Sample {
+    First(usize),
+    Random(usize),
+}
+
+impl Sample {
+    pub(crate) fn size(self) -> usize {
+        match self {
+            Self::First(n) | Self::Random(n) => n,
+        }
+    }
+}
+
+impl std::str::FromStr for Sample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (how, n) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected `first:N` or `random:N`, found `{s}`"))?;
+        let n = n
+            .parse()
+            .map_err(|e| format!("invalid number of files `{n}`: {e}"))?;
+        match how {
+            "first" => Ok(Self::First(n)),
+            "random" => Ok(Self::Random(n)),
+            _ => Err(format!("expected `first` or `random`, found `{how}`")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// The output of failed commands
+    #[default]
+    Human,
+    /// Test Anything Protocol, with one test per command
+    Tap,
+    /// A JSON object with the number of commands, failures, and diagnostics
+    /// of each tool
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub(crate) enum TimingsFormat {
+    /// A table on stderr
+    #[default]
+    Table,
+    /// A JSON object on stdout, with seconds spent in each phase
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub(crate) enum ExplainFormat {
+    /// Tab-separated reason, tool, and file
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Print the commands that `lun run` would run, and save them in the cache
+/// directory
+// `--diff` means something else than for `lun run`, see `Plan::diff`
+#[derive(Debug, clap::Parser)]
+#[command(mut_arg("diff", |a| a.requires(Resettable::Reset).conflicts_with(Resettable::Reset).help(
+    "Print the files that each tool needs to run on, and no longer does, compared to the previous plan"
+)))]
+pub(crate) struct Plan {
+    #[command(flatten)]
+    pub(crate) run: Run,
+}
+
+impl Plan {
+    pub(crate) fn diff(&self) -> bool {
+        self.run.diff
+    }
+}
+
+/// Run a command on files through the cache, as if it were a configured linter
+#[derive(Debug, clap::Parser)]
+pub(crate) struct Exec {
+    /// Files to run the command on (can be used multiple times)
+    #[arg(long, action = clap::ArgAction::Append, value_name = "GLOB", required = true)]
+    pub(crate) files: Vec<String>,
+    /// Name of the tool in output and the cache (defaults to the program)
+    #[arg(long)]
+    pub(crate) name: Option<String>,
+    /// How to pass files to the command
+    #[arg(long)]
+    pub(crate) granularity: Option<config::Granularity>,
+    #[command(flatten)]
+    pub(crate) run: Run,
+    /// The command, which is passed the files
+    #[arg(last = true, required = true, value_name = "CMD")]
+    pub(crate) cmd: Vec<String>,
+}
+
+/// Baseline commands
+#[derive(Debug, clap::Parser)]
+pub(crate) struct Baseline {
+    #[command(subcommand)]
+    pub(crate) command: BaselineCommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum BaselineCommand {
+    /// Record the current diagnostics of linters, so that `lun run` doesn't
+    /// report them
+    Create {
+        #[command(flatten)]
+        run: Run,
+    },
+}
+
+/// Time the tools without the cache, and compare to the previous benchmark
+#[derive(Debug, clap::Parser)]
+pub(crate) struct Bench {
+    /// Number of times to run each tool
+    #[arg(short = 'n', long, default_value = "5")]
+    pub(crate) iterations: NonZeroUsize,
+}
+
 /// Create a config file with detected linters and formatters
 #[derive(Debug, clap::Parser)]
 pub(crate) struct Init {
     /// Add a tool (can be used multiple times)
     #[arg(short, long, action = clap::ArgAction::Append)]
     pub(crate) tool: Vec<String>,
-    /// Include tool version in cache keys
-    #[arg(long)]
-    pub(crate) careful: bool,
+    /// Include tool versions in cache keys (`--careful=binary`: their executables)
+    #[arg(
+        long,
+        value_name = "WHAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "version"
+    )]
+    pub(crate) careful: Option<config::Careful>,
     /// Number of parallel jobs
     #[arg(long)]
     pub(crate) cores: Option<NonZeroUsize>,
     /// Disable mtime to skip unchanged files
     #[arg(long)]
     pub(crate) no_mtime: bool,
+    /// Overwrite an existing config file
+    #[arg(short, long)]
+    pub(crate) force: bool,
+    /// Translate the hooks of a pre-commit config (e.g., `.pre-commit-config.yaml`)
+    #[arg(long, value_name = "PATH")]
+    pub(crate) from_pre_commit: Option<PathBuf>,
+    /// Translate the formatters of a treefmt config (e.g., `treefmt.toml`)
+    #[arg(long, value_name = "PATH", conflicts_with = "from_pre_commit")]
+    pub(crate) from_treefmt: Option<PathBuf>,
+    /// Translate the commands of a lefthook config (e.g., `lefthook.yml`)
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["from_pre_commit", "from_treefmt"])]
+    pub(crate) from_lefthook: Option<PathBuf>,
+    /// Translate the hook scripts in a husky directory (e.g., `.husky`)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["from_pre_commit", "from_treefmt"])]
+    pub(crate) from_husky: Option<PathBuf>,
     /// Git refs assumed to be good (can be used multiple times)
     #[arg(short, long, action = clap::ArgAction::Append)]
     pub(crate) r#ref: Vec<String>,
@@ -212,6 +692,9 @@ pub(crate) struct Init {
 pub(crate) struct Add {
     /// Name of the tool to add
     pub(crate) tool: String,
+    /// Add a tool that isn't known, named TOOL (requires --cmd and --files)
+    #[arg(long, requires_all = ["cmd", "files"])]
+    pub(crate) custom: bool,
     /// Override the tool name
     #[arg(long)]
     pub(crate) name: Option<String>,
@@ -221,13 +704,53 @@ pub(crate) struct Add {
     /// Override the command
     #[arg(long)]
     pub(crate) cmd: Option<String>,
-    /// Override the file glob pattern
+    /// Override the file glob pattern (can be used multiple times)
+    #[arg(long, action = clap::ArgAction::Append)]
+    pub(crate) files: Vec<String>,
+    /// Override how files are passed to the tool
     #[arg(long)]
-    pub(crate) files: Option<String>,
-    /// Override the check command
+    pub(crate) granularity: Option<config::Granularity>,
+    /// Override the check command (for formatters)
     #[arg(long)]
     pub(crate) check: Option<String>,
+    /// Override the fix command (for linters)
+    #[arg(long)]
+    pub(crate) fix: Option<String>,
     /// Override the config file path
     #[arg(long)]
     pub(crate) config: Option<PathBuf>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(args: &[&str]) -> String {
+        Cli::try_parse_from(args).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_run() {
+        let cli = Cli::try_parse_from(["lun"]).unwrap();
+        assert!(matches!(cli.command, Command::Run(ref r) if !r.dry_run));
+        let cli = Cli::try_parse_from(["lun", "--dry-run"]).unwrap();
+        assert!(matches!(cli.command, Command::Run(ref r) if r.dry_run));
+        let cli = Cli::try_parse_from(["lun", "run", "--dry-run"]).unwrap();
+        assert!(matches!(cli.command, Command::Run(ref r) if r.dry_run));
+    }
+
+    #[test]
+    fn run_options_out_of_place() {
+        let msg = error(&["lun", "--dry-run", "run"]);
+        assert!(msg.contains("'--dry-run' must come after `run`"), "{msg}");
+        let msg = error(&["lun", "--dry-run", "lsp"]);
+        assert!(
+            msg.contains("'--dry-run' can only be used with `lun run`"),
+            "{msg}"
+        );
+        let msg = error(&["lun", "--dry-run", "cache", "rm"]);
+        assert!(msg.contains("can only be used with `lun run`"), "{msg}");
+        // Global options are fine anywhere
+        assert!(Cli::try_parse_from(["lun", "--cache", "c", "lsp"]).is_ok());
+    }
+}
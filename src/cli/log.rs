@@ -1,14 +1,52 @@
+use std::{env, ffi::OsString, io::IsTerminal as _};
+
 #[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
 pub(crate) enum Color {
     /// Always use color output
     Always,
     /// Never use color output
     Never,
-    /// Use color output if stdout is a terminal
+    /// Use color output if stdout is a terminal, unless `NO_COLOR` or
+    /// `CLICOLOR=0` is set (or if `CLICOLOR_FORCE` is set)
     #[default]
     Auto,
 }
 
+impl Color {
+    pub(crate) fn enabled(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => auto(
+                env::var_os("NO_COLOR"),
+                env::var_os("CLICOLOR_FORCE"),
+                env::var_os("CLICOLOR"),
+                std::io::stdout().is_terminal(),
+            ),
+        }
+    }
+}
+
+/// <https://no-color.org/> and <https://bixense.com/clicolors/>
+fn auto(
+    no_color: Option<OsString>,
+    clicolor_force: Option<OsString>,
+    clicolor: Option<OsString>,
+    terminal: bool,
+) -> bool {
+    let set = |var: &Option<OsString>| var.as_ref().is_some_and(|v| !v.is_empty() && v != "0");
+    if no_color.as_ref().is_some_and(|v| !v.is_empty()) {
+        return false;
+    }
+    if set(&clicolor_force) {
+        return true;
+    }
+    if clicolor.is_some_and(|v| v == "0") {
+        return false;
+    }
+    terminal
+}
+
 #[derive(Clone, Copy, Debug, clap::Args)]
 #[group(id = "log")]
 pub(crate) struct LogOptions {
@@ -25,3 +63,22 @@ pub(crate) struct LogOptions {
     #[arg(short, long, action = clap::ArgAction::Count, help_heading = "Logging options")]
     pub(crate) verbose: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_color() {
+        let var = |v: &str| Some(OsString::from(v));
+        assert!(auto(None, None, None, true));
+        assert!(!auto(None, None, None, false));
+        assert!(!auto(var("1"), None, None, true));
+        assert!(auto(var(""), None, None, true));
+        assert!(!auto(var("1"), var("1"), None, true));
+        assert!(auto(None, var("1"), None, false));
+        assert!(!auto(None, var("0"), None, false));
+        assert!(!auto(None, None, var("0"), true));
+        assert!(auto(None, None, var("1"), true));
+    }
+}
@@ -9,4 +9,7 @@ pub(crate) struct WarnOpts {
     /// Deny a warning (can be used multiple times)
     #[arg(short = 'D', long, action = clap::ArgAction::Append, value_name = "WARN", help_heading = "Warning options")]
     pub(crate) deny: Vec<String>,
+    /// Deny every warning that would be printed, e.g., in CI
+    #[arg(long, help_heading = "Warning options")]
+    pub(crate) warnings_as_errors: bool,
 }
@@ -1,32 +1,229 @@
-use std::{process, sync::Arc};
+use std::{
+    borrow::Cow,
+    env, fs,
+    io::Write as _,
+    path::{self, Component, Path, PathBuf},
+    process,
+    sync::Arc,
+};
 
-use crate::{config, file, tool};
+use anyhow::{Context as _, Result};
+
+use crate::{
+    config::{self, FilesVia},
+    file, tool,
+};
+
+/// Replaced by the path of the file that lists the files, in `argfile`
+pub(crate) const ARGFILE: &str = "{{argfile}}";
+
+/// Replaced by the paths of the files, in `cmd`
+pub(crate) const FILES: &str = "{{files}}";
+
+/// Whether `program` names an executable, either as a path (relative to `cd`)
+/// or on the `PATH`
+pub(crate) fn on_path(program: &str, cd: Option<&Path>) -> bool {
+    which(program, cd).is_some()
+}
+
+/// The executable that `program` names, either as a path (relative to `cd`)
+/// or on the `PATH`
+pub(crate) fn which(program: &str, cd: Option<&Path>) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = Path::new(program);
+        let path = match cd {
+            Some(cd) if path.is_relative() => cd.join(path),
+            _ => path.to_path_buf(),
+        };
+        is_executable(&path).then_some(path)
+    } else {
+        env::split_paths(&env::var_os("PATH")?)
+            .map(|d| d.join(program))
+            .find(|p| is_executable(p))
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub(crate) struct Command {
     pub(crate) tool: Arc<tool::Tool>,
     pub(crate) files: Vec<file::File>,
+    /// Removed once the last clone of the command is dropped, see
+    /// [`Command::write_argfile`]
+    pub(crate) argfile: Option<Arc<tempfile::TempPath>>,
+}
+
+#[cfg(test)]
+impl Command {
+    /// A command for tests, see [`tool::test_tool`] and [`file::File::test`]
+    pub(crate) fn test(toml: &str, paths: &[&str]) -> Self {
+        Self {
+            tool: Arc::new(tool::test_tool(toml)),
+            files: paths.iter().map(|p| file::File::test(p, 0)).collect(),
+            argfile: None,
+        }
+    }
 }
 
 impl Command {
     pub(crate) fn to_command(&self) -> process::Command {
         let cmd_str = &self.tool.cmd;
-        let parts: Vec<String> = cmd_str.split_whitespace().map(|s| s.to_string()).collect();
+        let mut parts: Vec<String> = cmd_str.split_whitespace().map(|s| s.to_string()).collect();
+        if self.tool.stdin.is_some() {
+            // The file is passed on stdin, but may be named in the command
+            debug_assert_eq!(self.files.len(), 1); // in job.rs
+            if let Some(f) = self.files.first() {
+                let path = self.path(f);
+                let path = path.to_string_lossy();
+                for part in &mut parts {
+                    *part = part.replace("{{file}}", &path);
+                }
+            }
+        }
+        let passes_files =
+            self.tool.granularity == config::Granularity::Individual && self.tool.stdin.is_none();
         let mut cmd = process::Command::new(&parts[0]);
-        cmd.args(&parts[1..]);
+        let mut templated = false;
+        for part in &parts[1..] {
+            if part == FILES {
+                // See `check_files_template` in `config.rs`
+                if passes_files && self.tool.files_via == FilesVia::Args {
+                    for f in &self.files {
+                        cmd.arg(self.path(f).as_os_str());
+                    }
+                }
+                templated = true;
+            } else {
+                cmd.arg(part);
+            }
+        }
         if let Some(cd) = &self.tool.cd {
             cmd.current_dir(cd);
         }
-        if self.tool.granularity == config::Granularity::Individual {
-            for f in &self.files {
-                let path = if let Some(cd) = &self.tool.cd {
-                    f.path.strip_prefix(cd).unwrap_or(f.path.as_path())
-                } else {
-                    f.path.as_path()
-                };
-                cmd.arg(path);
+        if passes_files {
+            match self.tool.files_via {
+                FilesVia::Args if templated => (),
+                FilesVia::Args => {
+                    for f in &self.files {
+                        cmd.arg(self.path(f).as_os_str());
+                    }
+                }
+                FilesVia::Argfile => {
+                    if let Some(argfile) = &self.tool.argfile {
+                        let path = self.argfile().map(|p| p.to_string_lossy());
+                        for part in argfile.split_whitespace() {
+                            cmd.arg(part.replace(ARGFILE, path.as_deref().unwrap_or(ARGFILE)));
+                        }
+                    }
+                }
+                // See `Command::stdin`
+                FilesVia::Stdin | FilesVia::Stdin0 => (),
             }
         }
         cmd
     }
+
+    /// Where the files are listed for tools that don't take them on the
+    /// command line (see `files_via`), once [`Command::write_argfile`] has
+    /// been called
+    pub(crate) fn argfile(&self) -> Option<&Path> {
+        self.argfile.as_deref().map(|p| p.as_ref())
+    }
+
+    fn argfile_contents(&self) -> Vec<u8> {
+        let sep = if self.tool.files_via == FilesVia::Stdin0 {
+            b'\0'
+        } else {
+            b'\n'
+        };
+        let mut contents = Vec::new();
+        for f in &self.files {
+            contents.extend(self.path(f).as_os_str().as_encoded_bytes());
+            contents.push(sep);
+        }
+        contents
+    }
+
+    /// Write the files to a new argfile, for tools that don't take them on the
+    /// command line. Its name is random and it is created exclusively, so that
+    /// concurrent runs (e.g., of other checkouts) don't share it.
+    pub(crate) fn write_argfile(&mut self) -> Result<()> {
+        if self.tool.files_via == FilesVia::Args {
+            return Ok(());
+        }
+        let mut file = tempfile::Builder::new()
+            .prefix("lun-")
+            .tempfile()
+            .context("Failed to create argfile")?;
+        file.write_all(&self.argfile_contents())
+            .with_context(|| format!("Failed to write argfile: {}", file.path().display()))?;
+        self.argfile = Some(Arc::new(file.into_temp_path()));
+        Ok(())
+    }
+
+    /// The argfile, for tools that read the files on stdin. Call
+    /// [`Command::write_argfile`] first.
+    pub(crate) fn stdin(&self) -> Result<Option<process::Stdio>> {
+        if !matches!(self.tool.files_via, FilesVia::Stdin | FilesVia::Stdin0) {
+            return Ok(None);
+        }
+        let Some(path) = self.argfile() else {
+            return Ok(None);
+        };
+        let f = fs::File::open(path)
+            .with_context(|| format!("Failed to open argfile: {}", path.display()))?;
+        Ok(Some(f.into()))
+    }
+
+    /// Path of the file as passed to the tool: relative to its working
+    /// directory, or absolute with `absolute_paths`
+    pub(crate) fn path<'a>(&self, f: &'a file::File) -> Cow<'a, Path> {
+        if self.tool.absolute_paths {
+            return absolute(&f.path);
+        }
+        match &self.tool.cd {
+            Some(cd) => relative_to(&f.path, cd),
+            None => Cow::Borrowed(&f.path),
+        }
+    }
+}
+
+fn absolute(p: &Path) -> Cow<'_, Path> {
+    path::absolute(p).map_or(Cow::Borrowed(p), Cow::Owned)
+}
+
+/// `p` relative to `dir`, e.g., `../b/c` for `b/c` and `a`
+fn relative_to<'a>(p: &'a Path, dir: &Path) -> Cow<'a, Path> {
+    if let Ok(rel) = p.strip_prefix(dir) {
+        return Cow::Borrowed(rel);
+    }
+    let common = p
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let up = dir.components().skip(common);
+    if p.is_absolute()
+        || dir.is_absolute()
+        || up.clone().any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return absolute(p);
+    }
+    let mut rel = up.map(|_| Component::ParentDir).collect::<PathBuf>();
+    rel.extend(p.components().skip(common));
+    Cow::Owned(rel)
 }
@@ -1,16 +1,22 @@
 use std::{
-    env, fs,
-    io::{self, IsTerminal},
-    num::NonZeroUsize,
+    cell::RefCell,
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs, io,
+    num::{NonZeroU16, NonZeroUsize},
     path::{Path, PathBuf},
-    process,
 };
 
 use anyhow::{Context as _, Result};
-use globset::{Glob, GlobSet, GlobSetBuilder};
 use tracing::debug;
 
-use crate::{file, known, run::RunMode, tool};
+use crate::{
+    cmd, diag, file, job, known, pattern::Patterns, run::RunMode, tool, toolchain, version,
+};
+
+/// Default path of the config file, relative to the project root
+pub(crate) const DEFAULT_PATH: &str = "lun.toml";
 
 fn default<T: Default + PartialEq>(t: &T) -> bool {
     *t == Default::default()
@@ -25,7 +31,27 @@ fn is_default_mtime(mtime: &bool) -> bool {
     *mtime == default_mtime()
 }
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+fn default_skip_generated() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_default_skip_generated(skip_generated: &bool) -> bool {
+    *skip_generated == default_skip_generated()
+}
+
+/// Swap files, backups, and the like that editors write while saving
+const DEFAULT_TRANSIENT_PATTERNS: [&str; 5] = ["*.swp", "*~", "*.tmp", "**/4913", "**/.#*"];
+
+pub(crate) fn default_transient_patterns() -> Vec<String> {
+    DEFAULT_TRANSIENT_PATTERNS.map(String::from).to_vec()
+}
+
+fn is_default_transient_patterns(patterns: &[String]) -> bool {
+    patterns == DEFAULT_TRANSIENT_PATTERNS
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct WarnCfg {
     #[serde(default)]
@@ -41,6 +67,16 @@ pub(crate) struct WarnCfg {
     pub(crate) deny: Vec<String>,
 }
 
+/// How to find files, see `file::collect_files`
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WalkCfg {
+    /// Paths that aren't walked at all, see `pattern.rs`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) ignore: Vec<String>,
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct Config {
@@ -52,22 +88,59 @@ pub(crate) struct Config {
     #[serde(skip_serializing_if = "default")]
     pub(crate) formatter: Vec<Formatter>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) cache_max_age: Option<NonZeroU16>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) cache_size: Option<usize>,
 
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
-    pub(crate) careful: bool,
+    pub(crate) cache_failures: bool,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) cache_dir: Option<PathBuf>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) arg_max: Option<usize>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) careful: Careful,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) config_hash: ConfigHash,
 
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) cores: Option<NonZeroUsize>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) follow_symlinks: bool,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) fsync: bool,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) ignore: Vec<String>,
 
+    /// Extra registry of known tools, see `doc/config.md`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) known_tools: Option<PathBuf>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) max_file_size: Option<u64>,
+
     #[serde(default = "default_mtime")]
     #[serde(skip_serializing_if = "is_default_mtime")]
     pub(crate) mtime: bool,
@@ -80,16 +153,47 @@ pub(crate) struct Config {
     #[serde(skip_serializing_if = "default")]
     pub(crate) refs: Vec<String>,
 
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) sandbox: bool,
+
+    /// Skip files marked as generated in `.gitattributes`
+    #[serde(default = "default_skip_generated")]
+    #[serde(skip_serializing_if = "is_default_skip_generated")]
+    pub(crate) skip_generated: bool,
+
+    /// Only include declared environment variables in cache keys, and deny
+    /// undeclared ones and unlisted config files
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) strict: bool,
+
+    /// Single warnings to silence, as `WARNING:SUBJECT`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) suppress: Vec<String>,
+
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) tool: Vec<KnownTool>,
 
+    /// Short-lived files that aren't collected, and don't trigger `--watch`
+    #[serde(default = "default_transient_patterns")]
+    #[serde(skip_serializing_if = "is_default_transient_patterns")]
+    pub(crate) transient_patterns: Vec<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) walk: WalkCfg,
+
     #[serde(flatten)]
     pub(crate) warns: WarnCfg,
 }
 
 impl Config {
-    pub(crate) fn load(path: &Path) -> Result<Option<Self>> {
+    /// Load the config file at `path`, with each of the `overlays` merged on
+    /// top of it in turn, see [`merge`]
+    pub(crate) fn load(path: &Path, overlays: &[PathBuf]) -> Result<Option<Self>> {
         debug!("Loading config file from {}", path.display());
         let r = fs::read_to_string(path);
         let contents = match r {
@@ -103,18 +207,87 @@ impl Config {
                     .with_context(|| format!("Failed to read config file: {}", path.display()))?,
             },
         };
-        let mut config: Config = toml::from_str(&contents)
+        let mut table: toml::Table = toml::from_str(&contents)
             .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
-        config.known_tools()?;
+        for overlay in overlays {
+            debug!("Loading config overlay from {}", overlay.display());
+            let contents = fs::read_to_string(overlay)
+                .with_context(|| format!("Failed to read config file: {}", overlay.display()))?;
+            let overlay_table = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse config file: {}", overlay.display()))?;
+            merge(&mut table, overlay_table);
+        }
+        let mut config: Config = table
+            .try_into()
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        let registry = known::Registry::load(config.known_tools.as_deref())?;
+        config.resolve_known_tools(&registry)?;
         Ok(Some(config))
     }
 
-    fn known_tools(&mut self) -> Result<()> {
+    /// Patterns of paths to skip while walking the repository, including
+    /// `extra` ones (e.g., from the command line)
+    pub(crate) fn walk_ignore(&self, extra: &[String]) -> Result<Option<Patterns>> {
+        let mut patterns = self.walk.ignore.clone();
+        patterns.extend_from_slice(extra);
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        Patterns::new(&patterns, "ignore", "walk").map(Some)
+    }
+
+    /// Patterns of short-lived files, e.g., editor swap files
+    pub(crate) fn transient(&self) -> Result<Patterns> {
+        Patterns::new(&self.transient_patterns, "transient_patterns", "walk")
+    }
+
+    /// What goes into the cache keys of tools, given `--careful`
+    pub(crate) fn stamp_opts(&self, careful: Careful) -> StampOpts {
+        StampOpts {
+            careful,
+            config_hash: self.config_hash,
+            strict: self.strict,
+        }
+    }
+
+    /// Find a tool by its name (or command, if it has no name)
+    pub(crate) fn tool_by_name(
+        &self,
+        name: &str,
+        mode: RunMode,
+        careful: Careful,
+        color: crate::cli::log::Color,
+    ) -> Result<Option<tool::Tool>> {
+        let stamp = self.stamp_opts(careful);
+        let config_files = ConfigFiles::default();
+        let has_name = |t: &Tool| t.name.as_ref().unwrap_or(&t.cmd) == name;
+        if let Some(linter) = self.linter.iter().find(|l| has_name(&l.tool)) {
+            return linter
+                .clone()
+                .into_tool(mode, stamp, color, &self.ignore, &config_files)
+                .map(Some);
+        }
+        if let Some(formatter) = self.formatter.iter().find(|f| has_name(&f.tool)) {
+            return formatter
+                .clone()
+                .into_tool(mode, stamp, color, &self.ignore, &config_files)
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    fn resolve_known_tools(&mut self, registry: &known::Registry) -> Result<()> {
         for known_tool in &self.tool {
-            if let Some(mut linter) = known::known_linter_by_name(&known_tool.name) {
+            // Known tools list every config file that they might read, but
+            // usually only a few exist, like in `lun init`
+            if let Some(mut linter) = registry.linter_by_name(&known_tool.name) {
+                linter.tool.configs.retain(|config| config.exists());
+                known::add_implicit_configs(&mut linter.tool);
                 known_tool.merge_into_linter(&mut linter)?;
                 self.linter.push(linter);
-            } else if let Some(mut formatter) = known::known_formatter_by_name(&known_tool.name) {
+            } else if let Some(mut formatter) = registry.formatter_by_name(&known_tool.name) {
+                formatter.tool.configs.retain(|config| config.exists());
+                known::add_implicit_configs(&mut formatter.tool);
                 known_tool.merge_into_formatter(&mut formatter)?;
                 self.formatter.push(formatter);
             } else {
@@ -125,8 +298,201 @@ impl Config {
     }
 }
 
+/// Merge the `overlay` config into `base`. Tables are merged recursively, and
+/// entries of arrays of tables with the same `name` (e.g., `[[linter]]`) are
+/// merged, while new ones are appended. Other values are replaced.
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (Some(toml::Value::Array(base)), toml::Value::Array(overlay))
+                if !overlay.is_empty() && overlay.iter().all(toml::Value::is_table) =>
+            {
+                for entry in overlay {
+                    let toml::Value::Table(entry) = entry else {
+                        continue;
+                    };
+                    let same = entry.get("name").and_then(|name| {
+                        base.iter_mut()
+                            .filter_map(toml::Value::as_table_mut)
+                            .find(|b| b.get("name") == Some(name))
+                    });
+                    match same {
+                        Some(same) => merge(same, entry),
+                        None => base.push(toml::Value::Table(entry)),
+                    }
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Options that apply to the cache keys of all tools, see [`Config::stamp_opts`]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct StampOpts {
+    pub(crate) careful: Careful,
+    pub(crate) config_hash: ConfigHash,
+    /// Only include the environment variables listed in `env` (or
+    /// `env_stamp`)
+    pub(crate) strict: bool,
+}
+
+/// Which of the environment variables that a tool might read, besides those in
+/// its `env`, are part of its cache keys
+///
+/// In the config file, this is `true`, `false`, or a list of names.
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(from = "EnvStampValue", into = "EnvStampValue")]
+pub(crate) enum EnvStamp {
+    /// Those that start with the upper-cased name of its executable and `_`,
+    /// see [`implicit_env`]
+    #[default]
+    Auto,
+    /// None
+    Off,
+    /// Exactly these
+    Only(Vec<String>),
+}
+
+impl EnvStamp {
+    /// The names and values of the variables, where `exe_name` is the name of
+    /// the executable of the tool
+    fn vars(&self, exe_name: &str, strict: bool) -> Vec<(OsString, OsString)> {
+        match self {
+            // With `strict`, only declared variables count
+            Self::Auto if strict => Vec::new(),
+            Self::Auto => implicit_env(exe_name),
+            Self::Off => Vec::new(),
+            Self::Only(names) => names
+                .iter()
+                .map(|key| (OsString::from(key), env::var_os(key).unwrap_or_default()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+enum EnvStampValue {
+    Bool(bool),
+    Names(Vec<String>),
+}
+
+impl From<EnvStampValue> for EnvStamp {
+    fn from(value: EnvStampValue) -> Self {
+        match value {
+            EnvStampValue::Bool(true) => Self::Auto,
+            EnvStampValue::Bool(false) => Self::Off,
+            EnvStampValue::Names(names) => Self::Only(names),
+        }
+    }
+}
+
+impl From<EnvStamp> for EnvStampValue {
+    fn from(env_stamp: EnvStamp) -> Self {
+        match env_stamp {
+            EnvStamp::Auto => Self::Bool(true),
+            EnvStamp::Off => Self::Bool(false),
+            EnvStamp::Only(names) => Self::Names(names),
+        }
+    }
+}
+
+/// Config files larger than this are hashed like [`ConfigHash::Metadata`]
+const CONFIG_CONTENT_MAX: u64 = 1024 * 1024;
+
+/// What the cache keys of tools include about their `configs`
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "lowercase")]
+pub(crate) enum ConfigHash {
+    /// The contents of files up to [`CONFIG_CONTENT_MAX`] bytes, so that
+    /// `touch` doesn't invalidate the cache and edits that keep the
+    /// modification time do
+    #[default]
+    Content,
+    /// The metadata and modification time of files
+    Metadata,
+}
+
+/// What `careful` adds to the cache keys of tools, ordered from least to most
+/// careful
+///
+/// In the config file, `true` means [`Careful::Version`].
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(try_from = "CarefulValue", into = "CarefulValue")]
+pub(crate) enum Careful {
+    #[default]
+    #[value(skip)]
+    Off,
+    /// The output of the version command of each tool
+    Version,
+    /// The path, size, and modification time of the executable of each tool,
+    /// e.g., for tools built locally, whose version doesn't change
+    Binary,
+}
+
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+enum CarefulValue {
+    Bool(bool),
+    Name(String),
+}
+
+impl TryFrom<CarefulValue> for Careful {
+    type Error = String;
+
+    fn try_from(value: CarefulValue) -> Result<Self, Self::Error> {
+        match value {
+            CarefulValue::Bool(false) => Ok(Self::Off),
+            CarefulValue::Bool(true) => Ok(Self::Version),
+            CarefulValue::Name(name) => match name.as_str() {
+                "version" => Ok(Self::Version),
+                "binary" => Ok(Self::Binary),
+                _ => Err(format!(
+                    "expected `true`, `false`, or `\"binary\"` for `careful`, found `\"{name}\"`"
+                )),
+            },
+        }
+    }
+}
+
+impl From<Careful> for CarefulValue {
+    fn from(careful: Careful) -> Self {
+        match careful {
+            Careful::Off => Self::Bool(false),
+            Careful::Version => Self::Bool(true),
+            Careful::Binary => Self::Name("binary".to_string()),
+        }
+    }
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    clap::ValueEnum,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub(crate) enum Granularity {
     #[default]
     Individual,
@@ -150,9 +516,150 @@ pub(crate) struct Tool {
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) configs: Vec<PathBuf>,
+    /// Environment variables that affect the tool
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) env: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) env_stamp: EnvStamp,
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) cd: Option<PathBuf>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) cache_failures: Option<bool>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) dedupe_group: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) stdin: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) min_version: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) version: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) version_cmd: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) enabled_if: Option<Condition>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) platforms: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) max_file_size: Option<u64>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) binary: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) batch_size: Option<BatchSize>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) files_via: Option<FilesVia>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) argfile: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) no_capture: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) absolute_paths: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) before: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) after: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) problem_matcher: Option<String>,
+    /// Warning levels for this tool, overriding the global ones
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) warns: WarnCfg,
+}
+
+/// How to pass files to tools with `granularity = "individual"`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FilesVia {
+    /// On the command line
+    #[default]
+    Args,
+    /// In a file, named on the command line per `argfile`
+    Argfile,
+    /// On stdin, one path per line
+    Stdin,
+    /// On stdin, separated by NUL bytes
+    Stdin0,
+}
+
+/// Default for `argfile`
+const DEFAULT_ARGFILE: &str = "@{{argfile}}";
+
+/// Prefix of `cd` that runs tools in the closest directory containing a file
+const CD_NEAREST: &str = "nearest:";
+
+/// Bounds on the number of files per batch, see `doc/config.md`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BatchSize {
+    /// Don't start more processes than needed to give each this many files
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) min: Option<usize>,
+    /// Start more processes than cores if needed to stay below this many files
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) max: Option<usize>,
+}
+
+/// When to run a tool. All of the given conditions must hold.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Condition {
+    /// A file that must exist
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) file_exists: Option<PathBuf>,
+    /// An environment variable that must be set and non-empty
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) env: Option<String>,
+}
+
+impl Tool {
+    /// Whether the tool runs on this platform, per `platforms`
+    pub(crate) fn on_platform(&self) -> bool {
+        self.platforms.is_empty() || self.platforms.iter().any(|p| p == env::consts::OS)
+    }
+
+    /// The marker file of `cd = "nearest:MARKER"`
+    pub(crate) fn cd_nearest(&self) -> Option<&str> {
+        self.cd.as_deref()?.to_str()?.strip_prefix(CD_NEAREST)
+    }
+
+    /// The working directory, unless it depends on the files
+    pub(crate) fn cd_dir(&self) -> Option<&Path> {
+        self.cd.as_deref().filter(|_| self.cd_nearest().is_none())
+    }
+}
+
+impl Condition {
+    pub(crate) fn holds(&self) -> bool {
+        self.file_exists.as_ref().is_none_or(|f| f.exists())
+            && self
+                .env
+                .as_ref()
+                .is_none_or(|var| env::var_os(var).is_some_and(|v| !v.is_empty()))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
@@ -173,6 +680,9 @@ pub(crate) struct Formatter {
     #[serde(default)]
     #[serde(skip_serializing_if = "default")]
     pub(crate) check: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) write_back: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
@@ -195,14 +705,77 @@ pub(crate) struct KnownTool {
     #[serde(skip_serializing_if = "default")]
     pub(crate) configs: Vec<PathBuf>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) env: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) env_stamp: Option<EnvStamp>,
+    #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) cd: Option<PathBuf>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cache_failures: Option<bool>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dedupe_group: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stdin: Option<bool>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) min_version: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) version: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) version_cmd: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) enabled_if: Option<Condition>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) platforms: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) max_file_size: Option<u64>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) binary: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) batch_size: Option<BatchSize>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) files_via: Option<FilesVia>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) argfile: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) no_capture: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) absolute_paths: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) before: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "default")]
+    pub(crate) after: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) problem_matcher: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) fix: Option<String>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) check: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) write_back: Option<bool>,
 }
 
 impl KnownTool {
@@ -222,9 +795,72 @@ impl KnownTool {
         if !self.configs.is_empty() {
             linter.tool.configs = self.configs.clone();
         }
+        if !self.env.is_empty() {
+            linter.tool.env = self.env.clone();
+        }
+        if let Some(ref env_stamp) = self.env_stamp {
+            linter.tool.env_stamp = env_stamp.clone();
+        }
         if let Some(ref cd) = self.cd {
             linter.tool.cd = Some(cd.clone());
         }
+        if let Some(cache_failures) = self.cache_failures {
+            linter.tool.cache_failures = Some(cache_failures);
+        }
+        if let Some(ref dedupe_group) = self.dedupe_group {
+            linter.tool.dedupe_group = Some(dedupe_group.clone());
+        }
+        if let Some(stdin) = self.stdin {
+            linter.tool.stdin = stdin;
+        }
+        if let Some(ref min_version) = self.min_version {
+            linter.tool.min_version = Some(min_version.clone());
+        }
+        if let Some(ref version) = self.version {
+            linter.tool.version = Some(version.clone());
+        }
+        if let Some(ref version_cmd) = self.version_cmd {
+            linter.tool.version_cmd = Some(version_cmd.clone());
+        }
+        if let Some(ref enabled_if) = self.enabled_if {
+            linter.tool.enabled_if = Some(enabled_if.clone());
+        }
+        if !self.platforms.is_empty() {
+            linter.tool.platforms = self.platforms.clone();
+        }
+        if self.max_file_size.is_some() {
+            linter.tool.max_file_size = self.max_file_size;
+        }
+        if self.binary {
+            linter.tool.binary = true;
+        }
+        if self.batch_size.is_some() {
+            linter.tool.batch_size = self.batch_size;
+        }
+        if self.files_via.is_some() {
+            linter.tool.files_via = self.files_via;
+        }
+        if self.argfile.is_some() {
+            linter.tool.argfile.clone_from(&self.argfile);
+        }
+        if self.no_capture {
+            linter.tool.no_capture = true;
+        }
+        if self.absolute_paths {
+            linter.tool.absolute_paths = true;
+        }
+        if self.before.is_some() {
+            linter.tool.before.clone_from(&self.before);
+        }
+        if self.after.is_some() {
+            linter.tool.after.clone_from(&self.after);
+        }
+        if self.problem_matcher.is_some() {
+            linter
+                .tool
+                .problem_matcher
+                .clone_from(&self.problem_matcher);
+        }
         if let Some(ref fix) = self.fix {
             linter.fix = Some(fix.clone());
         }
@@ -247,23 +883,95 @@ impl KnownTool {
         if !self.configs.is_empty() {
             formatter.tool.configs = self.configs.clone();
         }
+        if !self.env.is_empty() {
+            formatter.tool.env = self.env.clone();
+        }
+        if let Some(ref env_stamp) = self.env_stamp {
+            formatter.tool.env_stamp = env_stamp.clone();
+        }
         if let Some(ref cd) = self.cd {
             formatter.tool.cd = Some(cd.clone());
         }
+        if let Some(cache_failures) = self.cache_failures {
+            formatter.tool.cache_failures = Some(cache_failures);
+        }
+        if let Some(ref dedupe_group) = self.dedupe_group {
+            formatter.tool.dedupe_group = Some(dedupe_group.clone());
+        }
+        if let Some(stdin) = self.stdin {
+            formatter.tool.stdin = stdin;
+        }
+        if let Some(ref min_version) = self.min_version {
+            formatter.tool.min_version = Some(min_version.clone());
+        }
+        if let Some(ref version) = self.version {
+            formatter.tool.version = Some(version.clone());
+        }
+        if let Some(ref version_cmd) = self.version_cmd {
+            formatter.tool.version_cmd = Some(version_cmd.clone());
+        }
+        if let Some(ref enabled_if) = self.enabled_if {
+            formatter.tool.enabled_if = Some(enabled_if.clone());
+        }
+        if !self.platforms.is_empty() {
+            formatter.tool.platforms = self.platforms.clone();
+        }
+        if self.max_file_size.is_some() {
+            formatter.tool.max_file_size = self.max_file_size;
+        }
+        if self.binary {
+            formatter.tool.binary = true;
+        }
+        if self.batch_size.is_some() {
+            formatter.tool.batch_size = self.batch_size;
+        }
+        if self.files_via.is_some() {
+            formatter.tool.files_via = self.files_via;
+        }
+        if self.argfile.is_some() {
+            formatter.tool.argfile.clone_from(&self.argfile);
+        }
+        if self.no_capture {
+            formatter.tool.no_capture = true;
+        }
+        if self.absolute_paths {
+            formatter.tool.absolute_paths = true;
+        }
+        if self.before.is_some() {
+            formatter.tool.before.clone_from(&self.before);
+        }
+        if self.after.is_some() {
+            formatter.tool.after.clone_from(&self.after);
+        }
+        if self.problem_matcher.is_some() {
+            formatter
+                .tool
+                .problem_matcher
+                .clone_from(&self.problem_matcher);
+        }
         if let Some(ref check) = self.check {
             formatter.check = Some(check.clone());
         }
+        if let Some(write_back) = self.write_back {
+            formatter.write_back = write_back;
+        }
         Ok(())
     }
 }
 
-fn build_tool_stamp(tool: &Tool, cmd: &str, careful: bool) -> Result<tool::Stamp> {
+fn build_tool_stamp(
+    tool: &Tool,
+    cmd: &str,
+    opts: StampOpts,
+    write_back: bool,
+    config_files: &ConfigFiles,
+) -> Result<tool::Stamp> {
     let tool_name = tool.name.as_ref().unwrap_or(&tool.cmd);
-    let config = build_config_hash(tool_name, &tool.configs)?;
-    let version = if careful {
-        get_tool_version(&tool.cmd).map(|s| file::compute_hash(s.as_bytes()))
-    } else {
-        None
+    let config = build_config_hash(tool_name, &tool.configs, opts.config_hash, config_files)?;
+    let version = match opts.careful {
+        Careful::Off => None,
+        Careful::Version => version::get(tool).map(|s| file::compute_hash(s.as_bytes())),
+        Careful::Binary => version::binary(tool),
     };
 
     let mut hasher = xxhash_rust::xxh3::Xxh3::new();
@@ -274,16 +982,32 @@ fn build_tool_stamp(tool: &Tool, cmd: &str, careful: bool) -> Result<tool::Stamp
     if let Some(version_hash) = version {
         hasher.update(&version_hash.0.to_le_bytes());
     }
+    let exe_name = cmd.split_whitespace().next().unwrap_or(cmd);
+    if opts.careful != Careful::Off {
+        // Version managers may switch versions faster than `--version` notices
+        for (_, name, version) in toolchain::pins(Path::new("."), exe_name) {
+            hasher.update(name.as_bytes());
+            hasher.update(version.as_bytes());
+        }
+    }
     if let Some(cd) = &tool.cd {
         hasher.update(cd.as_os_str().as_encoded_bytes());
     }
+    if tool.stdin {
+        hasher.update(b"stdin");
+    }
+    if write_back {
+        hasher.update(b"write_back");
+    }
 
-    let exe_name = cmd.split_whitespace().next().unwrap_or(cmd);
-    let env_pfx = format!("{}_", exe_name.to_uppercase());
-    let mut env_vars = env::vars_os()
-        .filter(|(key, _)| key.as_encoded_bytes().starts_with(env_pfx.as_bytes()))
-        .collect::<Vec<_>>();
+    let mut env_vars = tool.env_stamp.vars(exe_name, opts.strict);
+    env_vars.extend(
+        tool.env
+            .iter()
+            .map(|key| (OsString::from(key), env::var_os(key).unwrap_or_default())),
+    );
     env_vars.sort_by(|a, b| a.0.cmp(&b.0));
+    env_vars.dedup_by(|a, b| a.0 == b.0);
     for (key, value) in &env_vars {
         debug!("Found relevant environment variable {}", key.display());
         hasher.update(key.as_encoded_bytes());
@@ -293,15 +1017,28 @@ fn build_tool_stamp(tool: &Tool, cmd: &str, careful: bool) -> Result<tool::Stamp
     Ok(tool::Stamp(file::Xxhash(hasher.digest128())))
 }
 
-fn build_tool_globsets(
+/// The environment variables that are set and that start with `EXE_`, where
+/// `EXE` is the upper-cased name of the executable of a tool
+pub(crate) fn implicit_env(exe_name: &str) -> Vec<(OsString, OsString)> {
+    let env_pfx = format!("{}_", exe_name.to_uppercase());
+    env::vars_os()
+        .filter(|(key, _)| key.as_encoded_bytes().starts_with(env_pfx.as_bytes()))
+        .collect()
+}
+
+pub(crate) fn build_tool_patterns(
     tool: &Tool,
     global_ignore: &[String],
-) -> Result<(GlobSet, Option<GlobSet>)> {
+) -> Result<(Patterns, Option<Patterns>)> {
     let tool_name = tool.name.as_ref().unwrap_or(&tool.cmd);
-    let files = build_files_globset(&tool.files, tool_name)?;
+    let files = Patterns::new(&tool.files, "files", tool_name)?;
     let mut all_ignore = global_ignore.to_vec();
     all_ignore.extend_from_slice(&tool.ignore);
-    let ignore = build_ignore_globset(&all_ignore, tool_name)?;
+    let ignore = if all_ignore.is_empty() {
+        None
+    } else {
+        Some(Patterns::new(&all_ignore, "ignore", tool_name)?)
+    };
     Ok((files, ignore))
 }
 
@@ -309,9 +1046,10 @@ impl Linter {
     pub(crate) fn into_tool(
         self,
         mode: RunMode,
-        careful: bool,
+        stamp: StampOpts,
         color: crate::cli::log::Color,
         global_ignore: &[String],
+        config_files: &ConfigFiles,
     ) -> Result<tool::Tool> {
         let color_str = color_to_str(color);
         let cmd = match mode {
@@ -325,8 +1063,15 @@ impl Linter {
             RunMode::Check | RunMode::Normal => self.tool.cmd.replace("{{color}}", color_str),
         };
 
-        let (files, ignore) = build_tool_globsets(&self.tool, global_ignore)?;
-        let stamp = build_tool_stamp(&self.tool, &cmd, careful)?;
+        check_stdin(&self.tool)?;
+        check_batch_size(&self.tool)?;
+        let files_via = check_files_via(&self.tool)?;
+        check_files_template(&self.tool, &cmd, files_via)?;
+        let problem_matcher = build_problem_matcher(&self.tool)?;
+        let (files, ignore) = build_tool_patterns(&self.tool, global_ignore)?;
+        let stamp = build_tool_stamp(&self.tool, &cmd, stamp, false, config_files)?;
+        let cd = self.tool.cd_dir().map(Path::to_path_buf);
+        let cd_nearest = self.tool.cd_nearest().map(str::to_string);
 
         Ok(tool::Tool {
             name: self.tool.name,
@@ -335,7 +1080,30 @@ impl Linter {
             ignore,
             granularity: self.tool.granularity,
             stamp,
-            cd: self.tool.cd,
+            cd,
+            cd_nearest,
+            cache_failures: self.tool.cache_failures.unwrap_or(false),
+            fresh: false,
+            diff: false,
+            formats: false,
+            sandbox: false,
+            max_file_size: self.tool.max_file_size,
+            binary: self.tool.binary,
+            batch_size: self.tool.batch_size.unwrap_or_default(),
+            argfile: (files_via == FilesVia::Argfile).then(|| {
+                self.tool
+                    .argfile
+                    .unwrap_or_else(|| DEFAULT_ARGFILE.to_string())
+            }),
+            files_via,
+            arg_max: job::DEFAULT_ARG_MAX,
+            no_capture: self.tool.no_capture,
+            absolute_paths: self.tool.absolute_paths,
+            before: self.tool.before,
+            after: self.tool.after,
+            problem_matcher,
+            dedupe_group: self.tool.dedupe_group,
+            stdin: self.tool.stdin.then_some(tool::Stdin::Report),
         })
     }
 }
@@ -344,9 +1112,10 @@ impl Formatter {
     pub(crate) fn into_tool(
         self,
         mode: RunMode,
-        careful: bool,
+        stamp: StampOpts,
         color: crate::cli::log::Color,
         global_ignore: &[String],
+        config_files: &ConfigFiles,
     ) -> Result<tool::Tool> {
         let color_str = color_to_str(color);
         let cmd = match mode {
@@ -360,8 +1129,27 @@ impl Formatter {
             RunMode::Fix | RunMode::Normal => self.tool.cmd.replace("{{color}}", color_str),
         };
 
-        let (files, ignore) = build_tool_globsets(&self.tool, global_ignore)?;
-        let stamp = build_tool_stamp(&self.tool, &cmd, careful)?;
+        check_stdin(&self.tool)?;
+        check_batch_size(&self.tool)?;
+        let files_via = check_files_via(&self.tool)?;
+        check_files_template(&self.tool, &cmd, files_via)?;
+        let problem_matcher = build_problem_matcher(&self.tool)?;
+        if self.write_back && !self.tool.stdin {
+            anyhow::bail!(
+                "{}: `write_back` requires `stdin = true`",
+                self.tool.name.as_ref().unwrap_or(&self.tool.cmd)
+            );
+        }
+        let stdin = match mode {
+            _ if !self.write_back => tool::Stdin::Report,
+            RunMode::Check if self.check.is_none() => tool::Stdin::Check,
+            RunMode::Check => tool::Stdin::Report,
+            RunMode::Fix | RunMode::Normal => tool::Stdin::Write,
+        };
+        let (files, ignore) = build_tool_patterns(&self.tool, global_ignore)?;
+        let stamp = build_tool_stamp(&self.tool, &cmd, stamp, self.write_back, config_files)?;
+        let cd = self.tool.cd_dir().map(Path::to_path_buf);
+        let cd_nearest = self.tool.cd_nearest().map(str::to_string);
 
         Ok(tool::Tool {
             name: self.tool.name,
@@ -370,90 +1158,511 @@ impl Formatter {
             ignore,
             granularity: self.tool.granularity,
             stamp,
-            cd: self.tool.cd,
+            cd,
+            cd_nearest,
+            cache_failures: self.tool.cache_failures.unwrap_or(false),
+            fresh: false,
+            diff: false,
+            formats: mode != RunMode::Check,
+            sandbox: false,
+            max_file_size: self.tool.max_file_size,
+            binary: self.tool.binary,
+            batch_size: self.tool.batch_size.unwrap_or_default(),
+            argfile: (files_via == FilesVia::Argfile).then(|| {
+                self.tool
+                    .argfile
+                    .unwrap_or_else(|| DEFAULT_ARGFILE.to_string())
+            }),
+            files_via,
+            arg_max: job::DEFAULT_ARG_MAX,
+            no_capture: self.tool.no_capture,
+            absolute_paths: self.tool.absolute_paths,
+            before: self.tool.before,
+            after: self.tool.after,
+            problem_matcher,
+            dedupe_group: self.tool.dedupe_group,
+            stdin: self.tool.stdin.then_some(stdin),
         })
     }
 }
 
-fn color_to_str(color: crate::cli::log::Color) -> &'static str {
-    match color {
-        crate::cli::log::Color::Always => "always",
-        crate::cli::log::Color::Never => "never",
-        crate::cli::log::Color::Auto => {
-            if io::stdout().is_terminal() {
-                "always"
-            } else {
-                "never"
+fn check_stdin(tool: &Tool) -> Result<()> {
+    if tool.stdin && tool.granularity == Granularity::Batch {
+        anyhow::bail!(
+            "{}: `stdin` tools must have `granularity = \"individual\"`",
+            tool.name.as_ref().unwrap_or(&tool.cmd)
+        );
+    }
+    Ok(())
+}
+
+/// `files_via`, which is `"argfile"` if only `argfile` is given
+fn check_files_via(tool: &Tool) -> Result<FilesVia> {
+    let name = tool.name.as_ref().unwrap_or(&tool.cmd);
+    let files_via = match (tool.files_via, &tool.argfile) {
+        (Some(files_via), None) => files_via,
+        (None, None) => FilesVia::Args,
+        (None | Some(FilesVia::Argfile), Some(argfile)) => {
+            if !argfile.contains(cmd::ARGFILE) {
+                anyhow::bail!("{name}: `argfile` must contain `{}`", cmd::ARGFILE);
             }
+            FilesVia::Argfile
         }
+        (Some(_), Some(_)) => {
+            anyhow::bail!("{name}: `argfile` requires `files_via = \"argfile\"`")
+        }
+    };
+    if files_via != FilesVia::Args && (tool.stdin || tool.granularity == Granularity::Batch) {
+        anyhow::bail!("{name}: `files_via` requires `granularity = \"individual\"` and no `stdin`");
+    }
+    Ok(files_via)
+}
+
+fn check_files_template(tool: &Tool, cmd: &str, files_via: FilesVia) -> Result<()> {
+    if cmd.split_whitespace().any(|part| part == cmd::FILES)
+        && (files_via != FilesVia::Args || tool.stdin || tool.granularity == Granularity::Batch)
+    {
+        anyhow::bail!(
+            "{}: `{}` requires `granularity = \"individual\"`, no `stdin`, and no `files_via`",
+            tool.name.as_ref().unwrap_or(&tool.cmd),
+            cmd::FILES
+        );
     }
+    Ok(())
+}
+
+fn build_problem_matcher(tool: &Tool) -> Result<Option<diag::ProblemMatcher>> {
+    let Some(re) = &tool.problem_matcher else {
+        return Ok(None);
+    };
+    let name = tool.name.as_ref().unwrap_or(&tool.cmd);
+    diag::ProblemMatcher::new(re)
+        .map(Some)
+        .with_context(|| format!("{name}: invalid `problem_matcher`"))
 }
 
-fn build_config_hash(tool: &str, configs: &[PathBuf]) -> Result<Option<file::Xxhash>> {
+fn check_batch_size(tool: &Tool) -> Result<()> {
+    let Some(BatchSize { min, max }) = tool.batch_size else {
+        return Ok(());
+    };
+    let name = tool.name.as_ref().unwrap_or(&tool.cmd);
+    if min == Some(0) || max == Some(0) {
+        anyhow::bail!("{name}: `batch_size` bounds must be positive");
+    }
+    if let (Some(min), Some(max)) = (min, max)
+        && min > max
+    {
+        anyhow::bail!("{name}: `batch_size.min` ({min}) is larger than `batch_size.max` ({max})");
+    }
+    Ok(())
+}
+
+fn color_to_str(color: crate::cli::log::Color) -> &'static str {
+    if color.enabled() { "always" } else { "never" }
+}
+
+fn build_config_hash(
+    tool: &str,
+    configs: &[PathBuf],
+    how: ConfigHash,
+    config_files: &ConfigFiles,
+) -> Result<Option<file::Xxhash>> {
     if configs.is_empty() {
         return Ok(None);
     }
-    let mut sorted = configs.to_vec();
-    sorted.sort();
     let mut hasher = xxhash_rust::xxh3::Xxh3::new();
-    for path in &sorted {
+    // Sorted, and including paths, so that adding or removing a file that a
+    // glob matches changes the hash
+    for path in &config_files.resolve(tool, configs)? {
         let metadata = fs::metadata(path).with_context(|| {
             format!(
                 "Failed to get metadata for config file for `{tool}`: {}",
                 path.display()
             )
         })?;
-        file::hash_md(path, &metadata, &mut hasher);
-        file::hash_mtime(path, &metadata, &mut hasher)?;
+        if how == ConfigHash::Content && metadata.len() <= CONFIG_CONTENT_MAX {
+            let content = fs::read(path).with_context(|| {
+                format!(
+                    "Failed to read config file for `{tool}`: {}",
+                    path.display()
+                )
+            })?;
+            hasher.update(path.as_os_str().as_encoded_bytes());
+            hasher.update(&file::compute_hash(&content).0.to_le_bytes());
+        } else {
+            file::hash_md(path, &metadata, &mut hasher);
+            file::hash_mtime(path, &metadata, &mut hasher)?;
+        }
     }
     Ok(Some(file::Xxhash(hasher.digest128())))
 }
 
-fn build_files_globset(patterns: &[String], tool_name: &str) -> Result<GlobSet> {
-    let mut builder = GlobSetBuilder::new();
-    for pattern in patterns {
-        let glob = Glob::new(pattern)
-            .with_context(|| format!("Invalid `files` glob `{pattern}` for `{tool_name}`"))?;
-        builder.add(glob);
-    }
-    builder
-        .build()
-        .with_context(|| format!("Failed to build `files` glob set for `{tool_name}`"))
+/// Whether an entry of `configs` is a glob, rather than a path
+fn is_config_glob(config: &Path) -> bool {
+    config
+        .to_str()
+        .is_some_and(|c| c.contains(['*', '?', '[', '{']))
 }
 
-fn build_ignore_globset(patterns: &[String], tool_name: &str) -> Result<Option<GlobSet>> {
-    if patterns.is_empty() {
-        return Ok(None);
+/// A directory, and how deep to walk it
+type Walk = (PathBuf, Option<usize>);
+
+/// The files that the `configs` of tools name, shared by the tools of a run
+/// (and warnings), so that each directory is only walked once per run
+#[derive(Debug, Default)]
+pub(crate) struct ConfigFiles {
+    /// Files in directories (to a maximum depth), see [`walk_configs`]
+    walks: RefCell<HashMap<Walk, Vec<PathBuf>>>,
+}
+
+impl ConfigFiles {
+    /// The files that `configs` names, sorted
+    ///
+    /// Entries are paths, globs (e.g., `.eslintrc.*`, where `*` doesn't match
+    /// `/`), or directories, which stand for all of the files in them. Globs
+    /// and directories skip files that Git ignores. Paths are returned even if
+    /// they don't exist.
+    pub(crate) fn resolve(&self, tool: &str, configs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for config in configs {
+            if is_config_glob(config) {
+                let pattern = config.to_string_lossy();
+                let glob = globset::GlobBuilder::new(&pattern)
+                    .literal_separator(true)
+                    .build()
+                    .with_context(|| format!("Invalid `configs` glob `{pattern}` for `{tool}`"))?
+                    .compile_matcher();
+                // Only walk the directory before the first component with a glob
+                let base = config
+                    .components()
+                    .take_while(|c| !is_config_glob(Path::new(c.as_os_str())))
+                    .collect::<PathBuf>();
+                let depth = config.components().count() - base.components().count();
+                let max_depth = (!pattern.contains("**")).then_some(depth);
+                let matches = self.walk(tool, base, max_depth)?;
+                files.extend(matches.into_iter().filter(|p| glob.is_match(p)));
+            } else if config.is_dir() {
+                files.extend(self.walk(tool, config.clone(), None)?);
+            } else {
+                files.push(config.clone());
+            }
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
     }
-    let mut builder = GlobSetBuilder::new();
-    for pattern in patterns {
-        let glob = Glob::new(pattern)
-            .with_context(|| format!("Invalid `ignore` glob `{pattern}` for `{tool_name}`"))?;
-        builder.add(glob);
+
+    fn walk(&self, tool: &str, dir: PathBuf, max_depth: Option<usize>) -> Result<Vec<PathBuf>> {
+        let key = (dir, max_depth);
+        if let Some(files) = self.walks.borrow().get(&key) {
+            return Ok(files.clone());
+        }
+        let files = walk_configs(tool, &key.0, max_depth)?;
+        self.walks.borrow_mut().insert(key, files.clone());
+        Ok(files)
     }
-    builder
-        .build()
-        .with_context(|| format!("Failed to build `ignore` glob set for `{tool_name}`"))
-        .map(Some)
 }
 
-fn get_tool_version(cmd: &str) -> Option<String> {
-    let program = cmd.split_whitespace().next()?;
-    let output = process::Command::new(program)
-        .arg("--version")
-        .output()
-        .ok()?;
-    if output.status.success() {
-        let version_output = if !output.stdout.is_empty() {
-            String::from_utf8_lossy(&output.stdout)
-        } else {
-            String::from_utf8_lossy(&output.stderr)
-        };
-        let version = version_output.trim();
-        debug!("Tool {} version: {}", program, version);
-        Some(version.to_string())
+/// The files in `dir` (or the working directory, if empty)
+fn walk_configs(tool: &str, dir: &Path, max_depth: Option<usize>) -> Result<Vec<PathBuf>> {
+    let root = if dir.as_os_str().is_empty() {
+        Path::new(".")
     } else {
-        debug!("Failed to get version for {}: {}", program, output.status);
-        None
+        dir
+    };
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(false)
+        .max_depth(max_depth)
+        .filter_entry(|e| e.file_name() != ".git")
+        .build();
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry.with_context(|| {
+            format!(
+                "Failed to list config files for `{tool}` in {}",
+                root.display()
+            )
+        })?;
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            let path = entry.path();
+            // Like the paths of files, without a leading `./`
+            let path = if dir.as_os_str().is_empty() {
+                path.strip_prefix(".").unwrap_or(path)
+            } else {
+                path
+            };
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn condition() {
+        let holds = |s| toml::from_str::<Condition>(s).unwrap().holds();
+        assert!(holds(""));
+        assert!(holds(r#"file_exists = "Cargo.toml""#));
+        assert!(!holds(r#"file_exists = "does-not-exist.toml""#));
+        assert!(holds(r#"env = "PATH""#));
+        assert!(!holds(r#"env = "LUN_TEST_UNSET_VARIABLE""#));
+        assert!(!holds(
+            r#"file_exists = "Cargo.toml"
+env = "LUN_TEST_UNSET_VARIABLE""#
+        ));
+    }
+
+    #[test]
+    fn platforms() {
+        let on_platform = |platforms: &[&str]| {
+            Tool {
+                platforms: platforms.iter().map(|p| (*p).to_string()).collect(),
+                ..toml::from_str("cmd = \"lint\"\nfiles = []").unwrap()
+            }
+            .on_platform()
+        };
+        assert!(on_platform(&[]));
+        assert!(on_platform(&["plan9", env::consts::OS]));
+        assert!(!on_platform(&["plan9"]));
+    }
+
+    #[test]
+    fn transient_patterns() {
+        let config: Config = toml::from_str("").unwrap();
+        let transient = config.transient().unwrap();
+        for path in [
+            ".a.rs.swp",
+            "src/a.rs~",
+            "a.tmp",
+            "4913",
+            "src/4913",
+            "src/.#a.rs",
+        ] {
+            assert!(transient.is_match(Path::new(path)), "{path}");
+        }
+        assert!(!transient.is_match(Path::new("src/a.rs")));
+        assert!(!transient.is_match(Path::new("14913")));
+        let config: Config = toml::from_str("transient_patterns = []").unwrap();
+        assert!(!config.transient().unwrap().is_match(Path::new("a.tmp")));
+    }
+
+    #[test]
+    fn batch_size() {
+        let check = |batch_size: &str| {
+            let tool: Tool = toml::from_str(&format!(
+                "cmd = \"lint\"\nfiles = []\nbatch_size = {batch_size}"
+            ))
+            .unwrap();
+            check_batch_size(&tool).is_ok()
+        };
+        assert!(check("{ min = 2, max = 10 }"));
+        assert!(check("{ max = 1 }"));
+        assert!(!check("{ min = 0 }"));
+        assert!(!check("{ min = 10, max = 2 }"));
+    }
+
+    #[test]
+    fn config_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in [
+            ".eslintrc.json",
+            "a/.eslintrc.js",
+            "conf/x.toml",
+            "conf/y/z.toml",
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let files = |configs: &[&str]| {
+            let configs = configs.iter().map(|c| root.join(c)).collect::<Vec<_>>();
+            ConfigFiles::default()
+                .resolve("lint", &configs)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.strip_prefix(root).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(files(&[".eslintrc.*"]), [".eslintrc.json"]);
+        assert_eq!(
+            files(&["**/.eslintrc.*"]),
+            [".eslintrc.json", "a/.eslintrc.js"]
+        );
+        assert_eq!(files(&["conf"]), ["conf/x.toml", "conf/y/z.toml"]);
+        assert_eq!(files(&["conf/*.toml", "conf/x.toml"]), ["conf/x.toml"]);
+        assert_eq!(files(&["missing.toml"]), ["missing.toml"]);
+        assert!(files(&["*.yaml"]).is_empty());
+
+        // Each directory is walked once
+        let config_files = ConfigFiles::default();
+        let configs = [root.join("conf")];
+        assert_eq!(config_files.resolve("lint", &configs).unwrap().len(), 2);
+        fs::write(root.join("conf/w.toml"), "").unwrap();
+        assert_eq!(config_files.resolve("fmt", &configs).unwrap().len(), 2);
+        assert_eq!(files(&["conf"]).len(), 3);
+    }
+
+    #[test]
+    fn config_hash_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lint.toml");
+        fs::write(&path, "a = 1").unwrap();
+        let configs = [path.clone()];
+        let hash = |how| build_config_hash("lint", &configs, how, &ConfigFiles::default()).unwrap();
+        let (content, metadata) = (hash(ConfigHash::Content), hash(ConfigHash::Metadata));
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        let mtime = file.metadata().unwrap().modified().unwrap();
+        file.set_modified(mtime + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(hash(ConfigHash::Content), content);
+        assert_ne!(hash(ConfigHash::Metadata), metadata);
+
+        // Same size and modification time
+        fs::write(&path, "a = 2").unwrap();
+        file.set_modified(mtime).unwrap();
+        assert_ne!(hash(ConfigHash::Content), content);
+    }
+
+    #[test]
+    fn strict_env() {
+        // Cargo sets `CARGO_*` variables for tests
+        let stamp = |env: &[&str], strict| {
+            let tool = Tool {
+                env: env.iter().map(|e| (*e).to_string()).collect(),
+                ..toml::from_str("cmd = \"cargo clippy\"\nfiles = []").unwrap()
+            };
+            let opts = StampOpts {
+                strict,
+                ..StampOpts::default()
+            };
+            build_tool_stamp(&tool, &tool.cmd, opts, false, &ConfigFiles::default()).unwrap()
+        };
+        assert_ne!(stamp(&[], true), stamp(&[], false));
+        assert_ne!(stamp(&["CARGO_PKG_NAME"], true), stamp(&[], true));
+        assert_eq!(
+            stamp(&["CARGO_PKG_NAME"], false),
+            stamp(&[], false),
+            "already included"
+        );
+    }
+
+    #[test]
+    fn env_stamp() {
+        // Cargo sets `CARGO_*` variables for tests
+        let stamp = |env_stamp: &str| {
+            let tool: Tool = toml::from_str(&format!(
+                "cmd = \"cargo clippy\"\nfiles = []\nenv_stamp = {env_stamp}"
+            ))
+            .unwrap();
+            build_tool_stamp(
+                &tool,
+                &tool.cmd,
+                StampOpts::default(),
+                false,
+                &ConfigFiles::default(),
+            )
+            .unwrap()
+        };
+        let only = stamp(r#"["CARGO_PKG_NAME"]"#);
+        assert_ne!(stamp("true"), stamp("false"));
+        assert_ne!(only, stamp("true"));
+        assert_ne!(only, stamp("false"));
+        assert_eq!(only, stamp(r#"["CARGO_PKG_NAME", "CARGO_PKG_NAME"]"#));
+        assert_eq!(
+            toml::Value::try_from(EnvStamp::Off).unwrap(),
+            toml::Value::Boolean(false)
+        );
+    }
+
+    #[test]
+    fn careful_values() {
+        let careful = |s: &str| {
+            toml::from_str::<toml::Table>(&format!("careful = {s}"))
+                .unwrap()
+                .remove("careful")
+                .unwrap()
+                .try_into::<Careful>()
+                .ok()
+        };
+        assert_eq!(careful("false"), Some(Careful::Off));
+        assert_eq!(careful("true"), Some(Careful::Version));
+        assert_eq!(careful("\"binary\""), Some(Careful::Binary));
+        assert_eq!(careful("\"bogus\""), None);
+        assert_eq!(
+            toml::Value::try_from(Careful::Binary).unwrap(),
+            toml::Value::String("binary".to_string())
+        );
+    }
+
+    #[test]
+    fn cache_max_age_zero() {
+        // Would expire every entry, even those just added
+        let err = toml::from_str::<Config>("cache_max_age = 0").unwrap_err();
+        assert!(err.to_string().contains("nonzero"), "{err}");
+        let config = toml::from_str::<Config>("cache_max_age = 3").unwrap();
+        assert_eq!(config.cache_max_age, NonZeroU16::new(3));
+    }
+
+    #[test]
+    fn merge_overlay() {
+        let mut base: toml::Table = toml::from_str(
+            r#"
+careful = false
+refs = ["main"]
+
+[[linter]]
+name = "ruff"
+cmd = "ruff check"
+files = ["*.py"]
+
+[[linter]]
+name = "mypy"
+cmd = "mypy"
+files = ["*.py"]
+"#,
+        )
+        .unwrap();
+        let overlay = toml::from_str(
+            r#"
+careful = true
+refs = []
+
+[[linter]]
+name = "ruff"
+cmd = "ruff check --select ALL"
+
+[[linter]]
+name = "shellcheck"
+cmd = "shellcheck"
+files = ["*.sh"]
+"#,
+        )
+        .unwrap();
+        merge(&mut base, overlay);
+        expect_test::expect![[r#"
+            careful = true
+            refs = []
+
+            [[linter]]
+            cmd = "ruff check --select ALL"
+            files = ["*.py"]
+            name = "ruff"
+
+            [[linter]]
+            cmd = "mypy"
+            files = ["*.py"]
+            name = "mypy"
+
+            [[linter]]
+            cmd = "shellcheck"
+            files = ["*.sh"]
+            name = "shellcheck"
+        "#]]
+        .assert_eq(&toml::to_string(&base).unwrap());
     }
 }
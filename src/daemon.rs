@@ -0,0 +1,282 @@
+//! `lun daemon`, which runs the requests of `lun run --daemon` sent over a Unix
+//! socket in the cache directory. See [`run::Daemon`].
+//!
+//! Requests and replies are single lines of JSON.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::{cli, config, warn::warns::Warns};
+#[cfg(unix)]
+use crate::{interrupt, run};
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Request {
+    /// The daemon only serves clients in the directory it runs in
+    cwd: PathBuf,
+    /// The command line of the client
+    args: Vec<String>,
+    globals: Globals,
+}
+
+/// Global options that the daemon can't change per request, as it keeps the
+/// configuration and cache it was started with
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct Globals {
+    /// The configuration file and its overlays
+    config: Vec<PathBuf>,
+    cache: PathBuf,
+    /// Of the contents of `config`, see [`run::config_hash`]
+    config_hash: u128,
+}
+
+impl Globals {
+    fn new(cli: &cli::Cli, cwd: &Path) -> Self {
+        Self {
+            config: std::iter::once(&cli.config)
+                .chain(&cli.config_overlays)
+                .map(|path| cwd.join(path))
+                .collect(),
+            cache: cwd.join(&cli.cache),
+            config_hash: run::config_hash(cli).0,
+        }
+    }
+
+    /// Check that the daemon (`self`) can serve a client with `request`
+    fn check(&self, request: &Globals) -> Result<()> {
+        if (&self.config, &self.cache) != (&request.config, &request.cache) {
+            anyhow::bail!(
+                "The daemon was started with a different `--config`, `--config-name`, or `--cache`"
+            );
+        }
+        if self.config_hash != request.config_hash {
+            anyhow::bail!("The configuration changed since the daemon started, restart it");
+        }
+        Ok(())
+    }
+}
+
+/// Output and result of a run, for the client to print
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Reply {
+    pub(crate) ok: bool,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+    /// The run couldn't be done, e.g., the configuration is invalid
+    pub(crate) error: Option<String>,
+}
+
+fn socket(cache: &Path) -> PathBuf {
+    cache.join("daemon.sock")
+}
+
+#[cfg(unix)]
+pub(crate) fn serve(cli: &cli::Cli, config: &config::Config, lints: &Warns) -> Result<()> {
+    use std::{env, fs, io, os::unix::net, sync::mpsc, thread, time};
+
+    use anyhow::Context as _;
+    use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+    use tracing::{info, warn};
+
+    let mut daemon = run::Daemon::new(cli, config, lints)?;
+    let path = socket(&cli.cache);
+    if net::UnixStream::connect(&path).is_ok() {
+        anyhow::bail!("A daemon is already listening on {}", path.display());
+    }
+    // Left behind by a daemon that was killed
+    drop(fs::remove_file(&path));
+    let listener = net::UnixListener::bind(&path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    listener
+        .set_nonblocking(true)
+        .context("Failed to configure socket")?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(
+        move |res| {
+            if let Err(e) = tx.send(res) {
+                warn!("Error sending watch event: {e}");
+            }
+        },
+        notify::Config::default(),
+    )
+    .context("Failed to create file watcher")?;
+    watcher
+        .watch(Path::new("."), RecursiveMode::Recursive)
+        .context("Failed to start watching directory")?;
+
+    let cwd = env::current_dir().context("Failed to get current directory")?;
+    let globals = Globals::new(cli, &cwd);
+    info!("Listening on {}", path.display());
+    let result = loop {
+        if interrupt::interrupted() {
+            break Ok(());
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                for ev in rx.try_iter() {
+                    match ev {
+                        Ok(ev) => daemon.event(&ev),
+                        Err(e) => warn!("File watcher error: {e}"),
+                    }
+                }
+                if let Err(e) = handle(&mut daemon, &stream, &cwd, &globals) {
+                    warn!("Failed to handle request: {e:#}");
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(time::Duration::from_millis(20));
+            }
+            Err(e) => break Err(e).context("Failed to accept connection"),
+        }
+    };
+    drop(fs::remove_file(&path));
+    result
+}
+
+#[cfg(not(unix))]
+pub(crate) fn serve(_cli: &cli::Cli, _config: &config::Config, _lints: &Warns) -> Result<()> {
+    anyhow::bail!("`lun daemon` is only supported on Unix")
+}
+
+#[cfg(unix)]
+fn handle(
+    daemon: &mut run::Daemon<'_>,
+    stream: &std::os::unix::net::UnixStream,
+    cwd: &Path,
+    globals: &Globals,
+) -> Result<()> {
+    use std::io::{BufRead as _, BufReader, Write as _};
+
+    use anyhow::Context as _;
+
+    stream
+        .set_nonblocking(false)
+        .context("Failed to configure socket")?;
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .context("Failed to read request")?;
+    let reply = serde_json::from_str::<Request>(&line)
+        .context("Failed to parse request")
+        .and_then(|request| {
+            if request.cwd != cwd {
+                anyhow::bail!(
+                    "The daemon runs in {}, not {}",
+                    cwd.display(),
+                    request.cwd.display()
+                );
+            }
+            globals.check(&request.globals)?;
+            let cli = cli::Cli::try_parse_from(&request.args)?;
+            let cli::Command::Run(run_cli) = &cli.command else {
+                anyhow::bail!("Only `lun run` can be sent to the daemon");
+            };
+            daemon.run(run_cli)
+        })
+        .unwrap_or_else(|e| Reply {
+            error: Some(format!("{e:#}")),
+            ..Reply::default()
+        });
+    let mut writer = stream;
+    serde_json::to_writer(&mut writer, &reply).context("Failed to write reply")?;
+    writer.write_all(b"\n").context("Failed to write reply")
+}
+
+/// Send this run to the daemon and print its output. Returns whether the run
+/// succeeded, or `None` if no daemon is listening.
+#[cfg(unix)]
+pub(crate) fn request(cli: &cli::Cli) -> Result<Option<bool>> {
+    use std::{
+        env,
+        io::{BufRead as _, BufReader, Write as _},
+        os::unix::net,
+    };
+
+    use anyhow::Context as _;
+    use tracing::debug;
+
+    let path = socket(&cli.cache);
+    let Ok(mut stream) = net::UnixStream::connect(&path) else {
+        debug!("No daemon listening on {}", path.display());
+        return Ok(None);
+    };
+    let cwd = env::current_dir().context("Failed to get current directory")?;
+    let request = Request {
+        globals: Globals::new(cli, &cwd),
+        cwd,
+        args: env::args_os()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect(),
+    };
+    serde_json::to_writer(&mut stream, &request).context("Failed to send request")?;
+    stream.write_all(b"\n").context("Failed to send request")?;
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("Failed to read reply")?;
+    let reply: Reply = serde_json::from_str(&line).context("Failed to parse reply")?;
+    if let Some(error) = reply.error {
+        anyhow::bail!("{error}");
+    }
+    print!("{}", reply.stdout);
+    eprint!("{}", reply.stderr);
+    Ok(Some(reply.ok))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn request(_cli: &cli::Cli) -> Result<Option<bool>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn globals() {
+        let cwd = Path::new("/repo");
+        // The paths needn't exist, see `config_changed` for the contents
+        let globals = |args: &[&str]| Globals {
+            config_hash: 0,
+            ..Globals::new(&cli::Cli::try_parse_from(args).unwrap(), cwd)
+        };
+        let daemon = globals(&["lun", "daemon"]);
+        assert!(daemon.check(&globals(&["lun", "run"])).is_ok());
+        assert!(
+            daemon
+                .check(&globals(&["lun", "-c", "/repo/lun.toml", "run"]))
+                .is_ok()
+        );
+        for args in [
+            ["lun", "-c", "other.toml", "run"],
+            ["lun", "--config-name", "ci", "run"],
+            ["lun", "--cache", "other", "run"],
+        ] {
+            assert!(daemon.check(&globals(&args)).is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn config_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("lun.toml");
+        let path = config.to_str().unwrap();
+        let globals = || {
+            let cli = cli::Cli::try_parse_from(["lun", "-c", path, "run"]).unwrap();
+            Globals::new(&cli, dir.path())
+        };
+        fs::write(&config, "[[linter]]\n").unwrap();
+        let daemon = globals();
+        assert!(daemon.check(&globals()).is_ok());
+        fs::write(&config, "").unwrap();
+        let err = daemon.check(&globals()).unwrap_err();
+        assert!(err.to_string().contains("restart it"));
+        // Sent as JSON
+        let json = serde_json::to_string(&daemon).unwrap();
+        assert_eq!(serde_json::from_str::<Globals>(&json).unwrap(), daemon);
+    }
+}
@@ -0,0 +1,108 @@
+//! Desktop notifications for `lun run --watch --notify`, so that Lūn can run in
+//! a background terminal.
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::run::RunResult;
+
+/// Check that notifications are available
+pub(crate) fn check() -> Result<()> {
+    if !cfg!(feature = "desktop-notify") {
+        anyhow::bail!("`--notify` requires building Lūn with the `desktop-notify` feature");
+    }
+    Ok(())
+}
+
+/// Notifies when runs start failing or passing again
+#[derive(Debug, Default)]
+pub(crate) struct Notifier {
+    /// Whether the last run passed
+    last: Option<bool>,
+}
+
+impl Notifier {
+    pub(crate) fn update(&mut self, result: &RunResult) {
+        let ok = bool::from(result);
+        if self.last.is_some_and(|last| last != ok) {
+            let (summary, body) = message(result);
+            if let Err(e) = show(&summary, &body) {
+                warn!("Failed to send desktop notification: {e}");
+            }
+        }
+        self.last = Some(ok);
+    }
+}
+
+fn message(result: &RunResult) -> (String, String) {
+    if result.ok {
+        let cmds = if result.cmds == 1 {
+            "command"
+        } else {
+            "commands"
+        };
+        (
+            "lun: passing".to_string(),
+            format!("{} {cmds} passed", result.cmds),
+        )
+    } else {
+        let failed = result
+            .tools
+            .iter()
+            .filter(|(_, t)| t.failed > 0)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        (
+            "lun: failing".to_string(),
+            format!("{} failed", failed.join(", ")),
+        )
+    }
+}
+
+#[cfg(feature = "desktop-notify")]
+fn show(summary: &str, body: &str) -> Result<()> {
+    notify_rust::Notification::new()
+        .appname("lun")
+        .summary(summary)
+        .body(body)
+        .show()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "desktop-notify"))]
+fn show(_summary: &str, _body: &str) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::ToolResult;
+
+    #[test]
+    fn messages() {
+        let mut result = RunResult {
+            cmds: 3,
+            ..RunResult::default()
+        };
+        assert_eq!(
+            message(&result),
+            ("lun: passing".to_string(), "3 commands passed".to_string())
+        );
+        result.ok = false;
+        result.tools.insert(
+            "mypy".to_string(),
+            ToolResult {
+                failed: 1,
+                ..ToolResult::default()
+            },
+        );
+        result
+            .tools
+            .insert("ruff".to_string(), ToolResult::default());
+        assert_eq!(
+            message(&result),
+            ("lun: failing".to_string(), "mypy failed".to_string())
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Diagnostics parsed from tool output.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::matcher;
+
+/// A diagnostic of the form `file:line[:col]: [CODE] message`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Diagnostic {
+    pub(crate) file: PathBuf,
+    pub(crate) line: u32,
+    pub(crate) col: Option<u32>,
+    pub(crate) code: Option<String>,
+}
+
+/// Remove ANSI escape sequences (e.g., colors) from a line of output
+pub(crate) fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'[').is_some() {
+            // CSI: parameters and intermediates, then a final byte in @..=~
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            chars.next();
+        }
+    }
+    out
+}
+
+fn is_code(s: &str) -> bool {
+    let s = s.trim_end_matches(':');
+    let alpha = s.trim_end_matches(|c: char| c.is_ascii_digit());
+    !alpha.is_empty()
+        && alpha.len() < s.len()
+        && alpha
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c == '-' || c == '_')
+}
+
+impl Diagnostic {
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        Self::parse_with_message(line).map(|(diag, _)| diag)
+    }
+
+    /// Parse a diagnostic, along with its message (including any code)
+    pub(crate) fn parse_with_message(line: &str) -> Option<(Self, String)> {
+        let line = strip_ansi(line);
+        let mut parts = line.splitn(4, ':');
+        let file = parts.next()?.trim();
+        if file.is_empty() || file.contains(char::is_whitespace) {
+            return None;
+        }
+        let line_no = parts.next()?.trim().parse().ok()?;
+        let rest = parts.next()?;
+        let (col, message) = match rest.trim().parse() {
+            Ok(col) => (Some(col), parts.next().unwrap_or_default().to_string()),
+            Err(_) => match parts.next() {
+                Some(tl) => (None, format!("{rest}:{tl}")),
+                None => (None, rest.to_string()),
+            },
+        };
+        let code = message
+            .split_whitespace()
+            .find(|w| is_code(w))
+            .map(|w| w.trim_end_matches(':').to_string());
+        let diag = Self {
+            file: PathBuf::from(file),
+            line: line_no,
+            col,
+            code,
+        };
+        Some((diag, message.trim().to_string()))
+    }
+}
+
+/// How serious a diagnostic is, per the `severity` group of a `problem_matcher`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Severity {
+    #[default]
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    pub(crate) fn parse(s: &str) -> Self {
+        match s.to_ascii_lowercase().as_str() {
+            "warning" | "warn" | "w" => Self::Warning,
+            "info" | "note" | "i" | "n" => Self::Info,
+            "hint" | "help" | "style" => Self::Hint,
+            _ => Self::Error,
+        }
+    }
+}
+
+/// A diagnostic, along with the message and severity shown in editors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Problem {
+    pub(crate) diag: Diagnostic,
+    pub(crate) message: String,
+    pub(crate) severity: Severity,
+}
+
+impl Problem {
+    /// Parse the diagnostics in the output of a tool with `matcher`, or in the
+    /// default format without one
+    pub(crate) fn parse_output(matcher: Option<&ProblemMatcher>, output: &str) -> Vec<Self> {
+        match matcher {
+            Some(ProblemMatcher::Regex(re)) => output
+                .lines()
+                .filter_map(|line| captures(re, &strip_ansi(line)))
+                .collect(),
+            Some(ProblemMatcher::Builtin(builtin)) => builtin.parse(output),
+            None => output
+                .lines()
+                .filter_map(Diagnostic::parse_with_message)
+                .map(|(diag, message)| Self {
+                    diag,
+                    message,
+                    severity: Severity::Error,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Parse a line with a regex with the named groups `file` and `line`, and
+/// optionally `col`, `code`, `severity`, and `message`
+pub(crate) fn captures(re: &Regex, line: &str) -> Option<Problem> {
+    let caps = re.captures(line)?;
+    let group = |name| caps.name(name).map(|m| m.as_str().trim());
+    let diag = Diagnostic {
+        file: PathBuf::from(group("file").filter(|f| !f.is_empty())?),
+        line: group("line")?.parse().ok()?,
+        col: group("col").and_then(|c| c.parse().ok()),
+        code: group("code").filter(|c| !c.is_empty()).map(str::to_string),
+    };
+    Some(Problem {
+        diag,
+        message: group("message").unwrap_or(line.trim()).to_string(),
+        severity: group("severity").map_or(Severity::Error, Severity::parse),
+    })
+}
+
+/// How to find diagnostics in the output of a tool, per its `problem_matcher`
+#[derive(Clone, Debug)]
+pub(crate) enum ProblemMatcher {
+    /// A regex that matches a line with a diagnostic, see [`captures`]
+    Regex(Regex),
+    /// A matcher for a known tool, e.g., `"clippy"`
+    Builtin(matcher::Builtin),
+}
+
+impl ProblemMatcher {
+    /// The built-in matcher named `re`, if any, else a regex
+    pub(crate) fn new(re: &str) -> Result<Self> {
+        if let Some(builtin) = matcher::Builtin::from_name(re) {
+            return Ok(Self::Builtin(builtin));
+        }
+        let re = Regex::new(re)?;
+        for group in ["file", "line"] {
+            if !re.capture_names().flatten().any(|name| name == group) {
+                anyhow::bail!("missing group `(?<{group}>...)`");
+            }
+        }
+        Ok(Self::Regex(re))
+    }
+}
+
+/// Diagnostics that have already been reported, per `dedupe_group`, and the
+/// tool that reported them
+#[derive(Debug, Default)]
+pub(crate) struct Seen(Mutex<HashMap<(String, Diagnostic), String>>);
+
+impl Seen {
+    /// Remove lines of `output` with diagnostics already reported in `group`
+    /// by other tools. Diagnostics without codes on the same line may differ,
+    /// so a tool's own are never removed.
+    pub(crate) fn dedupe(&self, group: &str, tool: &str, output: &[u8]) -> Vec<u8> {
+        let mut seen = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = Vec::with_capacity(output.len());
+        for line in output.split_inclusive(|b| *b == b'\n') {
+            let diag = Diagnostic::parse(&String::from_utf8_lossy(line));
+            if let Some(diag) = diag {
+                let by = seen
+                    .entry((group.to_string(), diag))
+                    .or_insert_with(|| tool.to_string());
+                if by != tool {
+                    continue;
+                }
+            }
+            out.extend_from_slice(line);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ruff() {
+        assert_eq!(
+            Diagnostic::parse("src/a.py:1:8: F401 [*] `os` imported but unused"),
+            Some(Diagnostic {
+                file: PathBuf::from("src/a.py"),
+                line: 1,
+                col: Some(8),
+                code: Some("F401".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_no_col() {
+        assert_eq!(
+            Diagnostic::parse("\x1b[1ma.sh\x1b[0m:3: warning: SC2086: quote this"),
+            Some(Diagnostic {
+                file: PathBuf::from("a.sh"),
+                line: 3,
+                col: None,
+                code: Some("SC2086".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_not_diagnostic() {
+        assert_eq!(Diagnostic::parse("Found 1 error."), None);
+        assert_eq!(Diagnostic::parse("error: a:b"), None);
+    }
+
+    #[test]
+    fn problem_matcher() {
+        let matcher = ProblemMatcher::new(
+            r"^(?<severity>\w+): (?<message>.*) at (?<file>[^:]+):(?<line>\d+)(:(?<col>\d+))?$",
+        )
+        .unwrap();
+        assert_eq!(
+            Problem::parse_output(Some(&matcher), "warning: unused variable at src/a.rs:3:5"),
+            [Problem {
+                diag: Diagnostic {
+                    file: PathBuf::from("src/a.rs"),
+                    line: 3,
+                    col: Some(5),
+                    code: None,
+                },
+                message: "unused variable".to_string(),
+                severity: Severity::Warning,
+            }]
+        );
+        assert_eq!(
+            Problem::parse_output(Some(&matcher), "a.py:1:8: F401 x"),
+            []
+        );
+        assert!(ProblemMatcher::new(r"(?<file>.*)").is_err());
+        assert!(matches!(
+            ProblemMatcher::new("ruff"),
+            Ok(ProblemMatcher::Builtin(matcher::Builtin::Ruff))
+        ));
+    }
+
+    #[test]
+    fn dedupe() {
+        let seen = Seen::default();
+        let out = b"a.py:1:1: E1 x\nsummary\n";
+        assert_eq!(seen.dedupe("g", "ruff", out), out.to_vec());
+        assert_eq!(seen.dedupe("g", "flake8", out), b"summary\n".to_vec());
+        assert_eq!(seen.dedupe("h", "flake8", out), out.to_vec());
+        // Different diagnostics without codes on the same line
+        let out = b"a.sh:3: quote this\na.sh:3: unused variable\n";
+        assert_eq!(seen.dedupe("g", "shellcheck", out), out.to_vec());
+        assert_eq!(seen.dedupe("g", "shellcheck", out), out.to_vec());
+    }
+}
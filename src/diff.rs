@@ -0,0 +1,294 @@
+//! Line-based unified diffs, for `--diff`.
+
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+/// Lines of context around each change
+const CONTEXT: usize = 3;
+const COPY_PREFIX: &str = ".lun-diff.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Shortest edit script from `a` to `b` (Myers' algorithm)
+fn edits<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    // Common prefixes and suffixes are common, and cheap to handle
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut script = vec![Edit::Keep; prefix];
+    script.extend(myers(a_mid, b_mid));
+    script.extend(std::iter::repeat_n(Edit::Keep, suffix));
+    script
+}
+
+/// Above this many edits, [`myers`] gives up and replaces all of the lines, as
+/// its memory use grows with the square of the number of edits
+const MAX_EDITS: isize = 2000;
+
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let idx = |k: isize| (k + max + 1) as usize;
+
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // The diagonals `-d-1..=d+1` of `v` before each step `d`, which are all
+    // that the backtracking reads
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        if d > MAX_EDITS {
+            let mut script = vec![Edit::Delete; n as usize];
+            script.extend(std::iter::repeat_n(Edit::Insert, m as usize));
+            return script;
+        }
+        trace.push(v[idx(-d - 1)..=idx(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            // Whether the furthest-reaching path on diagonal `k` came from
+            // the one above
+            let above = k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]);
+            let mut x = if above {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut script = Vec::with_capacity((n + m) as usize);
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let above = k == -d || (k != d && at(k - 1) < at(k + 1));
+        let prev_k = if above { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            script.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                script.push(Edit::Insert);
+                y -= 1;
+            } else {
+                script.push(Edit::Delete);
+                x -= 1;
+            }
+        }
+    }
+    script.reverse();
+    script
+}
+
+/// Where to put a copy of `path` to format for `--diff`.
+///
+/// The copy is next to the original so that formatters find the same
+/// configuration files, and keeps the extension so that they recognize it.
+pub(crate) fn copy_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(COPY_PREFIX);
+    name.push(path.file_name().unwrap_or_default());
+    path.with_file_name(name)
+}
+
+/// Whether `path` is a copy made by [`copy_path`]
+pub(crate) fn is_copy(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|n| n.as_encoded_bytes().starts_with(COPY_PREFIX.as_bytes()))
+}
+
+/// Unified diff of `old` and `new`, or nothing if they're the same.
+#[allow(clippy::unwrap_used)] // writing to a `String` can't fail
+pub(crate) fn unified(path: &Path, old: &[u8], new: &[u8]) -> String {
+    let a = old.split_inclusive(|c| *c == b'\n').collect::<Vec<_>>();
+    let b = new.split_inclusive(|c| *c == b'\n').collect::<Vec<_>>();
+    // Each edit, along with the line indices before it
+    let mut lines = Vec::with_capacity(a.len().max(b.len()));
+    let (mut i, mut j) = (0, 0);
+    for edit in edits(&a, &b) {
+        lines.push((edit, i, j));
+        match edit {
+            Edit::Keep => (i, j) = (i + 1, j + 1),
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    let changes = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (edit, _, _))| *edit != Edit::Keep)
+        .map(|(n, _)| n)
+        .collect::<Vec<_>>();
+    let Some(&first) = changes.first() else {
+        return String::new();
+    };
+
+    let mut out = String::new();
+    let path = path.display();
+    writeln!(out, "--- a/{path}").unwrap();
+    writeln!(out, "+++ b/{path}").unwrap();
+    let mut start = first.saturating_sub(CONTEXT);
+    let mut end = (first + CONTEXT + 1).min(lines.len());
+    for &change in &changes[1..] {
+        if change.saturating_sub(CONTEXT) <= end {
+            end = (change + CONTEXT + 1).min(lines.len());
+        } else {
+            hunk(&mut out, &lines[start..end], &a, &b);
+            start = change.saturating_sub(CONTEXT);
+            end = (change + CONTEXT + 1).min(lines.len());
+        }
+    }
+    hunk(&mut out, &lines[start..end], &a, &b);
+    out
+}
+
+#[allow(clippy::unwrap_used)]
+fn hunk(out: &mut String, lines: &[(Edit, usize, usize)], a: &[&[u8]], b: &[&[u8]]) {
+    let old_len = lines.iter().filter(|l| l.0 != Edit::Insert).count();
+    let new_len = lines.iter().filter(|l| l.0 != Edit::Delete).count();
+    let (i, j) = (lines[0].1, lines[0].2);
+    // Empty ranges start at the line before them
+    let old_start = if old_len == 0 { i } else { i + 1 };
+    let new_start = if new_len == 0 { j } else { j + 1 };
+    writeln!(out, "@@ -{old_start},{old_len} +{new_start},{new_len} @@").unwrap();
+    for &(edit, i, j) in lines {
+        let (prefix, line) = match edit {
+            Edit::Keep => (' ', a[i]),
+            Edit::Delete => ('-', a[i]),
+            Edit::Insert => ('+', b[j]),
+        };
+        out.push(prefix);
+        out.push_str(&String::from_utf8_lossy(line));
+        if !line.ends_with(b"\n") {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> String {
+        unified(Path::new("f.txt"), old.as_bytes(), new.as_bytes())
+    }
+
+    #[test]
+    fn same() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(diff("", ""), "");
+    }
+
+    #[test]
+    fn change() {
+        expect![[r#"
+            --- a/f.txt
+            +++ b/f.txt
+            @@ -2,7 +2,7 @@
+             2
+             3
+             4
+            -5
+            +five
+             6
+             7
+             8
+        "#]]
+        .assert_eq(&diff(
+            "1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+            "1\n2\n3\n4\nfive\n6\n7\n8\n9\n",
+        ));
+    }
+
+    #[test]
+    fn hunks() {
+        expect![[r#"
+            --- a/f.txt
+            +++ b/f.txt
+            @@ -1,4 +1,4 @@
+            -1
+            +one
+             2
+             3
+             4
+            @@ -7,3 +7,4 @@
+             7
+             8
+             9
+            +10
+        "#]]
+        .assert_eq(&diff(
+            "1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+            "one\n2\n3\n4\n5\n6\n7\n8\n9\n10\n",
+        ));
+    }
+
+    #[test]
+    fn no_newline() {
+        expect![[r#"
+            --- a/f.txt
+            +++ b/f.txt
+            @@ -1,1 +1,1 @@
+            -a
+            \ No newline at end of file
+            +a
+        "#]]
+        .assert_eq(&diff("a", "a\n"));
+    }
+
+    #[test]
+    fn shortest() {
+        // The example from Myers' paper
+        let script = edits(b"abcabba", b"cbabac");
+        assert_eq!(script.iter().filter(|e| **e != Edit::Keep).count(), 5);
+    }
+
+    #[test]
+    fn too_many_edits() {
+        let a = (0..3000).collect::<Vec<_>>();
+        let b = (0..3000).map(|i| -i - 1).collect::<Vec<_>>();
+        let script = edits(&a, &b);
+        assert_eq!(script.len(), 6000);
+        assert!(script[..3000].iter().all(|e| *e == Edit::Delete));
+        assert!(script[3000..].iter().all(|e| *e == Edit::Insert));
+    }
+
+    #[test]
+    fn empty() {
+        expect![[r#"
+            --- a/f.txt
+            +++ b/f.txt
+            @@ -0,0 +1,2 @@
+            +a
+            +b
+        "#]]
+        .assert_eq(&diff("", "a\nb\n"));
+    }
+}
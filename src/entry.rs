@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
 use xxhash_rust::xxh3::Xxh3;
@@ -7,68 +10,142 @@ use crate::cache::{CacheWriter, HashCache, Key, KeyHash};
 use crate::file;
 use crate::tool;
 
-pub(crate) fn add(cache_file: &Path, string: &str, files: &[PathBuf]) -> Result<(), anyhow::Error> {
+fn tool_stamp(string: &str) -> tool::Stamp {
     let mut hasher = Xxh3::new();
     hasher.update(string.as_bytes());
-    let tool_stamp = tool::Stamp(file::Xxhash(hasher.digest128()));
+    tool::Stamp(file::Xxhash(hasher.digest128()))
+}
+
+fn key(tool_stamp: tool::Stamp, file_path: &Path) -> Result<Key> {
+    let file = file::File::new(file_path.to_path_buf())
+        .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
+    Ok(Key {
+        stamp: file.mtime_stamp(),
+        tool_stamp,
+    })
+}
+
+pub(crate) fn add(cache_file: &Path, string: &str, files: &[PathBuf]) -> Result<(), anyhow::Error> {
+    let tool_stamp = tool_stamp(string);
     let mut cache = HashCache::from_file(cache_file, None)?;
     for file_path in files {
-        let file = file::File::new(file_path.clone())
-            .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
-        let key = Key {
-            stamp: file.mtime_stamp(),
-            tool_stamp,
-        };
-        cache.done(&key);
+        cache.done(&key(tool_stamp, file_path)?);
     }
     cache.flush()?;
     Ok(())
 }
 
+/// Whether each file has an entry
+fn lookup(cache_file: &Path, string: &str, files: &[PathBuf]) -> Result<Vec<bool>> {
+    let tool_stamp = tool_stamp(string);
+    let cache = HashCache::from_file(cache_file, None)?;
+    files
+        .iter()
+        .map(|file_path| {
+            let hash = KeyHash::from(&key(tool_stamp, file_path)?);
+            Ok(cache.hashes.contains_key(&hash))
+        })
+        .collect()
+}
+
+/// Remove the entries of the files, returning whether each had one
+fn remove(cache_file: &Path, string: &str, files: &[PathBuf]) -> Result<Vec<bool>> {
+    let tool_stamp = tool_stamp(string);
+    let mut cache = HashCache::from_file(cache_file, None)?;
+    let removed = files
+        .iter()
+        .map(|file_path| {
+            let hash = KeyHash::from(&key(tool_stamp, file_path)?);
+            Ok(cache.hashes.remove(&hash).is_some())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    cache.flush()?;
+    Ok(removed)
+}
+
+fn print_bools(bools: &[bool], null_separated: bool) {
+    for b in bools {
+        if null_separated {
+            print!("{b}\0");
+        } else {
+            println!("{b}");
+        }
+    }
+}
+
 pub(crate) fn get(
     cache_file: &Path,
     string: &str,
     files: &[PathBuf],
     null_separated: bool,
 ) -> Result<(), anyhow::Error> {
-    let mut hasher = Xxh3::new();
-    hasher.update(string.as_bytes());
-    let tool_stamp = tool::Stamp(file::Xxhash(hasher.digest128()));
-    let cache = HashCache::from_file(cache_file, None)?;
-    for file_path in files {
-        let file = file::File::new(file_path.clone())
-            .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
-        let key = Key {
-            stamp: file.mtime_stamp(),
-            tool_stamp,
-        };
-        let hash = KeyHash::from(&key);
-        let found = cache.hashes.contains_key(&hash);
-        if null_separated {
-            print!("{found}\0");
-        } else {
-            println!("{found}");
-        }
-    }
+    print_bools(&lookup(cache_file, string, files)?, null_separated);
     Ok(())
 }
 
-pub(crate) fn rm(cache_file: &Path, string: &str, files: &[PathBuf]) -> Result<(), anyhow::Error> {
-    let mut hasher = Xxh3::new();
-    hasher.update(string.as_bytes());
-    let tool_stamp = tool::Stamp(file::Xxhash(hasher.digest128()));
-    let mut cache = HashCache::from_file(cache_file, None)?;
-    for file_path in files {
-        let file = file::File::new(file_path.clone())
-            .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
-        let key = Key {
-            stamp: file.mtime_stamp(),
-            tool_stamp,
-        };
-        let hash = KeyHash::from(&key);
-        let was_present = cache.hashes.remove(&hash).is_some();
-        println!("{was_present}");
+pub(crate) fn rm(
+    cache_file: &Path,
+    string: &str,
+    files: &[PathBuf],
+    null_separated: bool,
+) -> Result<(), anyhow::Error> {
+    print_bools(&remove(cache_file, string, files)?, null_separated);
+    Ok(())
+}
+
+/// Print the paths for which `select` holds, one per line (or NUL-terminated)
+fn print_paths(files: &[PathBuf], select: &[bool], print0: bool) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for (path, _) in files.iter().zip(select).filter(|(_, s)| **s) {
+        stdout.write_all(path.as_os_str().as_encoded_bytes())?;
+        stdout.write_all(if print0 { b"\0" } else { b"\n" })?;
     }
-    cache.flush()?;
     Ok(())
 }
+
+/// `lun cache entry get --present` (or `--missing`): print the files with
+/// entries, or without them if `missing`
+pub(crate) fn get_paths(
+    cache_file: &Path,
+    string: &str,
+    files: &[PathBuf],
+    missing: bool,
+    print0: bool,
+) -> Result<()> {
+    let found = lookup(cache_file, string, files)?;
+    let select = found.into_iter().map(|f| f != missing).collect::<Vec<_>>();
+    print_paths(files, &select, print0)
+}
+
+/// `lun cache entry rm --removed`: print the files whose entries were removed
+pub(crate) fn rm_paths(
+    cache_file: &Path,
+    string: &str,
+    files: &[PathBuf],
+    print0: bool,
+) -> Result<()> {
+    let removed = remove(cache_file, string, files)?;
+    print_paths(files, &removed, print0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn add_lookup_remove() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache_file = tmp.path().join("cache");
+        let (a, b) = (tmp.path().join("a"), tmp.path().join("b"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        let files = [a.clone(), b.clone()];
+        add(&cache_file, "fmt", std::slice::from_ref(&a)).unwrap();
+        assert_eq!(lookup(&cache_file, "fmt", &files).unwrap(), [true, false]);
+        assert_eq!(lookup(&cache_file, "lint", &files).unwrap(), [false, false]);
+        assert_eq!(remove(&cache_file, "fmt", &files).unwrap(), [true, false]);
+        assert_eq!(lookup(&cache_file, "fmt", &files).unwrap(), [false, false]);
+    }
+}
@@ -1,11 +1,11 @@
-use std::collections::HashSet;
-use std::io::Write as _;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead as _, Write as _};
 use std::num::NonZeroUsize;
-use std::os::unix::process::ExitStatusExt as _;
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
-use std::{cmp, io, process, thread};
+use std::{cmp, fs, io, process, thread, time};
 
 use anyhow::{Context, Result};
 use rayon::prelude::*;
@@ -13,7 +13,10 @@ use tracing::{debug, error, trace};
 
 use crate::cache::CacheWriter;
 use crate::job;
-use crate::{cache, cmd};
+use crate::{
+    atomic, baseline::Baseline, cache, cmd, diag, diff, file, interrupt, quickfix, results,
+    sandbox, tool, width,
+};
 
 #[derive(Debug)]
 enum ReporterEvent {
@@ -21,6 +24,64 @@ enum ReporterEvent {
     Done { cmd: String },
 }
 
+/// Result of executing a single command
+#[derive(Debug)]
+pub(crate) struct Outcome {
+    pub(crate) tool: String,
+    /// Displayed command line
+    pub(crate) cmd: String,
+    pub(crate) files: Vec<PathBuf>,
+    /// Directory that the command ran in, if not the current one
+    pub(crate) cd: Option<PathBuf>,
+    /// Files that the command changed, if it formats them
+    pub(crate) modified: Vec<PathBuf>,
+    pub(crate) success: bool,
+    /// Output of the command, if it failed and the output was captured
+    pub(crate) output: Option<results::Output>,
+    /// Whether the output was replayed from a stored result
+    pub(crate) replayed: bool,
+    /// Whether the command failed because it tried to write in the sandbox
+    pub(crate) sandbox_violation: bool,
+    pub(crate) duration: time::Duration,
+}
+
+/// A command that succeeded, and so needs cache entries
+enum Succeeded {
+    /// Entries are computed after all commands are done
+    Pending(cmd::Command),
+    /// Entries were computed and recorded in the cache journal
+    Journaled(Vec<cache::KeyHash>),
+}
+
+/// When and how to prefix lines of uncaptured output with the names of tools,
+/// see [`wait_prefixed`]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Prefix {
+    pub(crate) when: PrefixWhen,
+    /// Whether to color the prefixes, and to ask tools for color output
+    pub(crate) color: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum PrefixWhen {
+    Never,
+    /// When commands may run concurrently
+    #[default]
+    Concurrent,
+    Always,
+}
+
+impl Prefix {
+    fn label(self, name: &str) -> String {
+        if !self.color {
+            return name.to_string();
+        }
+        // Red through cyan, so that each tool keeps its color between runs
+        let color = 31 + xxhash_rust::xxh3::xxh3_64(name.as_bytes()) % 6;
+        format!("\x1b[1;{color}m{name}\x1b[0m")
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum ProgressFormat {
     No,
@@ -34,12 +95,16 @@ pub(crate) fn exec(
     batches: Vec<cmd::Command>,
     cores: NonZeroUsize,
     no_capture: bool,
+    prefix: Prefix,
     format: ProgressFormat,
-    keep_going: bool,
+    keep_going: usize,
     mtime_enabled: bool,
-) -> Result<bool> {
+    results: Option<&results::Results>,
+    baseline: Option<&Baseline>,
+    report: bool,
+) -> Result<Vec<Outcome>> {
     if batches.is_empty() {
-        return Ok(true);
+        return Ok(Vec::new());
     }
     let n_batches = batches.len();
     debug!(batches = n_batches, "Executing batches in parallel");
@@ -52,52 +117,193 @@ pub(crate) fn exec(
     let (tx, rx) = mpsc::channel::<ReporterEvent>();
     let reporter_handle = thread::spawn(move || reporter(num_threads, n_batches, rx, format));
 
-    let failed = AtomicBool::new(false);
+    let failures = AtomicUsize::new(0);
+    // Formatters may change files that other commands ran on, see below
+    let journal = cache_writer
+        .journal()
+        .filter(|_| !batches.iter().any(|cmd| cmd.tool.formats));
+    // The output of tools with a `dedupe_group` is printed after all commands
+    // are done, in a fixed order, so that the same tool's diagnostics win
+    // every time
+    let dedupe_groups = batches
+        .iter()
+        .filter_map(|cmd| Some((cmd.tool.display_name(), cmd.tool.dedupe_group.clone()?)))
+        .map(|(name, group)| (name.to_string(), group))
+        .collect::<HashMap<_, _>>();
 
-    let (ok, all_hashes) = pool.install(|| -> Result<(bool, Vec<cache::KeyHash>)> {
-        let tx = tx.clone();
-        let results = batches
-            .into_par_iter()
-            .map(|cmd| -> Result<(bool, Vec<cache::KeyHash>)> {
-                if !keep_going && failed.load(Ordering::Relaxed) {
-                    return Ok((false, Vec::new()));
-                }
+    let (outcomes, all_hashes) =
+        pool.install(|| -> Result<(Vec<Outcome>, Vec<cache::KeyHash>)> {
+            let tx = tx.clone();
+            let results = batches
+                .into_par_iter()
+                .map(|mut cmd| -> Result<Option<(Outcome, Option<Succeeded>)>> {
+                    if interrupt::interrupted()
+                        || keep_going != 0 && failures.load(Ordering::Relaxed) >= keep_going
+                    {
+                        return Ok(None);
+                    }
 
-                let c = cmd.to_command();
-                let cmd_str = job::display_cmd(&c);
-                debug!("{}: running", cmd_str);
-                tx.send(ReporterEvent::Start {
-                    cmd: cmd_str.clone(),
+                    let start = time::Instant::now();
+                    let results = results.filter(|_| cmd.tool.cache_failures);
+                    let key = results.map(|_| results::ResultKey::from(&cmd));
+                    let stored = results.zip(key).and_then(|(r, k)| r.get(k));
+                    // The argfile is named on the command line, and `--diff`
+                    // writes its own, see `run_diff`
+                    if stored.is_none() && !cmd.tool.diff {
+                        cmd.write_argfile()?;
+                    }
+                    let c = cmd.to_command();
+                    let cmd_str = job::display_job(&cmd, &c);
+                    debug!("{}: running", cmd_str);
+                    tx.send(ReporterEvent::Start {
+                        cmd: cmd_str.clone(),
+                    })
+                    .ok();
+                    let replayed = stored.is_some();
+                    let mut violation = false;
+                    // Failures with only diagnostics from the baseline pass
+                    let baselined = |output: &results::Output| {
+                        baseline.filter(|_| !cmd.tool.formats).is_some_and(|b| {
+                            let problems = quickfix::output_problems(
+                                cmd.tool.problem_matcher.as_ref(),
+                                cmd.tool.cd.as_deref(),
+                                output,
+                            );
+                            b.covers(cmd.tool.display_name(), problems)
+                        })
+                    };
+                    let (success, output) = if let Some(output) = stored {
+                        debug!("{}: replaying stored result", cmd_str);
+                        (false, Some(output))
+                    } else {
+                        let no_capture = no_capture || cmd.tool.no_capture;
+                        let color = prefix.color;
+                        // Keep the lines of concurrent commands apart
+                        let prefix = match prefix.when {
+                            PrefixWhen::Never => None,
+                            PrefixWhen::Concurrent if num_threads == 1 => None,
+                            PrefixWhen::Concurrent | PrefixWhen::Always => {
+                                Some(prefix.label(cmd.tool.display_name()))
+                            }
+                        };
+                        let prefix = prefix.as_deref();
+                        let (success, output) = match (cmd.tool.stdin, cmd.files.first()) {
+                            (Some(stdin), Some(file)) => run_stdin(
+                                c,
+                                &cmd_str,
+                                &file.path,
+                                stdin,
+                                no_capture,
+                                prefix,
+                                color,
+                                cmd.tool.sandbox,
+                            )?,
+                            _ if cmd.tool.diff => run_diff(&cmd, &cmd_str, color)?,
+                            _ => cmd.stdin().and_then(|stdin| {
+                                run(
+                                    c,
+                                    &cmd_str,
+                                    no_capture,
+                                    prefix,
+                                    color,
+                                    cmd.tool.sandbox,
+                                    stdin,
+                                )
+                            })?,
+                        };
+                        if !success && interrupt::interrupted() {
+                            // Likely terminated, so neither report nor store
+                            // the failure
+                            return Ok(None);
+                        }
+                        violation =
+                            cmd.tool.sandbox && output.as_ref().is_some_and(sandbox::violation);
+                        if let (Some(output), Some(results), Some(key)) = (&output, results, key) {
+                            results.put(key, output)?;
+                        }
+                        (success, output)
+                    };
+                    let baselined = !success && output.as_ref().is_some_and(baselined);
+                    let (success, output) = if baselined {
+                        debug!("{}: only diagnostics from the baseline", cmd_str);
+                        (true, None)
+                    } else {
+                        if let (true, Some(output), None) =
+                            (report, &output, &cmd.tool.dedupe_group)
+                        {
+                            print_failure(&cmd_str, output, None, prefix.color)?;
+                        }
+                        (success, output)
+                    };
+
+                    // Removes it, as `cmd` outlives the run
+                    cmd.argfile = None;
+                    if !success {
+                        failures.fetch_add(1, Ordering::Relaxed);
+                    }
+                    debug!(
+                        "{}: {}",
+                        cmd_str,
+                        if success { "success" } else { "failed" },
+                    );
+                    tx.send(ReporterEvent::Done {
+                        cmd: cmd_str.clone(),
+                    })
+                    .ok();
+                    let outcome = Outcome {
+                        tool: cmd.tool.display_name().to_string(),
+                        cmd: cmd_str,
+                        files: cmd.files.iter().map(|f| f.path.clone()).collect(),
+                        cd: cmd.tool.cd.clone(),
+                        modified: if cmd.tool.formats && !replayed {
+                            modified(&cmd)
+                        } else {
+                            Vec::new()
+                        },
+                        success,
+                        output,
+                        replayed,
+                        sandbox_violation: violation,
+                        duration: start.elapsed(),
+                    };
+                    // Record entries right away, so that they survive if Lūn
+                    // is killed
+                    // Commands that passed thanks to the baseline run again
+                    // next time, in case it changes
+                    let success_cached = success && !baselined;
+                    if let (true, Some(journal)) = (success_cached, &journal) {
+                        let hashes = done(&cmd, mtime_enabled)?;
+                        journal.append(&hashes);
+                        return Ok(Some((outcome, Some(Succeeded::Journaled(hashes)))));
+                    }
+                    Ok(Some((
+                        outcome,
+                        success_cached.then_some(Succeeded::Pending(cmd)),
+                    )))
                 })
-                .ok();
-                let success = run(c, &cmd_str, no_capture)?.success();
+                .collect::<Result<Vec<_>>>()?;
 
-                if !success {
-                    failed.store(true, Ordering::Relaxed);
+            let mut outcomes = Vec::with_capacity(results.len());
+            let mut succeeded = Vec::with_capacity(results.len());
+            let mut journaled = Vec::new();
+            for (outcome, succeeded_cmd) in results.into_iter().flatten() {
+                outcomes.push(outcome);
+                match succeeded_cmd {
+                    Some(Succeeded::Pending(cmd)) => succeeded.push(cmd),
+                    Some(Succeeded::Journaled(hashes)) => journaled.push(hashes),
+                    None => (),
                 }
-                debug!(
-                    "{}: {}",
-                    cmd_str,
-                    if success { "success" } else { "failed" },
-                );
-                tx.send(ReporterEvent::Done { cmd: cmd_str }).ok();
-                let hashes = if success {
-                    done(cmd, mtime_enabled)?
-                } else {
-                    Vec::new()
-                };
-                Ok((success, hashes))
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        let mut ok = true;
-        let mut all_hashes = Vec::with_capacity(results.len());
-        for (b, hashes) in results {
-            ok &= b;
-            all_hashes.extend(hashes.into_iter());
-        }
-        Ok((ok, all_hashes))
-    })?;
+            }
+            // Only after all commands are done, so that no tool's entry is
+            // stored for a file that a formatter changed in the meantime
+            let all_hashes = succeeded
+                .into_par_iter()
+                .map(|cmd| done(&cmd, mtime_enabled))
+                .chain(journaled.into_par_iter().map(Ok))
+                .collect::<Result<Vec<_>>>()?
+                .concat();
+            Ok((outcomes, all_hashes))
+        })?;
 
     // Close the channel to signal the reporter thread to finish
     drop(tx);
@@ -108,7 +314,20 @@ pub(crate) fn exec(
         cache_writer.done_hash(hash);
     }
 
-    Ok(ok)
+    if report {
+        let seen = diag::Seen::default();
+        let mut deduped = outcomes
+            .iter()
+            .filter_map(|o| Some((o, dedupe_groups.get(&o.tool)?, o.output.as_ref()?)))
+            .collect::<Vec<_>>();
+        deduped.sort_by(|(a, _, _), (b, _, _)| (&a.tool, &a.cmd).cmp(&(&b.tool, &b.cmd)));
+        for (outcome, group, output) in deduped {
+            let dedupe = Some((&seen, group.as_str(), outcome.tool.as_str()));
+            print_failure(&outcome.cmd, output, dedupe, prefix.color)?;
+        }
+    }
+
+    Ok(outcomes)
 }
 
 fn reporter(
@@ -153,7 +372,7 @@ fn reporter(
     }
 }
 
-fn report(format: ProgressFormat, completed: usize, total: usize, cmd: &str) {
+pub(crate) fn report(format: ProgressFormat, completed: usize, total: usize, cmd: &str) {
     if cmd.is_empty() {
         match format {
             ProgressFormat::No => (),
@@ -161,41 +380,88 @@ fn report(format: ProgressFormat, completed: usize, total: usize, cmd: &str) {
             ProgressFormat::Newline => eprintln!("\x1b[2K\r[{completed}/{total}]"),
         }
     } else {
-        let shorter = &cmd[0..cmp::min(60, cmd.len())];
+        let line = || progress_line(completed, total, cmd, width::term_width());
         match format {
             ProgressFormat::No => (),
-            ProgressFormat::Yes => eprint!("\x1b[2K\r[{completed}/{total}] {shorter}"),
-            ProgressFormat::Newline => eprintln!("\x1b[2K\r[{completed}/{total}] {shorter}"),
+            ProgressFormat::Yes => eprint!("\x1b[2K\r{}", line()),
+            ProgressFormat::Newline => eprintln!("\x1b[2K\r{}", line()),
         };
     }
     drop(io::stderr().flush());
 }
 
+/// Truncated to fit on one line, as `\r` only returns to the start of the
+/// last line of wrapped text. The last column is left empty, as some
+/// terminals wrap when it's written.
+fn progress_line(completed: usize, total: usize, cmd: &str, term_width: usize) -> String {
+    let counts = format!("[{completed}/{total}] ");
+    let available = term_width.saturating_sub(counts.len() + 1);
+    format!("{counts}{}", width::truncate(cmd, available))
+}
+
+/// Ask tools for color output, or not. Captured output is printed after the
+/// command finishes, so terminal detection in the tool doesn't help.
+fn color_env(c: &mut process::Command, color: bool) {
+    if color {
+        // https://docs.astral.sh/ruff/faq/#how-can-i-disableforce-ruffs-color-output
+        c.env("FORCE_COLOR", "1");
+        // https://bixense.com/clicolors/
+        c.env("CLICOLOR_FORCE", "1");
+        c.env_remove("NO_COLOR");
+    } else {
+        // https://no-color.org/
+        c.env("NO_COLOR", "1");
+        c.env_remove("FORCE_COLOR");
+        c.env_remove("CLICOLOR_FORCE");
+    }
+}
+
+/// Returns whether the command succeeded, and its output if it was captured
+/// and the command failed. Doesn't print the output, unless `no_capture`, in
+/// which case each line is prefixed with `prefix` (if any).
 fn run(
     mut c: process::Command,
     displayed_command: &str,
     no_capture: bool,
-) -> Result<process::ExitStatus> {
-    // https://docs.astral.sh/ruff/faq/#how-can-i-disableforce-ruffs-color-output
-    c.env("FORCE_COLOR", "1");
-    // https://bixense.com/clicolors/
-    c.env("CLICOLOR_FORCE", "1");
+    prefix: Option<&str>,
+    color: bool,
+    sandbox: bool,
+    stdin: Option<process::Stdio>,
+) -> Result<(bool, Option<results::Output>)> {
+    color_env(&mut c, color);
     // Avoid running on very short-lived files (e.g., editor backups)
     #[allow(clippy::unwrap_used)]
-    if c.get_args().len() == 1 && !Path::new(c.get_args().next().unwrap()).exists() {
-        return Ok(process::ExitStatus::from_raw(0));
+    if stdin.is_none()
+        && c.get_args().len() == 1
+        && !Path::new(c.get_args().next().unwrap()).exists()
+    {
+        return Ok((true, None));
+    }
+    if sandbox {
+        c = sandbox::wrap(&c)?;
     }
+    match stdin {
+        Some(stdin) => c.stdin(stdin),
+        // Like `process::Command::output`
+        None if !no_capture => c.stdin(process::Stdio::null()),
+        None => &mut c,
+    };
     if no_capture {
-        let status = c
-            .status()
-            .with_context(|| format!("Failed to execute command: {displayed_command}"))?;
+        let status = match prefix {
+            Some(prefix) => {
+                c.stdout(process::Stdio::piped());
+                c.stderr(process::Stdio::piped());
+                interrupt::spawn(&mut c).and_then(|mut child| wait_prefixed(&mut child, prefix))
+            }
+            None => interrupt::status(&mut c),
+        }
+        .with_context(|| format!("Failed to execute command: {displayed_command}"))?;
         if !status.success() {
             error!("Command failed");
         }
-        Ok(status)
+        Ok((status.success(), None))
     } else {
-        let out = c
-            .output()
+        let out = interrupt::output(&mut c)
             .with_context(|| format!("Failed to execute command: {displayed_command}"))?;
         let success = out.status.success();
         if !out.stdout.is_empty() && success {
@@ -204,21 +470,286 @@ fn run(
         if !out.stderr.is_empty() && success {
             trace!("{}", String::from_utf8_lossy(&out.stderr));
         }
+        if success {
+            return Ok((true, None));
+        }
+        let output = results::Output {
+            code: out.status.code().unwrap_or(-1),
+            stdout: out.stdout,
+            stderr: out.stderr,
+        };
+        Ok((false, Some(output)))
+    }
+}
+
+/// Like [`run`], but passes the content of `path` on stdin and handles the
+/// output as specified by `stdin`.
+#[allow(clippy::too_many_arguments)]
+fn run_stdin(
+    mut c: process::Command,
+    displayed_command: &str,
+    path: &Path,
+    stdin: tool::Stdin,
+    no_capture: bool,
+    prefix: Option<&str>,
+    color: bool,
+    sandbox: bool,
+) -> Result<(bool, Option<results::Output>)> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        // Avoid running on very short-lived files (e.g., editor backups)
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((true, None)),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let stream = no_capture && stdin == tool::Stdin::Report;
+    // Output of other modes is compared to or replaces the file
+    color_env(&mut c, color && stdin == tool::Stdin::Report);
+    if sandbox {
+        c = sandbox::wrap(&c)?;
+    }
+    c.stdin(process::Stdio::piped());
+    if !stream || prefix.is_some() {
+        c.stdout(process::Stdio::piped());
+        c.stderr(process::Stdio::piped());
+    }
+    let mut child = interrupt::spawn(&mut c)
+        .with_context(|| format!("Failed to execute command: {displayed_command}"))?;
+    let out = thread::scope(|s| {
+        if let Some(mut input) = child.stdin.take() {
+            let content = &content;
+            s.spawn(move || {
+                // The tool may exit without reading all of its input
+                drop(input.write_all(content));
+            });
+        }
+        match (stream, prefix) {
+            (true, Some(prefix)) => {
+                wait_prefixed(&mut child, prefix).map(|status| process::Output {
+                    status,
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+            }
+            _ => interrupt::wait_with_output(&mut child),
+        }
+    });
+    let out = out.with_context(|| format!("Failed to execute command: {displayed_command}"))?;
+
+    let success = out.status.success();
+    if stream {
         if !success {
-            let mut stdout = io::stdout().lock();
-            let mut stderr = io::stderr().lock();
-            stdout.write_all(b"\n")?;
-            stdout.write_all(displayed_command.as_bytes())?;
-            stdout.write_all(b"\n")?;
-            stdout.write_all(out.stdout.as_slice())?;
-            stderr.write_all(b"\n")?;
-            stderr.write_all(out.stderr.as_slice())?;
+            error!("Command failed");
         }
-        Ok(out.status)
+        return Ok((success, None));
+    }
+    let failure = |stdout| results::Output {
+        code: out.status.code().unwrap_or(-1),
+        stdout,
+        stderr: out.stderr.clone(),
+    };
+    if !success {
+        return Ok((false, Some(failure(out.stdout.clone()))));
+    }
+    match stdin {
+        tool::Stdin::Report => {
+            if !out.stdout.is_empty() {
+                trace!("{}", String::from_utf8_lossy(&out.stdout));
+            }
+            Ok((true, None))
+        }
+        tool::Stdin::Write if out.stdout == content => Ok((true, None)),
+        tool::Stdin::Write if out.stdout.is_empty() => {
+            let msg = format!("{}: no output, not overwriting\n", path.display());
+            Ok((false, Some(failure(msg.into_bytes()))))
+        }
+        tool::Stdin::Write => {
+            atomic::write(path, &out.stdout, false)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok((true, None))
+        }
+        tool::Stdin::Check | tool::Stdin::Diff if out.stdout == content => Ok((true, None)),
+        tool::Stdin::Check => {
+            let msg = format!("{}: not formatted\n", path.display());
+            Ok((false, Some(failure(msg.into_bytes()))))
+        }
+        tool::Stdin::Diff => {
+            let diff = diff::unified(path, &content, &out.stdout);
+            Ok((false, Some(failure(diff.into_bytes()))))
+        }
+    }
+}
+
+/// Wait for `child`, copying its output to Lūn's a line at a time, prefixed
+/// with `prefix`, so that the output of concurrent commands isn't interleaved
+/// within lines
+fn wait_prefixed(child: &mut process::Child, prefix: &str) -> io::Result<process::ExitStatus> {
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    thread::scope(|s| {
+        if let Some(stdout) = stdout {
+            s.spawn(move || copy_lines(stdout, io::stdout(), prefix));
+        }
+        if let Some(stderr) = stderr {
+            s.spawn(move || copy_lines(stderr, io::stderr(), prefix));
+        }
+    });
+    interrupt::wait(child)
+}
+
+fn copy_lines(from: impl io::Read, mut to: impl io::Write, prefix: &str) {
+    for line in io::BufReader::new(from).split(b'\n') {
+        let Ok(line) = line else {
+            return;
+        };
+        let mut buf = Vec::with_capacity(prefix.len() + line.len() + 4);
+        buf.extend_from_slice(prefix.as_bytes());
+        buf.extend_from_slice(b" | ");
+        buf.extend_from_slice(&line);
+        buf.push(b'\n');
+        // A single write, which holds the lock on stdout or stderr
+        drop(to.write_all(&buf));
     }
 }
 
-fn done(cmd: cmd::Command, mtime_enabled: bool) -> Result<Vec<cache::KeyHash>> {
+/// Copies of files to format for `--diff`, removed on drop
+struct Copies(Vec<PathBuf>);
+
+impl Drop for Copies {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            drop(fs::remove_file(path));
+            interrupt::removed(path);
+        }
+    }
+}
+
+/// Runs a formatter on copies of the files, and fails with a diff if it
+/// changes any of them.
+fn run_diff(
+    cmd: &cmd::Command,
+    displayed_command: &str,
+    color: bool,
+) -> Result<(bool, Option<results::Output>)> {
+    let mut copies = Copies(Vec::with_capacity(cmd.files.len()));
+    let mut files = Vec::with_capacity(cmd.files.len());
+    for f in &cmd.files {
+        let copy = diff::copy_path(&f.path);
+        interrupt::remove_on_exit(copy.clone());
+        copies.0.push(copy.clone());
+        fs::copy(&f.path, &copy)
+            .with_context(|| format!("Failed to copy {} for diffing", f.path.display()))?;
+        files.push(file::File {
+            path: copy,
+            ..f.clone()
+        });
+    }
+    let mut copied = cmd::Command {
+        tool: cmd.tool.clone(),
+        files,
+        argfile: None,
+    };
+    copied.write_argfile()?;
+    let ran = copied.stdin().and_then(|stdin| {
+        run(
+            copied.to_command(),
+            displayed_command,
+            false,
+            None,
+            color,
+            false,
+            stdin,
+        )
+    });
+    drop(copied);
+    let (success, output) = ran?;
+    if !success {
+        return Ok((false, output));
+    }
+    let mut diffs = String::new();
+    for (f, copy) in cmd.files.iter().zip(&copies.0) {
+        let old =
+            fs::read(&f.path).with_context(|| format!("Failed to read {}", f.path.display()))?;
+        let new = fs::read(copy).with_context(|| format!("Failed to read {}", copy.display()))?;
+        diffs.push_str(&diff::unified(&f.path, &old, &new));
+    }
+    if diffs.is_empty() {
+        return Ok((true, None));
+    }
+    let output = results::Output {
+        code: 1,
+        stdout: diffs.into_bytes(),
+        stderr: Vec::new(),
+    };
+    Ok((false, Some(output)))
+}
+
+fn print_failure(
+    displayed_command: &str,
+    output: &results::Output,
+    dedupe: Option<(&diag::Seen, &str, &str)>,
+    color: bool,
+) -> Result<()> {
+    let (out, err) = if let Some((seen, group, tool)) = dedupe {
+        (
+            Cow::Owned(seen.dedupe(group, tool, &output.stdout)),
+            Cow::Owned(seen.dedupe(group, tool, &output.stderr)),
+        )
+    } else {
+        (
+            Cow::Borrowed(output.stdout.as_slice()),
+            Cow::Borrowed(output.stderr.as_slice()),
+        )
+    };
+    // Stored results, and tools that ignore `NO_COLOR`, may have color
+    let (out, err) = if color {
+        (out, err)
+    } else {
+        (strip_ansi(&out), strip_ansi(&err))
+    };
+    write_failure(
+        &mut io::stdout().lock(),
+        &mut io::stderr().lock(),
+        displayed_command,
+        &out,
+        &err,
+    )
+}
+
+fn strip_ansi(output: &[u8]) -> Cow<'_, [u8]> {
+    if output.contains(&0x1b) {
+        Cow::Owned(diag::strip_ansi(&String::from_utf8_lossy(output)).into_bytes())
+    } else {
+        Cow::Borrowed(output)
+    }
+}
+
+/// Write the output of a failed command as it is printed after it finishes
+pub(crate) fn write_failure(
+    stdout: &mut impl io::Write,
+    stderr: &mut impl io::Write,
+    displayed_command: &str,
+    out: &[u8],
+    err: &[u8],
+) -> Result<()> {
+    stdout.write_all(b"\n")?;
+    stdout.write_all(displayed_command.as_bytes())?;
+    stdout.write_all(b"\n")?;
+    stdout.write_all(out)?;
+    stderr.write_all(b"\n")?;
+    stderr.write_all(err)?;
+    Ok(())
+}
+
+/// Files that `cmd` changed, by re-`stat`ing them
+pub(crate) fn modified(cmd: &cmd::Command) -> Vec<PathBuf> {
+    cmd.files
+        .iter()
+        .filter(|f| f.modified())
+        .map(|f| f.path.clone())
+        .collect()
+}
+
+fn done(cmd: &cmd::Command, mtime_enabled: bool) -> Result<Vec<cache::KeyHash>> {
     let tool = cmd.tool.clone();
     let mut hashes = Vec::with_capacity(if mtime_enabled {
         cmd.files.len() * 2
@@ -227,6 +758,11 @@ fn done(cmd: cmd::Command, mtime_enabled: bool) -> Result<Vec<cache::KeyHash>> {
     });
     for file in &cmd.files {
         debug_assert!(file.content_stamp.is_some()); // should happen in plan.rs
+        if file.modified() {
+            // E.g., fixed. The old contents would need fixing again.
+            debug!("{}: modified, not caching", file.path.display());
+            continue;
+        }
         let content_key = cache::Key::from_content(file, &tool);
         hashes.push(cache::KeyHash::from(&content_key));
         if mtime_enabled {
@@ -236,3 +772,87 @@ fn done(cmd: cmd::Command, mtime_enabled: bool) -> Result<Vec<cache::KeyHash>> {
     }
     Ok(hashes)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn keep_going() {
+        let tool = Arc::new(tool::test_tool(
+            "cmd = \"false\"\ngranularity = \"individual\"",
+        ));
+        let batches = || {
+            ["Cargo.toml", "Cargo.lock", "src/main.rs"]
+                .into_iter()
+                .map(|p| cmd::Command {
+                    tool: tool.clone(),
+                    files: vec![file::File::new(PathBuf::from(p)).unwrap()],
+                    argfile: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = cache::HashCache::new(dir.path().to_path_buf(), 1000);
+        let one = NonZeroUsize::MIN;
+        let ran = |cache: &mut cache::HashCache, keep_going| {
+            exec(
+                cache,
+                batches(),
+                one,
+                false,
+                Prefix::default(),
+                ProgressFormat::No,
+                keep_going,
+                false,
+                None,
+                None,
+                false,
+            )
+            .unwrap()
+            .len()
+        };
+        assert_eq!(ran(&mut cache, 1), 1);
+        assert_eq!(ran(&mut cache, 2), 2);
+        assert_eq!(ran(&mut cache, 0), 3);
+    }
+
+    #[test]
+    fn prefixed_lines() {
+        let mut out = Vec::new();
+        copy_lines(&b"a.py:1: error\nno newline"[..], &mut out, "lint");
+        assert_eq!(out, b"lint | a.py:1: error\nlint | no newline\n");
+    }
+
+    #[test]
+    fn labels() {
+        let plain = Prefix::default();
+        assert_eq!(plain.label("ruff"), "ruff");
+        let color = Prefix {
+            color: true,
+            ..Prefix::default()
+        };
+        assert_eq!(color.label("ruff"), color.label("ruff"));
+        assert!(color.label("ruff").starts_with("\x1b[1;3"));
+        assert!(color.label("ruff").ends_with("ruff\x1b[0m"));
+    }
+
+    #[test]
+    fn progress_lines() {
+        assert_eq!(
+            progress_line(1, 2, "ruff check a.py", 80),
+            "[1/2] ruff check a.py"
+        );
+        assert_eq!(
+            progress_line(1, 2, "ruff check a.py", 17),
+            "[1/2] ruff check"
+        );
+        assert_eq!(
+            progress_line(1, 2, "ruff check 日本.py", 20),
+            "[1/2] ruff check 日"
+        );
+        assert_eq!(progress_line(10, 20, "ruff", 4), "[10/20] ");
+    }
+}
@@ -0,0 +1,55 @@
+//! Lūn's exit codes, so that scripts and CI can tell failures apart. Runs
+//! stopped by Ctrl-C exit with [`crate::interrupt::EXIT_CODE`].
+
+use std::fmt;
+
+use crate::warn;
+
+/// Some tool failed
+pub(crate) const TOOL_FAILURE: i32 = 1;
+/// The configuration file or command line is invalid. `clap` uses this code
+/// for invalid command lines too.
+pub(crate) const CONFIG_ERROR: i32 = 2;
+/// Any other error, e.g., failing to read the cache or to run a tool
+pub(crate) const INTERNAL_ERROR: i32 = 3;
+/// A warning fired at the `deny` level
+pub(crate) const DENIED_WARNING: i32 = 4;
+
+/// Context for errors in the configuration, see [`code`]
+#[derive(Debug)]
+pub(crate) struct ConfigError;
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid configuration")
+    }
+}
+
+/// The exit code for `err`
+pub(crate) fn code(err: &anyhow::Error) -> i32 {
+    if err.downcast_ref::<warn::Denied>().is_some() {
+        DENIED_WARNING
+    } else if err.downcast_ref::<ConfigError>().is_some() {
+        CONFIG_ERROR
+    } else {
+        INTERNAL_ERROR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let err = || anyhow::anyhow!("oops");
+        assert_eq!(code(&err()), INTERNAL_ERROR);
+        assert_eq!(code(&err().context(ConfigError)), CONFIG_ERROR);
+        assert_eq!(
+            code(&err().context(ConfigError).context("more")),
+            CONFIG_ERROR
+        );
+        let denied = anyhow::Error::new(warn::Denied("denied".to_string()));
+        assert_eq!(code(&denied.context(ConfigError)), DENIED_WARNING);
+    }
+}
@@ -1,12 +1,24 @@
 use anyhow::{Context, Result};
 use ignore::WalkBuilder;
+use std::cell::RefCell;
+use std::collections::{HashMap, hash_map};
+use std::ffi::OsStr;
 use std::fs;
-use std::io::Write;
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt as _;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tracing::debug;
 use xxhash_rust::xxh3::Xxh3;
 
-use crate::exec;
+use crate::{exec, pattern::Patterns};
+
+/// A file name that no real file has, to check whether patterns match
+/// everything in a directory
+const DIR_PROBE: &str = "\0";
+
+/// How many bytes [`File::is_binary`] reads
+const BINARY_SNIFF_LEN: usize = 8000;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct Xxhash(pub(crate) u128);
@@ -22,6 +34,8 @@ pub(crate) struct File {
     pub(crate) metadata_stamp: Stamp,
     pub(crate) mtime_stamp: Stamp,
     pub(crate) content_stamp: Option<Stamp>,
+    /// See [`File::is_binary`]
+    pub(crate) binary: OnceLock<bool>,
 }
 
 pub(crate) fn hash_md(path: &Path, metadata: &fs::Metadata, md: &mut Xxh3) {
@@ -79,6 +93,18 @@ impl File {
             metadata_stamp,
             mtime_stamp,
             content_stamp: None,
+            binary: OnceLock::new(),
+        })
+    }
+
+    /// Whether the file looks binary, i.e., has a NUL byte near the start
+    /// (like Git's heuristic). Unreadable files aren't binary.
+    pub(crate) fn is_binary(&self) -> bool {
+        *self.binary.get_or_init(|| {
+            let mut start = Vec::with_capacity(BINARY_SNIFF_LEN);
+            fs::File::open(&self.path)
+                .and_then(|f| f.take(BINARY_SNIFF_LEN as u64).read_to_end(&mut start))
+                .is_ok_and(|_| start.contains(&0))
         })
     }
 
@@ -87,9 +113,10 @@ impl File {
         if self.content_stamp.is_some() {
             return Ok(());
         }
-        let content = fs::read(&self.path)
+        let hash = fs::File::open(&self.path)
+            .and_then(|mut f| hash_reader(&mut f))
             .with_context(|| format!("Failed to read file: {}", self.path.display()))?;
-        self.content_stamp = Some(Stamp(compute_hash(&content)));
+        self.content_stamp = Some(Stamp(hash));
         Ok(())
     }
 
@@ -103,6 +130,13 @@ impl File {
         Stamp(Xxhash(hasher.digest128()))
     }
 
+    /// Whether the file changed on disk since it was collected
+    pub(crate) fn modified(&self) -> bool {
+        Self::new(self.path.clone())
+            .ok()
+            .is_none_or(|now| now.size != self.size || now.mtime_stamp != self.mtime_stamp)
+    }
+
     pub(crate) fn mtime_stamp(&self) -> Stamp {
         let mut hasher = Xxh3::new();
         hasher.update(&self.metadata_stamp.0.0.to_le_bytes());
@@ -111,6 +145,31 @@ impl File {
     }
 }
 
+/// Size of the buffer for [`hash_reader`]
+const HASH_BUF_LEN: usize = 64 * 1024;
+
+thread_local! {
+    /// Reused across files, so that hashing large files doesn't read them
+    /// into memory
+    static HASH_BUF: RefCell<Vec<u8>> = RefCell::new(vec![0; HASH_BUF_LEN]);
+}
+
+/// Hash the content of `reader` in chunks, same as [`compute_hash`]
+pub(crate) fn hash_reader(reader: &mut impl Read) -> io::Result<Xxhash> {
+    HASH_BUF.with_borrow_mut(|buf| {
+        let mut hasher = Xxh3::new();
+        loop {
+            match reader.read(buf) {
+                Ok(0) => break,
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Xxhash(hasher.digest128()))
+    })
+}
+
 pub(crate) fn compute_hash(content: &[u8]) -> Xxhash {
     let mut hasher = Xxh3::new();
     hasher.update(content);
@@ -121,49 +180,302 @@ pub(crate) fn collect_files(
     root: &Path,
     cache_dir: &Path,
     progress_format: exec::ProgressFormat,
+    follow_symlinks: bool,
+    skip: Option<&Patterns>,
+    transient: &Patterns,
 ) -> Result<Vec<File>> {
     match progress_format {
         exec::ProgressFormat::No => (),
         exec::ProgressFormat::Yes => eprint!("\x1b[2K\r[0/?] Collecting files"),
         exec::ProgressFormat::Newline => eprintln!("\x1b[2K\r[0/?] Collecting files"),
     }
-    drop(std::io::stderr().flush());
+    drop(io::stderr().flush());
     let mut files = Vec::new();
+    for path in walk(root, cache_dir, follow_symlinks, skip)? {
+        if transient.is_match(&path) {
+            debug!("Skipping transient file {}", path.display());
+            continue;
+        }
+        // This can fail due to TOCTTOU bugs between content/metadata
+        if let Ok(file) = File::new(path.clone()) {
+            files.push(file);
+        } else {
+            debug!("Failed to process {}", path.display());
+        }
+    }
+
+    // prevent very short-lived files (e.g., editor backups) from sneaking in
+    files.retain(|f| f.path.exists());
+    Ok(files)
+}
+
+/// Paths of the files under `root`, relative to it
+///
+/// Files reachable through several symlinks are only listed once, preferring
+/// the path without symlinks. Hardlinks are separate files. Paths that match
+/// `skip` aren't walked, nor are directories whose contents all match it.
+fn walk(
+    root: &Path,
+    cache_dir: &Path,
+    follow_symlinks: bool,
+    skip: Option<&Patterns>,
+) -> Result<Vec<PathBuf>> {
     let cache = fs::canonicalize(cache_dir).with_context(|| {
         format!(
             "Failed to canonicalize cache directory: {}",
             cache_dir.display()
         )
     })?;
+    let canonical_root = fs::canonicalize(root)
+        .with_context(|| format!("Failed to canonicalize {}", root.display()))?;
 
+    let skip = skip.cloned();
+    let walk_root = root.to_path_buf();
     let walker = WalkBuilder::new(root)
         .hidden(false)
+        .follow_links(follow_symlinks)
         .filter_entry(move |e| {
             let path = e.path();
+            let skipped = e.depth() > 0
+                && skip.as_ref().is_some_and(|skip| {
+                    let relative = path.strip_prefix(&walk_root).unwrap_or(path);
+                    // Like `.gitignore`, nothing under a skipped directory
+                    // can be re-included
+                    skip.is_match(relative)
+                        || (e.file_type().is_some_and(|t| t.is_dir())
+                            && skip.is_match(&relative.join(DIR_PROBE)))
+                });
 
-            path.extension().is_none_or(|e| e != "bck")
+            !skipped
+                && path.extension().is_none_or(|e| e != "bck")
                 && !path.starts_with("./.git")
                 && !path.starts_with(".git")
                 && fs::canonicalize(path).is_ok_and(|p| !p.starts_with(&cache))
         })
         .build();
+    let mut paths = Vec::new();
+    // Index into `paths` of each canonical path
+    let mut seen = HashMap::new();
     for result in walker {
-        let entry = result.with_context(|| "Failed to read directory entry")?;
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(e) if is_loop(&e) => {
+                debug!("Skipping symlink loop: {e}");
+                continue;
+            }
+            Err(e) => return Err(e).context("Failed to read directory entry"),
+        };
         let path = entry.path();
         if path.is_dir() {
             continue;
         }
 
         debug!("Found {}", path.display());
-        // This can fail due to TOCTTOU bugs between content/metadata
-        if let Ok(file) = File::new(path.strip_prefix(root)?.to_path_buf()) {
-            files.push(file);
-        } else {
-            debug!("Failed to process {}", path.display());
+        let Ok(canonical) = fs::canonicalize(path) else {
+            debug!("Failed to canonicalize {}", path.display());
+            continue;
+        };
+        let relative = path.strip_prefix(root)?.to_path_buf();
+        match seen.entry(canonical) {
+            hash_map::Entry::Vacant(v) => {
+                v.insert(paths.len());
+                paths.push(relative);
+            }
+            hash_map::Entry::Occupied(o) => {
+                debug!("Skipping duplicate {}", path.display());
+                if canonical_root.join(&relative) == *o.key() {
+                    paths[*o.get()] = relative;
+                }
+            }
         }
     }
+    Ok(paths)
+}
 
-    // prevent very short-lived files (e.g., editor backups) from sneaking in
-    files.retain(|f| f.path.exists());
-    Ok(files)
+fn is_loop(err: &ignore::Error) -> bool {
+    match err {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_loop(err),
+        _ => false,
+    }
+}
+
+/// Split a list of paths separated by newlines or NUL bytes.
+///
+/// Empty entries are skipped, as is a leading `./`.
+pub(crate) fn split_paths(bytes: &[u8], null_separated: bool) -> impl Iterator<Item = PathBuf> {
+    let sep = if null_separated { b'\0' } else { b'\n' };
+    bytes
+        .split(move |b| *b == sep)
+        .map(move |p| {
+            if null_separated {
+                p
+            } else {
+                p.strip_suffix(b"\r").unwrap_or(p)
+            }
+        })
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let path = Path::new(OsStr::from_bytes(p));
+            path.strip_prefix(".").unwrap_or(path).to_path_buf()
+        })
+}
+
+/// Read a list of files from `source` (or stdin, if `source` is `-`).
+pub(crate) fn collect_listed_files(source: &Path, null_separated: bool) -> Result<Vec<File>> {
+    let bytes = if source == Path::new("-") {
+        let mut buf = Vec::new();
+        io::stdin()
+            .read_to_end(&mut buf)
+            .context("Failed to read file list from stdin")?;
+        buf
+    } else {
+        fs::read(source)
+            .with_context(|| format!("Failed to read file list: {}", source.display()))?
+    };
+    split_paths(&bytes, null_separated)
+        .map(|path| {
+            debug!("Found listed file {}", path.display());
+            File::new(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+impl File {
+    /// A file for tests, which needn't exist
+    pub(crate) fn test(path: &str, size: usize) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            size,
+            metadata_stamp: Stamp(Xxhash(0)),
+            mtime_stamp: Stamp(Xxhash(0)),
+            content_stamp: None,
+            binary: false.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(bytes: &[u8], null_separated: bool) -> Vec<PathBuf> {
+        split_paths(bytes, null_separated).collect()
+    }
+
+    #[test]
+    fn split_newlines() {
+        assert_eq!(
+            split(b"a.rs\n./b c.rs\r\n\n", false),
+            vec![PathBuf::from("a.rs"), PathBuf::from("b c.rs")],
+        );
+    }
+
+    #[test]
+    #[allow(clippy::unwrap_used)]
+    fn modified() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f.txt");
+        fs::write(&path, "a").unwrap();
+        let file = File::new(path.clone()).unwrap();
+        assert!(!file.modified());
+        fs::write(&path, "ab").unwrap();
+        assert!(file.modified());
+        fs::remove_file(&path).unwrap();
+        assert!(file.modified());
+    }
+
+    #[test]
+    fn binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("a.txt");
+        fs::write(&text, "text\n").unwrap();
+        assert!(!File::new(text).unwrap().is_binary());
+        let binary = dir.path().join("a.bin");
+        fs::write(&binary, b"\x7fELF\x02\x01\x01\0").unwrap();
+        assert!(File::new(binary).unwrap().is_binary());
+    }
+
+    #[test]
+    fn hash_chunks() {
+        for len in [0, 1, HASH_BUF_LEN, HASH_BUF_LEN + 1, 3 * HASH_BUF_LEN - 1] {
+            let content = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            assert_eq!(
+                hash_reader(&mut content.as_slice()).unwrap(),
+                compute_hash(&content),
+            );
+        }
+    }
+
+    #[test]
+    fn split_nul() {
+        assert_eq!(
+            split(b"a\nb.rs\0./c.rs\0", true),
+            vec![PathBuf::from("a\nb.rs"), PathBuf::from("c.rs")],
+        );
+    }
+
+    fn walked(root: &Path, follow_symlinks: bool) -> Vec<PathBuf> {
+        let cache = tempfile::tempdir().unwrap();
+        let mut paths = walk(root, cache.path(), follow_symlinks, None).unwrap();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/a.rs"), "").unwrap();
+        symlink("src", root.join("link")).unwrap();
+        symlink("src/a.rs", root.join("b.rs")).unwrap();
+        // A loop back to the root
+        symlink("..", root.join("src/up")).unwrap();
+        fs::write(root.join("elsewhere.rs"), "").unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("c.rs"), "").unwrap();
+        symlink(outside.path(), root.join("outside")).unwrap();
+
+        assert_eq!(
+            walked(root, false),
+            ["elsewhere.rs", "src/a.rs"].map(PathBuf::from),
+        );
+        assert_eq!(
+            walked(root, true),
+            ["elsewhere.rs", "outside/c.rs", "src/a.rs"].map(PathBuf::from),
+        );
+    }
+
+    #[test]
+    fn walk_skip() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in [
+            "a.rs",
+            "node_modules/x/y.js",
+            "src/b.rs",
+            "src/gen/c.rs",
+            "d.log",
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let skip = Patterns::new(
+            &["node_modules/", "/src/gen/", "*.log"].map(String::from),
+            "ignore",
+            "walk",
+        )
+        .unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        let mut paths = walk(root, cache.path(), false, Some(&skip)).unwrap();
+        paths.sort();
+        assert_eq!(paths, ["a.rs", "src/b.rs"].map(PathBuf::from));
+    }
 }
@@ -1,46 +1,595 @@
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
 
 use anyhow::{Context, Result};
-use tracing::debug;
+use tracing::{debug, warn};
 
-fn file_content_in_branch(path: &Path, branch: &str) -> Result<Option<Vec<u8>>> {
-    let output = std::process::Command::new("git")
-        .args(["show", &format!("{branch}:{}", path.display())])
+use crate::file;
+
+/// The version control system of the repository
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Vcs {
+    #[default]
+    Git,
+    /// Jujutsu, including repositories colocated with git
+    Jj,
+    /// Not in a repository
+    None,
+}
+
+impl Vcs {
+    /// The system of the closest repository containing the current directory
+    pub(crate) fn detect() -> Self {
+        let Ok(cwd) = env::current_dir() else {
+            return Vcs::Git;
+        };
+        for dir in cwd.ancestors() {
+            if dir.join(".jj").is_dir() {
+                return Vcs::Jj;
+            }
+            // A file in worktrees and submodules
+            if dir.join(".git").exists() {
+                return Vcs::Git;
+            }
+        }
+        Vcs::None
+    }
+}
+
+/// Results of `git` (or `jj`) queries, cached for the duration of a single run.
+///
+/// This avoids reading the repository repeatedly for the same query, and
+/// keeps the answers consistent even if the repository changes during the run.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GitContext {
+    vcs: Vcs,
+    /// The directory of the repository, the current one if empty
+    dir: PathBuf,
+    /// The git repository (opened lazily)
+    repo: Option<gix::Repository>,
+    refs: Vec<String>,
+    /// Commits that `refs` resolve to, and their files (lazily computed)
+    snapshots: Option<Vec<(String, Snapshot)>>,
+    /// Staged files (lazily computed)
+    staged: Option<Vec<PathBuf>>,
+    /// Modified, staged, and untracked files (lazily computed)
+    dirty: Option<Vec<PathBuf>>,
+    /// Whether each file differs from all of the `refs`
+    changed: HashMap<PathBuf, bool>,
+}
+
+/// The files of a commit, read once per run rather than once per file
+#[derive(Clone, Debug)]
+enum Snapshot {
+    /// Ids of the blobs in the commit's tree, by path
+    Blobs {
+        kind: gix::hash::Kind,
+        blobs: HashMap<PathBuf, gix::ObjectId>,
+    },
+    /// Files that differ between the commit and the working copy
+    Changed(HashSet<PathBuf>),
+}
+
+fn jj(dir: &Path, args: &[&str]) -> Result<process::Output> {
+    process::Command::new("jj")
+        .args(args)
+        .current_dir(dir)
         .output()
-        .with_context(|| format!("Failed to execute git show {branch}:{}", path.display()))?;
+        .with_context(|| format!("Failed to execute jj {}", args.join(" ")))
+}
+
+fn open(dir: &Path) -> Result<gix::Repository> {
+    gix::discover(dir).context("Failed to open git repository")
+}
+
+/// Files changed in the working-copy commit of jj
+fn changed_in_working_copy(dir: &Path) -> Result<Vec<PathBuf>> {
+    // Deleted files are skipped in `staged.rs`
+    let output = jj(dir, &["diff", "--name-only", "--revisions", "@"])?;
     if !output.status.success() {
-        return Ok(None);
+        anyhow::bail!(
+            "jj diff failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
     }
-    Ok(Some(output.stdout))
+    Ok(file::split_paths(&output.stdout, false).collect())
+}
+
+/// Files that differ between `HEAD`, the index, and the working tree, and
+/// untracked files, like `git status --untracked-files=all`
+fn dirty_in_worktree(repo: &gix::Repository) -> Result<Vec<PathBuf>> {
+    let mut files = repo
+        .status(gix::progress::Discard)?
+        .untracked_files(gix::status::UntrackedFiles::Files)
+        .index_worktree_rewrites(None)
+        .tree_index_track_renames(gix::status::tree_index::TrackRenames::Disabled)
+        .into_iter(Vec::new())?
+        .map(|item| Ok(gix::path::from_bstr(item?.location()).into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    // A file may differ from both `HEAD` and the index, and the comparisons
+    // run in parallel
+    files.sort();
+    files.dedup();
+    Ok(files)
 }
 
-pub(crate) fn file_changed_from_refs(path: &Path, refs: &[String]) -> Result<bool> {
-    if !path.exists() {
-        return Ok(true);
+/// The id that git would give to the contents of `path`
+fn blob_id(path: &Path, kind: gix::hash::Kind) -> Result<gix::ObjectId> {
+    let content =
+        fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok(gix::objs::compute_hash(
+        kind,
+        gix::objs::Kind::Blob,
+        &content,
+    )?)
+}
+
+/// Files that differ between the index and `HEAD`, like `git diff --cached`
+fn staged_in_index(repo: &gix::Repository) -> Result<Vec<PathBuf>> {
+    let head = repo
+        .find_tree(repo.head_tree_id_or_empty()?)?
+        .traverse()
+        .breadthfirst
+        .files()?
+        .into_iter()
+        .map(|e| (e.filepath, (e.oid, e.mode)))
+        .collect::<HashMap<_, _>>();
+    let index = repo.index_or_empty()?;
+    let mut files = Vec::new();
+    for entry in index.entries() {
+        // Conflicts aren't staged yet
+        if entry.stage_raw() != 0 || entry.mode.is_sparse() {
+            continue;
+        }
+        let path = entry.path(&index);
+        let unchanged = head.get(path).is_some_and(|(oid, mode)| {
+            *oid == entry.id && entry.mode.to_tree_entry_mode() == Some(*mode)
+        });
+        if !unchanged {
+            files.push(gix::path::from_bstr(path).into_owned());
+        }
     }
+    Ok(files)
+}
 
-    let current_content =
-        std::fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+impl GitContext {
+    pub(crate) fn new(refs: Vec<String>) -> Self {
+        Self {
+            vcs: Vcs::detect(),
+            refs,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn refs(&self) -> &[String] {
+        &self.refs
+    }
+
+    fn dir(&self) -> &Path {
+        if self.dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.dir
+        }
+    }
+
+    fn repo(&mut self) -> Result<&gix::Repository> {
+        let repo = match self.repo.take() {
+            Some(repo) => repo,
+            None => open(self.dir())?,
+        };
+        Ok(self.repo.insert(repo))
+    }
+
+    /// The commit that `ref` names, if any
+    fn resolve(&mut self, r#ref: &str) -> Result<Option<String>> {
+        let out = match self.vcs {
+            Vcs::Git => {
+                let spec = format!("{ref}^{{commit}}");
+                return Ok(self
+                    .repo()?
+                    .rev_parse_single(spec.as_str())
+                    .ok()
+                    .map(|id| id.to_string()));
+            }
+            // Refs are revsets, e.g., bookmarks
+            Vcs::Jj => jj(
+                self.dir(),
+                &[
+                    "log",
+                    "--ignore-working-copy",
+                    "--no-graph",
+                    "--limit",
+                    "1",
+                    "--revisions",
+                    r#ref,
+                    "--template",
+                    "commit_id",
+                ],
+            )?,
+            Vcs::None => return Ok(None),
+        };
+        let commit = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        Ok(Some(commit).filter(|c| out.status.success() && !c.is_empty()))
+    }
 
-    for r#ref in refs {
-        match file_content_in_branch(path, r#ref) {
-            Ok(Some(branch_content)) => {
-                if branch_content == current_content {
-                    debug!("{}: matches ref {}, skipping", path.display(), r#ref);
-                    return Ok(false);
+    fn snapshots(&mut self) -> &[(String, Snapshot)] {
+        if self.snapshots.is_none() {
+            let snapshots = self.load_snapshots();
+            self.snapshots = Some(snapshots);
+        }
+        self.snapshots.as_deref().unwrap_or_default()
+    }
+
+    fn load_snapshots(&mut self) -> Vec<(String, Snapshot)> {
+        if self.vcs == Vcs::None {
+            warn!("Not in a git or jj repository, ignoring refs");
+            return Vec::new();
+        }
+        let mut snapshots = Vec::with_capacity(self.refs.len());
+        for r#ref in self.refs.clone() {
+            let commit = match self.resolve(&r#ref) {
+                Ok(Some(commit)) => {
+                    debug!("Resolved ref {ref} to {commit}");
+                    commit
+                }
+                Ok(None) => {
+                    warn!("ref `{ref}` does not name a commit");
+                    continue;
+                }
+                Err(e) => {
+                    warn!("failed to resolve ref `{ref}` ({e})");
+                    continue;
                 }
+            };
+            match self.snapshot(&commit) {
+                Ok(snapshot) => snapshots.push((commit, snapshot)),
+                Err(e) => warn!("failed to read commit {commit} of ref `{ref}` ({e:#})"),
             }
-            Ok(None) => {
-                debug!("{}: doesn't exist in ref {}", path.display(), r#ref);
+        }
+        snapshots
+    }
+
+    /// Read the files of `commit` all at once
+    fn snapshot(&mut self, commit: &str) -> Result<Snapshot> {
+        match self.vcs {
+            Vcs::Git => {
+                let id = gix::ObjectId::from_hex(commit.as_bytes())?;
+                let repo = self.repo()?;
+                let blobs = repo
+                    .find_commit(id)?
+                    .tree()?
+                    .traverse()
+                    .breadthfirst
+                    .files()?
+                    .into_iter()
+                    .filter(|e| e.mode.is_blob())
+                    .map(|e| (gix::path::from_bstring(e.filepath), e.oid))
+                    .collect();
+                Ok(Snapshot::Blobs {
+                    kind: repo.object_hash(),
+                    blobs,
+                })
             }
-            Err(e) => {
-                debug!(
-                    "{}: failed to check file in ref {} ({e})",
-                    path.display(),
-                    r#ref,
-                );
+            Vcs::Jj => {
+                let output = jj(
+                    self.dir(),
+                    &["diff", "--name-only", "--from", commit, "--to", "@"],
+                )?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "jj diff failed: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+                Ok(Snapshot::Changed(
+                    file::split_paths(&output.stdout, false).collect(),
+                ))
             }
+            Vcs::None => anyhow::bail!("Not in a git or jj repository"),
+        }
+    }
+
+    /// Files staged in git, or changed in the working-copy commit of jj, which
+    /// has no index
+    pub(crate) fn staged_files(&mut self) -> Result<&[PathBuf]> {
+        if self.staged.is_none() {
+            let files = match self.vcs {
+                Vcs::Git => staged_in_index(self.repo()?)?,
+                Vcs::Jj => changed_in_working_copy(self.dir())?,
+                Vcs::None => anyhow::bail!("`--staged` needs a git or jj repository"),
+            };
+            for f in &files {
+                debug!("Found staged file {}", f.display());
+            }
+            self.staged = Some(files);
+        }
+        Ok(self.staged.as_deref().unwrap_or_default())
+    }
+
+    /// Files with uncommitted changes (including untracked files) in git, or
+    /// changed in the working-copy commit of jj
+    pub(crate) fn dirty_files(&mut self) -> Result<&[PathBuf]> {
+        if self.dirty.is_none() {
+            let files = match self.vcs {
+                Vcs::Git => dirty_in_worktree(self.repo()?)?,
+                Vcs::Jj => changed_in_working_copy(self.dir())?,
+                Vcs::None => anyhow::bail!("`--only-dirty` needs a git or jj repository"),
+            };
+            for f in &files {
+                debug!("Found dirty file {}", f.display());
+            }
+            self.dirty = Some(files);
+        }
+        Ok(self.dirty.as_deref().unwrap_or_default())
+    }
+
+    pub(crate) fn file_changed_from_refs(&mut self, path: &Path) -> Result<bool> {
+        if self.refs.is_empty() {
+            return Ok(true);
+        }
+        if let Some(changed) = self.changed.get(path) {
+            return Ok(*changed);
+        }
+        let changed = self.file_changed_from_commits(path)?;
+        self.changed.insert(path.to_path_buf(), changed);
+        Ok(changed)
+    }
+
+    fn file_changed_from_commits(&mut self, path: &Path) -> Result<bool> {
+        let full_path = self.dir().join(path);
+        if !full_path.exists() {
+            return Ok(true);
+        }
+
+        // Computed lazily, as most files don't exist in some commit
+        let mut current = None;
+        for (commit, snapshot) in self.snapshots() {
+            let unchanged = match snapshot {
+                Snapshot::Blobs { kind, blobs } => match blobs.get(path) {
+                    Some(id) => {
+                        let current = match current {
+                            Some(current) => current,
+                            None => *current.insert(blob_id(&full_path, *kind)?),
+                        };
+                        current == *id
+                    }
+                    None => {
+                        debug!("{}: doesn't exist in commit {}", path.display(), commit);
+                        false
+                    }
+                },
+                Snapshot::Changed(changed) => !changed.contains(path),
+            };
+            if unchanged {
+                debug!("{}: matches commit {}, skipping", path.display(), commit);
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Directory of the repository's git hooks (respects `core.hooksPath`)
+pub(crate) fn hooks_dir() -> Result<PathBuf> {
+    let repo = open(Path::new("."))?;
+    if let Some(path) = repo.config_snapshot().trusted_path("core.hooksPath") {
+        let path = path.context("Invalid core.hooksPath")?;
+        // Relative to the working tree, where hooks run
+        return Ok(repo
+            .workdir()
+            .map_or_else(|| path.to_path_buf(), |w| w.join(&path)));
+    }
+    Ok(repo.common_dir().join("hooks"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_vcs() {
+        let mut git = GitContext {
+            vcs: Vcs::None,
+            refs: vec!["main".to_string()],
+            ..GitContext::default()
+        };
+        assert!(git.staged_files().is_err());
+        // Refs are ignored, so all files count as changed
+        assert!(git.file_changed_from_refs(Path::new("Cargo.toml")).unwrap());
+        assert!(git.dirty_files().is_err());
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = process::Command::new("git")
+            .args(["-c", "user.name=lun", "-c", "user.email=lun@example.com"])
+            .args([
+                "-c",
+                "commit.gpgsign=false",
+                "-c",
+                "init.defaultBranch=main",
+            ])
+            .args(args)
+            .current_dir(dir)
+            .stdout(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {}", args.join(" "));
+    }
+
+    /// A repository with `a.txt` and `b.txt` committed on `main`
+    fn repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "-q"]);
+        fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        git(dir.path(), &["add", "."]);
+        git(dir.path(), &["commit", "-q", "-m", "first"]);
+        dir
+    }
+
+    fn context(dir: &Path, refs: &[&str]) -> GitContext {
+        GitContext {
+            vcs: Vcs::Git,
+            dir: dir.to_path_buf(),
+            refs: refs.iter().map(|r| (*r).to_string()).collect(),
+            ..GitContext::default()
+        }
+    }
+
+    fn paths(files: &[PathBuf]) -> Vec<&str> {
+        let mut paths = files.iter().filter_map(|f| f.to_str()).collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths
+    }
+
+    #[test]
+    fn dirty() {
+        let dir = repo();
+        let path = dir.path();
+        fs::write(path.join("a.txt"), "changed\n").unwrap();
+        fs::remove_file(path.join("b.txt")).unwrap();
+        fs::create_dir(path.join("new")).unwrap();
+        fs::write(path.join("new/c.txt"), "c\n").unwrap();
+        fs::write(path.join("staged.txt"), "s\n").unwrap();
+        git(path, &["add", "staged.txt"]);
+        fs::write(path.join("d.txt"), "d\n").unwrap();
+        git(path, &["add", "d.txt"]);
+        fs::write(path.join(".gitignore"), "ignored.txt\n").unwrap();
+        fs::write(path.join("ignored.txt"), "").unwrap();
+        let mut git = context(path, &[]);
+        assert_eq!(
+            paths(git.dirty_files().unwrap()),
+            [
+                ".gitignore",
+                "a.txt",
+                "b.txt",
+                "d.txt",
+                "new/c.txt",
+                "staged.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn staged() {
+        let dir = repo();
+        let path = dir.path();
+        fs::write(path.join("a.txt"), "changed\n").unwrap();
+        fs::write(path.join("c.txt"), "c\n").unwrap();
+        git(path, &["add", "a.txt", "c.txt"]);
+        git(path, &["rm", "-q", "b.txt"]);
+        // Not staged
+        fs::write(path.join("d.txt"), "d\n").unwrap();
+        let mut git = context(path, &[]);
+        // Deleted files can't be linted
+        assert_eq!(paths(git.staged_files().unwrap()), ["a.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn staged_no_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "-q"]);
+        fs::write(dir.path().join("a.txt"), "a\n").unwrap();
+        git(dir.path(), &["add", "a.txt"]);
+        let mut git = context(dir.path(), &[]);
+        assert_eq!(paths(git.staged_files().unwrap()), ["a.txt"]);
+    }
+
+    #[test]
+    fn changed_from_refs() {
+        let dir = repo();
+        let path = dir.path();
+        let changed = |refs: &[&str], file: &str| {
+            context(path, refs)
+                .file_changed_from_refs(Path::new(file))
+                .unwrap()
+        };
+        // Equal to the blob in the ref
+        assert!(!changed(&["main"], "a.txt"));
+        fs::write(path.join("a.txt"), "changed\n").unwrap();
+        assert!(changed(&["main"], "a.txt"));
+        // Missing from the ref
+        fs::write(path.join("new.txt"), "new\n").unwrap();
+        assert!(changed(&["main"], "new.txt"));
+        // Missing from the working tree
+        fs::remove_file(path.join("b.txt")).unwrap();
+        assert!(changed(&["main"], "b.txt"));
+        // Refs that don't resolve are ignored
+        assert!(changed(&["nonexistent"], "a.txt"));
+        git(path, &["checkout", "-q", "--", "a.txt"]);
+        assert!(!changed(&["nonexistent", "main"], "a.txt"));
+        // Without refs, every file needs running
+        assert!(changed(&[], "a.txt"));
+    }
+
+    #[test]
+    fn changed_from_several_refs() {
+        let dir = repo();
+        let path = dir.path();
+        git(path, &["branch", "old"]);
+        fs::write(path.join("a.txt"), "new\n").unwrap();
+        fs::write(path.join("c.txt"), "c\n").unwrap();
+        git(path, &["add", "."]);
+        git(path, &["commit", "-q", "-m", "second"]);
+        fs::write(path.join("a.txt"), "a\n").unwrap();
+        let mut git = context(path, &["main", "old"]);
+        // Only matches `old`
+        assert!(!git.file_changed_from_refs(Path::new("a.txt")).unwrap());
+        // Only exists in `main`
+        assert!(!git.file_changed_from_refs(Path::new("c.txt")).unwrap());
+        fs::write(path.join("b.txt"), "changed\n").unwrap();
+        assert!(git.file_changed_from_refs(Path::new("b.txt")).unwrap());
+        // Each ref is read once
+        assert_eq!(git.snapshots().len(), 2);
+    }
+
+    #[test]
+    fn changed_memo() {
+        let dir = repo();
+        let path = dir.path();
+        let mut git = context(path, &["main"]);
+        assert!(!git.file_changed_from_refs(Path::new("a.txt")).unwrap());
+        // The answer is kept for the rest of the run
+        fs::write(path.join("a.txt"), "changed\n").unwrap();
+        assert!(!git.file_changed_from_refs(Path::new("a.txt")).unwrap());
+        assert_eq!(git.changed.len(), 1);
+        assert!(
+            context(path, &["main"])
+                .file_changed_from_refs(Path::new("a.txt"))
+                .unwrap()
+        );
+    }
+
+    /// The snapshots are made by hand, so that the tests don't need `jj`, and
+    /// the commands that make them aren't tested
+    #[test]
+    fn changed_from_jj_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["a.txt", "b.txt", "c.txt"] {
+            fs::write(dir.path().join(file), "").unwrap();
         }
+        let changed = |files: &[&str]| {
+            Snapshot::Changed(files.iter().map(PathBuf::from).collect::<HashSet<_>>())
+        };
+        let mut git = GitContext {
+            vcs: Vcs::Jj,
+            dir: dir.path().to_path_buf(),
+            refs: vec!["main".to_string(), "old".to_string()],
+            snapshots: Some(vec![
+                ("1".to_string(), changed(&["a.txt", "b.txt"])),
+                ("2".to_string(), changed(&["b.txt", "c.txt"])),
+            ]),
+            ..GitContext::default()
+        };
+        assert!(!git.file_changed_from_refs(Path::new("a.txt")).unwrap());
+        assert!(git.file_changed_from_refs(Path::new("b.txt")).unwrap());
+        assert!(!git.file_changed_from_refs(Path::new("c.txt")).unwrap());
+        // Deleted
+        assert!(git.file_changed_from_refs(Path::new("d.txt")).unwrap());
     }
-    Ok(true)
 }
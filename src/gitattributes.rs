@@ -0,0 +1,179 @@
+//! Files marked as generated in `.gitattributes`, with `linguist-generated` or
+//! `-diff`. Generated code usually shouldn't be linted or formatted, so such
+//! files aren't collected unless `skip_generated = false`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use tracing::debug;
+
+use crate::file;
+
+/// A line of a `.gitattributes` file
+#[derive(Debug)]
+struct Rule {
+    glob: GlobMatcher,
+    /// Whether the pattern has a `/`, else it matches file names
+    anchored: bool,
+    linguist_generated: Option<bool>,
+    diff: Option<bool>,
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse(contents: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in contents.lines() {
+        let mut words = line.split_whitespace();
+        let Some(pattern) = words.next().filter(|p| !p.starts_with('#')) else {
+            continue;
+        };
+        let mut rule = Rule {
+            glob: match GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+            {
+                Ok(glob) => glob.compile_matcher(),
+                Err(e) => {
+                    debug!("Invalid pattern in .gitattributes: {pattern}: {e}");
+                    continue;
+                }
+            },
+            anchored: pattern.contains('/'),
+            linguist_generated: None,
+            diff: None,
+        };
+        for attr in words {
+            match attr {
+                "linguist-generated" => rule.linguist_generated = Some(true),
+                "-linguist-generated" => rule.linguist_generated = Some(false),
+                "diff" => rule.diff = Some(true),
+                "-diff" => rule.diff = Some(false),
+                _ => {
+                    if let Some(value) = attr.strip_prefix("linguist-generated=") {
+                        rule.linguist_generated = parse_bool(value);
+                    } else if attr.starts_with("diff=") {
+                        rule.diff = Some(true);
+                    }
+                }
+            }
+        }
+        if rule.linguist_generated.is_some() || rule.diff.is_some() {
+            rules.push(rule);
+        }
+    }
+    rules
+}
+
+/// The rules of `.gitattributes` files, by directory
+#[derive(Debug, Default)]
+struct Attributes {
+    dirs: HashMap<PathBuf, Vec<Rule>>,
+    /// From `.git/info/attributes`, which take precedence
+    info: Vec<Rule>,
+}
+
+impl Attributes {
+    fn load(path: &Path) -> Vec<Rule> {
+        match fs::read_to_string(path) {
+            Ok(contents) => parse(&contents),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn new() -> Self {
+        Self {
+            dirs: HashMap::new(),
+            info: Self::load(Path::new(".git/info/attributes")),
+        }
+    }
+
+    fn generated(&mut self, path: &Path) -> bool {
+        let mut linguist_generated = None;
+        let mut diff = None;
+        let mut apply = |rules: &[Rule], relative: &Path| {
+            for rule in rules {
+                let matched = if rule.anchored {
+                    rule.glob.is_match(relative)
+                } else {
+                    relative
+                        .file_name()
+                        .is_some_and(|name| rule.glob.is_match(name))
+                };
+                if matched {
+                    linguist_generated = rule.linguist_generated.or(linguist_generated);
+                    diff = rule.diff.or(diff);
+                }
+            }
+        };
+        // Deeper files take precedence
+        let mut dirs = path.ancestors().skip(1).collect::<Vec<_>>();
+        dirs.reverse();
+        for dir in dirs {
+            let rules = self
+                .dirs
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| Self::load(&dir.join(".gitattributes")));
+            apply(rules, path.strip_prefix(dir).unwrap_or(path));
+        }
+        apply(&self.info, path);
+        linguist_generated.unwrap_or(diff == Some(false))
+    }
+}
+
+/// Drop the files marked as generated
+pub(crate) fn skip_generated(files: &mut Vec<file::File>) {
+    let mut attributes = Attributes::new();
+    files.retain(|f| {
+        let generated = attributes.generated(&f.path);
+        if generated {
+            debug!("Skipping generated file {}", f.path.display());
+        }
+        !generated
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated() {
+        let mut attributes = Attributes::default();
+        attributes.dirs.insert(
+            PathBuf::new(),
+            parse(
+                "# comment\n\
+                 *.pb.go linguist-generated\n\
+                 Cargo.lock -diff\n\
+                 /gen/*.rs linguist-generated=true\n\
+                 gen/keep.rs -linguist-generated\n\
+                 *.txt text\n",
+            ),
+        );
+        attributes
+            .dirs
+            .insert(PathBuf::from("sub"), parse("*.rs -diff\nlib.rs diff\n"));
+        let generated =
+            |attributes: &mut Attributes, path: &str| attributes.generated(Path::new(path));
+        assert!(generated(&mut attributes, "api/a.pb.go"));
+        assert!(generated(&mut attributes, "Cargo.lock"));
+        assert!(generated(&mut attributes, "gen/a.rs"));
+        assert!(!generated(&mut attributes, "gen/keep.rs"));
+        // `*` doesn't match `/`
+        assert!(!generated(&mut attributes, "gen/sub/a.rs"));
+        assert!(!generated(&mut attributes, "a.txt"));
+        assert!(generated(&mut attributes, "sub/main.rs"));
+        assert!(!generated(&mut attributes, "sub/lib.rs"));
+    }
+}
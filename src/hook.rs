@@ -0,0 +1,48 @@
+//! Installing git hooks, for `lun hook install`.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context as _, Result};
+use tracing::info;
+
+use crate::git;
+
+/// Script for a git hook
+fn script(hook: &str) -> String {
+    let args = if hook == "pre-commit" {
+        "run --check --staged"
+    } else {
+        "run --check"
+    };
+    format!("#!/bin/sh\nexec lun {args}\n")
+}
+
+pub(crate) fn install(hook: &str, force: bool) -> Result<()> {
+    let dir = git::hooks_dir()?;
+    let path = dir.join(hook);
+    if !force && path.exists() {
+        anyhow::bail!(
+            "Hook already exists: {} (pass --force to overwrite it)",
+            path.display()
+        );
+    }
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create hooks directory: {}", dir.display()))?;
+    fs::write(&path, script(hook))
+        .with_context(|| format!("Failed to write hook: {}", path.display()))?;
+    make_executable(&path)?;
+    info!("Installed {}", path.display());
+    Ok(())
+}
+
+fn make_executable(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to make hook executable: {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
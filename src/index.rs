@@ -0,0 +1,105 @@
+//! Index of file content hashes, so that files with unchanged metadata and
+//! modification times aren't re-hashed. See `doc/cache.md`.
+
+use crate::{
+    aged,
+    file::{self, Stamp, Xxhash},
+};
+
+#[derive(Debug)]
+pub(crate) struct IndexFormat;
+
+impl aged::Format for IndexFormat {
+    const NAME: &'static str = "index";
+    const MAGIC: &'static [u8; 4] = b"luni";
+    const VERSION: u16 = 1;
+    const MAX_AGE: u16 = 8;
+    /// Content stamps
+    type Value = u128;
+}
+
+/// Map from mtime stamps (see [`file::File::mtime_stamp`], which include the
+/// path and size) to content stamps
+pub(crate) type Index = aged::Store<IndexFormat>;
+
+impl Index {
+    /// Fill in the content stamp of `f` if its metadata and modification time
+    /// are unchanged since it was recorded
+    pub(crate) fn fill(&mut self, f: &mut file::File) {
+        if f.content_stamp.is_some() {
+            return;
+        }
+        if let Some(content) = self.touch(f.mtime_stamp().0.0) {
+            f.content_stamp = Some(Stamp(Xxhash(content)));
+        }
+    }
+
+    pub(crate) fn record(&mut self, f: &file::File) {
+        if let Some(content) = f.content_stamp {
+            self.insert(f.mtime_stamp().0.0, content.0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::aged::Format as _;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "a").unwrap();
+        let index_file = dir.path().join("index");
+
+        let mut index = Index::from_file(&index_file).unwrap();
+        let mut f = file::File::new(path.clone()).unwrap();
+        index.fill(&mut f);
+        assert!(f.content_stamp.is_none());
+        f.fill_content_stamp().unwrap();
+        index.record(&f);
+        index.flush().unwrap();
+
+        let mut index = Index::from_file(&index_file).unwrap();
+        let mut g = file::File::new(path.clone()).unwrap();
+        index.fill(&mut g);
+        assert_eq!(g.content_stamp, f.content_stamp);
+
+        // Different size, so a different mtime stamp
+        fs::write(&path, "ab").unwrap();
+        let mut h = file::File::new(path).unwrap();
+        index.fill(&mut h);
+        assert!(h.content_stamp.is_none());
+    }
+
+    #[test]
+    fn expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "a").unwrap();
+        let index_file = dir.path().join("index");
+        let mut f = file::File::new(path).unwrap();
+        f.fill_content_stamp().unwrap();
+
+        let mut index = Index::from_file(&index_file).unwrap();
+        index.record(&f);
+        for _ in 0..=IndexFormat::MAX_AGE {
+            index.flush().unwrap();
+            index = Index::from_file(&index_file).unwrap();
+        }
+        assert_eq!(index.len(), 0);
+    }
+
+    #[test]
+    fn corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_file = dir.path().join("index");
+        fs::write(&index_file, "garbage").unwrap();
+        let index = Index::from_file(&index_file).unwrap();
+        assert_eq!(index.len(), 0);
+        assert!(!index_file.exists());
+    }
+}
@@ -1,21 +1,31 @@
 use std::path::Path;
-use std::{collections::HashSet, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
 
 use anyhow::{Context as _, Result};
+use ignore::WalkBuilder;
+use tracing::info;
 
 use crate::cli::Init;
 use crate::config::{self, Config};
-use crate::known;
+use crate::{known, lefthook, pre_commit, treefmt};
 
-fn get_known_tools(names: &[String]) -> Result<(Vec<config::Linter>, Vec<config::Formatter>)> {
+fn get_known_tools(
+    names: &[String],
+    registry: &known::Registry,
+) -> Result<(Vec<config::Linter>, Vec<config::Formatter>)> {
     let mut linters = Vec::new();
     let mut formatters = Vec::new();
     for n in names {
-        if let Some(mut linter) = known::known_linter_by_name(n) {
+        if let Some(mut linter) = registry.linter_by_name(n) {
             linter.tool.configs.retain(|config| config.exists());
+            known::add_implicit_configs(&mut linter.tool);
             linters.push(linter);
-        } else if let Some(mut formatter) = known::known_formatter_by_name(n) {
+        } else if let Some(mut formatter) = registry.formatter_by_name(n) {
             formatter.tool.configs.retain(|config| config.exists());
+            known::add_implicit_configs(&mut formatter.tool);
             formatters.push(formatter);
         } else {
             anyhow::bail!("Unknown tool: {n}");
@@ -24,44 +34,167 @@ fn get_known_tools(names: &[String]) -> Result<(Vec<config::Linter>, Vec<config:
     Ok((linters, formatters))
 }
 
-fn collect_tools(linters: &[String]) -> Result<(Vec<config::Linter>, Vec<config::Formatter>)> {
+/// Minimum percentage of files in a language for suggesting its tools
+const MIN_SHARE: usize = 10;
+
+/// Number of files with each extension
+#[derive(Debug, Default)]
+pub(crate) struct Extensions {
+    counts: HashMap<String, usize>,
+    total: usize,
+}
+
+impl Extensions {
+    /// Count the files under `root`, respecting `.gitignore`
+    fn walk(root: &Path) -> Self {
+        let mut exts = Self::default();
+        let walker = WalkBuilder::new(root)
+            .hidden(false)
+            .filter_entry(|e| e.file_name() != ".git")
+            .build();
+        for entry in walker.flatten() {
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            exts.total += 1;
+            if let Some(ext) = entry.path().extension() {
+                *exts
+                    .counts
+                    .entry(ext.to_string_lossy().into_owned())
+                    .or_default() += 1;
+            }
+        }
+        exts
+    }
+
+    fn count(&self, language: &known::Language) -> usize {
+        language
+            .extensions
+            .iter()
+            .filter_map(|ext| self.counts.get(*ext))
+            .sum()
+    }
+}
+
+/// Languages that make up a significant share of the files, with their counts
+fn detect_languages(exts: &Extensions) -> Vec<(&'static known::Language, usize)> {
+    known::LANGUAGES
+        .iter()
+        .map(|language| (language, exts.count(language)))
+        .filter(|(_, n)| *n > 0 && n * 100 >= exts.total * MIN_SHARE)
+        .collect()
+}
+
+fn collect_tools(
+    linters: &[String],
+    exts: &Extensions,
+    registry: &known::Registry,
+) -> Result<(Vec<config::Linter>, Vec<config::Formatter>)> {
     if linters.is_empty() {
+        let suggested = detect_languages(exts)
+            .into_iter()
+            .flat_map(|(language, _)| language.tools)
+            .copied()
+            .collect::<HashSet<_>>();
+        let is_suggested =
+            |tool: &config::Tool| tool.name.as_deref().is_some_and(|n| suggested.contains(n));
         let mut detected_linters = Vec::new();
         let mut detected_formatters = Vec::new();
-        for mut linter in known::known_linters() {
+        for mut linter in registry.linters().iter().cloned() {
             linter.tool.configs.retain(|config| config.exists());
-            if !linter.tool.configs.is_empty() {
+            if !linter.tool.configs.is_empty() || is_suggested(&linter.tool) {
+                known::add_implicit_configs(&mut linter.tool);
                 detected_linters.push(linter);
             }
         }
-        for mut formatter in known::known_formatters() {
+        for mut formatter in registry.formatters().iter().cloned() {
             formatter.tool.configs.retain(|config| config.exists());
-            if !formatter.tool.configs.is_empty() {
+            if !formatter.tool.configs.is_empty() || is_suggested(&formatter.tool) {
+                known::add_implicit_configs(&mut formatter.tool);
                 detected_formatters.push(formatter);
             }
         }
         Ok((detected_linters, detected_formatters))
     } else {
-        get_known_tools(linters)
+        get_known_tools(linters, registry)
+    }
+}
+
+/// Tools from `--from-pre-commit`, `--from-lefthook`, and `--from-husky`
+fn translate_hooks(
+    init: &Init,
+    registry: &known::Registry,
+) -> Result<Option<pre_commit::Translated>> {
+    if let Some(path) = &init.from_pre_commit {
+        return pre_commit::load(path).map(Some);
+    }
+    if init.from_lefthook.is_none() && init.from_husky.is_none() {
+        return Ok(None);
+    }
+    let mut translated = pre_commit::Translated::default();
+    if let Some(path) = &init.from_lefthook {
+        translated.extend(lefthook::load(path, registry)?);
     }
+    if let Some(dir) = &init.from_husky {
+        translated.extend(lefthook::load_husky(dir, registry)?);
+    }
+    Ok(Some(translated))
 }
 
-pub(crate) fn gen_config(init: &Init) -> Result<Config, anyhow::Error> {
+pub(crate) fn gen_config(
+    init: &Init,
+    exts: &Extensions,
+    registry: &known::Registry,
+) -> Result<Config, anyhow::Error> {
     let mut names = HashSet::new();
     let mut tool_names = init.tool.clone();
-    tool_names.retain(|l| names.insert(l.clone()));
-    let (linter, formatter) = collect_tools(&tool_names)?;
+    let mut ignore = Vec::new();
+    let (linter, formatter) = if let Some(path) = &init.from_treefmt {
+        let imported = treefmt::load(path)?;
+        tool_names.retain(|l| names.insert(l.clone()));
+        let (linter, mut formatter) = get_known_tools(&tool_names, registry)?;
+        formatter.extend(imported.formatters);
+        ignore = imported.ignore;
+        (linter, formatter)
+    } else if let Some(translated) = translate_hooks(init, registry)? {
+        for hook in &translated.hooks {
+            info!("Run `lun hook install {hook}` to run lun in the {hook} hook");
+        }
+        tool_names.extend(translated.known);
+        tool_names.retain(|l| names.insert(l.clone()));
+        let (mut linter, formatter) = get_known_tools(&tool_names, registry)?;
+        linter.extend(translated.custom);
+        (linter, formatter)
+    } else {
+        tool_names.retain(|l| names.insert(l.clone()));
+        collect_tools(&tool_names, exts, registry)?
+    };
     let config = Config {
         linter,
         formatter,
         refs: init.r#ref.clone(),
-        careful: init.careful,
+        cache_failures: false,
+        cache_dir: None,
+        arg_max: None,
+        careful: init.careful.unwrap_or_default(),
+        config_hash: config::ConfigHash::default(),
         cores: init.cores,
+        follow_symlinks: false,
+        fsync: false,
         mtime: !init.no_mtime,
         ninja: None,
-        ignore: Vec::new(),
+        ignore,
+        known_tools: None,
+        max_file_size: None,
+        sandbox: false,
+        strict: false,
+        suppress: Vec::new(),
+        cache_max_age: None,
         cache_size: None,
         tool: Vec::new(),
+        skip_generated: true,
+        transient_patterns: config::default_transient_patterns(),
+        walk: config::WalkCfg::default(),
         warns: config::WarnCfg {
             allow: init.allow.clone(),
             warn: init.warn.clone(),
@@ -71,8 +204,48 @@ pub(crate) fn gen_config(init: &Init) -> Result<Config, anyhow::Error> {
     Ok(config)
 }
 
+/// Suggest creating config files for tools suggested by [`detect_languages`]
+fn suggest_configs(exts: &Extensions, registry: &known::Registry) {
+    let known = registry.tools_by_name();
+    for (language, n) in detect_languages(exts) {
+        let Some(config) = language.config else {
+            continue;
+        };
+        let configured = language
+            .tools
+            .iter()
+            .filter_map(|name| known.get(*name))
+            .any(|tool| tool.configs.iter().any(|c| c.exists()));
+        if !configured {
+            let files = if n == 1 { "file" } else { "files" };
+            info!(
+                "Found {n} {} {files}, consider creating {config} to configure {}",
+                language.name,
+                language.tools.join(" and ")
+            );
+        }
+    }
+}
+
 pub(crate) fn go(config_path: &Path, init: &Init) -> Result<()> {
-    let config = gen_config(init)?;
+    if !init.force && config_path.exists() {
+        anyhow::bail!(
+            "Config file already exists: {} (pass --force to overwrite it)",
+            config_path.display()
+        );
+    }
+    let translating = init.from_pre_commit.is_some()
+        || init.from_treefmt.is_some()
+        || init.from_lefthook.is_some()
+        || init.from_husky.is_some();
+    let exts = if init.tool.is_empty() && !translating {
+        Extensions::walk(Path::new("."))
+    } else {
+        Extensions::default()
+    };
+    let registry = known::Registry::load(None)?;
+    let config = gen_config(init, &exts, &registry)?;
+    suggest_configs(&exts, &registry);
     let toml = toml::to_string_pretty(&config).context("Failed to serialize config to TOML")?;
     let mut s = String::from("# https://langston-barrett.github.io/lun/config.html\n\n");
     s.push_str(&toml);
@@ -90,15 +263,21 @@ mod tests {
     fn init() {
         let init = Init {
             tool: vec!["cargo clippy".to_string(), "ruff check".to_string()],
-            careful: false,
+            careful: None,
             cores: None,
             no_mtime: false,
+            force: false,
+            from_pre_commit: None,
+            from_treefmt: None,
+            from_lefthook: None,
+            from_husky: None,
             r#ref: Vec::new(),
             allow: Vec::new(),
             warn: Vec::new(),
             deny: Vec::new(),
         };
-        let config = gen_config(&init).unwrap();
+        let config =
+            gen_config(&init, &Extensions::default(), &known::Registry::default()).unwrap();
         let toml = toml::to_string_pretty(&config).unwrap();
         expect![[r#"
             [[linter]]
@@ -107,30 +286,83 @@ mod tests {
             files = ["*.rs"]
             granularity = "batch"
             configs = ["Cargo.toml"]
+            problem_matcher = "clippy"
             fix = "cargo clippy --color={{color}} --allow-dirty --fix"
 
             [[linter]]
             name = "ruff check"
             cmd = "ruff check --"
             files = ["*.py"]
+            problem_matcher = "ruff"
             fix = "ruff check --fix --"
         "#]]
         .assert_eq(&toml);
     }
 
+    #[test]
+    fn init_languages() {
+        let init = Init {
+            tool: Vec::new(),
+            careful: None,
+            cores: None,
+            no_mtime: false,
+            force: false,
+            from_pre_commit: None,
+            from_treefmt: None,
+            from_lefthook: None,
+            from_husky: None,
+            r#ref: Vec::new(),
+            allow: Vec::new(),
+            warn: Vec::new(),
+            deny: Vec::new(),
+        };
+        let exts = Extensions {
+            counts: HashMap::from([("py".to_string(), 9), ("md".to_string(), 1)]),
+            total: 20,
+        };
+        let config = gen_config(&init, &exts, &known::Registry::default()).unwrap();
+        let names = config
+            .linter
+            .iter()
+            .map(|l| &l.tool)
+            .chain(config.formatter.iter().map(|f| &f.tool))
+            .filter_map(|t| t.name.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["cargo clippy", "ruff check", "cargo fmt", "ruff format"]
+        );
+    }
+
+    #[test]
+    fn languages_known() {
+        let known = known::Registry::default().tools_by_name();
+        for language in known::LANGUAGES {
+            for tool in language.tools {
+                assert!(known.contains_key(*tool), "{tool}");
+            }
+        }
+    }
+
     #[test]
     fn init_detect() {
         let init = Init {
             tool: Vec::new(),
-            careful: false,
+            careful: None,
             cores: None,
             no_mtime: false,
+            force: false,
+            from_pre_commit: None,
+            from_treefmt: None,
+            from_lefthook: None,
+            from_husky: None,
             r#ref: Vec::new(),
             allow: Vec::new(),
             warn: Vec::new(),
             deny: Vec::new(),
         };
-        let config = gen_config(&init).unwrap();
+        let config =
+            gen_config(&init, &Extensions::default(), &known::Registry::default()).unwrap();
         let toml = toml::to_string_pretty(&config).unwrap();
         expect![[r#"
             [[linter]]
@@ -139,6 +371,7 @@ mod tests {
             files = ["*.rs"]
             granularity = "batch"
             configs = ["Cargo.toml"]
+            problem_matcher = "clippy"
             fix = "cargo clippy --color={{color}} --allow-dirty --fix"
 
             [[formatter]]
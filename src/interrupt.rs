@@ -0,0 +1,221 @@
+//! Handling Ctrl-C: stop starting new commands, terminate the running ones,
+//! and keep the results of the ones that completed.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    os::unix::process::CommandExt as _,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use anyhow::{Context as _, Result};
+use rustix::process::{Pid, Signal, WaitId, WaitIdOptions};
+use tracing::debug;
+
+/// Exit code for runs stopped by Ctrl-C, like shells use for `SIGINT`
+pub(crate) const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Process IDs of running commands, which are also the IDs of their process
+/// groups, see [`spawn`]
+static CHILDREN: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Temporary files in the repository, see [`remove_on_exit`]
+static TEMP_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Terminal settings to restore, see [`restore_terminal_on_exit`]
+static TERMINAL: Mutex<Option<String>> = Mutex::new(None);
+
+pub(crate) fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        // A second Ctrl-C exits right away
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            remove_temp_files();
+            restore_terminal();
+            process::exit(EXIT_CODE);
+        }
+        terminate();
+    })
+    .context("Failed to install Ctrl-C handler")
+}
+
+pub(crate) fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Like [`process::Command::status`], but the command is terminated on Ctrl-C
+pub(crate) fn status(c: &mut process::Command) -> io::Result<process::ExitStatus> {
+    wait(&mut spawn(c)?)
+}
+
+/// Like [`process::Command::output`], but the command is terminated on Ctrl-C
+///
+/// Unlike [`process::Command::output`], stdin is inherited unless set.
+pub(crate) fn output(c: &mut process::Command) -> io::Result<process::Output> {
+    c.stdout(process::Stdio::piped());
+    c.stderr(process::Stdio::piped());
+    wait_with_output(&mut spawn(c)?)
+}
+
+/// Spawn a command that is terminated on Ctrl-C, in its own process group so
+/// that its subprocesses are too. Wait for it with [`wait`] or
+/// [`wait_with_output`].
+pub(crate) fn spawn(c: &mut process::Command) -> io::Result<process::Child> {
+    let child = c.process_group(0).spawn()?;
+    CHILDREN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(child.id());
+    Ok(child)
+}
+
+/// Like [`process::Child::wait`], for commands from [`spawn`]
+///
+/// The process ID is forgotten after the command exits but before it is
+/// reaped, so that [`terminate`] can't signal another process that reuses it.
+pub(crate) fn wait(child: &mut process::Child) -> io::Result<process::ExitStatus> {
+    if let Some(pid) = Pid::from_raw(child.id().cast_signed()) {
+        let options = WaitIdOptions::EXITED | WaitIdOptions::NOWAIT;
+        while let Err(rustix::io::Errno::INTR) = rustix::process::waitid(WaitId::Pid(pid), options)
+        {
+        }
+    }
+    CHILDREN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&child.id());
+    child.wait()
+}
+
+/// Like [`process::Child::wait_with_output`], for commands from [`spawn`]
+pub(crate) fn wait_with_output(child: &mut process::Child) -> io::Result<process::Output> {
+    fn read(pipe: Option<impl io::Read>) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    }
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let (stdout, stderr) = std::thread::scope(|s| {
+        let stderr = s.spawn(|| read(stderr));
+        let stdout = read(stdout);
+        (stdout, stderr.join().unwrap_or_else(|_| Ok(Vec::new())))
+    });
+    let status = wait(child)?;
+    Ok(process::Output {
+        status,
+        stdout: stdout?,
+        stderr: stderr?,
+    })
+}
+
+/// Remove `path` if a second Ctrl-C exits right away. Call [`removed`] after
+/// removing it.
+pub(crate) fn remove_on_exit(path: PathBuf) {
+    TEMP_FILES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(path);
+}
+
+/// Mark a file as removed, see [`remove_on_exit`]
+pub(crate) fn removed(path: &Path) {
+    TEMP_FILES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(path);
+}
+
+/// Restore the terminal settings `saved` (from `stty -g`) if a second Ctrl-C
+/// exits right away, or stop doing so with `None`, see [`crate::keys::RawMode`]
+pub(crate) fn restore_terminal_on_exit(saved: Option<String>) {
+    *TERMINAL.lock().unwrap_or_else(|e| e.into_inner()) = saved;
+}
+
+fn restore_terminal() {
+    if let Some(saved) = TERMINAL.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        crate::keys::restore(&saved);
+    }
+}
+
+fn remove_temp_files() {
+    let files = std::mem::take(&mut *TEMP_FILES.lock().unwrap_or_else(|e| e.into_inner()));
+    for path in files {
+        drop(fs::remove_file(path));
+    }
+}
+
+/// Terminate the running commands and their subprocesses. They are in their
+/// own process groups, so Ctrl-C in a terminal doesn't reach them.
+fn terminate() {
+    // Held while signalling, so that no process ID is forgotten (and reused)
+    // in the meantime, see [`wait`]
+    let children = CHILDREN.lock().unwrap_or_else(|e| e.into_inner());
+    if children.is_empty() {
+        return;
+    }
+    debug!("Terminating {} commands", children.len());
+    for pid in children
+        .iter()
+        .filter_map(|id| Pid::from_raw(id.cast_signed()))
+    {
+        // The command may have exited already
+        let _ = rustix::process::kill_process_group(pid, Signal::TERM);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn children() {
+        let mut c = process::Command::new("sh");
+        c.args(["-c", "echo hi"]);
+        let out = output(&mut c).unwrap();
+        assert_eq!(out.stdout, b"hi\n");
+        assert!(status(&mut c).unwrap().success());
+        // More than fits in a pipe
+        let mut c = process::Command::new("sh");
+        c.args([
+            "-c",
+            "head -c 1000000 /dev/zero; head -c 1000000 /dev/zero >&2",
+        ]);
+        let out = output(&mut c).unwrap();
+        assert_eq!((out.stdout.len(), out.stderr.len()), (1_000_000, 1_000_000));
+    }
+
+    #[test]
+    fn process_group() {
+        let mut c = process::Command::new("cat");
+        c.stdin(process::Stdio::piped());
+        let mut child = spawn(&mut c).unwrap();
+        let pid = Pid::from_child(&child);
+        assert_eq!(rustix::process::getpgid(Some(pid)).unwrap(), pid);
+        assert!(CHILDREN.lock().unwrap().contains(&child.id()));
+        drop(child.stdin.take());
+        assert!(wait(&mut child).unwrap().success());
+        assert!(!CHILDREN.lock().unwrap().contains(&child.id()));
+    }
+
+    #[test]
+    fn temp_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (kept, gone) = (dir.path().join("kept"), dir.path().join("gone"));
+        fs::write(&kept, "").unwrap();
+        fs::write(&gone, "").unwrap();
+        remove_on_exit(kept.clone());
+        removed(&kept);
+        remove_on_exit(gone.clone());
+        remove_temp_files();
+        assert!(kept.exists());
+        assert!(!gone.exists());
+    }
+}
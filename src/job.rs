@@ -1,38 +1,256 @@
-use std::{num::NonZero, process};
+use std::{
+    borrow::Cow,
+    cmp,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ffi::OsStr,
+    mem,
+    num::NonZero,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
 
 use tracing::debug;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
 
-use crate::{cmd, config::Granularity, file};
+use crate::{
+    cli, cmd,
+    config::{BatchSize, FilesVia, Granularity},
+    file,
+    timings::Timings,
+};
 
 pub(crate) fn display_cmd(c: &process::Command) -> String {
-    format!(
-        "{} {}",
-        c.get_program().display(),
-        c.get_args()
-            .map(|a| a.display().to_string())
-            .collect::<Vec<_>>()
-            .join(" ")
-    )
+    std::iter::once(c.get_program())
+        .chain(c.get_args())
+        .map(|a| quote(&a.to_string_lossy()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Like [`display_cmd`], but also shows the file passed on stdin (if any).
+///
+/// For tools that read the list of files on stdin, this is a valid shell
+/// command once [`cmd::Command::write_argfile`] has been called, see
+/// `ninja.rs`.
+pub(crate) fn display_job(cmd: &cmd::Command, c: &process::Command) -> String {
+    let displayed = display_cmd(c);
+    match (cmd.tool.stdin, cmd.files.first()) {
+        (Some(_), Some(f)) => format!("{displayed} < {}", quote(&f.path.to_string_lossy())),
+        _ => match cmd.tool.files_via {
+            FilesVia::Stdin | FilesVia::Stdin0 => match cmd.argfile() {
+                Some(path) => format!("{displayed} < {}", quote(&path.to_string_lossy())),
+                None => displayed,
+            },
+            FilesVia::Args | FilesVia::Argfile => displayed,
+        },
+    }
+}
+
+/// Quote a string for display as a shell word, if needed.
+///
+/// The result is valid input to a POSIX shell (and to Bash, so `{` and `}`
+/// are quoted to avoid brace expansion).
+pub(crate) fn quote(s: &str) -> Cow<'_, str> {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "+,-./:=@_%".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("'{}'", s.replace('\'', r"'\''")))
+    }
 }
 
 pub(crate) fn create_jobs(
     commands: Vec<cmd::Command>,
     cores: NonZero<usize>,
     no_batch: bool,
+    timings: &Timings,
 ) -> Vec<cmd::Command> {
     if commands.is_empty() {
         debug!("No commands to execute");
         return Vec::new();
     }
     let mut batches = Vec::with_capacity(commands.len() /* min */);
-    for cmd in commands {
-        if no_batch {
+    for cmd in commands.into_iter().flat_map(by_nearest) {
+        // Stored failures are replayed per command, see `results.rs`, so
+        // that a file that failed isn't run again when others change
+        if no_batch || cmd.tool.cache_failures {
             batches.extend(unbatch(cmd));
         } else {
-            batches.extend(batch(cmd, cores));
+            batches.extend(batch(cmd, cores, timings));
         }
     }
-    batches
+    batches.into_iter().flat_map(split_args).collect()
+}
+
+/// The position of `path` in a random order that is determined by `seed`
+pub(crate) fn random_key(path: &Path, seed: u64) -> u64 {
+    xxh3_64_with_seed(path.as_os_str().as_encoded_bytes(), seed)
+}
+
+/// Put the jobs, and the files of each job, in a random order that is
+/// determined by `seed`, for `--shuffle`
+pub(crate) fn shuffle(jobs: &mut [cmd::Command], seed: u64) {
+    for job in jobs.iter_mut() {
+        job.files.sort_by_cached_key(|f| random_key(&f.path, seed));
+    }
+    jobs.sort_by_cached_key(|job| {
+        let tool = xxh3_64_with_seed(job.tool.display_name().as_bytes(), seed);
+        let file = job.files.first().map(|f| random_key(&f.path, seed));
+        (
+            tool ^ file.unwrap_or_default(),
+            job.tool.display_name().to_string(),
+        )
+    });
+}
+
+/// Only keep the first `sample.size()` distinct files of `jobs`, in path
+/// order or in an order determined by `seed`, for `--limit` and `--sample`.
+///
+/// Files in `chosen` were kept in an earlier phase, and are kept again. Returns
+/// the files that were dropped.
+pub(crate) fn sample(
+    jobs: &mut Vec<cmd::Command>,
+    sample: cli::Sample,
+    seed: u64,
+    chosen: &mut HashSet<PathBuf>,
+) -> HashSet<PathBuf> {
+    let mut paths = jobs
+        .iter()
+        .flat_map(|job| job.files.iter().map(|f| f.path.as_path()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    if let cli::Sample::Random(_) = sample {
+        paths.sort_by_cached_key(|p| random_key(p, seed));
+    }
+    let mut dropped = HashSet::new();
+    for path in paths {
+        if !chosen.contains(path) {
+            if chosen.len() < sample.size() {
+                chosen.insert(path.to_path_buf());
+            } else {
+                dropped.insert(path.to_path_buf());
+            }
+        }
+    }
+    for job in jobs.iter_mut() {
+        job.files.retain(|f| !dropped.contains(&f.path));
+    }
+    jobs.retain(|job| !job.files.is_empty());
+    dropped
+}
+
+/// Split a command by the closest directory that contains the marker file of
+/// `cd = "nearest:MARKER"`, running each part there. Files without such a
+/// directory run in the project root.
+pub(crate) fn by_nearest(cmd: cmd::Command) -> Vec<cmd::Command> {
+    group_by_nearest(cmd, Path::is_file)
+}
+
+fn group_by_nearest(cmd: cmd::Command, is_file: impl Fn(&Path) -> bool) -> Vec<cmd::Command> {
+    let Some(marker) = cmd.tool.cd_nearest.as_deref() else {
+        return vec![cmd];
+    };
+    // Files in the same directory share a marker
+    let mut nearest = HashMap::new();
+    let mut groups = BTreeMap::<Option<PathBuf>, Vec<file::File>>::new();
+    for f in cmd.files {
+        let parent = f.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let dir = nearest
+            .entry(parent)
+            .or_insert_with_key(|parent: &PathBuf| {
+                parent
+                    .ancestors()
+                    .find(|d| is_file(&d.join(marker)))
+                    .filter(|d| !d.as_os_str().is_empty())
+                    .map(Path::to_path_buf)
+            })
+            .clone();
+        groups.entry(dir).or_default().push(f);
+    }
+    groups
+        .into_iter()
+        .map(|(dir, files)| {
+            debug!(
+                "Running {} on {} files in {}",
+                cmd.tool.display_name(),
+                files.len(),
+                dir.as_deref().unwrap_or(Path::new(".")).display()
+            );
+            let mut tool = (*cmd.tool).clone();
+            tool.cd = dir;
+            tool.cd_nearest = None;
+            cmd::Command {
+                tool: Arc::new(tool),
+                files,
+                argfile: None,
+            }
+        })
+        .collect()
+}
+
+/// Default for `arg_max`. Linux allows 2 MiB for the arguments and the
+/// environment together, and macOS 1 MiB, so this leaves plenty of room.
+pub(crate) const DEFAULT_ARG_MAX: usize = 128 * 1024;
+
+/// Bytes that an argument takes when spawning a process: the string, its NUL
+/// terminator, and a pointer to it
+fn arg_bytes(arg: &OsStr) -> usize {
+    arg.len() + 1 + size_of::<usize>()
+}
+
+/// Split a command whose arguments would take more than `arg_max` bytes
+fn split_args(cmd: cmd::Command) -> Vec<cmd::Command> {
+    if cmd.files.len() <= 1
+        || cmd.tool.granularity == Granularity::Batch
+        || cmd.tool.stdin.is_some()
+        || cmd.tool.files_via != FilesVia::Args
+    {
+        return vec![cmd];
+    }
+    let empty = cmd::Command {
+        tool: cmd.tool.clone(),
+        files: Vec::new(),
+        argfile: None,
+    }
+    .to_command();
+    let base = arg_bytes(empty.get_program()) + empty.get_args().map(arg_bytes).sum::<usize>();
+    let sizes = cmd
+        .files
+        .iter()
+        .map(|f| arg_bytes(cmd.path(f).as_os_str()))
+        .collect::<Vec<_>>();
+    let arg_max = cmd.tool.arg_max;
+    if base + sizes.iter().sum::<usize>() <= arg_max {
+        return vec![cmd];
+    }
+    let mut chunks = Vec::new();
+    let mut files = Vec::new();
+    let mut bytes = base;
+    for (f, n) in cmd.files.into_iter().zip(sizes) {
+        if !files.is_empty() && bytes + n > arg_max {
+            chunks.push(cmd::Command {
+                tool: cmd.tool.clone(),
+                files: mem::take(&mut files),
+                argfile: None,
+            });
+            bytes = base;
+        }
+        bytes += n;
+        files.push(f);
+    }
+    chunks.push(cmd::Command {
+        tool: cmd.tool.clone(),
+        files,
+        argfile: None,
+    });
+    debug!(
+        "Split {} into {} commands to stay below {arg_max} bytes of arguments",
+        cmd.tool.display_name(),
+        chunks.len()
+    );
+    chunks
 }
 
 fn unbatch(cmd: cmd::Command) -> Vec<cmd::Command> {
@@ -47,47 +265,97 @@ fn unbatch(cmd: cmd::Command) -> Vec<cmd::Command> {
         .map(|file| cmd::Command {
             tool: cmd.tool.clone(),
             files: vec![file],
+            argfile: None,
+        })
+        .collect()
+}
+
+/// Estimated cost of running the tool on each file: how long it took on the
+/// file before or, for other files, their size scaled by how long the tool
+/// took per byte on the files with timings. Without timings, just the size.
+fn weights(cmd: &cmd::Command, timings: &Timings) -> Vec<u64> {
+    let name = cmd.tool.display_name();
+    let known = cmd
+        .files
+        .iter()
+        .map(|f| timings.get(name, &f.path))
+        .collect::<Vec<_>>();
+    let (micros, bytes) = cmd
+        .files
+        .iter()
+        .zip(&known)
+        .filter_map(|(f, micros)| Some((micros.as_ref()?, f.size as u64 + 1)))
+        .fold((0u64, 0u64), |(m, b), (micros, bytes)| {
+            (m.saturating_add(*micros), b + bytes)
+        });
+    if micros == 0 {
+        return cmd.files.iter().map(|f| f.size as u64).collect();
+    }
+    cmd.files
+        .iter()
+        .zip(known)
+        .map(|(f, known)| {
+            known.unwrap_or_else(|| (f.size as u64 + 1).saturating_mul(micros) / bytes)
         })
         .collect()
 }
 
-fn batch(mut cmd: cmd::Command, cores: NonZero<usize>) -> Vec<cmd::Command> {
+fn batch(cmd: cmd::Command, cores: NonZero<usize>, timings: &Timings) -> Vec<cmd::Command> {
     debug_assert!(!cmd.files.is_empty());
     if cmd.files.is_empty() {
         return Vec::new();
     }
     let cores = cores.get();
-    if cmd.files.len() == 1 || cmd.tool.granularity == Granularity::Batch || cores == 1 {
+    // Tools that read files on stdin are run once per file
+    if cmd.tool.stdin.is_some() {
+        return unbatch(cmd);
+    }
+    if cmd.files.len() == 1 || cmd.tool.granularity == Granularity::Batch {
         return vec![cmd];
     }
-    if cmd.files.len() < cores {
+    let n = cmd.files.len();
+    let BatchSize { min, max } = cmd.tool.batch_size;
+    let mut n_jobs = cores.min(n.div_ceil(min.unwrap_or(1)));
+    if let Some(max) = max {
+        n_jobs = n_jobs.max(n.div_ceil(max));
+    }
+    if n_jobs == 1 {
+        return vec![cmd];
+    }
+    if n_jobs == n {
         return cmd
             .files
             .into_iter()
             .map(|file| cmd::Command {
                 tool: cmd.tool.clone(),
                 files: vec![file],
+                argfile: None,
             })
             .collect();
     }
 
-    cmd.files.sort_by(|a, b| b.size.cmp(&a.size));
-    let mut jobs: Vec<(Vec<file::File>, usize)> = (0..cores).map(|_| (Vec::new(), 0)).collect();
+    let weights = weights(&cmd, timings);
+    let mut files = cmd.files.into_iter().zip(weights).collect::<Vec<_>>();
+    files.sort_by_key(|(_, weight)| cmp::Reverse(*weight));
+    let max = max.unwrap_or(usize::MAX);
+    let mut jobs: Vec<(Vec<file::File>, u64)> = (0..n_jobs).map(|_| (Vec::new(), 0)).collect();
     // Distribute files to jobs using a greedy algorithm
-    for file in cmd.files {
-        // Find the batch with the smallest total size
+    for (file, weight) in files {
+        // Find the batch with the smallest total weight (then, fewest files)
+        // that has room
         let smallest_batch_idx = jobs
             .iter()
             .enumerate()
-            .min_by_key(|(_, (_, total_size))| *total_size)
+            .filter(|(_, (files, _))| files.len() < max)
+            .min_by_key(|(_, (files, total))| (*total, files.len()))
             .map_or(0, |(idx, _)| idx);
 
-        jobs[smallest_batch_idx].1 += file.size;
+        jobs[smallest_batch_idx].1 += weight;
         jobs[smallest_batch_idx].0.push(file);
     }
 
     jobs.into_iter()
-        .filter_map(|(mut files, sz)| {
+        .filter_map(|(mut files, weight)| {
             if files.is_empty() {
                 None
             } else {
@@ -95,11 +363,254 @@ fn batch(mut cmd: cmd::Command, cores: NonZero<usize>) -> Vec<cmd::Command> {
                 let cmd = cmd::Command {
                     tool: cmd.tool.clone(),
                     files,
+                    argfile: None,
                 };
                 let c = cmd.to_command();
-                debug!("Batched {} (size: {sz})", display_cmd(&c));
+                debug!("Batched {} (weight: {weight})", display_cmd(&c));
                 Some(cmd)
             }
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{exec, tool};
+
+    #[test]
+    fn sample_files() {
+        let tool = Arc::new(tool::test_tool(""));
+        let jobs = || {
+            vec![
+                cmd::Command {
+                    tool: Arc::clone(&tool),
+                    files: ["c.py", "a.py"].map(|p| file::File::test(p, 0)).to_vec(),
+                    argfile: None,
+                },
+                cmd::Command {
+                    tool: Arc::clone(&tool),
+                    files: ["b.py", "a.py", "d.py"]
+                        .map(|p| file::File::test(p, 0))
+                        .to_vec(),
+                    argfile: None,
+                },
+            ]
+        };
+        let paths = |jobs: &[cmd::Command]| {
+            jobs.iter()
+                .map(|j| {
+                    j.files
+                        .iter()
+                        .map(|f| f.path.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut first = jobs();
+        let mut chosen = HashSet::new();
+        let dropped = sample(&mut first, cli::Sample::First(2), 0, &mut chosen);
+        assert_eq!(paths(&first), ["a.py", "b.py a.py"]);
+        assert_eq!(dropped.len(), 2);
+        // Later phases keep the same files
+        let mut later = jobs();
+        sample(&mut later, cli::Sample::First(2), 0, &mut chosen);
+        assert_eq!(paths(&later), paths(&first));
+
+        let random = |seed| {
+            let mut jobs = jobs();
+            sample(&mut jobs, cli::Sample::Random(2), seed, &mut HashSet::new());
+            paths(&jobs)
+        };
+        assert_eq!(random(1), random(1));
+        assert_eq!(
+            random(1).join(" ").split(' ').collect::<HashSet<_>>().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn shuffled() {
+        let tool = Arc::new(tool::test_tool(""));
+        let shuffled = |seed| {
+            let mut jobs = ["a", "b", "c"]
+                .map(|dir| cmd::Command {
+                    tool: Arc::clone(&tool),
+                    files: ["1", "2", "3", "4"]
+                        .map(|f| file::File::test(&format!("{dir}/{f}.py"), 0))
+                        .to_vec(),
+                    argfile: None,
+                })
+                .to_vec();
+            shuffle(&mut jobs, seed);
+            jobs.iter()
+                .flat_map(|j| j.files.iter().map(|f| f.path.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(shuffled(1), shuffled(1));
+        assert_ne!(shuffled(1), shuffled(2));
+        let mut paths = shuffled(1);
+        paths.sort();
+        assert_eq!(paths.len(), 12);
+        assert_eq!(paths[0], PathBuf::from("a/1.py"));
+    }
+
+    #[test]
+    fn split() {
+        let mut tool = tool::test_tool("");
+        // "lint" and two of the files
+        tool.arg_max = 3 * (6 + size_of::<usize>());
+        let cmd = cmd::Command {
+            tool: Arc::new(tool),
+            files: ["a.py", "b.py", "c.py", "d.py", "e.py"]
+                .map(|p| file::File::test(p, 0))
+                .to_vec(),
+            argfile: None,
+        };
+        let chunks = split_args(cmd)
+            .iter()
+            .map(|c| display_cmd(&c.to_command()))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, ["lint a.py b.py", "lint c.py d.py", "lint e.py"]);
+    }
+
+    #[test]
+    fn cache_failures_unbatched() {
+        let files = ["a.py", "b.py", "c.py"];
+        let jobs = |toml| {
+            let cmd = cmd::Command::test(toml, &files);
+            create_jobs(vec![cmd], NonZero::<usize>::MIN, false, &Timings::default()).len()
+        };
+        assert_eq!(jobs(""), 1);
+        assert_eq!(jobs("cache_failures = true"), 3);
+        // Can't be split
+        assert_eq!(jobs("cache_failures = true\ngranularity = \"batch\""), 1);
+    }
+
+    #[test]
+    fn argfile() {
+        let mut cmd = cmd::Command::test(
+            "argfile = \"--files-from {{argfile}}\"",
+            &["a.py", "b c.py"],
+        );
+        assert!(cmd.argfile().is_none());
+        assert_eq!(
+            display_cmd(&cmd.to_command()),
+            "lint --files-from '{{argfile}}'"
+        );
+        cmd.write_argfile().unwrap();
+        let argfile = cmd.argfile().unwrap().to_path_buf();
+        assert_eq!(
+            display_cmd(&cmd.to_command()),
+            format!("lint --files-from {}", argfile.display())
+        );
+        assert_eq!(std::fs::read_to_string(&argfile).unwrap(), "a.py\nb c.py\n");
+        // Each argfile is new
+        let mut other = cmd.clone();
+        other.write_argfile().unwrap();
+        assert_ne!(other.argfile().unwrap(), argfile);
+        // Removed with the last clone
+        let clone = cmd.clone();
+        drop(cmd);
+        assert!(argfile.exists());
+        drop(clone);
+        assert!(!argfile.exists());
+    }
+
+    #[test]
+    fn files_via_stdin() {
+        let mut cmd = cmd::Command::test("files_via = \"stdin0\"", &["a.py", "b c.py"]);
+        cmd.write_argfile().unwrap();
+        let argfile = cmd.argfile().unwrap();
+        assert_eq!(
+            display_job(&cmd, &cmd.to_command()),
+            format!("lint < {}", argfile.display())
+        );
+        assert_eq!(std::fs::read(argfile).unwrap(), b"a.py\0b c.py\0");
+        assert!(cmd.stdin().unwrap().is_some());
+    }
+
+    #[test]
+    fn paths() {
+        let mut tool = tool::test_tool("cd = \"a\"");
+        tool.cmd = format!("lint {} --end", cmd::FILES);
+        let cmd = cmd::Command {
+            tool: Arc::new(tool),
+            files: vec![file::File::test("a/x.py", 0), file::File::test("b/y.py", 0)],
+            argfile: None,
+        };
+        assert_eq!(display_cmd(&cmd.to_command()), "lint x.py ../b/y.py --end");
+        let mut tool = (*cmd.tool).clone();
+        tool.absolute_paths = true;
+        let cmd = cmd::Command {
+            tool: Arc::new(tool),
+            ..cmd
+        };
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            display_cmd(&cmd.to_command()),
+            format!(
+                "lint {} {} --end",
+                cwd.join("a/x.py").display(),
+                cwd.join("b/y.py").display()
+            )
+        );
+    }
+
+    #[test]
+    fn nearest() {
+        let cmd = cmd::Command {
+            tool: Arc::new(tool::test_tool("cd = \"nearest:package.json\"")),
+            files: ["a/src/x.js", "a/y.js", "b/c/z.js", "top.js"]
+                .map(|p| file::File::test(p, 0))
+                .to_vec(),
+            argfile: None,
+        };
+        let markers = [Path::new("a/package.json"), Path::new("b/package.json")];
+        let cmds = group_by_nearest(cmd, |p| markers.contains(&p))
+            .iter()
+            .map(|c| display_job(c, &c.to_command()))
+            .collect::<Vec<_>>();
+        // The root has no marker, so `top.js` runs there
+        assert_eq!(cmds, ["lint top.js", "lint src/x.js y.js", "lint c/z.js"]);
+    }
+
+    #[test]
+    fn timed_weights() {
+        let tool = tool::test_tool("");
+        let cmd = cmd::Command {
+            tool: Arc::new(tool),
+            files: vec![
+                file::File::test("slow.py", 9),
+                file::File::test("fast.py", 99),
+                file::File::test("new.py", 9),
+            ],
+            argfile: None,
+        };
+        assert_eq!(weights(&cmd, &Timings::default()), [9, 99, 9]);
+
+        let mut timings = Timings::default();
+        timings.record(
+            &exec::Outcome {
+                tool: "lint".to_string(),
+                cmd: "lint".to_string(),
+                files: vec![PathBuf::from("slow.py"), PathBuf::from("fast.py")],
+                cd: None,
+                modified: Vec::new(),
+                success: true,
+                output: None,
+                replayed: false,
+                sandbox_violation: false,
+                duration: Duration::from_micros(1100),
+            },
+            |p| if p == Path::new("slow.py") { 999 } else { 99 },
+        );
+        // The timed files took 1100 microseconds for 110 bytes (counting one
+        // per file), so the new file should take 100
+        assert_eq!(weights(&cmd, &timings), [1000, 100, 100]);
+    }
+}
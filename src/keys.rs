@@ -0,0 +1,122 @@
+//! Keys in `--watch` mode: `r` re-runs everything, `f` toggles fix mode, `t`
+//! cycles through the tools, and `q` quits.
+
+use std::{
+    io::{self, IsTerminal as _, Read as _},
+    process, thread,
+};
+
+use tracing::debug;
+
+use crate::interrupt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Key {
+    /// Run every tool on every file, ignoring the cache
+    Rerun,
+    /// Toggle fix mode
+    Fix,
+    /// Only run the next tool
+    Tool,
+    Quit,
+}
+
+impl Key {
+    fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            b'r' => Some(Key::Rerun),
+            b'f' => Some(Key::Fix),
+            b't' => Some(Key::Tool),
+            b'q' => Some(Key::Quit),
+            _ => None,
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<process::Output> {
+    process::Command::new("stty")
+        .args(args)
+        .stdin(process::Stdio::inherit())
+        .output()
+}
+
+/// The terminal without line buffering or echo, restored when dropped
+#[derive(Debug)]
+pub(crate) struct RawMode {
+    /// Settings from `stty -g`
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<Self> {
+        let out = stty(&["-g"]).ok().filter(|o| o.status.success())?;
+        let saved = String::from_utf8(out.stdout).ok()?.trim().to_string();
+        // Keep `isig`, so that Ctrl-C still works
+        stty(&["-icanon", "-echo"])
+            .ok()
+            .filter(|o| o.status.success())?;
+        // `Drop` doesn't run if a second Ctrl-C exits right away
+        interrupt::restore_terminal_on_exit(Some(saved.clone()));
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        interrupt::restore_terminal_on_exit(None);
+        restore(&self.saved);
+    }
+}
+
+/// Restore terminal settings from `stty -g`
+pub(crate) fn restore(saved: &str) {
+    drop(stty(&[saved]));
+}
+
+/// Read keys from stdin in a separate thread, if it's a terminal. Reading
+/// stops once `send` returns `false`.
+pub(crate) fn listen(send: impl Fn(Key) -> bool + Send + 'static) -> Option<RawMode> {
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    let raw = RawMode::enable();
+    if raw.is_none() {
+        debug!("Failed to read keys from the terminal");
+        return None;
+    }
+    thread::spawn(move || {
+        for b in io::stdin().lock().bytes() {
+            let Ok(b) = b else { break };
+            if let Some(key) = Key::from_byte(b)
+                && !send(key)
+            {
+                break;
+            }
+        }
+    });
+    raw
+}
+
+/// The tool after `current`, or none (i.e., all of them) after the last one
+pub(crate) fn next_tool(names: &[String], current: Option<&str>) -> Option<String> {
+    match current {
+        None => names.first().cloned(),
+        Some(current) => names.iter().skip_while(|n| *n != current).nth(1).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_tools() {
+        let names = ["a".to_string(), "b".to_string()];
+        assert_eq!(next_tool(&names, None).as_deref(), Some("a"));
+        assert_eq!(next_tool(&names, Some("a")).as_deref(), Some("b"));
+        assert_eq!(next_tool(&names, Some("b")), None);
+        // E.g., removed from the config file
+        assert_eq!(next_tool(&names, Some("c")), None);
+        assert_eq!(next_tool(&[], None), None);
+    }
+}
@@ -1,9 +1,100 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use tracing::debug;
 
 use crate::config::{self, Granularity};
 
 pub(crate) fn known_linters() -> Vec<config::Linter> {
     vec![
+        config::Linter {
+            tool: config::Tool {
+                name: Some("actionlint".to_string()),
+                cmd: "actionlint --".to_string(),
+                files: vec![
+                    ".github/workflows/*.yml".to_string(),
+                    ".github/workflows/*.yaml".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".github/actionlint.yml"),
+                    PathBuf::from(".github/actionlint.yaml"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: None,
+        },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("biome".to_string()),
+                cmd: "biome check --colors=off --".to_string(),
+                files: vec![
+                    "*.js".to_string(),
+                    "*.jsx".to_string(),
+                    "*.mjs".to_string(),
+                    "*.cjs".to_string(),
+                    "*.ts".to_string(),
+                    "*.tsx".to_string(),
+                    "*.mts".to_string(),
+                    "*.cts".to_string(),
+                    "*.json".to_string(),
+                    "*.jsonc".to_string(),
+                    "*.css".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![PathBuf::from("biome.json"), PathBuf::from("biome.jsonc")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: Some("biome check --colors=off --write --".to_string()),
+        },
         config::Linter {
             tool: config::Tool {
                 name: Some(String::from("cargo clippy")),
@@ -12,10 +103,203 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Batch,
                 configs: vec![PathBuf::from("Cargo.toml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: Some("clippy".to_string()),
+                warns: config::WarnCfg::default(),
             },
             fix: Some("cargo clippy --color={{color}} --allow-dirty --fix".to_string()),
         },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("clang-tidy".to_string()),
+                // `--` would start the compiler flags
+                cmd: "clang-tidy".to_string(),
+                files: vec![
+                    "*.c".to_string(),
+                    "*.cc".to_string(),
+                    "*.cpp".to_string(),
+                    "*.cxx".to_string(),
+                    "*.h".to_string(),
+                    "*.hh".to_string(),
+                    "*.hpp".to_string(),
+                    "*.hxx".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".clang-tidy"),
+                    PathBuf::from("compile_commands.json"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: Some("clang-tidy --fix".to_string()),
+        },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("eslint".to_string()),
+                cmd: "eslint --".to_string(),
+                files: vec![
+                    "*.js".to_string(),
+                    "*.jsx".to_string(),
+                    "*.mjs".to_string(),
+                    "*.cjs".to_string(),
+                    "*.ts".to_string(),
+                    "*.tsx".to_string(),
+                    "*.mts".to_string(),
+                    "*.cts".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from("eslint.config.js"),
+                    PathBuf::from("eslint.config.mjs"),
+                    PathBuf::from("eslint.config.cjs"),
+                    PathBuf::from("eslint.config.ts"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: Some("eslint".to_string()),
+                warns: config::WarnCfg::default(),
+            },
+            fix: Some("eslint --fix --".to_string()),
+        },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("golangci-lint".to_string()),
+                cmd: "golangci-lint run".to_string(),
+                files: vec!["*.go".to_string()],
+                ignore: Vec::new(),
+                // Lints packages, not files
+                granularity: Granularity::Batch,
+                configs: vec![
+                    PathBuf::from(".golangci.yml"),
+                    PathBuf::from(".golangci.yaml"),
+                    PathBuf::from(".golangci.toml"),
+                    PathBuf::from(".golangci.json"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: Some("golangci-lint run --fix".to_string()),
+        },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("hadolint".to_string()),
+                cmd: "hadolint --".to_string(),
+                files: vec![
+                    "*Dockerfile".to_string(),
+                    "*Dockerfile.*".to_string(),
+                    "*.dockerfile".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".hadolint.yml"),
+                    PathBuf::from(".hadolint.yaml"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: None,
+        },
         config::Linter {
             tool: config::Tool {
                 name: None,
@@ -24,10 +308,69 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: vec![PathBuf::from(".hlint.yml"), PathBuf::from(".hlint.yaml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: None,
         },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("markdownlint".to_string()),
+                cmd: "markdownlint --".to_string(),
+                files: vec!["*.md".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".markdownlint.json"),
+                    PathBuf::from(".markdownlint.jsonc"),
+                    PathBuf::from(".markdownlint.yml"),
+                    PathBuf::from(".markdownlint.yaml"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: Some("markdownlint --fix --".to_string()),
+        },
         config::Linter {
             tool: config::Tool {
                 name: Some(String::from("mdlynx")),
@@ -36,7 +379,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: Vec::new(),
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: None,
         },
@@ -52,10 +416,64 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                     PathBuf::from("mypy.ini"),
                     PathBuf::from(".mypy.ini"),
                 ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: Some("mypy".to_string()),
+                warns: config::WarnCfg::default(),
             },
             fix: None,
         },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("rubocop".to_string()),
+                cmd: "rubocop --".to_string(),
+                files: vec!["*.rb".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![PathBuf::from(".rubocop.yml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: Some("rubocop --autocorrect --".to_string()),
+        },
         config::Linter {
             tool: config::Tool {
                 name: Some(String::from("ruff check")),
@@ -68,7 +486,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                     PathBuf::from("ruff.toml"),
                     PathBuf::from(".ruff.toml"),
                 ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: Some("ruff".to_string()),
+                warns: config::WarnCfg::default(),
             },
             fix: Some("ruff check --fix --".to_string()),
         },
@@ -80,7 +519,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: vec![PathBuf::from(".shellcheckrc")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: Some("shellcheck".to_string()),
+                warns: config::WarnCfg::default(),
             },
             fix: None,
         },
@@ -92,7 +552,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Batch,
                 configs: Vec::new(),
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: None,
         },
@@ -104,7 +585,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: Vec::new(),
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: Some("ttlint --fix --".to_string()),
         },
@@ -116,7 +618,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Batch,
                 configs: vec![PathBuf::from("pyproject.toml"), PathBuf::from("ty.toml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: None,
         },
@@ -132,10 +655,68 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                     PathBuf::from("_typos.toml"),
                     PathBuf::from(".typos.toml"),
                 ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: Some("typos --write-changes --".to_string()),
         },
+        config::Linter {
+            tool: config::Tool {
+                name: Some("yamllint".to_string()),
+                cmd: "yamllint --".to_string(),
+                files: vec!["*.yml".to_string(), "*.yaml".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".yamllint"),
+                    PathBuf::from(".yamllint.yml"),
+                    PathBuf::from(".yamllint.yaml"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            fix: None,
+        },
         config::Linter {
             tool: config::Tool {
                 name: Some("zizmor".to_string()),
@@ -144,7 +725,28 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: vec![PathBuf::from("zizmor.yml"), PathBuf::from("zizmor.yaml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             fix: Some("zizmor --fix=safe --".to_string()),
         },
@@ -153,6 +755,40 @@ pub(crate) fn known_linters() -> Vec<config::Linter> {
 
 pub(crate) fn known_formatters() -> Vec<config::Formatter> {
     vec![
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("black".to_string()),
+                cmd: "black --".to_string(),
+                files: vec!["*.py".to_string(), "*.pyi".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: Vec::new(),
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: Some("black --check --".to_string()),
+            write_back: false,
+        },
         config::Formatter {
             tool: config::Tool {
                 name: Some(String::from("cargo fmt")),
@@ -166,9 +802,203 @@ pub(crate) fn known_formatters() -> Vec<config::Formatter> {
                     PathBuf::from("rustfmt.toml"),
                     PathBuf::from(".rustfmt.toml"),
                 ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             check: Some("cargo fmt --check -- --color={{color}} --".to_string()),
+            write_back: false,
+        },
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("clang-format".to_string()),
+                cmd: "clang-format -i --".to_string(),
+                files: vec![
+                    "*.c".to_string(),
+                    "*.cc".to_string(),
+                    "*.cpp".to_string(),
+                    "*.cxx".to_string(),
+                    "*.h".to_string(),
+                    "*.hh".to_string(),
+                    "*.hpp".to_string(),
+                    "*.hxx".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".clang-format"),
+                    PathBuf::from("_clang-format"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: Some("clang-format --dry-run --Werror --".to_string()),
+            write_back: false,
+        },
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("gofmt".to_string()),
+                cmd: "gofmt".to_string(),
+                files: vec!["*.go".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: Vec::new(),
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                // `gofmt -l` doesn't fail, so compare the output to the file
+                stdin: true,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: None,
+            write_back: true,
+        },
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("isort".to_string()),
+                cmd: "isort --".to_string(),
+                files: vec!["*.py".to_string(), "*.pyi".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![PathBuf::from(".isort.cfg")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: Some("isort --check-only --".to_string()),
+            write_back: false,
+        },
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("prettier".to_string()),
+                cmd: "prettier --write --".to_string(),
+                files: vec![
+                    "*.js".to_string(),
+                    "*.jsx".to_string(),
+                    "*.mjs".to_string(),
+                    "*.cjs".to_string(),
+                    "*.ts".to_string(),
+                    "*.tsx".to_string(),
+                    "*.mts".to_string(),
+                    "*.cts".to_string(),
+                    "*.json".to_string(),
+                    "*.css".to_string(),
+                    "*.scss".to_string(),
+                    "*.html".to_string(),
+                    "*.vue".to_string(),
+                    "*.yml".to_string(),
+                    "*.yaml".to_string(),
+                ],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![
+                    PathBuf::from(".prettierrc"),
+                    PathBuf::from(".prettierrc.json"),
+                    PathBuf::from(".prettierrc.yml"),
+                    PathBuf::from(".prettierrc.yaml"),
+                    PathBuf::from(".prettierrc.js"),
+                    PathBuf::from("prettier.config.js"),
+                ],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: Some("prettier --check --".to_string()),
+            write_back: false,
         },
         config::Formatter {
             tool: config::Tool {
@@ -178,9 +1008,99 @@ pub(crate) fn known_formatters() -> Vec<config::Formatter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: vec![PathBuf::from("ruff.toml"), PathBuf::from(".ruff.toml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             check: Some("ruff format --check --".to_string()),
+            write_back: false,
+        },
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("shfmt".to_string()),
+                cmd: "shfmt --write --".to_string(),
+                files: vec!["*.sh".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: Vec::new(),
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: Some("shfmt --diff --".to_string()),
+            write_back: false,
+        },
+        config::Formatter {
+            tool: config::Tool {
+                name: Some("stylua".to_string()),
+                cmd: "stylua --".to_string(),
+                files: vec!["*.lua".to_string()],
+                ignore: Vec::new(),
+                granularity: Granularity::Individual,
+                configs: vec![PathBuf::from("stylua.toml"), PathBuf::from(".stylua.toml")],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
+                cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
+            },
+            check: Some("stylua --check --".to_string()),
+            write_back: false,
         },
         config::Formatter {
             tool: config::Tool {
@@ -190,38 +1110,390 @@ pub(crate) fn known_formatters() -> Vec<config::Formatter> {
                 ignore: Vec::new(),
                 granularity: Granularity::Individual,
                 configs: vec![],
+                env: Vec::new(),
+                env_stamp: config::EnvStamp::Auto,
                 cd: None,
+                cache_failures: None,
+                dedupe_group: None,
+                stdin: false,
+                min_version: None,
+                version: None,
+                version_cmd: None,
+                enabled_if: None,
+                platforms: Vec::new(),
+                max_file_size: None,
+                binary: false,
+                batch_size: None,
+                files_via: None,
+                argfile: None,
+                no_capture: false,
+                absolute_paths: false,
+                before: None,
+                after: None,
+                problem_matcher: None,
+                warns: config::WarnCfg::default(),
             },
             check: Some("taplo format --check --".to_string()),
+            write_back: false,
         },
     ]
 }
 
-pub(crate) fn known_tools_by_name() -> HashMap<String, config::Tool> {
-    let mut m = HashMap::new();
-    for linter in known_linters() {
-        if let Some(name) = &linter.tool.name {
-            debug_assert!(!m.contains_key(name));
-            m.insert(name.clone(), linter.tool);
+/// How to install each known tool, for when it isn't on the `PATH`
+pub(crate) const INSTALL_HINTS: &[(&str, &str)] = &[
+    (
+        "actionlint",
+        "go install github.com/rhysd/actionlint/cmd/actionlint@latest",
+    ),
+    ("biome", "npm install --save-dev @biomejs/biome"),
+    ("black", "pip install black"),
+    ("cargo clippy", "rustup component add clippy"),
+    ("cargo fmt", "rustup component add rustfmt"),
+    ("clang-format", "apt install clang-format"),
+    ("clang-tidy", "apt install clang-tidy"),
+    ("eslint", "npm install --save-dev eslint"),
+    ("gofmt", "install Go (https://go.dev/doc/install)"),
+    (
+        "golangci-lint",
+        "go install github.com/golangci/golangci-lint/v2/cmd/golangci-lint@latest",
+    ),
+    (
+        "hadolint",
+        "download it from https://github.com/hadolint/hadolint/releases",
+    ),
+    ("isort", "pip install isort"),
+    ("markdownlint", "npm install -g markdownlint-cli"),
+    ("mdlynx", "cargo install mdlynx"),
+    ("mypy", "pip install mypy"),
+    ("prettier", "npm install --save-dev prettier"),
+    ("rubocop", "gem install rubocop"),
+    ("ruff check", "pip install ruff"),
+    ("ruff format", "pip install ruff"),
+    ("shellcheck", "apt install shellcheck"),
+    ("shfmt", "go install mvdan.cc/sh/v3/cmd/shfmt@latest"),
+    ("stylua", "cargo install stylua"),
+    ("tagref", "cargo install tagref"),
+    ("taplo", "cargo install taplo-cli --locked"),
+    ("ttlint", "cargo install ttlint"),
+    ("ty", "pip install ty"),
+    ("typos", "cargo install typos-cli"),
+    ("yamllint", "pip install yamllint"),
+    ("zizmor", "pip install zizmor"),
+];
+
+pub(crate) fn install_hint(name: &str) -> Option<&'static str> {
+    INSTALL_HINTS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, hint)| *hint)
+}
+
+/// Config files that each known tool reads in addition to its own, e.g.,
+/// `.editorconfig` or sections of `pyproject.toml`. Unlike its own, these don't
+/// show that a project uses the tool, so `lun init` doesn't detect tools by
+/// them.
+pub(crate) const IMPLICIT_CONFIGS: &[(&str, &[&str])] = &[
+    ("black", &["pyproject.toml"]),
+    ("cargo clippy", &["clippy.toml", ".clippy.toml"]),
+    ("golangci-lint", &["go.mod"]),
+    (
+        "isort",
+        &["pyproject.toml", "setup.cfg", "tox.ini", ".editorconfig"],
+    ),
+    ("mypy", &["setup.cfg"]),
+    ("prettier", &[".editorconfig", ".prettierignore"]),
+    ("ruff format", &["pyproject.toml"]),
+    ("shfmt", &[".editorconfig"]),
+    ("stylua", &[".editorconfig"]),
+    ("typos", &["pyproject.toml", "Cargo.toml"]),
+];
+
+pub(crate) fn implicit_configs(name: &str) -> &'static [&'static str] {
+    IMPLICIT_CONFIGS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map_or(&[], |(_, configs)| *configs)
+}
+
+/// Add the implicit config files of a known tool that exist to its `configs`
+pub(crate) fn add_implicit_configs(tool: &mut config::Tool) {
+    let Some(name) = &tool.name else {
+        return;
+    };
+    for config in implicit_configs(name) {
+        let config = PathBuf::from(config);
+        if config.exists() && !tool.configs.contains(&config) {
+            tool.configs.push(config);
         }
     }
-    for formatter in known_formatters() {
-        if let Some(name) = &formatter.tool.name {
-            debug_assert!(!m.contains_key(name));
-            m.insert(name.clone(), formatter.tool);
+}
+
+/// Tools to suggest for a language in `lun init`, even without config files
+pub(crate) struct Language {
+    pub(crate) name: &'static str,
+    pub(crate) extensions: &'static [&'static str],
+    pub(crate) tools: &'static [&'static str],
+    /// Config file to suggest creating, if none of the tools' exist
+    pub(crate) config: Option<&'static str>,
+}
+
+pub(crate) const LANGUAGES: &[Language] = &[
+    Language {
+        name: "C/C++",
+        extensions: &["c", "cc", "cpp", "cxx", "h", "hh", "hpp", "hxx"],
+        tools: &["clang-format"],
+        config: Some(".clang-format"),
+    },
+    Language {
+        name: "Go",
+        extensions: &["go"],
+        tools: &["gofmt"],
+        config: None,
+    },
+    Language {
+        name: "JavaScript/TypeScript",
+        extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"],
+        tools: &["prettier"],
+        config: Some(".prettierrc"),
+    },
+    Language {
+        name: "Lua",
+        extensions: &["lua"],
+        tools: &["stylua"],
+        config: Some("stylua.toml"),
+    },
+    Language {
+        name: "Markdown",
+        extensions: &["md"],
+        tools: &["mdlynx"],
+        config: None,
+    },
+    Language {
+        name: "Python",
+        extensions: &["py"],
+        tools: &["ruff check", "ruff format"],
+        config: Some("ruff.toml"),
+    },
+    Language {
+        name: "Ruby",
+        extensions: &["rb"],
+        tools: &["rubocop"],
+        config: Some(".rubocop.yml"),
+    },
+    Language {
+        name: "shell",
+        extensions: &["sh"],
+        tools: &["shellcheck", "shfmt"],
+        config: Some(".shellcheckrc"),
+    },
+    Language {
+        name: "TOML",
+        extensions: &["toml"],
+        tools: &["taplo"],
+        config: None,
+    },
+];
+
+/// Definitions of known tools from a registry file
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(default)]
+    linter: Vec<config::Linter>,
+    #[serde(default)]
+    formatter: Vec<config::Formatter>,
+}
+
+/// Known tools, including those defined in registry files
+#[derive(Debug)]
+pub(crate) struct Registry {
+    linters: Vec<config::Linter>,
+    formatters: Vec<config::Formatter>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            linters: known_linters(),
+            formatters: known_formatters(),
         }
     }
-    m
 }
 
-pub(crate) fn known_linter_by_name(name: &str) -> Option<config::Linter> {
-    known_linters()
-        .into_iter()
-        .find(|l| l.tool.name.as_deref() == Some(name))
+impl Registry {
+    /// Built-in tools, then those in `~/.config/lun/tools/*.toml`, then those
+    /// in `path`. Later definitions replace earlier ones with the same name.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self> {
+        let mut registry = Self::default();
+        if let Some(dir) = user_dir() {
+            let mut paths = match fs::read_dir(&dir) {
+                Ok(entries) => entries
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                    .collect::<Vec<_>>(),
+                Err(_) => Vec::new(),
+            };
+            paths.sort();
+            for path in paths {
+                registry.add_file(&path)?;
+            }
+        }
+        if let Some(path) = path {
+            registry.add_file(path)?;
+        }
+        Ok(registry)
+    }
+
+    fn add_file(&mut self, path: &Path) -> Result<()> {
+        debug!("Loading known tools from {}", path.display());
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read known tools: {}", path.display()))?;
+        let file: RegistryFile = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse known tools: {}", path.display()))?;
+        for linter in file.linter {
+            let name = registry_name(path, &linter.tool)?;
+            self.remove(&name);
+            self.linters.push(linter);
+        }
+        for formatter in file.formatter {
+            let name = registry_name(path, &formatter.tool)?;
+            self.remove(&name);
+            self.formatters.push(formatter);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, name: &str) {
+        self.linters
+            .retain(|l| l.tool.name.as_deref() != Some(name));
+        self.formatters
+            .retain(|f| f.tool.name.as_deref() != Some(name));
+    }
+
+    pub(crate) fn linters(&self) -> &[config::Linter] {
+        &self.linters
+    }
+
+    pub(crate) fn formatters(&self) -> &[config::Formatter] {
+        &self.formatters
+    }
+
+    pub(crate) fn tools_by_name(&self) -> HashMap<String, config::Tool> {
+        let mut m = HashMap::new();
+        for linter in &self.linters {
+            if let Some(name) = &linter.tool.name {
+                debug_assert!(!m.contains_key(name));
+                m.insert(name.clone(), linter.tool.clone());
+            }
+        }
+        for formatter in &self.formatters {
+            if let Some(name) = &formatter.tool.name {
+                debug_assert!(!m.contains_key(name));
+                m.insert(name.clone(), formatter.tool.clone());
+            }
+        }
+        m
+    }
+
+    pub(crate) fn linter_by_name(&self, name: &str) -> Option<config::Linter> {
+        self.linters
+            .iter()
+            .find(|l| l.tool.name.as_deref() == Some(name))
+            .cloned()
+    }
+
+    pub(crate) fn formatter_by_name(&self, name: &str) -> Option<config::Formatter> {
+        self.formatters
+            .iter()
+            .find(|f| f.tool.name.as_deref() == Some(name))
+            .cloned()
+    }
+}
+
+/// `$XDG_CONFIG_HOME/lun/tools`, or `~/.config/lun/tools`
+fn user_dir() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(config.join("lun").join("tools"))
+}
+
+fn registry_name(path: &Path, tool: &config::Tool) -> Result<String> {
+    tool.name.clone().with_context(|| {
+        format!(
+            "Tool without a `name` in known tools: {} (`cmd = {:?}`)",
+            path.display(),
+            tool.cmd
+        )
+    })
 }
 
-pub(crate) fn known_formatter_by_name(name: &str) -> Option<config::Formatter> {
-    known_formatters()
-        .into_iter()
-        .find(|f| f.tool.name.as_deref() == Some(name))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_hints_known() {
+        let registry = Registry::default();
+        let known = registry.tools_by_name();
+        for (name, _) in INSTALL_HINTS {
+            assert!(known.contains_key(*name), "{name}");
+        }
+        for name in known.keys() {
+            assert!(install_hint(name).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn implicit_configs_known() {
+        let known = Registry::default().tools_by_name();
+        for (name, configs) in IMPLICIT_CONFIGS {
+            let tool = &known[*name];
+            for config in *configs {
+                assert!(!tool.configs.contains(&PathBuf::from(config)), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn registry_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tools.toml");
+        fs::write(
+            &path,
+            r#"
+            [[linter]]
+            name = "ruff format"
+            cmd = "ruff-format-lint --"
+            files = ["*.py"]
+
+            [[formatter]]
+            name = "fmt"
+            cmd = "fmt --"
+            files = ["*.txt"]
+            "#,
+        )
+        .unwrap();
+        let mut registry = Registry::default();
+        registry.add_file(&path).unwrap();
+        assert_eq!(
+            registry.formatter_by_name("fmt").unwrap().tool.cmd,
+            "fmt --"
+        );
+        assert!(registry.formatter_by_name("ruff format").is_none());
+        assert_eq!(
+            registry.linter_by_name("ruff format").unwrap().tool.cmd,
+            "ruff-format-lint --"
+        );
+    }
+
+    #[test]
+    fn registry_file_needs_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tools.toml");
+        fs::write(&path, "[[linter]]\ncmd = \"lint\"\nfiles = [\"*\"]\n").unwrap();
+        let err = Registry::default().add_file(&path).unwrap_err();
+        assert!(err.to_string().contains("without a `name`"), "{err}");
+    }
 }
@@ -0,0 +1,307 @@
+//! Translating `lefthook.yml` and `.husky/` hooks, for `lun init
+//! --from-lefthook` and `--from-husky`.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context as _, Result};
+use tracing::warn;
+
+use crate::{
+    config::{self, Granularity},
+    known,
+    pre_commit::Translated,
+};
+
+/// Hooks that `lun hook install` can install
+pub(crate) const HOOKS: &[&str] = &["pre-commit", "pre-push"];
+
+/// Lefthook replaces these with the files to run on
+const PLACEHOLDERS: &[&str] = &["{staged_files}", "{push_files}", "{all_files}", "{files}"];
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct Hook {
+    #[serde(default)]
+    commands: BTreeMap<String, Command>,
+    #[serde(default)]
+    jobs: Vec<Job>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Command {
+    run: Option<String>,
+    glob: Option<OneOrMany>,
+    exclude: Option<OneOrMany>,
+    root: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Job {
+    name: Option<String>,
+    #[serde(flatten)]
+    command: Command,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+pub(crate) fn load(path: &Path, registry: &known::Registry) -> Result<Translated> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read lefthook config: {}", path.display()))?;
+    translate(&contents, registry)
+        .with_context(|| format!("Failed to parse lefthook config: {}", path.display()))
+}
+
+fn translate(yaml: &str, registry: &known::Registry) -> Result<Translated> {
+    // Hooks are top-level keys, next to settings like `min_version`
+    let config: BTreeMap<String, serde_yaml_ng::Value> = serde_yaml_ng::from_str(yaml)?;
+    let known = registry.tools_by_name();
+    let mut translated = Translated::default();
+    for (hook_name, value) in config {
+        let Ok(hook) = serde_yaml_ng::from_value::<Hook>(value) else {
+            continue;
+        };
+        if hook.commands.is_empty() && hook.jobs.is_empty() {
+            continue;
+        }
+        if !HOOKS.contains(&hook_name.as_str()) {
+            warn!("Skipping lefthook {hook_name} hook");
+            continue;
+        }
+        translated.hooks.push(hook_name);
+        let jobs = hook
+            .jobs
+            .into_iter()
+            .filter_map(|j| Some((j.name?, j.command)));
+        for (name, command) in hook.commands.into_iter().chain(jobs) {
+            let Some(run) = &command.run else {
+                continue;
+            };
+            let Some((cmd, granularity)) = strip_placeholder(run) else {
+                warn!("{name}: can't translate `run: {run}`, files must come last");
+                continue;
+            };
+            if known.contains_key(&cmd) {
+                if !translated.known.contains(&cmd) {
+                    translated.known.push(cmd);
+                }
+                continue;
+            }
+            let ignore = match command.exclude {
+                None => Vec::new(),
+                Some(OneOrMany::Many(globs)) => globs,
+                Some(OneOrMany::One(regex)) => {
+                    warn!("{name}: can't translate `exclude: {regex}` to globs");
+                    Vec::new()
+                }
+            };
+            translated.custom.push(config::Linter {
+                tool: config::Tool {
+                    name: Some(name),
+                    cmd,
+                    files: match command.glob {
+                        None => vec!["*".to_string()],
+                        Some(OneOrMany::One(glob)) => vec![glob],
+                        Some(OneOrMany::Many(globs)) => globs,
+                    },
+                    ignore,
+                    granularity,
+                    configs: Vec::new(),
+                    env: Vec::new(),
+                    env_stamp: config::EnvStamp::Auto,
+                    cd: command.root.map(Into::into),
+                    cache_failures: None,
+                    dedupe_group: None,
+                    stdin: false,
+                    min_version: None,
+                    version: None,
+                    version_cmd: None,
+                    enabled_if: None,
+                    platforms: Vec::new(),
+                    max_file_size: None,
+                    binary: false,
+                    batch_size: None,
+                    files_via: None,
+                    argfile: None,
+                    no_capture: false,
+                    absolute_paths: false,
+                    before: None,
+                    after: None,
+                    problem_matcher: None,
+                    warns: config::WarnCfg::default(),
+                },
+                fix: None,
+            });
+        }
+    }
+    Ok(translated)
+}
+
+/// Command without a trailing file placeholder, and whether it had one
+fn strip_placeholder(run: &str) -> Option<(String, Granularity)> {
+    let run = run.trim();
+    for placeholder in PLACEHOLDERS {
+        if let Some(cmd) = run.strip_suffix(placeholder) {
+            let cmd = cmd.trim_end();
+            return (!cmd.contains('{')).then(|| (cmd.to_string(), Granularity::Individual));
+        }
+    }
+    (!run.contains('{')).then(|| (run.to_string(), Granularity::Batch))
+}
+
+/// Translate the scripts in a `.husky/` directory
+///
+/// Each command becomes a batched linter that runs on all files.
+pub(crate) fn load_husky(dir: &Path, registry: &known::Registry) -> Result<Translated> {
+    let known = registry.tools_by_name();
+    let mut translated = Translated::default();
+    for hook in HOOKS {
+        let path = dir.join(hook);
+        if !path.exists() {
+            continue;
+        }
+        let script = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read husky hook: {}", path.display()))?;
+        translated.hooks.push((*hook).to_string());
+        for cmd in husky_commands(&script) {
+            if known.contains_key(cmd) {
+                if !translated.known.iter().any(|n| n == cmd) {
+                    translated.known.push(cmd.to_string());
+                }
+                continue;
+            }
+            translated.custom.push(config::Linter {
+                tool: config::Tool {
+                    name: None,
+                    cmd: cmd.to_string(),
+                    files: vec!["*".to_string()],
+                    ignore: Vec::new(),
+                    granularity: Granularity::Batch,
+                    configs: Vec::new(),
+                    env: Vec::new(),
+                    env_stamp: config::EnvStamp::Auto,
+                    cd: None,
+                    cache_failures: None,
+                    dedupe_group: None,
+                    stdin: false,
+                    min_version: None,
+                    version: None,
+                    version_cmd: None,
+                    enabled_if: None,
+                    platforms: Vec::new(),
+                    max_file_size: None,
+                    binary: false,
+                    batch_size: None,
+                    files_via: None,
+                    argfile: None,
+                    no_capture: false,
+                    absolute_paths: false,
+                    before: None,
+                    after: None,
+                    problem_matcher: None,
+                    warns: config::WarnCfg::default(),
+                },
+                fix: None,
+            });
+        }
+    }
+    Ok(translated)
+}
+
+/// Commands of a husky script, without comments or husky's own setup
+fn husky_commands(script: &str) -> impl Iterator<Item = &str> {
+    script
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter(|l| !l.starts_with(". ") && !l.contains("husky.sh"))
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            strip_placeholder("npx eslint {staged_files}"),
+            Some(("npx eslint".to_string(), Granularity::Individual))
+        );
+        assert_eq!(
+            strip_placeholder("make lint"),
+            Some(("make lint".to_string(), Granularity::Batch))
+        );
+        assert_eq!(strip_placeholder("prettier {staged_files} --check"), None);
+    }
+
+    #[test]
+    fn translate_lefthook() {
+        let yaml = r#"
+min_version: 1.5.0
+pre-commit:
+  parallel: true
+  commands:
+    shellcheck:
+      glob: "*.sh"
+      run: shellcheck {staged_files}
+    eslint:
+      glob: "*.{js,ts}"
+      exclude: ["vendor/*"]
+      run: npx eslint {staged_files}
+    web:
+      root: web/
+      run: npm run lint
+pre-push:
+  jobs:
+    - name: test
+      run: cargo test
+commit-msg:
+  commands:
+    commitlint:
+      run: npx commitlint --edit
+"#;
+        let translated = translate(yaml, &known::Registry::default()).unwrap();
+        assert_eq!(translated.known, ["shellcheck"]);
+        assert_eq!(translated.hooks, ["pre-commit", "pre-push"]);
+        let toml = toml::to_string_pretty(&config::Config {
+            linter: translated.custom,
+            ..toml::from_str("").unwrap()
+        })
+        .unwrap();
+        expect![[r#"
+            [[linter]]
+            name = "eslint"
+            cmd = "npx eslint"
+            files = ["*.{js,ts}"]
+            ignore = ["vendor/*"]
+
+            [[linter]]
+            name = "web"
+            cmd = "npm run lint"
+            files = ["*"]
+            granularity = "batch"
+            cd = "web/"
+
+            [[linter]]
+            name = "test"
+            cmd = "cargo test"
+            files = ["*"]
+            granularity = "batch"
+        "#]]
+        .assert_eq(&toml);
+    }
+
+    #[test]
+    fn husky() {
+        let script = "#!/usr/bin/env sh\n. \"$(dirname -- \"$0\")/_/husky.sh\"\n\n# lint\nnpx lint-staged\nshellcheck\n";
+        assert_eq!(
+            husky_commands(script).collect::<Vec<_>>(),
+            ["npx lint-staged", "shellcheck"]
+        );
+    }
+}
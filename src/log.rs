@@ -1,5 +1,3 @@
-use std::io::IsTerminal;
-
 use tracing::{Level, level_filters::LevelFilter};
 use tracing_subscriber::{
     fmt::format::FmtSpan, layer::SubscriberExt as _, util::SubscriberInitExt as _,
@@ -20,11 +18,7 @@ fn verbosity_to_log_level(verbosity: u8) -> Level {
 pub(crate) fn init_tracing(opts: log::LogOptions) {
     let effective_verbosity = opts.verbose.saturating_sub(opts.quiet);
     let verbose = verbosity_to_log_level(effective_verbosity + 1);
-    let ansi = match opts.color {
-        log::Color::Always => true,
-        log::Color::Never => false,
-        log::Color::Auto => std::io::stdout().is_terminal(),
-    };
+    let ansi = opts.color.enabled();
     let tgts = tracing_subscriber::filter::Targets::new()
         .with_target(env!("CARGO_CRATE_NAME"), verbose)
         .with_target("regex", LevelFilter::OFF)
@@ -34,6 +28,7 @@ pub(crate) fn init_tracing(opts: log::LogOptions) {
             .with_span_events(FmtSpan::ENTER | FmtSpan::CLOSE)
             .with_max_level(verbose)
             .with_target(false)
+            .with_writer(std::io::stderr)
             .with_ansi(ansi);
         builder.finish().with(tgts).try_init().unwrap();
     } else {
@@ -41,6 +36,7 @@ pub(crate) fn init_tracing(opts: log::LogOptions) {
             .with_span_events(FmtSpan::ENTER | FmtSpan::CLOSE)
             .with_max_level(verbose)
             .with_target(false)
+            .with_writer(std::io::stderr)
             .with_ansi(ansi)
             .without_time();
         builder.finish().with(tgts).try_init().unwrap();
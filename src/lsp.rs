@@ -0,0 +1,398 @@
+//! A language server that runs the linters on files as they are opened and
+//! saved, and publishes the diagnostics in their output (`lun lsp`).
+//!
+//! This speaks just enough of the Language Server Protocol for that, over
+//! stdio. Diagnostics are parsed with each tool's `problem_matcher`, or in the
+//! `file:line:col: message` form of [`Diagnostic::parse_with_message`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+use crate::{
+    cli, config,
+    diag::{Diagnostic, Problem, Severity, strip_ansi},
+    exec, run, tool,
+    warn::warns::Warns,
+};
+
+/// <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#errorCodes>
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Returns whether the client shut the server down before it exited
+pub(crate) fn go(cli: &cli::Cli, config: &config::Config, lints: &Warns) -> Result<bool> {
+    let run_cli =
+        <cli::Run as clap::Parser>::try_parse_from(["run"]).context("Failed to parse options")?;
+    let mut session = run::Session::new(cli, &run_cli, config, lints)?;
+    let cwd = env::current_dir().context("Failed to get current directory")?;
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    // For each checked file, the other files that its tools reported on
+    let mut published = HashMap::<PathBuf, BTreeSet<PathBuf>>::new();
+    let mut shutdown = false;
+    while let Some(msg) = read(&mut stdin)? {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let id = msg.get("id").cloned();
+        debug!("LSP message: {method}");
+        match method {
+            "initialize" => {
+                let result = json!({
+                    "capabilities": {
+                        "textDocumentSync": { "openClose": true, "save": true },
+                    },
+                    "serverInfo": { "name": "lun", "version": env!("CARGO_PKG_VERSION") },
+                });
+                send(
+                    &mut stdout,
+                    &json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                )?;
+            }
+            "shutdown" => {
+                shutdown = true;
+                send(
+                    &mut stdout,
+                    &json!({ "jsonrpc": "2.0", "id": id, "result": null }),
+                )?;
+            }
+            "exit" => return Ok(shutdown),
+            "textDocument/didOpen" | "textDocument/didSave" => {
+                let uri = msg["params"]["textDocument"]["uri"]
+                    .as_str()
+                    .unwrap_or_default();
+                let Some(path) = uri_to_path(uri).and_then(|p| relative(&cwd, &p)) else {
+                    debug!("Not a file in the workspace: {uri}");
+                    continue;
+                };
+                let outcomes = match session.check(&path) {
+                    Ok(outcomes) => outcomes,
+                    Err(e) => {
+                        warn!("Failed to check {}: {e:#}", path.display());
+                        continue;
+                    }
+                };
+                let diags = diagnostics(session.tools(), &outcomes, &path);
+                let stale = published.remove(&path).unwrap_or_default();
+                for file in stale.iter().filter(|f| !diags.contains_key(*f)) {
+                    publish(&mut stdout, &cwd.join(file), &[])?;
+                }
+                for (file, diags) in &diags {
+                    publish(&mut stdout, &cwd.join(file), diags)?;
+                }
+                published.insert(path, diags.into_keys().collect());
+            }
+            _ if id.is_some() => {
+                let error = json!({ "code": METHOD_NOT_FOUND, "message": format!("Unknown method: {method}") });
+                send(
+                    &mut stdout,
+                    &json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                )?;
+            }
+            _ => {} // other notifications
+        }
+    }
+    Ok(shutdown)
+}
+
+/// Read a message, or `None` at the end of the input
+fn read(r: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)
+            .context("Failed to read LSP header")?
+            == 0
+        {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            len = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .context("Bad Content-Length")?,
+            );
+        }
+    }
+    let len = len.context("LSP message without Content-Length")?;
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)
+        .context("Failed to read LSP message")?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .context("Failed to parse LSP message")
+}
+
+fn send(w: &mut impl Write, msg: &Value) -> Result<()> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{body}", body.len())
+        .and_then(|()| w.flush())
+        .context("Failed to write LSP message")
+}
+
+fn publish(w: &mut impl Write, path: &Path, diags: &[Value]) -> Result<()> {
+    let params = json!({ "uri": path_to_uri(path), "diagnostics": diags });
+    send(
+        w,
+        &json!({ "jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": params }),
+    )
+}
+
+/// LSP diagnostics by file, always including `path`
+fn diagnostics(
+    tools: &[tool::Tool],
+    outcomes: &[exec::Outcome],
+    path: &Path,
+) -> BTreeMap<PathBuf, Vec<Value>> {
+    let mut diags = BTreeMap::<PathBuf, Vec<Value>>::new();
+    diags.insert(path.to_path_buf(), Vec::new());
+    for outcome in outcomes.iter().filter(|o| !o.success) {
+        let Some(output) = &outcome.output else {
+            continue;
+        };
+        let matcher = tools
+            .iter()
+            .find(|t| t.display_name() == outcome.tool)
+            .and_then(|t| t.problem_matcher.as_ref());
+        let mut found = false;
+        for stream in [&output.stdout, &output.stderr] {
+            for problem in Problem::parse_output(matcher, &String::from_utf8_lossy(stream)) {
+                // Tools with `cd` print paths relative to it
+                let file = match &outcome.cd {
+                    Some(cd) if problem.diag.file.is_relative() => cd.join(&problem.diag.file),
+                    _ => problem.diag.file.clone(),
+                };
+                let file = normalize(&file);
+                diags
+                    .entry(file)
+                    .or_default()
+                    .push(to_lsp(&outcome.tool, &problem));
+                found = true;
+            }
+        }
+        if !found {
+            // E.g., a crash, or a tool that doesn't say where the problem is
+            let mut message = format!("{} failed", outcome.tool);
+            for stream in [&output.stdout, &output.stderr] {
+                let text = strip_ansi(&String::from_utf8_lossy(stream));
+                if !text.trim().is_empty() {
+                    message.push_str("\n\n");
+                    message.push_str(text.trim());
+                }
+            }
+            let problem = Problem {
+                diag: Diagnostic {
+                    file: path.to_path_buf(),
+                    line: 1,
+                    col: None,
+                    code: None,
+                },
+                message,
+                severity: Severity::Error,
+            };
+            diags
+                .entry(path.to_path_buf())
+                .or_default()
+                .push(to_lsp(&outcome.tool, &problem));
+        }
+    }
+    diags
+}
+
+fn to_lsp(tool: &str, problem: &Problem) -> Value {
+    // LSP lines and columns are zero-based
+    let line = problem.diag.line.saturating_sub(1);
+    let (start, end) = match problem.diag.col {
+        Some(col) => (
+            json!({ "line": line, "character": col.saturating_sub(1) }),
+            json!({ "line": line, "character": col }),
+        ),
+        None => (
+            json!({ "line": line, "character": 0 }),
+            json!({ "line": line + 1, "character": 0 }),
+        ),
+    };
+    let severity = match problem.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Info => 3,
+        Severity::Hint => 4,
+    };
+    let mut diag = json!({
+        "range": { "start": start, "end": end },
+        "severity": severity,
+        "source": tool,
+        "message": problem.message,
+    });
+    if let Some(code) = &problem.diag.code {
+        diag["code"] = json!(code);
+    }
+    diag
+}
+
+/// Remove `.` components, as tools often print `./a.py`
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// `path` relative to `cwd`, where the collected files are relative to it
+fn relative(cwd: &Path, path: &Path) -> Option<PathBuf> {
+    path.strip_prefix(cwd).ok().map(normalize)
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%'
+            && let Some(hex) = tail.get(..2)
+            && let Ok(hex) = std::str::from_utf8(hex)
+            && let Ok(decoded) = u8::from_str_radix(hex, 16)
+        {
+            bytes.push(decoded);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.as_os_str().as_encoded_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(b) {
+            uri.push(char::from(*b));
+        } else {
+            #[allow(clippy::unwrap_used)]
+            write!(uri, "%{b:02X}").unwrap();
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use expect_test::expect;
+
+    use super::*;
+    use crate::results;
+
+    #[test]
+    fn uri() {
+        let path = Path::new("/home/me/my project/a#1.py");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/me/my%20project/a%231.py");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn framing() {
+        let mut out = Vec::new();
+        send(&mut out, &json!({ "id": 1 })).unwrap();
+        assert_eq!(out, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+        let mut r = io::Cursor::new(out);
+        assert_eq!(read(&mut r).unwrap(), Some(json!({ "id": 1 })));
+        assert_eq!(read(&mut r).unwrap(), None);
+    }
+
+    #[test]
+    fn diagnostics_by_file() {
+        let outcome = |cd: Option<&str>, stdout: &str| exec::Outcome {
+            tool: "lint".to_string(),
+            cmd: "lint".to_string(),
+            files: Vec::new(),
+            cd: cd.map(PathBuf::from),
+            modified: Vec::new(),
+            success: false,
+            output: Some(results::Output {
+                code: 1,
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            }),
+            replayed: false,
+            sandbox_violation: false,
+            duration: Duration::ZERO,
+        };
+        let outcomes = [
+            outcome(None, "./a.py:1:8: F401 `os` imported but unused\n"),
+            outcome(Some("web"), "b.ts:2: bad\n"),
+            outcome(None, "Segmentation fault\n"),
+        ];
+        let diags = diagnostics(&[], &outcomes, Path::new("a.py"));
+        expect![[r#"
+            {
+              "a.py": [
+                {
+                  "code": "F401",
+                  "message": "F401 `os` imported but unused",
+                  "range": {
+                    "end": {
+                      "character": 8,
+                      "line": 0
+                    },
+                    "start": {
+                      "character": 7,
+                      "line": 0
+                    }
+                  },
+                  "severity": 1,
+                  "source": "lint"
+                },
+                {
+                  "message": "lint failed\n\nSegmentation fault",
+                  "range": {
+                    "end": {
+                      "character": 0,
+                      "line": 1
+                    },
+                    "start": {
+                      "character": 0,
+                      "line": 0
+                    }
+                  },
+                  "severity": 1,
+                  "source": "lint"
+                }
+              ],
+              "web/b.ts": [
+                {
+                  "message": "bad",
+                  "range": {
+                    "end": {
+                      "character": 0,
+                      "line": 2
+                    },
+                    "start": {
+                      "character": 0,
+                      "line": 1
+                    }
+                  },
+                  "severity": 1,
+                  "source": "lint"
+                }
+              ]
+            }"#]]
+        .assert_eq(&serde_json::to_string_pretty(&diags).unwrap());
+    }
+}
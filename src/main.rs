@@ -3,37 +3,76 @@
 #![cfg_attr(not(test), warn(clippy::unwrap_used))]
 
 mod add;
+mod adhoc;
+mod aged;
+mod atomic;
+mod baseline;
+mod bench;
 mod cache;
 mod cli;
 mod cmd;
 mod config;
+mod daemon;
+mod desktop;
+mod diag;
+mod diff;
 mod entry;
 mod exec;
+mod exit;
 mod file;
 mod git;
+mod gitattributes;
+mod hook;
+mod index;
 mod init;
+mod interrupt;
 mod job;
+mod keys;
 mod known;
+mod lefthook;
 mod log;
+mod lsp;
+mod matcher;
 mod ninja;
+mod pattern;
 mod plan;
+mod plan_file;
+mod pre_commit;
+mod profile;
+mod quickfix;
+mod results;
+mod root;
 mod run;
+mod sandbox;
+mod setup;
 mod staged;
+mod tap;
+mod timings;
 mod tool;
+mod toolchain;
+mod treefmt;
+mod version;
 mod warn;
+mod width;
 
 #[cfg(test)]
 mod test;
 
-use anyhow::Result;
-use clap::Parser as _;
-use std::process;
+use anyhow::{Context as _, Result};
+use std::{fs, process};
 use tracing::trace;
 
 #[cfg(feature = "dhat")]
 #[global_allocator]
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
+/// The config file, for commands that need one
+fn required(cli: &cli::Cli, config: Option<config::Config>) -> Result<config::Config> {
+    config
+        .with_context(|| format!("Config file not found: {}", cli.config.display()))
+        .context(exit::ConfigError)
+}
+
 pub(crate) fn go(cli: cli::Cli, config: Option<config::Config>) -> Result<bool> {
     let lints = warn::warns::Warns::from_cli_and_config(&cli.warn, config.as_ref())?;
     match &cli.command {
@@ -42,9 +81,11 @@ pub(crate) fn go(cli: cli::Cli, config: Option<config::Config>) -> Result<bool>
                 cache::rm(&cli.cache)?;
                 Ok(true)
             }
-            cli::CacheCommand::Gc { size } => {
+            cli::CacheCommand::Gc { size, max_age } => {
                 let cache_file = cli.cache.join("cache");
-                cache::gc(&cache_file, *size)?;
+                let max_age = max_age.or(config.as_ref().and_then(|c| c.cache_max_age));
+                cache::gc(&cache_file, *size, max_age)?;
+                results::Results::new(&cli.cache, false).clear()?;
                 Ok(true)
             }
             cli::CacheCommand::Stats => {
@@ -52,6 +93,36 @@ pub(crate) fn go(cli: cli::Cli, config: Option<config::Config>) -> Result<bool>
                 cache::stats(&cache_file)?;
                 Ok(true)
             }
+            cli::CacheCommand::Export { path } => {
+                let cache_file = cli.cache.join("cache");
+                cache::export(&cache_file, path)?;
+                Ok(true)
+            }
+            cli::CacheCommand::Import { path } => {
+                fs::create_dir_all(&cli.cache).with_context(|| {
+                    format!("Failed to create cache directory: {}", cli.cache.display())
+                })?;
+                let cache_file = cli.cache.join("cache");
+                let size = config.as_ref().and_then(|c| c.cache_size);
+                cache::import(&cache_file, path, size)?;
+                Ok(true)
+            }
+            cli::CacheCommand::Key {
+                tool,
+                file,
+                careful,
+                check,
+                fix,
+            } => {
+                let config = required(&cli, config)?;
+                let mode = run::RunMode::new(*check, *fix);
+                let careful = careful.unwrap_or_default().max(config.careful);
+                let tool = config
+                    .tool_by_name(tool, mode, careful, cli.log.color)?
+                    .ok_or_else(|| anyhow::anyhow!("Unknown tool: {tool}"))?;
+                cache::key(&cli.cache.join("cache"), &tool, file)?;
+                Ok(true)
+            }
             cli::CacheCommand::Entry(entry_cmd) => {
                 let cache_file = cli.cache.join("cache");
                 match &entry_cmd.command {
@@ -63,27 +134,88 @@ pub(crate) fn go(cli: cli::Cli, config: Option<config::Config>) -> Result<bool>
                         key,
                         files,
                         null_separated,
+                        present,
+                        missing,
                     } => {
-                        entry::get(&cache_file, key, files, *null_separated)?;
+                        if *present || *missing {
+                            entry::get_paths(&cache_file, key, files, *missing, *null_separated)?;
+                        } else {
+                            entry::get(&cache_file, key, files, *null_separated)?;
+                        }
                         Ok(true)
                     }
-                    cli::CacheEntryCommand::Rm { key, files } => {
-                        entry::rm(&cache_file, key, files)?;
+                    cli::CacheEntryCommand::Rm {
+                        key,
+                        files,
+                        null_separated,
+                        removed,
+                    } => {
+                        if *removed {
+                            entry::rm_paths(&cache_file, key, files, *null_separated)?;
+                        } else {
+                            entry::rm(&cache_file, key, files, *null_separated)?;
+                        }
                         Ok(true)
                     }
                 }
             }
         },
         cli::Command::Run(run) => {
-            let config = config.ok_or_else(|| anyhow::anyhow!("Config file not found"))?;
+            let config = required(&cli, config)?;
             run::go(&cli, run, &config, &lints).map(bool::from)
         }
+        cli::Command::Plan(plan) => {
+            let config = required(&cli, config)?;
+            plan_file::go(&cli, plan, &config, &lints)?;
+            Ok(true)
+        }
+        cli::Command::Exec(exec) => {
+            let config = adhoc::config(exec, config)?;
+            run::go(&cli, &exec.run, &config, &lints).map(bool::from)
+        }
         cli::Command::Init(init) => {
             init::go(&cli.config, init)?;
             Ok(true)
         }
         cli::Command::Add(add) => {
-            add::go(&cli.config, add)?;
+            let known_tools = config.as_ref().and_then(|c| c.known_tools.as_deref());
+            add::go(&cli.config, add, &known::Registry::load(known_tools)?)?;
+            Ok(true)
+        }
+        cli::Command::Config(config_cmd) => match &config_cmd.command {
+            cli::ConfigCommand::Export { format } => {
+                let config = required(&cli, config)?;
+                match format {
+                    cli::ExportFormat::Treefmt => print!("{}", treefmt::export(&config)?),
+                }
+                Ok(true)
+            }
+        },
+        cli::Command::Hook(hook_cmd) => match &hook_cmd.command {
+            cli::HookCommand::Install { hook, force } => {
+                hook::install(hook, *force)?;
+                Ok(true)
+            }
+        },
+        cli::Command::Lsp => {
+            let config = required(&cli, config)?;
+            lsp::go(&cli, &config, &lints)
+        }
+        cli::Command::Daemon => {
+            let config = required(&cli, config)?;
+            daemon::serve(&cli, &config, &lints)?;
+            Ok(true)
+        }
+        cli::Command::Baseline(baseline_cmd) => match &baseline_cmd.command {
+            cli::BaselineCommand::Create { run } => {
+                let config = required(&cli, config)?;
+                run::create_baseline(&cli, run, &config, &lints)?;
+                Ok(true)
+            }
+        },
+        cli::Command::Bench(bench) => {
+            let config = required(&cli, config)?;
+            bench::go(&cli, bench, &config, &lints)?;
             Ok(true)
         }
         cli::Command::Warns { warn } => {
@@ -93,18 +225,42 @@ pub(crate) fn go(cli: cli::Cli, config: Option<config::Config>) -> Result<bool>
     }
 }
 
-fn main() -> Result<()> {
+/// Parse the command line and the config file, and run the command
+fn try_main() -> Result<bool> {
+    let mut cli = cli::Cli::parse();
+    log::init_tracing(cli.log);
+    root::enter(&mut cli)?;
+    let config =
+        config::Config::load(&cli.config, &cli.config_overlays).context(exit::ConfigError)?;
+    trace!(?config);
+    cli.resolve_cache(config.as_ref());
+    trace!(?cli);
+    if matches!(
+        cli.command,
+        cli::Command::Run(_)
+            | cli::Command::Exec(_)
+            | cli::Command::Daemon
+            | cli::Command::Bench(_)
+    ) {
+        interrupt::install()?;
+    }
+    go(cli, config)
+}
+
+fn main() {
     #[cfg(feature = "dhat")]
     let _profiler = dhat::Profiler::new_heap();
 
-    let cli = cli::Cli::parse();
-    log::init_tracing(cli.log);
-    trace!(?cli);
-    let config = config::Config::load(&cli.config)?;
-    trace!(?config);
-    let ok = go(cli, config)?;
-    if !ok {
-        process::exit(1);
+    match try_main() {
+        Ok(_) if interrupt::interrupted() => process::exit(interrupt::EXIT_CODE),
+        Ok(true) => (),
+        Ok(false) => process::exit(exit::TOOL_FAILURE),
+        Err(err) => {
+            #[allow(clippy::use_debug)] // like `anyhow`'s `Termination` for `Result`
+            {
+                eprintln!("Error: {err:?}");
+            }
+            process::exit(exit::code(&err));
+        }
     }
-    Ok(())
 }
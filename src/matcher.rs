@@ -0,0 +1,438 @@
+//! Problem matchers for the output of known tools, for `problem_matcher =
+//! "NAME"`. Unlike regexes, they match diagnostics that span several lines,
+//! like those of `cargo clippy`, `eslint`, and `shellcheck`.
+
+use std::{path::PathBuf, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::diag::{self, Diagnostic, Problem, Severity};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Builtin {
+    /// Human-readable, short, or JSON (`--message-format=json`) output
+    Clippy,
+    /// The `stylish` (default) or `unix` format
+    Eslint,
+    Mypy,
+    Ruff,
+    /// The `tty` (default) or `gcc` format
+    Shellcheck,
+}
+
+const NAMES: [(&str, Builtin); 5] = [
+    ("clippy", Builtin::Clippy),
+    ("eslint", Builtin::Eslint),
+    ("mypy", Builtin::Mypy),
+    ("ruff", Builtin::Ruff),
+    ("shellcheck", Builtin::Shellcheck),
+];
+
+fn regex(re: &str) -> Regex {
+    #[allow(clippy::unwrap_used)] // see `tests::builtins`
+    Regex::new(re).unwrap()
+}
+
+/// `file:line:col: ...`, as many tools print
+const LOCATION: &str = r"^(?<file>[^\s:][^:]*):(?<line>\d+):(?<col>\d+):";
+
+static CLIPPY_HEADER: LazyLock<Regex> =
+    LazyLock::new(|| regex(r"^(?<severity>error|warning)(\[(?<code>[^\]]+)\])?: (?<message>.*)$"));
+static CLIPPY_ARROW: LazyLock<Regex> =
+    LazyLock::new(|| regex(r"^\s*--> (?<file>[^:]+):(?<line>\d+):(?<col>\d+)$"));
+static CLIPPY_SHORT: LazyLock<Regex> = LazyLock::new(|| {
+    regex(&format!(
+        r"{LOCATION} (?<severity>error|warning)(\[(?<code>[^\]]+)\])?: (?<message>.*)$"
+    ))
+});
+static ESLINT_STYLISH: LazyLock<Regex> = LazyLock::new(|| {
+    regex(
+        r"^\s+(?<line>\d+):(?<col>\d+)\s+(?<severity>error|warning)\s+(?<message>.+?)(\s{2,}(?<code>\S+))?$",
+    )
+});
+static ESLINT_UNIX: LazyLock<Regex> = LazyLock::new(|| {
+    regex(&format!(
+        r"{LOCATION} (?<message>.*) \[(?<severity>\w+)/(?<code>[^\]]+)\]$"
+    ))
+});
+static MYPY: LazyLock<Regex> = LazyLock::new(|| {
+    regex(
+        r"^(?<file>[^\s:][^:]*):(?<line>\d+):((?<col>\d+):)? (?<severity>error|warning|note): (?<message>.*?)(  \[(?<code>[a-z0-9-]+)\])?$",
+    )
+});
+static RUFF: LazyLock<Regex> = LazyLock::new(|| {
+    regex(&format!(
+        r"{LOCATION} (?<code>[A-Z]+[0-9]+) (\[\*\] )?(?<message>.*)$"
+    ))
+});
+static SHELLCHECK_HEADER: LazyLock<Regex> =
+    LazyLock::new(|| regex(r"^In (?<file>.+) line (?<line>\d+):$"));
+static SHELLCHECK_CARET: LazyLock<Regex> = LazyLock::new(|| {
+    regex(r"^(?<indent>\s*)\^-*\s*(?<code>SC\d+)( \((?<severity>\w+)\))?: (?<message>.*)$")
+});
+static SHELLCHECK_GCC: LazyLock<Regex> = LazyLock::new(|| {
+    regex(&format!(
+        r"{LOCATION} (?<severity>\w+): (?<message>.*) \[(?<code>SC\d+)\]$"
+    ))
+});
+
+impl Builtin {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        NAMES.iter().find(|(n, _)| *n == name).map(|(_, b)| *b)
+    }
+
+    pub(crate) fn parse(self, output: &str) -> Vec<Problem> {
+        let lines = output.lines().map(diag::strip_ansi);
+        match self {
+            Builtin::Clippy => clippy(lines),
+            Builtin::Eslint => eslint(lines),
+            Builtin::Mypy => lines.filter_map(|l| diag::captures(&MYPY, &l)).collect(),
+            Builtin::Ruff => lines.filter_map(|l| diag::captures(&RUFF, &l)).collect(),
+            Builtin::Shellcheck => shellcheck(lines),
+        }
+    }
+}
+
+/// A diagnostic that was announced on an earlier line
+struct Pending {
+    severity: Severity,
+    code: Option<String>,
+    message: String,
+}
+
+fn clippy(lines: impl Iterator<Item = String>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut pending = None;
+    for line in lines {
+        if line.starts_with('{') {
+            problems.extend(clippy_json(&line));
+        } else if let Some(problem) = diag::captures(&CLIPPY_SHORT, &line) {
+            problems.push(problem);
+        } else if let Some(caps) = CLIPPY_HEADER.captures(&line) {
+            pending = Some(Pending {
+                severity: Severity::parse(&caps["severity"]),
+                code: caps.name("code").map(|m| m.as_str().to_string()),
+                message: caps["message"].to_string(),
+            });
+        } else if let Some(caps) = CLIPPY_ARROW.captures(&line)
+            && let Some(Pending {
+                severity,
+                code,
+                message,
+            }) = pending.take()
+        {
+            let (Ok(line), Ok(col)) = (caps["line"].parse(), caps["col"].parse()) else {
+                continue;
+            };
+            problems.push(Problem {
+                diag: Diagnostic {
+                    file: PathBuf::from(&caps["file"]),
+                    line,
+                    col: Some(col),
+                    code,
+                },
+                message,
+                severity,
+            });
+        }
+    }
+    problems
+}
+
+/// A `compiler-message` of `cargo --message-format=json`
+fn clippy_json(line: &str) -> Option<Problem> {
+    let json = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    if json["reason"] != "compiler-message" {
+        return None;
+    }
+    let message = &json["message"];
+    let span = message["spans"]
+        .as_array()?
+        .iter()
+        .find(|s| s["is_primary"] == true)?;
+    let number = |v: &serde_json::Value| v.as_u64().and_then(|n| u32::try_from(n).ok());
+    Some(Problem {
+        diag: Diagnostic {
+            file: PathBuf::from(span["file_name"].as_str()?),
+            line: number(&span["line_start"])?,
+            col: number(&span["column_start"]),
+            code: message["code"]["code"].as_str().map(str::to_string),
+        },
+        message: message["message"].as_str()?.to_string(),
+        severity: Severity::parse(message["level"].as_str().unwrap_or_default()),
+    })
+}
+
+fn eslint(lines: impl Iterator<Item = String>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut file = None;
+    for line in lines {
+        if let Some(problem) = diag::captures(&ESLINT_UNIX, &line) {
+            problems.push(problem);
+        } else if let Some(caps) = ESLINT_STYLISH.captures(&line)
+            && let Some(file) = &file
+        {
+            let (Ok(line), Ok(col)) = (caps["line"].parse(), caps["col"].parse()) else {
+                continue;
+            };
+            problems.push(Problem {
+                diag: Diagnostic {
+                    file: PathBuf::from(file),
+                    line,
+                    col: Some(col),
+                    code: caps.name("code").map(|m| m.as_str().to_string()),
+                },
+                message: caps["message"].to_string(),
+                severity: Severity::parse(&caps["severity"]),
+            });
+        } else if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+            // Each file's problems follow its path
+            file = Some(line);
+        }
+    }
+    problems
+}
+
+fn shellcheck(lines: impl Iterator<Item = String>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut location = None;
+    for line in lines {
+        if let Some(problem) = diag::captures(&SHELLCHECK_GCC, &line) {
+            problems.push(problem);
+        } else if let Some(caps) = SHELLCHECK_HEADER.captures(&line) {
+            location = caps["line"]
+                .parse::<u32>()
+                .ok()
+                .map(|n| (PathBuf::from(&caps["file"]), n));
+        } else if let Some(caps) = SHELLCHECK_CARET.captures(&line)
+            && let Some((file, line)) = &location
+        {
+            // The caret points at the column in the quoted source line
+            let col = u32::try_from(caps["indent"].len() + 1).ok();
+            problems.push(Problem {
+                diag: Diagnostic {
+                    file: file.clone(),
+                    line: *line,
+                    col,
+                    code: Some(caps["code"].to_string()),
+                },
+                message: caps["message"].to_string(),
+                severity: caps
+                    .name("severity")
+                    .map_or(Severity::Error, |s| Severity::parse(s.as_str())),
+            });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(clippy::type_complexity)]
+    fn parse(
+        name: &str,
+        output: &str,
+    ) -> Vec<(String, u32, Option<u32>, Option<String>, Severity, String)> {
+        Builtin::from_name(name)
+            .unwrap()
+            .parse(output)
+            .into_iter()
+            .map(|p| {
+                (
+                    p.diag.file.display().to_string(),
+                    p.diag.line,
+                    p.diag.col,
+                    p.diag.code,
+                    p.severity,
+                    p.message,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn builtins() {
+        for (name, _) in NAMES {
+            assert!(parse(name, "").is_empty());
+        }
+        assert!(Builtin::from_name("rustc").is_none());
+    }
+
+    #[test]
+    fn clippy() {
+        let human = "\
+warning: unused variable: `x`
+ --> src/main.rs:2:9
+  |
+2 |     let x = 1;
+  |         ^ help: if this is intentional, prefix it with an underscore: `_x`
+  |
+error[E0425]: cannot find value `y` in this scope
+  --> src/lib.rs:10:5
+warning: 1 warning emitted
+src/a.rs:1:2: warning: short
+{\"reason\":\"compiler-message\",\"message\":{\"message\":\"json\",\"level\":\"warning\",\"code\":{\"code\":\"clippy::needless_return\"},\"spans\":[{\"file_name\":\"src/b.rs\",\"line_start\":3,\"column_start\":4,\"is_primary\":true}]}}
+{\"reason\":\"build-finished\",\"success\":false}
+";
+        assert_eq!(
+            parse("clippy", human),
+            [
+                (
+                    "src/main.rs".to_string(),
+                    2,
+                    Some(9),
+                    None,
+                    Severity::Warning,
+                    "unused variable: `x`".to_string()
+                ),
+                (
+                    "src/lib.rs".to_string(),
+                    10,
+                    Some(5),
+                    Some("E0425".to_string()),
+                    Severity::Error,
+                    "cannot find value `y` in this scope".to_string()
+                ),
+                (
+                    "src/a.rs".to_string(),
+                    1,
+                    Some(2),
+                    None,
+                    Severity::Warning,
+                    "short".to_string()
+                ),
+                (
+                    "src/b.rs".to_string(),
+                    3,
+                    Some(4),
+                    Some("clippy::needless_return".to_string()),
+                    Severity::Warning,
+                    "json".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn eslint() {
+        let stylish = "
+/repo/a.js
+  1:7  error    'x' is assigned a value but never used  no-unused-vars
+  2:1  warning  Unexpected console statement            no-console
+
+\u{2716} 2 problems (1 error, 1 warning)
+";
+        assert_eq!(
+            parse("eslint", stylish),
+            [
+                (
+                    "/repo/a.js".to_string(),
+                    1,
+                    Some(7),
+                    Some("no-unused-vars".to_string()),
+                    Severity::Error,
+                    "'x' is assigned a value but never used".to_string()
+                ),
+                (
+                    "/repo/a.js".to_string(),
+                    2,
+                    Some(1),
+                    Some("no-console".to_string()),
+                    Severity::Warning,
+                    "Unexpected console statement".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            parse(
+                "eslint",
+                "/repo/b.js:3:5: Missing semicolon. [Warning/semi]\n"
+            ),
+            [(
+                "/repo/b.js".to_string(),
+                3,
+                Some(5),
+                Some("semi".to_string()),
+                Severity::Warning,
+                "Missing semicolon.".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn mypy_and_ruff() {
+        assert_eq!(
+            parse(
+                "mypy",
+                "a.py:3: error: Incompatible types  [assignment]\na.py:4:2: note: See docs\nFound 1 error in 1 file\n"
+            ),
+            [
+                (
+                    "a.py".to_string(),
+                    3,
+                    None,
+                    Some("assignment".to_string()),
+                    Severity::Error,
+                    "Incompatible types".to_string()
+                ),
+                (
+                    "a.py".to_string(),
+                    4,
+                    Some(2),
+                    None,
+                    Severity::Info,
+                    "See docs".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            parse(
+                "ruff",
+                "a.py:1:8: F401 [*] `os` imported but unused\n  |\n1 | import os\nFound 1 error.\n"
+            ),
+            [(
+                "a.py".to_string(),
+                1,
+                Some(8),
+                Some("F401".to_string()),
+                Severity::Error,
+                "`os` imported but unused".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn shellcheck() {
+        let tty = "
+In a.sh line 3:
+echo $x
+     ^-- SC2086 (info): Double quote to prevent globbing and word splitting.
+
+For more information:
+";
+        assert_eq!(
+            parse("shellcheck", tty),
+            [(
+                "a.sh".to_string(),
+                3,
+                Some(6),
+                Some("SC2086".to_string()),
+                Severity::Info,
+                "Double quote to prevent globbing and word splitting.".to_string()
+            )]
+        );
+        assert_eq!(
+            parse("shellcheck", "a.sh:4:1: warning: x is unused [SC2034]\n"),
+            [(
+                "a.sh".to_string(),
+                4,
+                Some(1),
+                Some("SC2034".to_string()),
+                Severity::Warning,
+                "x is unused".to_string()
+            )]
+        );
+    }
+}
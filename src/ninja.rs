@@ -2,23 +2,34 @@
 #![allow(clippy::panic)]
 #![allow(clippy::unwrap_used)]
 
-use std::{collections::HashSet, fmt::Write, fs, num::NonZeroUsize, path::Path, process};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead as _, BufReader, Read as _},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process, thread,
+};
 
 use anyhow::{Context as _, Result};
 use tracing::{debug, error, trace};
 use xxhash_rust::xxh3::Xxh3;
 
-use crate::{cache, cache::CacheWriter, cmd};
+use crate::{atomic, cache, cache::CacheWriter, cmd, exec, interrupt, job, tool};
+
+/// Prefix of Ninja's progress lines, see [`parse_status`]
+const STATUS: &str = "[%s/%t] ";
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn exec(
     cache: &mut (impl CacheWriter + ?Sized),
     cache_dir: &Path,
-    batches: Vec<cmd::Command>,
+    mut batches: Vec<cmd::Command>,
     cores: NonZeroUsize,
     dry_run: bool,
     no_capture: bool,
-    keep_going: bool,
+    format: exec::ProgressFormat,
+    keep_going: usize,
     mtime_enabled: bool,
 ) -> Result<bool> {
     let ninja_file = cache_dir.join("build.ninja");
@@ -27,7 +38,7 @@ pub(crate) fn exec(
         return Ok(true);
     }
 
-    generate_ninja_file(cache_dir, &ninja_file, &batches)?;
+    generate_ninja_file(cache_dir, &ninja_file, &mut batches)?;
     if dry_run {
         return Ok(true);
     }
@@ -35,67 +46,142 @@ pub(crate) fn exec(
     let mut cmd = process::Command::new("ninja");
     cmd.arg("-f").arg(&ninja_file);
     cmd.arg("-j").arg(cores.get().to_string());
-    if keep_going {
-        cmd.args(["-k", "0"]);
-    }
+    cmd.arg("-k").arg(keep_going.to_string());
     debug!("Running ninja -f {}", ninja_file.display());
 
     let builddir = cache_dir.join("ninja");
     fs::create_dir_all(&builddir)
         .with_context(|| format!("Failed to create builddir: {}", builddir.display()))?;
 
-    let executed_targets = if no_capture {
-        let status = cmd
-            .status()
-            .context("Failed to execute ninja. Is ninja installed?")?;
-        if !status.success() {
-            return Ok(false);
-        }
-        // When not capturing output, we can't parse which targets were executed,
-        // so mark all targets as executed if ninja succeeded
-        batches.iter().map(tgt_name).collect()
+    remove_targets(&builddir, &batches)?;
+    let success = if no_capture {
+        interrupt::status(&mut cmd)
+            .context("Failed to execute ninja. Is ninja installed?")?
+            .success()
     } else {
-        let out = cmd
-            .output()
-            .context("Failed to execute ninja. Is ninja installed?")?;
-        let stdout = String::from_utf8_lossy(&out.stdout);
-        let stderr = String::from_utf8_lossy(&out.stderr);
-        trace!("{stdout}");
-        trace!("{stderr}");
-        if !out.status.success() {
-            error!("{stdout}\n{stderr}");
-            return Ok(false);
-        }
-        parse_ninja_output(&stdout, &stderr, &batches, &builddir)
+        run_captured(cmd, format)?
     };
 
+    let all_done = record(cache, &builddir, &batches, mtime_enabled);
+    Ok(success && all_done)
+}
+
+/// Remove the targets of `batches`, so that Ninja doesn't consider them up
+/// to date if their files changed since they were last touched
+fn remove_targets(builddir: &Path, batches: &[cmd::Command]) -> Result<()> {
     for cmd in batches {
-        let target_name = tgt_name(&cmd);
-        if executed_targets.contains(&target_name) {
-            let tool = cmd.tool.clone();
-            for file in &cmd.files {
-                debug_assert!(file.content_stamp.is_some()); // in plan.rs
-                let content_key = cache::Key::from_content(file, &tool);
-                cache.done(&content_key);
-                if mtime_enabled {
-                    let mtime_key = cache::Key::from_mtime(file, &tool);
-                    cache.done(&mtime_key);
-                }
+        let target = target(builddir, cmd);
+        match fs::remove_file(&target) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| {
+                    format!("Failed to remove Ninja target: {}", target.display())
+                });
             }
+            _ => {}
         }
     }
+    Ok(())
+}
 
-    Ok(true)
+/// Record the files of the commands that succeeded in the cache, and return
+/// whether they all did.
+///
+/// Targets are files that are touched when their command succeeds, see
+/// `generate_ninja_file`, and removed before running Ninja, see
+/// [`remove_targets`], so whether they exist tells which commands succeeded
+/// (even if others failed), regardless of how Ninja reports progress.
+fn record(
+    cache: &mut (impl CacheWriter + ?Sized),
+    builddir: &Path,
+    batches: &[cmd::Command],
+    mtime_enabled: bool,
+) -> bool {
+    let mut all_done = true;
+    for cmd in batches {
+        if !target(builddir, cmd).exists() {
+            all_done = false;
+            continue;
+        }
+        let tool = cmd.tool.clone();
+        for file in &cmd.files {
+            debug_assert!(file.content_stamp.is_some()); // in plan.rs
+            if file.modified() {
+                continue; // see exec::done
+            }
+            let content_key = cache::Key::from_content(file, &tool);
+            cache.done(&content_key);
+            if mtime_enabled {
+                let mtime_key = cache::Key::from_mtime(file, &tool);
+                cache.done(&mtime_key);
+            }
+        }
+    }
+    all_done
+}
+
+/// Run Ninja, reporting its progress like [`exec::exec`] does, and printing
+/// its output if it fails
+fn run_captured(mut cmd: process::Command, format: exec::ProgressFormat) -> Result<bool> {
+    cmd.env("NINJA_STATUS", STATUS);
+    cmd.stdout(process::Stdio::piped());
+    cmd.stderr(process::Stdio::piped());
+    let mut child =
+        interrupt::spawn(&mut cmd).context("Failed to execute ninja. Is ninja installed?")?;
+    let mut stderr_pipe = child.stderr.take().expect("stderr is piped");
+    // Read stderr concurrently, so that Ninja doesn't block on a full pipe
+    let stderr_handle = thread::spawn(move || {
+        let mut stderr = Vec::new();
+        drop(stderr_pipe.read_to_end(&mut stderr));
+        stderr
+    });
+    let mut stdout = String::new();
+    let reader = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    for line in reader.split(b'\n') {
+        let line = line.context("Failed to read output of ninja")?;
+        let line = String::from_utf8_lossy(&line);
+        if let Some((started, total, desc)) = parse_status(&line) {
+            exec::report(format, started, total, desc);
+        }
+        stdout.push_str(&line);
+        stdout.push('\n');
+    }
+    let status = interrupt::wait(&mut child).context("Failed to wait for ninja")?;
+    let stderr = stderr_handle.join().expect("stderr thread panicked");
+    let stderr = String::from_utf8_lossy(&stderr);
+    trace!("{stdout}");
+    trace!("{stderr}");
+    if !status.success() {
+        error!("{stdout}\n{stderr}");
+    }
+    Ok(status.success())
+}
+
+/// Number of started commands, total number of commands, and description from
+/// a line of Ninja's output that starts with [`STATUS`]
+fn parse_status(line: &str) -> Option<(usize, usize, &str)> {
+    let (counts, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let (started, total) = counts.split_once('/')?;
+    let desc = rest.strip_prefix("Running ").unwrap_or(rest);
+    Some((started.parse().ok()?, total.parse().ok()?, desc))
 }
 
 fn tgt_name(cmd: &cmd::Command) -> String {
-    let hash = cmd_hash(cmd);
-    format!("$builddir/{hash:032x}")
+    format!("$builddir/{:032x}", cmd_hash(cmd))
+}
+
+/// The file that Ninja touches when `cmd` succeeds
+fn target(builddir: &Path, cmd: &cmd::Command) -> PathBuf {
+    builddir.join(format!("{:032x}", cmd_hash(cmd)))
 }
 
 fn cmd_hash(cmd: &cmd::Command) -> u128 {
     let mut hasher = Xxh3::new();
-    let cmd_obj = cmd.to_command();
+    // Without the name of the argfile, which differs between runs
+    let cmd_obj = cmd::Command {
+        argfile: None,
+        ..cmd.clone()
+    }
+    .to_command();
     let program_str = cmd_obj.get_program().to_string_lossy();
     hasher.update(program_str.as_bytes());
     hasher.update(&[0]);
@@ -115,7 +201,7 @@ fn cmd_hash(cmd: &cmd::Command) -> u128 {
 fn generate_ninja_file(
     cache_dir: &Path,
     ninja_file: &Path,
-    batches: &[cmd::Command],
+    batches: &mut [cmd::Command],
 ) -> Result<()> {
     debug!("Generating {}", ninja_file.display());
     let builddir = cache_dir.join("ninja");
@@ -125,23 +211,28 @@ fn generate_ninja_file(
     content.push_str("  description = Running $desc\n\n");
     content.reserve(batches.len()); // at least
 
-    for cmd in batches {
+    for cmd in batches.iter_mut() {
+        // Kept until the command is dropped, after Ninja runs
+        cmd.write_argfile()?;
         let cmd_obj = cmd.to_command();
-        let mut cmd_parts = Vec::new();
-        let program_str = cmd_obj.get_program().to_string_lossy();
-        cmd_parts.push(escape_ninja_string(&program_str));
-        for arg in cmd_obj.get_args() {
-            let arg_str = arg.to_string_lossy();
-            if arg_str.contains(' ') || arg_str.contains('$') || arg_str.contains(':') {
-                cmd_parts.push(format!(
-                    "\"{}\"",
-                    escape_ninja_string(&arg_str).replace('"', "\\\"")
-                ));
-            } else {
-                cmd_parts.push(escape_ninja_string(&arg_str));
+        if cmd.tool.stdin.is_some_and(|s| s != tool::Stdin::Report) {
+            anyhow::bail!(
+                "{}: `write_back` is not supported with Ninja",
+                cmd.tool.display_name()
+            );
+        }
+        // Ninja can't represent newlines, and lossily-converted paths would
+        // name the wrong files
+        for arg in std::iter::once(cmd_obj.get_program()).chain(cmd_obj.get_args()) {
+            if arg.to_str().is_none_or(|a| a.contains('\n')) {
+                anyhow::bail!(
+                    "{}: paths that contain newlines or aren't valid UTF-8 are not supported with Ninja: {}",
+                    cmd.tool.display_name(),
+                    arg.display()
+                );
             }
         }
-        let cmd_str = cmd_parts.join(" ");
+        let cmd_str = job::display_job(cmd, &cmd_obj);
 
         let desc = describe(&cmd_obj);
         let name = tgt_name(cmd);
@@ -151,103 +242,130 @@ fn generate_ninja_file(
         writeln!(content).unwrap();
     }
 
-    fs::write(ninja_file, content)
+    atomic::write(ninja_file, content.as_bytes(), false)
         .with_context(|| format!("Failed to write Ninja file: {}", ninja_file.display()))?;
     Ok(())
 }
 
 fn describe(cmd: &process::Command) -> String {
-    format!(
-        "{} {}",
-        cmd.get_program().display(),
-        cmd.get_args()
-            .map(|a| a.display().to_string())
-            .collect::<Vec<_>>()
-            .join(" ")
-    )
+    job::display_cmd(cmd)
 }
 
-fn parse_ninja_output(
-    stdout: &str,
-    stderr: &str,
-    batches: &[cmd::Command],
-    builddir: &Path,
-) -> HashSet<String> {
-    let mut executed = HashSet::new();
+/// Escape a variable value. Only `$` is special in values (`:` and `|` are
+/// only special in `build` lines, and `$|` isn't a valid escape), and
+/// newlines can't be represented, see [`generate_ninja_file`].
+fn escape_ninja_string(s: &str) -> String {
+    s.replace('$', "$$")
+}
 
-    let mut desc_to_target: Vec<(String, String)> = Vec::new();
-    for cmd in batches {
-        let target_name = tgt_name(cmd);
-        let desc = describe(&cmd.to_command());
-        desc_to_target.push((desc, target_name));
-    }
-
-    // Parse stdout for lines like "[1/5] Running $desc"
-    // These indicate targets that were actually executed (not up-to-date)
-    // Ninja outputs "Running $desc" when executing a target
-    for line in stdout.lines() {
-        if line.contains("Running") && line.starts_with('[') {
-            // Extract the description part after "Running "
-            if let Some(running_pos) = line.find("Running ") {
-                let desc_part = &line[running_pos + 8..].trim(); // Skip "Running "
-                // Match this description to a target by comparing normalized strings
-                for (desc, target_name) in &desc_to_target {
-                    // Normalize whitespace for comparison
-                    let normalized_desc = desc.split_whitespace().collect::<Vec<_>>().join(" ");
-                    let normalized_part =
-                        desc_part.split_whitespace().collect::<Vec<_>>().join(" ");
-                    // Check if descriptions match (either exact or one contains the other)
-                    if normalized_part == normalized_desc
-                        || normalized_part.starts_with(&normalized_desc)
-                        || normalized_desc.starts_with(&normalized_part)
-                    {
-                        executed.insert(target_name.clone());
-                        break;
-                    }
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache::Cache as _, file};
+
+    fn command(path: &str) -> cmd::Command {
+        cmd::Command::test("", &[path])
     }
 
-    for line in stderr.lines() {
-        if let Some(target) = extract_target_from_failed_line(line, builddir) {
-            executed.remove(&target);
-        }
+    #[test]
+    fn pathological_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let ninja_file = dir.path().join("build.ninja");
+        let mut batches = [
+            command("a b.py"),
+            command("$x: y|z.py"),
+            command("it's ünïcode.py"),
+        ];
+        generate_ninja_file(dir.path(), &ninja_file, &mut batches).unwrap();
+        let content = fs::read_to_string(&ninja_file).unwrap();
+        let cmds = content
+            .lines()
+            .filter_map(|l| l.strip_prefix("  cmd = "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cmds,
+            [
+                "lint 'a b.py'",
+                "lint '$$x: y|z.py'",
+                r"lint 'it'\''s ünïcode.py'",
+            ]
+        );
+
+        let err = generate_ninja_file(dir.path(), &ninja_file, &mut [command("a\nb.py")]);
+        assert!(err.is_err());
     }
 
-    executed
-}
+    #[test]
+    fn status() {
+        assert_eq!(
+            parse_status("[1/3] Running lint 'a b.py'"),
+            Some((1, 3, "lint 'a b.py'"))
+        );
+        assert_eq!(parse_status("a.py:1: error"), None);
+        assert_eq!(parse_status("[a/b] Running lint"), None);
+    }
 
-fn extract_target_from_failed_line(line: &str, builddir: &Path) -> Option<String> {
-    if let Some(line) = line.strip_prefix("FAILED: ") {
-        let target = line.trim();
-        // Check if this target is in our builddir
-        // Ninja outputs the full path, so we check if it starts with builddir
-        if let Some(builddir_str) = builddir.to_str()
-            && target.starts_with(builddir_str)
-        {
-            return Some(target.to_string());
-        }
-        // Also handle relative paths (Ninja might output relative to builddir)
-        if let Some(builddir_name) = builddir.file_name().and_then(|n| n.to_str())
-            && target.starts_with(builddir_name)
-        {
-            // Reconstruct full path
-            let relative_part = target
-                .strip_prefix(builddir_name)
-                .and_then(|s| s.strip_prefix('/'))
-                .unwrap_or(target);
-            return Some(builddir.join(relative_part).display().to_string());
-        }
+    #[test]
+    fn targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let cmd = command("a.py");
+        let name = tgt_name(&cmd);
+        assert_eq!(
+            target(dir.path(), &cmd),
+            dir.path().join(name.strip_prefix("$builddir/").unwrap())
+        );
     }
-    None
-}
 
-fn escape_ninja_string(s: &str) -> String {
-    // Escape special Ninja characters: $ : | \n
-    // Note: spaces don't need escaping in command lines
-    s.replace('$', "$$")
-        .replace(':', "$:")
-        .replace('|', "$|")
-        .replace('\n', "$\n")
+    /// Ninja touching the target of a command that succeeded
+    fn touch(builddir: &Path, cmd: &cmd::Command) {
+        fs::write(target(builddir, cmd), "").unwrap();
+    }
+
+    #[test]
+    fn rerun_after_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let (builddir, path) = (dir.path().join("ninja"), dir.path().join("a.py"));
+        fs::create_dir_all(&builddir).unwrap();
+        let mut cache = cache::HashCache::new(dir.path().join("cache"), 1000);
+        let plan = |content: &str| {
+            fs::write(&path, content).unwrap();
+            let mut cmd = command(path.to_str().unwrap());
+            cmd.files = vec![file::File::new(path.clone()).unwrap()];
+            cmd.files[0].fill_content_stamp().unwrap();
+            cmd
+        };
+        let key = |cmd: &cmd::Command| cache::Key::from_content(&cmd.files[0], &cmd.tool);
+
+        let first = plan("x = 1\n");
+        remove_targets(&builddir, std::slice::from_ref(&first)).unwrap();
+        touch(&builddir, &first);
+        assert!(record(
+            &mut cache,
+            &builddir,
+            std::slice::from_ref(&first),
+            false
+        ));
+        assert!(!cache.needed(&key(&first)));
+
+        // The same command line, so Ninja would consider the target up to
+        // date if it were still there
+        let second = plan("x = 2\n");
+        assert_eq!(tgt_name(&first), tgt_name(&second));
+        remove_targets(&builddir, std::slice::from_ref(&second)).unwrap();
+        assert!(!record(
+            &mut cache,
+            &builddir,
+            std::slice::from_ref(&second),
+            false
+        ));
+        assert!(cache.needed(&key(&second)));
+        touch(&builddir, &second);
+        assert!(record(
+            &mut cache,
+            &builddir,
+            std::slice::from_ref(&second),
+            false
+        ));
+        assert!(!cache.needed(&key(&second)));
+    }
 }
@@ -0,0 +1,114 @@
+//! Patterns for `files` and `ignore`.
+//!
+//! These are globs (where `*` matches across `/`), plus some of the semantics
+//! of `.gitignore`: patterns starting with `!` exclude files that earlier
+//! patterns matched, and patterns ending with `/` match everything under the
+//! directories that they name. Later patterns take precedence.
+
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Patterns {
+    set: GlobSet,
+    /// Whether each pattern is negated
+    negated: Vec<bool>,
+}
+
+impl Patterns {
+    /// `field` and `tool_name` are for error messages
+    pub(crate) fn new(patterns: &[String], field: &str, tool_name: &str) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut negated = Vec::with_capacity(patterns.len());
+        for pattern in patterns {
+            let (negate, glob) = translate(pattern);
+            let glob = Glob::new(&glob)
+                .with_context(|| format!("Invalid `{field}` glob `{pattern}` for `{tool_name}`"))?;
+            builder.add(glob);
+            negated.push(negate);
+        }
+        let set = builder
+            .build()
+            .with_context(|| format!("Failed to build `{field}` glob set for `{tool_name}`"))?;
+        Ok(Self { set, negated })
+    }
+
+    pub(crate) fn is_match(&self, path: &Path) -> bool {
+        if !self.negated.contains(&true) {
+            return self.set.is_match(path);
+        }
+        self.set
+            .matches(path)
+            .into_iter()
+            .max()
+            .is_some_and(|i| !self.negated[i])
+    }
+}
+
+/// Whether `pattern` is negated, and the equivalent glob
+fn translate(pattern: &str) -> (bool, String) {
+    let (negated, pattern) = match pattern.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let glob = match pattern.strip_suffix('/') {
+        Some(dir) => match dir.strip_prefix('/') {
+            // Anchored to the root, like `.gitignore` patterns with a `/`
+            Some(dir) => format!("{dir}/**"),
+            None if dir.contains('/') => format!("{dir}/**"),
+            None => format!("**/{dir}/**"),
+        },
+        None => pattern.strip_prefix('/').unwrap_or(pattern).to_string(),
+    };
+    (negated, glob)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(patterns: &[&str], path: &str) -> bool {
+        let patterns = patterns
+            .iter()
+            .map(|p| (*p).to_string())
+            .collect::<Vec<_>>();
+        Patterns::new(&patterns, "files", "test")
+            .unwrap()
+            .is_match(Path::new(path))
+    }
+
+    #[test]
+    fn globs() {
+        assert!(matches(&["*.py"], "a.py"));
+        assert!(matches(&["*.py"], "src/a.py"));
+        assert!(!matches(&["*.py"], "a.rs"));
+        assert!(matches(&["/src/*.py"], "src/a.py"));
+    }
+
+    #[test]
+    fn negation() {
+        let patterns = ["src/**", "!src/generated/**"];
+        assert!(matches(&patterns, "src/a.rs"));
+        assert!(!matches(&patterns, "src/generated/a.rs"));
+        assert!(!matches(&patterns, "other/a.rs"));
+        // Later patterns win
+        let patterns = ["*.rs", "!src/*", "src/keep.rs"];
+        assert!(matches(&patterns, "lib.rs"));
+        assert!(!matches(&patterns, "src/a.rs"));
+        assert!(matches(&patterns, "src/keep.rs"));
+    }
+
+    #[test]
+    fn directories() {
+        assert!(matches(&["generated/"], "generated/a.rs"));
+        assert!(matches(&["generated/"], "src/generated/a/b.rs"));
+        assert!(!matches(&["generated/"], "generated.rs"));
+        assert!(matches(&["src/generated/"], "src/generated/a.rs"));
+        assert!(!matches(&["src/generated/"], "lib/src/generated/a.rs"));
+        assert!(!matches(&["/generated/"], "src/generated/a.rs"));
+        assert!(matches(&["src/", "!generated/"], "src/a.rs"));
+        assert!(!matches(&["src/", "!generated/"], "src/generated/a.rs"));
+    }
+}
@@ -1,9 +1,16 @@
-use std::{num::NonZeroUsize, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
+use rayon::prelude::*;
 use tracing::{debug, trace};
 
-use crate::{cache, cmd, file as files, git, job, tool};
+use crate::{cache, cli, cmd, file as files, git, index, job, timings, tool};
 
 fn is_match(tool: &Arc<tool::Tool>, f: &files::File) -> bool {
     let path = f.path.as_path();
@@ -16,18 +23,243 @@ fn is_match(tool: &Arc<tool::Tool>, f: &files::File) -> bool {
         debug!("{}: ignored", f.path.display());
         return false;
     }
+    if let Some(max) = tool.max_file_size
+        && f.size as u64 > max
+    {
+        debug!(
+            "{}: larger than {max} bytes, skipping for {}",
+            f.path.display(),
+            tool.display_name()
+        );
+        return false;
+    }
+    if !tool.binary && f.is_binary() {
+        debug!(
+            "{}: binary, skipping for {}",
+            f.path.display(),
+            tool.display_name()
+        );
+        return false;
+    }
     trace!("{}: match", f.path.display());
     true
 }
 
-// The workings of this function are described in `doc/cache.md`.
+/// Why a (tool, file) pair was or wasn't scheduled, see `--explain-cache`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Reason {
+    /// Skipped due to an `mtime` cache entry
+    Mtime,
+    /// Skipped due to a content cache entry
+    Content,
+    /// Skipped because the file is unchanged from one of the refs
+    Refs,
+    /// Skipped because the file content couldn't be read
+    Unreadable,
+    /// Scheduled
+    Miss,
+    /// Scheduled because the tool ignores the cache (`--fresh-tool`)
+    Fresh,
+}
+
+impl Reason {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Reason::Mtime => "mtime",
+            Reason::Content => "content",
+            Reason::Refs => "refs",
+            Reason::Unreadable => "unreadable",
+            Reason::Miss => "miss",
+            Reason::Fresh => "fresh",
+        }
+    }
+
+    pub(crate) fn needed(self) -> bool {
+        matches!(self, Reason::Miss | Reason::Fresh)
+    }
+}
+
+/// Number of (tool, file) pairs by whether they were scheduled (and why
+/// not), and the time spent deciding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Stats {
+    /// Skipped due to an `mtime` cache entry
+    pub(crate) mtime: usize,
+    /// Skipped due to a content cache entry
+    pub(crate) content: usize,
+    /// Skipped because the file is unchanged from one of the refs
+    pub(crate) refs: usize,
+    /// Scheduled
+    pub(crate) misses: usize,
+    /// Hashing file contents up front
+    pub(crate) hashing: Duration,
+    /// Consulting the cache and refs
+    pub(crate) lookup: Duration,
+}
+
+impl Stats {
+    /// Skipped due to the cache or refs
+    pub(crate) fn hits(&self) -> usize {
+        self.mtime + self.content + self.refs
+    }
+
+    fn record(&mut self, reason: Reason) {
+        match reason {
+            Reason::Mtime => self.mtime += 1,
+            Reason::Content => self.content += 1,
+            Reason::Refs => self.refs += 1,
+            Reason::Unreadable => (),
+            Reason::Miss | Reason::Fresh => self.misses += 1,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Stats {
+    fn add_assign(&mut self, other: Self) {
+        self.mtime += other.mtime;
+        self.content += other.content;
+        self.refs += other.refs;
+        self.misses += other.misses;
+        self.hashing += other.hashing;
+        self.lookup += other.lookup;
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Explanation<'a> {
+    tool: &'a str,
+    file: &'a Path,
+    needed: bool,
+    reason: Reason,
+}
+
+fn explain(format: cli::ExplainFormat, tool: &tool::Tool, file: &files::File, reason: Reason) {
+    match format {
+        cli::ExplainFormat::Text => {
+            println!(
+                "{}\t{}\t{}",
+                reason.as_str(),
+                tool.display_name(),
+                file.path.display()
+            );
+        }
+        cli::ExplainFormat::Json => {
+            let explanation = Explanation {
+                tool: tool.display_name(),
+                file: &file.path,
+                needed: reason.needed(),
+                reason,
+            };
+            if let Ok(json) = serde_json::to_string(&explanation) {
+                println!("{json}");
+            }
+        }
+    }
+}
+
+/// Which files each tool matched in the previous plan.
+///
+/// Used to avoid re-matching every glob against every file in `--watch` mode,
+/// `lun lsp`, and `lun daemon`, where the changed files are known. Only kept in
+/// memory, so a fresh `lun run` matches every file. The matches are forgotten
+/// when the tools change, e.g., with `--only-tool`.
+#[derive(Debug, Default)]
+pub(crate) struct WarmStart {
+    /// The tools of the previous plan, see [`WarmStart::tools`]
+    tools: Vec<(String, tool::Stamp)>,
+    /// For each tool (by index), whether it matched each file
+    matches: Vec<HashMap<PathBuf, bool>>,
+    /// Files that changed since the previous plan
+    changed: HashSet<PathBuf>,
+}
+
+impl WarmStart {
+    /// Record that a file changed since the previous plan
+    pub(crate) fn changed(&mut self, path: PathBuf) {
+        self.changed.insert(path);
+    }
+
+    /// Forget the matches if the tools differ from those of the previous plan,
+    /// as they are by index
+    fn tools(&mut self, tools: &[tool::Tool]) {
+        let same = self.tools.len() == tools.len()
+            && self
+                .tools
+                .iter()
+                .zip(tools)
+                .all(|((name, stamp), t)| name == t.display_name() && *stamp == t.stamp);
+        if !same {
+            self.tools = tools
+                .iter()
+                .map(|t| (t.display_name().to_string(), t.stamp))
+                .collect();
+            self.matches.clear();
+        }
+    }
+
+    /// Forget the changed files, once every tool has been planned
+    pub(crate) fn planned(&mut self) {
+        self.changed.clear();
+    }
+
+    fn is_match(&mut self, idx: usize, tool: &Arc<tool::Tool>, f: &files::File) -> bool {
+        if self.matches.len() <= idx {
+            self.matches.resize_with(idx + 1, HashMap::new);
+        }
+        let matches = &mut self.matches[idx];
+        if !self.changed.contains(&f.path)
+            && let Some(matched) = matches.get(&f.path)
+        {
+            return *matched;
+        }
+        let matched = is_match(tool, f);
+        matches.insert(f.path.clone(), matched);
+        matched
+    }
+}
+
+/// Fill in the content stamp, from the index if possible
+fn hash(index: &mut index::Index, file: &mut files::File) -> Result<()> {
+    index.fill(file);
+    if file.content_stamp.is_none() {
+        file.fill_content_stamp()?;
+        index.record(file);
+    }
+    Ok(())
+}
+
+/// Whether `tool` needs to run on `file`, counted in `stats`
 fn need_file<C: cache::Cache + ?Sized>(
     cache: &mut C,
-    git_refs: &[String],
+    index: &mut index::Index,
+    git: &mut git::GitContext,
     mtime_enabled: bool,
     tool: &Arc<tool::Tool>,
     file: &mut files::File,
-) -> bool {
+    stats: &mut Stats,
+) -> Reason {
+    let reason = reason(cache, index, git, mtime_enabled, tool, file);
+    stats.record(reason);
+    reason
+}
+
+// The workings of this function are described in `doc/cache.md`.
+fn reason<C: cache::Cache + ?Sized>(
+    cache: &mut C,
+    index: &mut index::Index,
+    git: &mut git::GitContext,
+    mtime_enabled: bool,
+    tool: &Arc<tool::Tool>,
+    file: &mut files::File,
+) -> Reason {
+    if tool.fresh {
+        if let Err(e) = hash(index, file) {
+            debug!("{}: failed to read content ({e})", file.path.display());
+            return Reason::Unreadable;
+        }
+        return Reason::Fresh;
+    }
     let mtime_key = cache::Key::from_mtime(file, tool);
     if mtime_enabled && !cache.needed(&mtime_key) {
         debug!(
@@ -35,11 +267,11 @@ fn need_file<C: cache::Cache + ?Sized>(
             file.path.display(),
             tool.display_name(),
         );
-        return false;
+        return Reason::Mtime;
     }
-    if let Err(e) = file.fill_content_stamp() {
+    if let Err(e) = hash(index, file) {
         debug!("{}: failed to read content ({e})", file.path.display());
-        return false;
+        return Reason::Unreadable;
     }
     let content_key = cache::Key::from_content(file, tool);
     if !cache.needed(&content_key) {
@@ -51,39 +283,83 @@ fn need_file<C: cache::Cache + ?Sized>(
         if mtime_enabled {
             cache.done(&mtime_key);
         }
-        false
-    } else if let Ok(true) = git::file_changed_from_refs(&file.path, git_refs) {
-        true
+        Reason::Content
+    } else if let Ok(true) = git.file_changed_from_refs(&file.path) {
+        Reason::Miss
     } else {
         cache.done(&content_key);
         if mtime_enabled {
             cache.done(&mtime_key);
         }
-        false
+        Reason::Refs
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn tool_commands<C: cache::Cache + ?Sized>(
+    idx: usize,
     tool: &tool::Tool,
     files: &mut [files::File],
     cache: &mut C,
-    git_refs: &[String],
+    index: &mut index::Index,
+    git: &mut git::GitContext,
     mtime_enabled: bool,
+    explain_cache: Option<cli::ExplainFormat>,
+    mut warm: Option<&mut WarmStart>,
+    stats: &mut Stats,
 ) -> Result<Option<cmd::Command>> {
     debug!("Planning for {}", tool.display_name());
     debug_assert!(!files.is_empty());
     let tool = Arc::new(tool.clone());
 
+    let matched = files
+        .iter()
+        .map(|f| match warm.as_deref_mut() {
+            Some(warm) => warm.is_match(idx, &tool, f),
+            None => is_match(&tool, f),
+        })
+        .collect::<Vec<_>>();
+    // Hash the files that neither the mtime cache nor the index cover up front,
+    // in parallel. Errors are reported by `need_file`.
+    let start = Instant::now();
+    let to_hash = files
+        .iter_mut()
+        .zip(&matched)
+        .map(|(f, matched)| {
+            if !matched {
+                return false;
+            }
+            index.fill(f);
+            f.content_stamp.is_none()
+                && (tool.fresh || !mtime_enabled || cache.needed(&cache::Key::from_mtime(f, &tool)))
+        })
+        .collect::<Vec<_>>();
+    files
+        .par_iter_mut()
+        .zip(&to_hash)
+        .filter(|(_, hash)| **hash)
+        .for_each(|(f, _)| drop(f.fill_content_stamp()));
+    for (f, _) in files.iter().zip(&to_hash).filter(|(_, hash)| **hash) {
+        index.record(f);
+    }
+    stats.hashing += start.elapsed();
+
+    let start = Instant::now();
     let files = files
         .iter_mut()
-        .filter_map(|f| {
-            if is_match(&tool, f) && need_file(cache, git_refs, mtime_enabled, &tool, f) {
-                Some(f.clone())
-            } else {
-                None
+        .zip(matched)
+        .filter_map(|(f, matched)| {
+            if !matched {
+                return None;
             }
+            let reason = need_file(cache, index, git, mtime_enabled, &tool, f, stats);
+            if let Some(format) = explain_cache {
+                explain(format, &tool, f, reason);
+            }
+            reason.needed().then(|| f.clone())
         })
         .collect::<Vec<_>>();
+    stats.lookup += start.elapsed();
 
     if files.is_empty() {
         Ok(None)
@@ -91,27 +367,60 @@ fn tool_commands<C: cache::Cache + ?Sized>(
         Ok(Some(cmd::Command {
             tool: tool.clone(),
             files,
+            argfile: None,
         }))
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn plan<C: cache::Cache + ?Sized>(
     cache: &mut C,
+    index: &mut index::Index,
+    timings: &timings::Timings,
     tools: &[tool::Tool],
+    include: impl Fn(&tool::Tool) -> bool,
     files: &[files::File],
-    git_refs: &[String],
+    git: &mut git::GitContext,
     cores: NonZeroUsize,
     no_batch: bool,
     mtime_enabled: bool,
-) -> Result<Vec<cmd::Command>> {
+    explain_cache: Option<cli::ExplainFormat>,
+    mut warm: Option<&mut WarmStart>,
+    shuffle: Option<u64>,
+) -> Result<(Vec<cmd::Command>, Stats)> {
+    let mut stats = Stats::default();
     if files.is_empty() {
-        return Ok(Vec::new());
+        return Ok((Vec::new(), stats));
     }
     debug!("Collected {} files", files.len());
     let mut files = Vec::from(files);
+    // Files are collected in directory order, which varies between runs, and
+    // which files are batched together depends on their order
+    match shuffle {
+        None => files.sort_by(|a, b| a.path.cmp(&b.path)),
+        Some(seed) => files.sort_by_cached_key(|f| job::random_key(&f.path, seed)),
+    }
+    if let Some(warm) = warm.as_deref_mut() {
+        warm.tools(tools);
+    }
     let mut commands = Vec::with_capacity(tools.len());
-    for tool in tools {
-        let Some(cmd) = tool_commands(tool, &mut files, cache, git_refs, mtime_enabled)? else {
+    for (idx, tool) in tools.iter().enumerate() {
+        if !include(tool) {
+            continue;
+        }
+        let Some(cmd) = tool_commands(
+            idx,
+            tool,
+            &mut files,
+            cache,
+            index,
+            git,
+            mtime_enabled,
+            explain_cache,
+            warm.as_deref_mut(),
+            &mut stats,
+        )?
+        else {
             debug!(
                 "No needed files for {}",
                 tool.name.as_ref().unwrap_or(&tool.cmd)
@@ -121,5 +430,51 @@ pub(crate) fn plan<C: cache::Cache + ?Sized>(
         debug_assert!(cmd.files.iter().all(|f| f.content_stamp.is_some()));
         commands.push(cmd);
     }
-    Ok(job::create_jobs(commands, cores, no_batch))
+    let mut jobs = job::create_jobs(commands, cores, no_batch, timings);
+    if let Some(seed) = shuffle {
+        job::shuffle(&mut jobs, seed);
+    }
+    Ok((jobs, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warm_start() {
+        let py = Arc::new(tool::test_tool("name = \"py\"\nfiles = [\"*.py\"]"));
+        let rs = Arc::new(tool::test_tool("name = \"rs\"\nfiles = [\"*.rs\"]"));
+        let (a, b) = (files::File::test("a.py", 0), files::File::test("b.rs", 0));
+        let mut warm = WarmStart::default();
+        warm.tools(&[(*py).clone(), (*rs).clone()]);
+        assert!(warm.is_match(0, &py, &a));
+        assert!(warm.is_match(1, &rs, &b));
+        // Unchanged files keep their matches
+        assert!(warm.is_match(0, &rs, &a));
+        // Changed files are matched again
+        warm.changed(a.path.clone());
+        assert!(!warm.is_match(0, &rs, &a));
+        warm.planned();
+        // Only `rs` now, which is at index 0
+        warm.tools(&[(*rs).clone()]);
+        assert!(warm.is_match(0, &rs, &b));
+        assert!(!warm.is_match(0, &rs, &a));
+    }
+
+    #[test]
+    fn warm_start_changed() {
+        let tool = Arc::new(tool::test_tool("max_file_size = 10"));
+        let (small, big) = (files::File::test("a.py", 5), files::File::test("a.py", 20));
+        let mut warm = WarmStart::default();
+        warm.tools(&[(*tool).clone()]);
+        assert!(warm.is_match(0, &tool, &small));
+        // Not known to have changed, so the previous match is reused
+        assert!(warm.is_match(0, &tool, &big));
+        warm.changed(big.path.clone());
+        assert!(!warm.is_match(0, &tool, &big));
+        // Until the file changes again, the new match is reused
+        warm.planned();
+        assert!(!warm.is_match(0, &tool, &small));
+    }
 }
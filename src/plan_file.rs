@@ -0,0 +1,176 @@
+//! `lun plan`: print the commands that `lun run` would run, and save them in
+//! the cache directory, so that `lun plan --diff` can show which files became
+//! dirty since the previous plan (e.g., to debug surprising cache misses).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+};
+
+use anyhow::{Context as _, Result};
+use tracing::warn;
+
+use crate::{atomic, cli, cmd, config, job, run, warn::warns::Warns};
+
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+struct Command {
+    tool: String,
+    /// Displayed command line
+    cmd: String,
+    files: Vec<String>,
+}
+
+impl From<&cmd::Command> for Command {
+    fn from(cmd: &cmd::Command) -> Self {
+        Self {
+            tool: cmd.tool.display_name().to_string(),
+            cmd: job::display_job(cmd, &cmd.to_command()),
+            files: cmd
+                .files
+                .iter()
+                .map(|f| f.path.to_string_lossy().into_owned())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct Plan {
+    version: u32,
+    commands: Vec<Command>,
+}
+
+pub(crate) fn go(
+    cli: &cli::Cli,
+    plan: &cli::Plan,
+    config: &config::Config,
+    lints: &Warns,
+) -> Result<()> {
+    let run_cli = cli::Run {
+        diff: false,
+        ..plan.run.clone()
+    };
+    let jobs = run::plan_jobs(cli, &run_cli, config, lints)?;
+    let commands = jobs.iter().map(Command::from).collect::<Vec<_>>();
+    let path = cli.cache.join("plan.json");
+    if plan.diff() {
+        print!("{}", render_diff(&load(&path).commands, &commands));
+    } else {
+        for command in &commands {
+            println!("{}", command.cmd);
+        }
+    }
+    let plan = Plan {
+        version: VERSION,
+        commands,
+    };
+    let json = serde_json::to_vec_pretty(&plan).context("Failed to serialize plan")?;
+    atomic::write(&path, &json, false)
+        .with_context(|| format!("Failed to write plan: {}", path.display()))
+}
+
+/// The previous plan, if any
+fn load(path: &Path) -> Plan {
+    match fs::read(path) {
+        Ok(contents) => match serde_json::from_slice::<Plan>(&contents) {
+            Ok(plan) if plan.version == VERSION => plan,
+            Ok(_) => Plan::default(),
+            Err(e) => {
+                warn!("Ignoring corrupted plan at {}: {e}", path.display());
+                Plan::default()
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Plan::default(),
+        Err(e) => {
+            warn!("Failed to read plan at {}: {e}", path.display());
+            Plan::default()
+        }
+    }
+}
+
+/// The files of each tool
+fn files(commands: &[Command]) -> BTreeMap<&str, BTreeSet<&str>> {
+    let mut files = BTreeMap::<_, BTreeSet<_>>::new();
+    for command in commands {
+        files
+            .entry(command.tool.as_str())
+            .or_default()
+            .extend(command.files.iter().map(String::as_str));
+    }
+    files
+}
+
+/// Files are compared rather than commands, which depend on batching.
+///
+/// `+` marks files that a tool needs to run on and didn't before, `-` files
+/// that it no longer needs to run on (e.g., because `lun run` ran since).
+fn render_diff(old: &[Command], new: &[Command]) -> String {
+    let (old, new) = (files(old), files(new));
+    let tools = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    let mut out = String::new();
+    for tool in tools {
+        let empty = BTreeSet::new();
+        let before = old.get(tool).unwrap_or(&empty);
+        let after = new.get(tool).unwrap_or(&empty);
+        let added = after.difference(before).collect::<Vec<_>>();
+        let removed = before.difference(after).collect::<Vec<_>>();
+        if added.is_empty() && removed.is_empty() {
+            continue;
+        }
+        #[allow(clippy::unwrap_used)] // writing to a `String`
+        writeln!(out, "{tool}:").unwrap();
+        for file in added {
+            #[allow(clippy::unwrap_used)]
+            writeln!(out, "+ {file}").unwrap();
+        }
+        for file in removed {
+            #[allow(clippy::unwrap_used)]
+            writeln!(out, "- {file}").unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    fn command(tool: &str, files: &[&str]) -> Command {
+        Command {
+            tool: tool.to_string(),
+            cmd: format!("{tool} {}", files.join(" ")),
+            files: files.iter().map(|f| (*f).to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let old = [
+            command("ruff", &["a.py", "b.py"]),
+            command("mypy", &["a.py"]),
+            command("taplo", &["lun.toml"]),
+        ];
+        let new = [
+            command("ruff", &["b.py"]),
+            command("ruff", &["c.py"]),
+            command("mypy", &["a.py"]),
+            command("shellcheck", &["x.sh"]),
+        ];
+        expect![[r#"
+            ruff:
+            + c.py
+            - a.py
+            shellcheck:
+            + x.sh
+            taplo:
+            - lun.toml
+        "#]]
+        .assert_eq(&render_diff(&old, &new));
+    }
+}
@@ -0,0 +1,298 @@
+//! Translating `.pre-commit-config.yaml`, for `lun init --from-pre-commit`.
+
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{Context as _, Result};
+use tracing::warn;
+
+use crate::config::{self, Granularity};
+
+#[derive(Debug, serde::Deserialize)]
+struct PreCommitConfig {
+    #[serde(default)]
+    repos: Vec<Repo>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Repo {
+    #[serde(default)]
+    hooks: Vec<Hook>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Hook {
+    id: String,
+    entry: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    files: Option<String>,
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    types_or: Vec<String>,
+    pass_filenames: Option<bool>,
+}
+
+/// Hook IDs of known tools
+const KNOWN_HOOKS: &[(&str, &str)] = &[
+    ("actionlint", "actionlint"),
+    ("biome-check", "biome"),
+    ("black", "black"),
+    ("clang-format", "clang-format"),
+    ("clang-tidy", "clang-tidy"),
+    ("clippy", "cargo clippy"),
+    ("eslint", "eslint"),
+    ("fmt", "cargo fmt"),
+    ("go-fmt", "gofmt"),
+    ("golangci-lint", "golangci-lint"),
+    ("hadolint", "hadolint"),
+    ("hadolint-docker", "hadolint"),
+    ("isort", "isort"),
+    ("markdownlint", "markdownlint"),
+    ("mypy", "mypy"),
+    ("prettier", "prettier"),
+    ("rubocop", "rubocop"),
+    ("ruff", "ruff check"),
+    ("ruff-check", "ruff check"),
+    ("ruff-format", "ruff format"),
+    ("shellcheck", "shellcheck"),
+    ("shfmt", "shfmt"),
+    ("stylua", "stylua"),
+    ("stylua-github", "stylua"),
+    ("taplo-format", "taplo"),
+    ("typos", "typos"),
+    ("yamllint", "yamllint"),
+    ("zizmor", "zizmor"),
+];
+
+/// Files of each `types` tag
+const TYPES: &[(&str, &[&str])] = &[
+    ("bash", &["*.bash", "*.sh"]),
+    ("c", &["*.c", "*.h"]),
+    ("c++", &["*.cc", "*.cpp", "*.cxx", "*.hh", "*.hpp", "*.hxx"]),
+    ("dockerfile", &["*Dockerfile"]),
+    ("go", &["*.go"]),
+    ("javascript", &["*.js", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("jsx", &["*.jsx"]),
+    ("lua", &["*.lua"]),
+    ("markdown", &["*.md"]),
+    ("pyi", &["*.pyi"]),
+    ("python", &["*.py", "*.pyi"]),
+    ("ruby", &["*.rb"]),
+    ("rust", &["*.rs"]),
+    ("shell", &["*.sh", "*.bash"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts"]),
+    ("tsx", &["*.tsx"]),
+    ("yaml", &["*.yml", "*.yaml"]),
+];
+
+/// Tools translated from the hooks of a pre-commit (or lefthook, or husky)
+/// config
+#[derive(Debug, Default)]
+pub(crate) struct Translated {
+    /// Names of known tools
+    pub(crate) known: Vec<String>,
+    /// Hooks that run a local command
+    pub(crate) custom: Vec<config::Linter>,
+    /// Git hooks that ran the tools, if known
+    pub(crate) hooks: Vec<String>,
+}
+
+impl Translated {
+    pub(crate) fn extend(&mut self, other: Self) {
+        for name in other.known {
+            if !self.known.contains(&name) {
+                self.known.push(name);
+            }
+        }
+        self.custom.extend(other.custom);
+        for hook in other.hooks {
+            if !self.hooks.contains(&hook) {
+                self.hooks.push(hook);
+            }
+        }
+    }
+}
+
+pub(crate) fn load(path: &Path) -> Result<Translated> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pre-commit config: {}", path.display()))?;
+    translate(&contents)
+        .with_context(|| format!("Failed to parse pre-commit config: {}", path.display()))
+}
+
+fn translate(yaml: &str) -> Result<Translated> {
+    let config: PreCommitConfig = serde_yaml_ng::from_str(yaml)?;
+    let mut translated = Translated::default();
+    for hook in config.repos.into_iter().flat_map(|r| r.hooks) {
+        if let Some((_, name)) = KNOWN_HOOKS.iter().find(|(id, _)| *id == hook.id) {
+            if !translated.known.iter().any(|n| n == name) {
+                translated.known.push((*name).to_string());
+            }
+        } else if let Some(entry) = &hook.entry {
+            let mut cmd = entry.clone();
+            for arg in &hook.args {
+                cmd.push(' ');
+                cmd.push_str(arg);
+            }
+            translated.custom.push(config::Linter {
+                tool: config::Tool {
+                    name: Some(hook.id.clone()),
+                    cmd,
+                    files: globs(&hook),
+                    ignore: Vec::new(),
+                    granularity: if hook.pass_filenames == Some(false) {
+                        Granularity::Batch
+                    } else {
+                        Granularity::Individual
+                    },
+                    configs: Vec::new(),
+                    env: Vec::new(),
+                    env_stamp: config::EnvStamp::Auto,
+                    cd: None,
+                    cache_failures: None,
+                    dedupe_group: None,
+                    stdin: false,
+                    min_version: None,
+                    version: None,
+                    version_cmd: None,
+                    enabled_if: None,
+                    platforms: Vec::new(),
+                    max_file_size: None,
+                    binary: false,
+                    batch_size: None,
+                    files_via: None,
+                    argfile: None,
+                    no_capture: false,
+                    absolute_paths: false,
+                    before: None,
+                    after: None,
+                    problem_matcher: None,
+                    warns: config::WarnCfg::default(),
+                },
+                fix: None,
+            });
+        } else {
+            warn!("Skipping unknown pre-commit hook: {}", hook.id);
+        }
+    }
+    Ok(translated)
+}
+
+/// Globs for the files that a hook runs on
+fn globs(hook: &Hook) -> Vec<String> {
+    if let Some(files) = &hook.files {
+        if let Some(globs) = regex_globs(files) {
+            return globs;
+        }
+        warn!(
+            "{}: can't translate `files: {files}` to globs, using types",
+            hook.id
+        );
+    }
+    let mut globs = Vec::new();
+    for tag in hook.types.iter().chain(&hook.types_or) {
+        if let Some((_, tag_globs)) = TYPES.iter().find(|(t, _)| t == tag) {
+            globs.extend(tag_globs.iter().map(|g| (*g).to_string()));
+        }
+    }
+    let mut seen = HashSet::new();
+    globs.retain(|g| seen.insert(g.clone()));
+    if globs.is_empty() {
+        globs.push("*".to_string());
+    }
+    globs
+}
+
+/// Globs for simple regexes of extensions, like `\.py$` or `\.(c|h)$`
+fn regex_globs(regex: &str) -> Option<Vec<String>> {
+    let exts = regex.strip_suffix('$')?.strip_prefix(r"\.")?;
+    let exts = exts
+        .strip_prefix("(?:")
+        .or_else(|| exts.strip_prefix('('))
+        .and_then(|e| e.strip_suffix(')'))
+        .unwrap_or(exts);
+    exts.split('|')
+        .map(|ext| {
+            ext.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                .then(|| format!("*.{ext}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn regex() {
+        assert_eq!(regex_globs(r"\.py$"), Some(vec!["*.py".to_string()]));
+        assert_eq!(
+            regex_globs(r"\.(c|h)$"),
+            Some(vec!["*.c".to_string(), "*.h".to_string()])
+        );
+        assert_eq!(regex_globs(r"\.(?:js)$"), Some(vec!["*.js".to_string()]));
+        assert_eq!(regex_globs(r"^src/.*\.rs$"), None);
+        assert_eq!(regex_globs(r"\.p.$"), None);
+    }
+
+    #[test]
+    fn translate_hooks() {
+        let yaml = r#"
+repos:
+  - repo: https://github.com/astral-sh/ruff-pre-commit
+    rev: v0.6.0
+    hooks:
+      - id: ruff
+        args: [--fix]
+      - id: ruff-format
+  - repo: https://github.com/example/unknown
+    rev: v1
+    hooks:
+      - id: unknown
+  - repo: local
+    hooks:
+      - id: check-docs
+        name: Check docs
+        entry: ./scripts/check-docs.sh --strict
+        language: system
+        files: \.(md|rst)$
+      - id: unit-tests
+        entry: make test
+        language: system
+        types: [python]
+        pass_filenames: false
+"#;
+        let translated = translate(yaml).unwrap();
+        assert_eq!(translated.known, ["ruff check", "ruff format"]);
+        let toml = toml::to_string_pretty(&config::Config {
+            linter: translated.custom,
+            ..toml::from_str("").unwrap()
+        })
+        .unwrap();
+        expect![[r#"
+            [[linter]]
+            name = "check-docs"
+            cmd = "./scripts/check-docs.sh --strict"
+            files = [
+                "*.md",
+                "*.rst",
+            ]
+
+            [[linter]]
+            name = "unit-tests"
+            cmd = "make test"
+            files = [
+                "*.py",
+                "*.pyi",
+            ]
+            granularity = "batch"
+        "#]]
+        .assert_eq(&toml);
+    }
+}
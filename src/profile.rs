@@ -0,0 +1,112 @@
+//! Where `lun run` spent its time, for `--timings`. Not to be confused with
+//! `timings.rs`, which records how long tools take.
+
+use std::{fmt::Write as _, time::Duration};
+
+use crate::cli;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Profile {
+    /// Walking the repository and `stat`ing files
+    pub(crate) walk: Duration,
+    /// Reading the cache, index, and timings
+    pub(crate) load: Duration,
+    /// Hashing file contents
+    pub(crate) hash: Duration,
+    /// Looking up files in the cache
+    pub(crate) cache: Duration,
+    /// Matching files and batching jobs
+    pub(crate) plan: Duration,
+    /// Running commands
+    pub(crate) exec: Duration,
+    /// Writing the cache, index, and timings
+    pub(crate) save: Duration,
+}
+
+impl Profile {
+    fn phases(&self) -> [(&'static str, Duration); 7] {
+        [
+            ("walk", self.walk),
+            ("load", self.load),
+            ("hash", self.hash),
+            ("cache", self.cache),
+            ("plan", self.plan),
+            ("exec", self.exec),
+            ("save", self.save),
+        ]
+    }
+
+    fn total(&self) -> Duration {
+        self.phases().iter().map(|(_, d)| *d).sum()
+    }
+
+    fn table(&self) -> String {
+        let total = self.total();
+        let mut out = String::new();
+        for (phase, d) in self.phases().into_iter().chain([("total", total)]) {
+            #[allow(clippy::cast_precision_loss)]
+            let pct = if total.is_zero() {
+                0.0
+            } else {
+                d.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            #[allow(clippy::unwrap_used)] // writing to a `String`
+            writeln!(
+                out,
+                "{phase:<6} {:>10.1}ms {pct:>5.1}%",
+                d.as_secs_f64() * 1000.0
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Seconds spent in each phase
+    fn json(&self) -> String {
+        let map = self
+            .phases()
+            .into_iter()
+            .chain([("total", self.total())])
+            .map(|(phase, d)| (phase.to_string(), serde_json::Value::from(d.as_secs_f64())))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::Value::Object(map).to_string()
+    }
+
+    /// Print the table on stderr, or the JSON on stdout
+    pub(crate) fn print(&self, format: cli::TimingsFormat) {
+        match format {
+            cli::TimingsFormat::Table => eprint!("{}", self.table()),
+            cli::TimingsFormat::Json => println!("{}", self.json()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn render() {
+        let profile = Profile {
+            walk: Duration::from_millis(30),
+            hash: Duration::from_millis(20),
+            exec: Duration::from_millis(50),
+            ..Profile::default()
+        };
+        expect![[r#"
+            walk         30.0ms  30.0%
+            load          0.0ms   0.0%
+            hash         20.0ms  20.0%
+            cache         0.0ms   0.0%
+            plan          0.0ms   0.0%
+            exec         50.0ms  50.0%
+            save          0.0ms   0.0%
+            total       100.0ms 100.0%
+        "#]]
+        .assert_eq(&profile.table());
+        expect![[r#"{"cache":0.0,"exec":0.05,"hash":0.02,"load":0.0,"plan":0.0,"save":0.0,"total":0.1,"walk":0.03}"#]]
+            .assert_eq(&profile.json());
+    }
+}
@@ -0,0 +1,154 @@
+//! Diagnostics of failed commands in `file:line:col: message` form, for
+//! `--error-file`.
+//!
+//! This is the default `errorformat` of Vim's quickfix list, and Emacs'
+//! `compilation-mode` and most other editors understand it too.
+
+use std::{collections::HashSet, fmt::Write as _, fs, path::Path};
+
+use anyhow::{Context as _, Result};
+
+use crate::{
+    baseline::Baseline,
+    diag::{Problem, ProblemMatcher},
+    exec, results, tool,
+};
+
+/// The diagnostics in the output of a failed command, found with the
+/// `problem_matcher` of its tool, less those in the baseline
+pub(crate) fn problems(
+    outcome: &exec::Outcome,
+    tools: &[tool::Tool],
+    baseline: Option<&Baseline>,
+) -> Vec<Problem> {
+    let Some(output) = &outcome.output else {
+        return Vec::new();
+    };
+    let matcher = tools
+        .iter()
+        .find(|t| t.display_name() == outcome.tool)
+        .and_then(|t| t.problem_matcher.as_ref());
+    let problems = output_problems(matcher, outcome.cd.as_deref(), output);
+    match baseline {
+        Some(baseline) => baseline.filter(&outcome.tool, problems),
+        None => problems,
+    }
+}
+
+/// The diagnostics in the output of a command that ran in `cd`
+pub(crate) fn output_problems(
+    matcher: Option<&ProblemMatcher>,
+    cd: Option<&Path>,
+    output: &results::Output,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for stream in [&output.stdout, &output.stderr] {
+        problems.extend(Problem::parse_output(
+            matcher,
+            &String::from_utf8_lossy(stream),
+        ));
+    }
+    // Tools with `cd` print paths relative to it
+    if let Some(cd) = cd {
+        for problem in &mut problems {
+            if problem.diag.file.is_relative() {
+                problem.diag.file = cd.join(&problem.diag.file);
+            }
+        }
+    }
+    problems
+}
+
+pub(crate) fn render(
+    outcomes: &[exec::Outcome],
+    tools: &[tool::Tool],
+    baseline: Option<&Baseline>,
+) -> String {
+    let mut out = String::new();
+    let mut seen = HashSet::new();
+    for outcome in outcomes {
+        for problem in problems(outcome, tools, baseline) {
+            let diag = &problem.diag;
+            let mut entry = format!("{}:{}:", diag.file.display(), diag.line);
+            if let Some(col) = diag.col {
+                #[allow(clippy::unwrap_used)]
+                write!(entry, "{col}:").unwrap();
+            }
+            #[allow(clippy::unwrap_used)]
+            write!(entry, " {}", problem.message).unwrap();
+            // Matchers may separate the code from the message
+            if let Some(code) = &diag.code
+                && !problem.message.contains(code.as_str())
+            {
+                #[allow(clippy::unwrap_used)]
+                write!(entry, " [{code}]").unwrap();
+            }
+            if seen.insert(entry.clone()) {
+                out.push_str(&entry);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// Write the diagnostics to `path`, which may also be, e.g., `/dev/fd/3`
+pub(crate) fn write(
+    path: &Path,
+    outcomes: &[exec::Outcome],
+    tools: &[tool::Tool],
+    baseline: Option<&Baseline>,
+) -> Result<()> {
+    fs::write(path, render(outcomes, tools, baseline))
+        .with_context(|| format!("Failed to write error file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use expect_test::expect;
+
+    use super::*;
+
+    fn outcome(cd: Option<&str>, stdout: &str) -> exec::Outcome {
+        exec::Outcome {
+            tool: "lint".to_string(),
+            cmd: "lint".to_string(),
+            files: Vec::new(),
+            cd: cd.map(PathBuf::from),
+            modified: Vec::new(),
+            success: false,
+            output: Some(results::Output {
+                code: 1,
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: b"Found 2 errors.\n".to_vec(),
+            }),
+            replayed: false,
+            sandbox_violation: false,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn render_outcomes() {
+        let outcomes = [
+            outcome(
+                None,
+                "a.py:1:8: F401 `os` imported but unused\n\x1b[1ma.sh\x1b[0m:3: warning: SC2086: quote this\n",
+            ),
+            outcome(
+                Some("web"),
+                "src/b.ts:2:1: error no-var\n/abs.ts:4:2: bad\n",
+            ),
+            outcome(None, "a.py:1:8: F401 `os` imported but unused\n"),
+        ];
+        expect![[r#"
+            a.py:1:8: F401 `os` imported but unused
+            a.sh:3: warning: SC2086: quote this
+            web/src/b.ts:2:1: error no-var
+            /abs.ts:4:2: bad
+        "#]]
+        .assert_eq(&render(&outcomes, &[], None));
+    }
+}
@@ -0,0 +1,191 @@
+//! Stored results of failed commands, see `doc/cache.md`.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use tracing::debug;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{atomic, cache, cmd, file};
+
+const MAGIC: &[u8; 4] = b"lunr";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_SIZE: usize = MAGIC.len() + size_of::<u16>();
+
+/// Captured output and exit status of a command
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Output {
+    pub(crate) code: i32,
+    pub(crate) stdout: Vec<u8>,
+    pub(crate) stderr: Vec<u8>,
+}
+
+impl Output {
+    fn serialize(&self) -> Vec<u8> {
+        let mut content = Vec::with_capacity(
+            HEADER_SIZE + size_of::<i32>() + 16 + self.stdout.len() + self.stderr.len(),
+        );
+        content.extend_from_slice(MAGIC);
+        content.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        content.extend_from_slice(&self.code.to_le_bytes());
+        content.extend_from_slice(&(self.stdout.len() as u64).to_le_bytes());
+        content.extend_from_slice(&self.stdout);
+        content.extend_from_slice(&(self.stderr.len() as u64).to_le_bytes());
+        content.extend_from_slice(&self.stderr);
+        content
+    }
+
+    fn deserialize(content: &[u8]) -> Option<Self> {
+        fn take<'a>(content: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+            if content.len() < n {
+                return None;
+            }
+            let (hd, tl) = content.split_at(n);
+            *content = tl;
+            Some(hd)
+        }
+        fn take_len(content: &mut &[u8]) -> Option<usize> {
+            let bytes = take(content, size_of::<u64>())?.try_into().ok()?;
+            usize::try_from(u64::from_le_bytes(bytes)).ok()
+        }
+
+        let mut content = content;
+        if take(&mut content, MAGIC.len())? != MAGIC {
+            return None;
+        }
+        let version = u16::from_le_bytes(take(&mut content, size_of::<u16>())?.try_into().ok()?);
+        if version != FORMAT_VERSION {
+            return None;
+        }
+        let code = i32::from_le_bytes(take(&mut content, size_of::<i32>())?.try_into().ok()?);
+        let stdout_len = take_len(&mut content)?;
+        let stdout = take(&mut content, stdout_len)?.to_vec();
+        let stderr_len = take_len(&mut content)?;
+        let stderr = take(&mut content, stderr_len)?.to_vec();
+        if !content.is_empty() {
+            return None;
+        }
+        Some(Self {
+            code,
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Hash of the tool and the content keys of all files in a command.
+///
+/// Output can't be split by file, so tools that store their failures run on
+/// one file per command, see `job::create_jobs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ResultKey(pub(crate) file::Xxhash);
+
+impl From<&cmd::Command> for ResultKey {
+    fn from(cmd: &cmd::Command) -> Self {
+        let mut hasher = Xxh3::new();
+        hasher.update(&cmd.tool.stamp.0.0.to_le_bytes());
+        for file in &cmd.files {
+            debug_assert!(file.content_stamp.is_some()); // in plan.rs
+            let key = cache::Key::from_content(file, &cmd.tool);
+            hasher.update(&cache::KeyHash::from(&key).0.0.to_le_bytes());
+        }
+        ResultKey(file::Xxhash(hasher.digest128()))
+    }
+}
+
+/// Directory of stored command results, one file per [`ResultKey`]
+#[derive(Clone, Debug)]
+pub(crate) struct Results {
+    dir: PathBuf,
+    fsync: bool,
+}
+
+impl Results {
+    pub(crate) fn new(cache_dir: &Path, fsync: bool) -> Self {
+        Self {
+            dir: cache_dir.join("results"),
+            fsync,
+        }
+    }
+
+    fn path(&self, key: ResultKey) -> PathBuf {
+        self.dir.join(format!("{:032x}", key.0.0))
+    }
+
+    pub(crate) fn get(&self, key: ResultKey) -> Option<Output> {
+        let path = self.path(key);
+        let content = fs::read(&path).ok()?;
+        let output = Output::deserialize(&content);
+        if output.is_none() {
+            debug!("Corrupted result at {}", path.display());
+            drop(fs::remove_file(&path));
+        }
+        output
+    }
+
+    pub(crate) fn put(&self, key: ResultKey, output: &Output) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.path(key);
+        atomic::write(&path, &output.serialize(), self.fsync)
+            .with_context(|| format!("Failed to write result: {}", path.display()))
+    }
+
+    /// Remove all stored results
+    pub(crate) fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove results: {}", self.dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let output = Output {
+            code: 2,
+            stdout: b"out".to_vec(),
+            stderr: b"err\n".to_vec(),
+        };
+        assert_eq!(Output::deserialize(&output.serialize()), Some(output));
+    }
+
+    #[test]
+    fn truncated() {
+        let output = Output {
+            code: 1,
+            stdout: b"stdout".to_vec(),
+            stderr: b"stderr".to_vec(),
+        };
+        let content = output.serialize();
+        for len in 0..content.len() {
+            assert_eq!(Output::deserialize(&content[..len]), None);
+        }
+    }
+
+    #[test]
+    fn corrupted_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let results = Results::new(dir.path(), false);
+        let key = ResultKey(file::Xxhash(0));
+        let output = Output {
+            code: 1,
+            stdout: b"stdout".to_vec(),
+            stderr: Vec::new(),
+        };
+        results.put(key, &output).unwrap();
+        assert_eq!(results.get(key), Some(output));
+        fs::write(results.path(key), b"lunr").unwrap();
+        assert_eq!(results.get(key), None);
+        assert!(!results.path(key).exists());
+    }
+}
@@ -0,0 +1,151 @@
+//! Finding the project root, i.e., the directory that contains the config
+//! file, like `git` and `cargo` do. Lūn runs in the project root, so paths given
+//! on the command line are made absolute before entering it.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use tracing::debug;
+
+use crate::cli;
+
+/// The closest directory containing `config`, starting at `start`
+fn find(start: &Path, config: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(config).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Change to the project root: the `--project-root`, or else the closest
+/// directory with a config file, unless the config file was given.
+pub(crate) fn enter(cli: &mut cli::Cli) -> Result<()> {
+    let cwd = env::current_dir().context("Failed to get current directory")?;
+    let root = match &cli.project_root {
+        Some(root) => cwd.join(root),
+        // `lun init` creates a config file here
+        None if cli.config_given || matches!(cli.command, cli::Command::Init(_)) => {
+            return Ok(());
+        }
+        None => match find(&cwd, &cli.config) {
+            Some(root) if root != cwd => root,
+            _ => return Ok(()),
+        },
+    };
+    debug!("Entering project root {}", root.display());
+    absolutize(cli, &cwd, &root);
+    env::set_current_dir(&root)
+        .with_context(|| format!("Failed to enter project root: {}", root.display()))
+}
+
+/// Make paths given on the command line independent of the current directory
+fn absolutize(cli: &mut cli::Cli, cwd: &Path, root: &Path) {
+    if cli.cache_given {
+        cli.cache = cwd.join(&cli.cache);
+    }
+    if cli.config_given {
+        cli.config = cwd.join(&cli.config);
+        for overlay in &mut cli.config_overlays {
+            *overlay = cwd.join(&*overlay);
+        }
+    }
+    match &mut cli.command {
+        cli::Command::Run(run) | cli::Command::Exec(cli::Exec { run, .. }) => {
+            if let Some(files_from) = run.files_from.as_mut().filter(|p| *p != Path::new("-")) {
+                *files_from = cwd.join(&*files_from);
+            }
+            if let Some(error_file) = &mut run.error_file {
+                *error_file = cwd.join(&*error_file);
+            }
+        }
+        cli::Command::Cache(cache) => match &mut cache.command {
+            cli::CacheCommand::Export { path } | cli::CacheCommand::Import { path } => {
+                *path = cwd.join(&*path);
+            }
+            cli::CacheCommand::Key { file, .. } => relative_to_root(file, cwd, root),
+            cli::CacheCommand::Entry(entry) => match &mut entry.command {
+                cli::CacheEntryCommand::Add { files, .. }
+                | cli::CacheEntryCommand::Get { files, .. }
+                | cli::CacheEntryCommand::Rm { files, .. } => {
+                    for f in files {
+                        relative_to_root(f, cwd, root);
+                    }
+                }
+            },
+            _ => (),
+        },
+        _ => (),
+    }
+}
+
+/// Cache keys contain paths relative to the root
+fn relative_to_root(path: &mut PathBuf, cwd: &Path, root: &Path) {
+    let abs = cwd.join(&*path);
+    *path = match abs.strip_prefix(root) {
+        Ok(rel) => rel.to_path_buf(),
+        Err(_) => abs,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn find_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sub = tmp.path().join("a").join("b");
+        fs::create_dir_all(&sub).unwrap();
+        let config = Path::new("lun.toml");
+        assert_eq!(find(&sub, config), None);
+        fs::write(tmp.path().join(config), "").unwrap();
+        assert_eq!(find(&sub, config), Some(tmp.path().to_path_buf()));
+        // A directory with the same name isn't a config file
+        fs::create_dir(tmp.path().join("a").join(config)).unwrap();
+        assert_eq!(find(&sub, config), Some(tmp.path().to_path_buf()));
+    }
+
+    #[test]
+    fn absolutize_paths() {
+        let mut cli = cli::Cli::try_parse_from([
+            "lun",
+            "--cache",
+            "c",
+            "run",
+            "--files-from",
+            "files.txt",
+            "--error-file",
+            "errors",
+        ])
+        .unwrap();
+        absolutize(&mut cli, Path::new("/repo/sub"), Path::new("/repo"));
+        assert_eq!(cli.cache, Path::new("/repo/sub/c"));
+        assert_eq!(cli.config, Path::new("lun.toml"));
+        let cli::Command::Run(run) = &cli.command else {
+            unreachable!()
+        };
+        assert_eq!(
+            run.files_from.as_deref(),
+            Some(Path::new("/repo/sub/files.txt"))
+        );
+        assert_eq!(
+            run.error_file.as_deref(),
+            Some(Path::new("/repo/sub/errors"))
+        );
+
+        let mut cli = cli::Cli::try_parse_from(["lun", "cache", "key", "t", "f.py"]).unwrap();
+        absolutize(&mut cli, Path::new("/repo/sub"), Path::new("/repo"));
+        let cli::Command::Cache(cache) = &cli.command else {
+            unreachable!()
+        };
+        let cli::CacheCommand::Key { file, .. } = &cache.command else {
+            unreachable!()
+        };
+        assert_eq!(file, Path::new("sub/f.py"));
+    }
+}
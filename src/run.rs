@@ -1,21 +1,28 @@
 use std::{
-    collections::HashSet,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write as _,
     fs,
-    num::NonZeroUsize,
-    path::{Path, PathBuf},
+    num::{NonZeroU16, NonZeroUsize},
+    path::{Component, Path, PathBuf},
     process,
-    sync::mpsc,
+    sync::{Arc, mpsc},
     thread, time,
 };
 
 use anyhow::{Context, Result};
 use globset::Glob;
 use notify::{Config as NotifyConfig, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use tracing::{debug, trace, warn};
+use rayon::prelude::*;
+use tracing::{debug, error, info, trace, warn};
 
 use crate::{
+    baseline::{self, Baseline},
     cache::{self, CacheWriter},
-    cli, config, exec, file, ninja, plan, staged, tool,
+    cli, cmd, config, daemon, desktop, diag, diff, exec, exit, file, git, gitattributes, index,
+    interrupt, job, keys, ninja,
+    pattern::Patterns,
+    plan, profile, quickfix, results, sandbox, setup, staged, tap, timings, tool,
     warn::{self, warns::Warns},
 };
 
@@ -26,11 +33,11 @@ pub(crate) enum RunMode {
     Fix,
 }
 
-impl From<&cli::Run> for RunMode {
-    fn from(run: &cli::Run) -> Self {
-        if run.fix {
+impl RunMode {
+    pub(crate) fn new(check: bool, fix: bool) -> Self {
+        if fix {
             RunMode::Fix
-        } else if run.check {
+        } else if check {
             RunMode::Check
         } else {
             RunMode::Normal
@@ -38,22 +45,48 @@ impl From<&cli::Run> for RunMode {
     }
 }
 
+impl From<&cli::Run> for RunMode {
+    fn from(run: &cli::Run) -> Self {
+        Self::new(run.check, run.fix || run.fix_and_check)
+    }
+}
+
 pub(crate) fn num_cores(cores: Option<NonZeroUsize>) -> NonZeroUsize {
     cores.unwrap_or_else(|| {
         thread::available_parallelism().unwrap_or(const { NonZeroUsize::new(1).unwrap() })
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 fn collect_files(
     cli: &cli::Cli,
     run: &cli::Run,
+    git: &mut git::GitContext,
     progress_format: exec::ProgressFormat,
+    follow_symlinks: bool,
+    walk_ignore: Option<&Patterns>,
+    transient: &Patterns,
+    skip_generated: bool,
 ) -> Result<Vec<file::File>, anyhow::Error> {
     let mut files = if run.staged {
-        staged::collect_staged_files()?
+        staged::collect_staged_files(git)?
+    } else if run.only_dirty {
+        staged::collect_dirty_files(git)?
+    } else if let Some(files_from) = &run.files_from {
+        file::collect_listed_files(files_from, run.null)?
     } else {
-        file::collect_files(Path::new("."), &cli.cache, progress_format)?
+        file::collect_files(
+            Path::new("."),
+            &cli.cache,
+            progress_format,
+            follow_symlinks,
+            walk_ignore,
+            transient,
+        )?
     };
+    if skip_generated {
+        gitattributes::skip_generated(&mut files);
+    }
     filter_files(&mut files, &run.only_files, &run.skip_files)?;
     Ok(files)
 }
@@ -118,7 +151,31 @@ fn include_tool(tool: &config::Tool, run: &cli::Run) -> bool {
             .name
             .as_ref()
             .is_some_and(|n| run.only_tool.contains(n));
-    skip && only
+    let enabled = tool.enabled_if.as_ref().is_none_or(|c| {
+        let holds = c.holds();
+        if !holds {
+            debug!(
+                "Skipping {}, `enabled_if` doesn't hold",
+                tool.name.as_ref().unwrap_or(&tool.cmd)
+            );
+        }
+        holds
+    });
+    let on_platform = tool.on_platform();
+    if !on_platform {
+        debug!(
+            "Skipping {}, not enabled on {}",
+            tool.name.as_ref().unwrap_or(&tool.cmd),
+            std::env::consts::OS
+        );
+    }
+    skip && only && enabled && on_platform
+}
+
+fn is_fresh(tool: &config::Tool, run: &cli::Run) -> bool {
+    tool.name
+        .as_ref()
+        .is_some_and(|n| run.fresh_tool.contains(n))
 }
 
 fn filter_tools(
@@ -126,29 +183,70 @@ fn filter_tools(
     config: &config::Config,
     mode: RunMode,
     color: cli::log::Color,
+    config_files: &config::ConfigFiles,
 ) -> Result<Vec<tool::Tool>> {
-    let careful = run.careful || config.careful;
+    let careful = run.careful.unwrap_or_default().max(config.careful);
+    let cache_failures = run.cache_failures || config.cache_failures;
+    // Fixes and formatters need to write
+    let sandbox = (run.sandbox || config.sandbox) && mode != RunMode::Fix;
     let mut tools = Vec::new();
 
     if !run.format {
         for linter in &config.linter {
             if include_tool(&linter.tool, run) {
-                tools.push(
-                    linter
-                        .clone()
-                        .into_tool(mode, careful, color, &config.ignore)?,
-                );
+                let mut tool = linter
+                    .clone()
+                    .into_tool(
+                        mode,
+                        config.stamp_opts(careful),
+                        color,
+                        &config.ignore,
+                        config_files,
+                    )
+                    .context(exit::ConfigError)?;
+                tool.cache_failures = linter.tool.cache_failures.unwrap_or(cache_failures);
+                tool.max_file_size = tool.max_file_size.or(config.max_file_size);
+                if let Some(arg_max) = config.arg_max {
+                    tool.arg_max = arg_max;
+                }
+                tool.fresh = is_fresh(&linter.tool, run);
+                tool.sandbox = sandbox;
+                tools.push(tool);
             }
         }
     }
 
     for formatter in &config.formatter {
         if include_tool(&formatter.tool, run) {
-            tools.push(
-                formatter
-                    .clone()
-                    .into_tool(mode, careful, color, &config.ignore)?,
-            );
+            // Diffs need the formatted files, so use the formatting command
+            let diff = run.diff
+                && formatter.tool.granularity == config::Granularity::Individual
+                && (!formatter.tool.stdin || formatter.write_back);
+            let mode = if diff { RunMode::Normal } else { mode };
+            let mut tool = formatter
+                .clone()
+                .into_tool(
+                    mode,
+                    config.stamp_opts(careful),
+                    color,
+                    &config.ignore,
+                    config_files,
+                )
+                .context(exit::ConfigError)?;
+            if diff && tool.stdin.is_some() {
+                tool.stdin = Some(tool::Stdin::Diff);
+            } else if diff {
+                tool.diff = true;
+            }
+            tool.formats &= !diff;
+            tool.cache_failures = formatter.tool.cache_failures.unwrap_or(cache_failures);
+            tool.max_file_size = tool.max_file_size.or(config.max_file_size);
+            if let Some(arg_max) = config.arg_max {
+                tool.arg_max = arg_max;
+            }
+            tool.fresh = is_fresh(&formatter.tool, run);
+            tool.sandbox = sandbox && !tool.formats && !tool.diff;
+            tools.push(tool);
         }
     }
 
@@ -157,25 +255,62 @@ fn filter_tools(
 
 #[derive(Debug, Clone)]
 struct Config {
-    refs: Vec<String>,
+    git: git::GitContext,
     cache: PathBuf,
     cores: NonZeroUsize,
     dry_run: bool,
     files: Vec<file::File>,
+    follow_symlinks: bool,
+    /// Paths that aren't walked, see `file::collect_files`
+    walk_ignore: Option<Patterns>,
+    /// Short-lived files, e.g., editor swap files
+    transient: Patterns,
+    /// Skip files marked as generated, see `gitattributes.rs`
+    skip_generated: bool,
+    fsync: bool,
     mtime: bool,
     ninja: bool,
     no_batch: bool,
     no_capture: bool,
+    prefix: exec::Prefix,
     no_cache: bool,
+    /// Print failures and a summary
+    report: bool,
+    /// Print results as TAP (see `tap.rs`) or JSON on stdout, rather than
+    /// the output of failed commands
+    output_format: cli::OutputFormat,
+    /// Write diagnostics here, see `quickfix.rs`
+    error_file: Option<PathBuf>,
+    /// Diagnostics not to report, see `baseline.rs`
+    baseline: Option<Arc<Baseline>>,
+    /// Only run on some of the files that need it (`--limit`, `--sample`)
+    sample: Option<cli::Sample>,
+    /// Orders files for `--sample random:N` and `--shuffle`
+    seed: u64,
+    shuffle: bool,
     tools: Vec<tool::Tool>,
     show_progress: exec::ProgressFormat,
-    keep_going: bool,
+    /// Stop after this many commands fail, or never if zero, like Ninja's `-k`
+    keep_going: usize,
     then: Option<String>,
     r#else: Option<String>,
     cache_size: Option<usize>,
+    cache_max_age: Option<NonZeroU16>,
+    explain_cache: Option<cli::ExplainFormat>,
+    /// Report where the time went, see `profile.rs`
+    timings: Option<cli::TimingsFormat>,
+    /// Time spent collecting `files`
+    walk: time::Duration,
 }
 
-fn mk_config(cli: &cli::Cli, run: &cli::Run, config: &config::Config) -> Result<Config> {
+/// Collects the files to run on, unless `files` are given
+fn mk_config(
+    cli: &cli::Cli,
+    run: &cli::Run,
+    config: &config::Config,
+    files: Option<Vec<file::File>>,
+    config_files: &config::ConfigFiles,
+) -> Result<Config> {
     let mode = RunMode::from(run);
     let show_progress = if cli.log.quiet == cli.log.verbose {
         // verbosity == info
@@ -193,30 +328,248 @@ fn mk_config(cli: &cli::Cli, run: &cli::Run, config: &config::Config) -> Result<
         config.refs.clone()
     };
     let mtime = config.mtime && !run.no_mtime;
+    let mut git = git::GitContext::new(refs);
+    let walk_ignore = config
+        .walk_ignore(&run.walk_ignore)
+        .context(exit::ConfigError)?;
+    let transient = config.transient().context(exit::ConfigError)?;
+    let skip_generated = config.skip_generated && !run.include_generated;
+    let start = time::Instant::now();
+    let files = match files {
+        Some(mut files) => {
+            filter_files(&mut files, &run.only_files, &run.skip_files)?;
+            files
+        }
+        None => collect_files(
+            cli,
+            run,
+            &mut git,
+            show_progress,
+            config.follow_symlinks,
+            walk_ignore.as_ref(),
+            &transient,
+            skip_generated,
+        )?,
+    };
+    let walk = start.elapsed();
+    let tools = filter_tools(run, config, mode, cli.log.color, config_files)?;
+    let seed = run.seed.unwrap_or_else(|| {
+        let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH);
+        let seed = now.map_or(0, |d| d.as_nanos() as u64);
+        if run.shuffle || matches!(run.sample, Some(cli::Sample::Random(_))) {
+            info!("Reproduce with --seed {seed}");
+        }
+        seed
+    });
+    let baseline = if run.no_baseline {
+        None
+    } else {
+        Baseline::load(Path::new(baseline::FILE))?.map(Arc::new)
+    };
+    // Ninja doesn't say which commands failed
+    let ninja = (run.ninja || config.ninja.unwrap_or(false))
+        && run.output_format == cli::OutputFormat::Human
+        && run.error_file.is_none()
+        && baseline.is_none();
+    if tools.iter().any(|t| t.sandbox) && !run.dry_run {
+        if ninja {
+            anyhow::bail!("`sandbox` is not supported with `ninja`");
+        }
+        sandbox::check()?;
+    }
     Ok(Config {
-        refs,
+        git,
         cache: cli.cache.clone(),
         cores: num_cores(run.jobs.or(config.cores)),
         dry_run: run.dry_run,
-        files: collect_files(cli, run, show_progress)?,
+        files,
+        follow_symlinks: config.follow_symlinks,
+        walk_ignore,
+        transient,
+        skip_generated,
+        fsync: config.fsync,
         mtime,
-        ninja: run.ninja || config.ninja.unwrap_or(false),
+        ninja,
         no_batch: run.no_batch,
-        no_capture: run.no_capture,
+        no_capture: run.no_capture || run.output != cli::OutputMode::Captured,
+        prefix: exec::Prefix {
+            when: match run.output {
+                _ if run.no_capture => exec::PrefixWhen::Never,
+                // Only applies to tools with `no_capture`
+                cli::OutputMode::Captured => exec::PrefixWhen::Concurrent,
+                cli::OutputMode::Raw => exec::PrefixWhen::Never,
+                cli::OutputMode::Prefixed => exec::PrefixWhen::Always,
+            },
+            color: cli.log.color.enabled() && run.output_format == cli::OutputFormat::Human,
+        },
         no_cache: run.no_cache || run.fresh,
-        tools: filter_tools(run, config, mode, cli.log.color)?,
+        report: true,
+        output_format: run.output_format,
+        error_file: run.error_file.clone(),
+        baseline,
+        sample: run.sample.or(run.limit.map(cli::Sample::First)),
+        seed,
+        shuffle: run.shuffle,
+        tools,
         show_progress,
-        keep_going: run.keep_going,
+        keep_going: run.keep_going.unwrap_or(1),
         then: run.then.clone(),
         r#else: run.r#else.clone(),
         cache_size: run.cache_size.or(config.cache_size),
+        cache_max_age: run.cache_max_age.or(config.cache_max_age),
+        explain_cache: run.explain_cache,
+        timings: run.timings,
+        walk,
     })
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum RunResult {
-    AllGood { cmds: usize, files: usize },
-    Errors,
+/// Summary of the commands run by a single tool
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ToolResult {
+    pub(crate) cmds: usize,
+    pub(crate) files: usize,
+    pub(crate) failed: usize,
+    /// Commands that failed because they tried to write in the sandbox
+    pub(crate) sandbox_violations: usize,
+    /// Time spent running the tool's commands, summed over all of them
+    pub(crate) duration: time::Duration,
+    /// Diagnostics in the output of failed commands, see `problem_matcher`
+    pub(crate) errors: usize,
+    pub(crate) warnings: usize,
+    /// Infos and hints
+    pub(crate) notes: usize,
+    /// Files with diagnostics
+    pub(crate) problem_files: BTreeSet<PathBuf>,
+}
+
+impl ToolResult {
+    fn add_problems(&mut self, problems: Vec<diag::Problem>) {
+        for problem in problems {
+            match problem.severity {
+                diag::Severity::Error => self.errors += 1,
+                diag::Severity::Warning => self.warnings += 1,
+                diag::Severity::Info | diag::Severity::Hint => self.notes += 1,
+            }
+            self.problem_files.insert(problem.diag.file);
+        }
+    }
+
+    fn problems(&self) -> usize {
+        self.errors + self.warnings + self.notes
+    }
+}
+
+/// Summary of a run, used for all reporting
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RunResult {
+    pub(crate) ok: bool,
+    pub(crate) cmds: usize,
+    /// Number of distinct files that some tool ran on
+    pub(crate) files: usize,
+    /// By tool name
+    pub(crate) tools: BTreeMap<String, ToolResult>,
+    /// Displayed command lines of the commands that failed
+    pub(crate) failed: Vec<String>,
+    /// Files that some failed command ran on
+    pub(crate) failed_files: BTreeSet<PathBuf>,
+    /// Number of distinct files that some formatter ran on
+    pub(crate) formatted: usize,
+    /// Number of distinct files that some linter ran on
+    pub(crate) linted: usize,
+    /// Files that formatters changed
+    pub(crate) modified: BTreeSet<PathBuf>,
+    /// Number of failed commands whose output was replayed from a stored result
+    pub(crate) replayed: usize,
+    /// Number of commands that ran to completion
+    pub(crate) completed: usize,
+    /// Number of files that needed running, but were left out by `--limit` or
+    /// `--sample`
+    pub(crate) skipped: usize,
+    pub(crate) cache: plan::Stats,
+    pub(crate) duration: time::Duration,
+}
+
+impl Default for RunResult {
+    fn default() -> Self {
+        Self {
+            ok: true,
+            cmds: 0,
+            files: 0,
+            tools: BTreeMap::new(),
+            failed: Vec::new(),
+            failed_files: BTreeSet::new(),
+            formatted: 0,
+            linted: 0,
+            modified: BTreeSet::new(),
+            replayed: 0,
+            completed: 0,
+            skipped: 0,
+            cache: plan::Stats::default(),
+            duration: time::Duration::ZERO,
+        }
+    }
+}
+
+impl RunResult {
+    fn new(jobs: &[cmd::Command], cache: plan::Stats) -> Self {
+        let mut tools = BTreeMap::<String, ToolResult>::new();
+        for job in jobs {
+            let tool = tools
+                .entry(job.tool.display_name().to_string())
+                .or_default();
+            tool.cmds += 1;
+            tool.files += job.files.len();
+        }
+        let files = jobs
+            .iter()
+            .flat_map(|job| job.files.iter().map(|f| &f.path))
+            .collect::<HashSet<_>>()
+            .len();
+        let formatted = jobs
+            .iter()
+            .filter(|job| job.tool.formats)
+            .flat_map(|job| job.files.iter().map(|f| &f.path))
+            .collect::<HashSet<_>>()
+            .len();
+        let linted = jobs
+            .iter()
+            .filter(|job| !job.tool.formats)
+            .flat_map(|job| job.files.iter().map(|f| &f.path))
+            .collect::<HashSet<_>>()
+            .len();
+        Self {
+            cmds: jobs.len(),
+            files,
+            formatted,
+            linted,
+            tools,
+            cache,
+            ..Self::default()
+        }
+    }
+
+    fn add_outcomes(
+        &mut self,
+        outcomes: &[exec::Outcome],
+        tools: &[tool::Tool],
+        baseline: Option<&Baseline>,
+    ) {
+        self.completed += outcomes.len();
+        for outcome in outcomes {
+            let tool = self.tools.entry(outcome.tool.clone()).or_default();
+            tool.duration += outcome.duration;
+            self.modified.extend(outcome.modified.iter().cloned());
+            tool.sandbox_violations += usize::from(outcome.sandbox_violation);
+            if !outcome.success {
+                tool.failed += 1;
+                tool.add_problems(quickfix::problems(outcome, tools, baseline));
+                self.ok = false;
+                self.replayed += usize::from(outcome.replayed);
+                self.failed.push(outcome.cmd.clone());
+                self.failed_files.extend(outcome.files.iter().cloned());
+            }
+        }
+    }
 }
 
 impl From<RunResult> for bool {
@@ -227,90 +580,347 @@ impl From<RunResult> for bool {
 
 impl From<&RunResult> for bool {
     fn from(value: &RunResult) -> Self {
-        match value {
-            RunResult::AllGood { .. } => true,
-            RunResult::Errors => false,
-        }
+        value.ok
     }
 }
 
-fn run(config: &Config, lints: &Warns) -> Result<RunResult> {
+/// Re-collect the modified files, so that they are re-hashed
+fn refresh(files: &[file::File], modified: &HashSet<&PathBuf>) -> Vec<file::File> {
+    files
+        .iter()
+        .filter_map(|f| {
+            if modified.contains(&f.path) {
+                file::File::new(f.path.clone()).ok()
+            } else {
+                Some(f.clone())
+            }
+        })
+        .collect()
+}
+
+fn run(
+    config: &mut Config,
+    lints: &Warns,
+    warm: Option<&mut plan::WarmStart>,
+) -> Result<RunResult> {
+    run_outcomes(config, lints, warm, None).map(|(result, _)| result)
+}
+
+/// Like [`run`], but also return the outcomes of the commands
+fn run_outcomes(
+    config: &mut Config,
+    lints: &Warns,
+    mut warm: Option<&mut plan::WarmStart>,
+    mut resident: Option<&mut Resident>,
+) -> Result<(RunResult, Vec<exec::Outcome>)> {
     trace!(?config);
+    let start = time::Instant::now();
     debug_assert!(config.files.iter().all(|f| f.content_stamp.is_none()));
+    let mut profile = profile::Profile {
+        walk: config.walk,
+        ..profile::Profile::default()
+    };
     let cache_file = config.cache.join("cache");
-    let mut cache = if config.no_cache {
-        cache::HashCache::new(PathBuf::from("/dev/null"), 0)
+    let resident_cache = resident.as_deref_mut().and_then(|r| r.cache.take());
+    let mut cache = match resident_cache {
+        _ if config.no_cache => cache::HashCache::new(PathBuf::from("/dev/null"), 0),
+        Some(cache) => cache,
+        None => cache::HashCache::from_file(&cache_file, config.cache_size)?,
+    };
+    cache.max_age = config.cache_max_age;
+    cache.fsync = config.fsync;
+    // Without `mtime`, modification times aren't trusted
+    let resident_index = resident.as_deref_mut().and_then(|r| r.index.take());
+    let mut index = match resident_index {
+        _ if config.no_cache || !config.mtime => index::Index::default(),
+        Some(index) => index,
+        None => index::Index::from_file(&config.cache.join("index"))?,
+    };
+    index.fsync = config.fsync;
+    let mut timings = if config.no_cache {
+        timings::Timings::default()
     } else {
-        cache::HashCache::from_file(&cache_file, config.cache_size)?
+        timings::Timings::from_file(&config.cache.join("timings"))?
     };
-    let jobs = plan::plan(
-        &mut cache,
-        &config.tools,
-        &config.files,
-        &config.refs,
-        config.cores,
-        config.no_batch,
-        config.mtime,
-    )?;
-    if !config.no_cache {
-        cache.flush()?;
+    timings.fsync = config.fsync;
+    profile.load = start.elapsed();
+
+    // Formatters run first, then the other tools are planned against the
+    // formatted files, so that they don't see (or cache) the old contents
+    let phases: [fn(&tool::Tool) -> bool; 2] = [|t| t.formats, |t| !t.formats];
+    let mut files = Cow::Borrowed(config.files.as_slice());
+    let mut all_jobs = Vec::new();
+    let mut stats = plan::Stats::default();
+    let (mut ok, mut outcomes) = (true, Vec::new());
+    let mut failures = 0;
+    // Outcomes of `before` and `after` commands
+    let mut setup_outcomes = Vec::new();
+    let report_setup = config.report && config.output_format == cli::OutputFormat::Human;
+    // Files kept and left out by `--limit` and `--sample`
+    let (mut sampled, mut skipped) = (HashSet::new(), HashSet::new());
+    let check_writes = !config.dry_run && warn::check_writes(lints);
+    for include in phases {
+        let planning = time::Instant::now();
+        let (mut jobs, phase_stats) = plan::plan(
+            &mut cache,
+            &mut index,
+            &timings,
+            &config.tools,
+            include,
+            &files,
+            &mut config.git,
+            config.cores,
+            config.no_batch,
+            config.mtime,
+            config.explain_cache,
+            warm.as_deref_mut(),
+            config.shuffle.then_some(config.seed),
+        )?;
+        profile.plan += planning
+            .elapsed()
+            .saturating_sub(phase_stats.hashing + phase_stats.lookup);
+        stats += phase_stats;
+        let saving = time::Instant::now();
+        if !config.no_cache {
+            cache.flush()?;
+        };
+        profile.save += saving.elapsed();
+        if let Some(sample) = config.sample {
+            skipped.extend(job::sample(&mut jobs, sample, config.seed, &mut sampled));
+        }
+        let phase_setup = setup_outcomes.len();
+        if !config.dry_run {
+            let missing = warn::check_missing_tools(lints, &jobs)?;
+            jobs.retain(|job| !missing.contains(job.tool.display_name()));
+            setup_outcomes.extend(setup::before(&mut jobs, report_setup)?);
+        }
+        let no_jobs = jobs.is_empty();
+        let phase_jobs = all_jobs.len();
+        all_jobs.extend(jobs.iter().cloned());
+        let keep_going = match config.keep_going {
+            0 => 0,
+            n => n - failures,
+        };
+        let executing = time::Instant::now();
+        let exec_result = do_exec(config, &mut cache, jobs, keep_going);
+        profile.exec += executing.elapsed();
+        let saving = time::Instant::now();
+        if !no_jobs && !config.no_cache {
+            let cache_full = cache.flush()?;
+            warn::check_cache_usage(lints, cache.entries_added, cache.max_entries)?;
+            warn::check_cache_full(lints, cache_full)?;
+        }
+        profile.save += saving.elapsed();
+        let (phase_ok, phase_outcomes) = exec_result?;
+        if !config.dry_run {
+            setup_outcomes.extend(setup::after(&all_jobs[phase_jobs..], report_setup)?);
+        }
+        failures += setup_outcomes[phase_setup..]
+            .iter()
+            .filter(|o| !o.success)
+            .count();
+        if !phase_outcomes.is_empty() {
+            let sizes = files
+                .iter()
+                .map(|f| (f.path.as_path(), f.size))
+                .collect::<HashMap<_, _>>();
+            for outcome in &phase_outcomes {
+                timings.record(outcome, |p| sizes.get(p).copied().unwrap_or_default());
+            }
+        }
+        ok &= phase_ok;
+        // Ninja doesn't say which commands failed
+        failures += phase_outcomes
+            .iter()
+            .filter(|o| !o.success)
+            .count()
+            .max(usize::from(!phase_ok));
+        let mut modified = phase_outcomes
+            .iter()
+            .flat_map(|o| &o.modified)
+            .collect::<HashSet<_>>();
+        let written = if check_writes {
+            files
+                .iter()
+                .filter(|f| !modified.contains(&f.path) && f.modified())
+                .map(|f| f.path.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        warn::check_unexpected_writes(lints, &phase_outcomes, &written)?;
+        modified.extend(&written);
+        if !modified.is_empty() {
+            files = Cow::Owned(refresh(&files, &modified));
+        }
+        outcomes.extend(phase_outcomes);
+        if interrupt::interrupted() {
+            ok = false;
+            break;
+        }
+        if config.keep_going != 0 && failures >= config.keep_going {
+            break;
+        }
+    }
+    let saving = time::Instant::now();
+    index.flush()?;
+    timings.flush()?;
+    profile.save += saving.elapsed();
+    profile.hash = stats.hashing;
+    profile.cache = stats.lookup;
+    if let Some(warm) = warm {
+        warm.planned();
+    }
+    if let Some(resident) = resident
+        && !config.no_cache
+    {
+        cache.next_run();
+        resident.cache = Some(cache);
+        if config.mtime {
+            index.next_run();
+            resident.index = Some(index);
+        }
+    }
+
+    let mut result = if config.dry_run {
+        RunResult::default()
+    } else {
+        RunResult::new(&all_jobs, stats)
     };
-    let no_jobs = jobs.is_empty();
-    let n_jobs = jobs.len();
-    let files_linted = jobs
-        .iter()
-        .flat_map(|job| job.files.iter().map(|f| &f.path))
-        .collect::<HashSet<_>>()
-        .len();
-    let result = do_exec(config, &mut cache, jobs);
-    if !no_jobs && !config.no_cache {
-        let cache_full = cache.flush()?;
-        warn::check_cache_usage(lints, cache.entries_added, cache.max_entries)?;
-        warn::check_cache_full(lints, cache_full)?;
-    }
-    let result = match result {
-        _ if config.dry_run => Ok(RunResult::AllGood { cmds: 0, files: 0 }),
-        Ok(true) => Ok(RunResult::AllGood {
-            cmds: n_jobs,
-            files: files_linted,
-        }),
-        Ok(false) => Ok(RunResult::Errors),
-        Err(e) => Err(e),
-    }?;
-    report_result(&result);
+    result.cmds += setup_outcomes.len();
+    result.skipped = skipped.difference(&sampled).count();
+    ok &= setup_outcomes.iter().all(|o| o.success);
+    outcomes.extend(setup_outcomes);
+    if config.output_format == cli::OutputFormat::Tap && config.report {
+        print!("{}", tap::render(&outcomes));
+    }
+    if let (Some(error_file), false) = (&config.error_file, config.dry_run) {
+        quickfix::write(
+            error_file,
+            &outcomes,
+            &config.tools,
+            config.baseline.as_deref(),
+        )?;
+    }
+    if !config.dry_run {
+        result.ok = ok;
+        result.add_outcomes(&outcomes, &config.tools, config.baseline.as_deref());
+    }
+    result.duration = start.elapsed();
+    if config.output_format == cli::OutputFormat::Json && config.report {
+        println!("{}", render_json(&result));
+    }
+    if config.report {
+        report_result(&result);
+        if let Some(format) = config.timings {
+            profile.print(format);
+        }
+    }
     then_else(config, &result)?;
-    Ok(result)
+    Ok((result, outcomes))
 }
 
 fn do_exec(
     config: &Config,
     cache: &mut (impl CacheWriter + ?Sized),
-    jobs: Vec<crate::cmd::Command>,
-) -> std::result::Result<bool, anyhow::Error> {
+    jobs: Vec<cmd::Command>,
+    keep_going: usize,
+) -> std::result::Result<(bool, Vec<exec::Outcome>), anyhow::Error> {
     if config.ninja {
-        ninja::exec(
+        // Ninja reports its own failures, but not which files were formatted
+        let formatters = jobs
+            .iter()
+            .filter(|job| job.tool.formats)
+            .cloned()
+            .collect::<Vec<_>>();
+        let ok = ninja::exec(
             cache,
             config.cache.as_path(),
             jobs,
             config.cores,
             config.dry_run,
             config.no_capture,
-            config.keep_going,
+            config.show_progress,
+            keep_going,
             config.mtime,
-        )
+        )?;
+        let outcomes = formatters
+            .into_iter()
+            .map(|job| exec::Outcome {
+                tool: job.tool.display_name().to_string(),
+                cmd: job::display_job(&job, &job.to_command()),
+                files: job.files.iter().map(|f| f.path.clone()).collect(),
+                cd: job.tool.cd.clone(),
+                modified: exec::modified(&job),
+                success: true, // not known
+                output: None,
+                replayed: false,
+                sandbox_violation: false,
+                duration: time::Duration::ZERO,
+            })
+            .collect();
+        Ok((ok, outcomes))
     } else if config.dry_run {
-        Ok(true)
+        Ok((true, Vec::new()))
     } else {
-        exec::exec(
+        let cache_failures = config.tools.iter().any(|t| t.cache_failures);
+        let results = (cache_failures && !config.no_cache && !config.no_capture)
+            .then(|| results::Results::new(&config.cache, config.fsync));
+        let outcomes = exec::exec(
             cache,
             jobs,
             config.cores,
             config.no_capture,
+            config.prefix,
             config.show_progress,
-            config.keep_going,
+            keep_going,
             config.mtime,
-        )
+            results.as_ref(),
+            config.baseline.as_deref(),
+            config.report && config.output_format == cli::OutputFormat::Human,
+        )?;
+        Ok((outcomes.iter().all(|o| o.success), outcomes))
+    }
+}
+
+/// Replace each `{{name}}` in `cmd` by the value of `name` in `vars`, quoted
+/// as a shell word, leaving unknown names alone
+fn template(cmd: &str, vars: &[(&str, String)]) -> String {
+    let mut cmd = cmd.to_string();
+    for (name, value) in vars {
+        cmd = cmd.replace(&format!("{{{{{name}}}}}"), &job::quote(value));
     }
+    cmd
+}
+
+/// The `bash` command for `--then` or `--else`, see `doc/usage.md`
+fn then_else_cmd(cmd: &str, result: &RunResult) -> process::Command {
+    let failed_tools = result
+        .tools
+        .iter()
+        .filter(|(_, t)| t.failed > 0)
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    let duration_ms = result.duration.as_millis().to_string();
+    let cmd = template(
+        cmd,
+        &[
+            ("commands", result.cmds.to_string()),
+            ("files", result.files.to_string()),
+            ("files_linted", result.linted.to_string()),
+            ("failed", failed_tools.join(", ")),
+            ("duration_ms", duration_ms.clone()),
+        ],
+    );
+    let mut bash_cmd = process::Command::new("bash");
+    bash_cmd.arg("-c").arg(&cmd);
+    bash_cmd.env("LUN_COMMANDS", result.cmds.to_string());
+    bash_cmd.env("LUN_FILES", result.files.to_string());
+    bash_cmd.env("LUN_FILES_LINTED", result.linted.to_string());
+    bash_cmd.env("LUN_FAILED", result.failed.join("\n"));
+    bash_cmd.env("LUN_FAILED_TOOLS", failed_tools.join("\n"));
+    bash_cmd.env("LUN_DURATION_MS", duration_ms);
+    bash_cmd
 }
 
 fn then_else(config: &Config, result: &RunResult) -> Result<(), anyhow::Error> {
@@ -321,9 +931,7 @@ fn then_else(config: &Config, result: &RunResult) -> Result<(), anyhow::Error> {
         ("else", config.r#else.as_deref())
     };
     if let Some(cmd) = cmd_to_run {
-        let mut bash_cmd = process::Command::new("bash");
-        bash_cmd.arg("-c").arg(cmd);
-        let status = bash_cmd
+        let status = then_else_cmd(cmd, result)
             .status()
             .with_context(|| format!("Failed to execute `{which}` command: {cmd}"))?;
         if !status.success() {
@@ -339,22 +947,52 @@ pub(crate) fn go(
     config: &config::Config,
     lints: &Warns,
 ) -> std::result::Result<RunResult, anyhow::Error> {
-    lint(run_cli, config, lints)?;
+    if run_cli.daemon
+        && let Some(ok) = daemon::request(cli)?
+    {
+        return Ok(RunResult {
+            ok,
+            ..RunResult::default()
+        });
+    }
+    let config_files = lint(run_cli, config, lints)?;
     fs::create_dir_all(&cli.cache)?; // just to create the dir
     if run_cli.watch {
-        watch(cli, run_cli, config, lints)?;
-        Ok(RunResult::AllGood { cmds: 0, files: 0 })
+        watch(cli, run_cli, config, lints, &config_files)?;
+        Ok(RunResult::default())
+    } else if run_cli.fix_and_check {
+        fix_and_check(cli, run_cli, config, lints, &config_files)
     } else {
-        let config = mk_config(cli, run_cli, config)?;
-        let result = run(&config, lints);
-        #[cfg(debug_assertions)]
+        let config_file = config;
+        let mut config = mk_config(cli, run_cli, config_file, None, &config_files)?;
+        // Only meaningful if all of the tools run
+        if run_cli.only_tool.is_empty() && run_cli.skip_tool.is_empty() && !run_cli.format {
+            warn::check_unmatched_files(lints, &config.tools, &config.files)?;
+        }
+        warn::check_overlapping_tools(lints, config_file, &config.files)?;
+        let result = run(&mut config, lints, None);
+        // Only meaningful if all of the warnings were checked
+        if result.is_ok()
+            && !interrupt::interrupted()
+            && run_cli.only_tool.is_empty()
+            && run_cli.skip_tool.is_empty()
+            && !run_cli.format
         {
+            warn::check_unused_suppressions(lints, config.dry_run)?;
+        }
+        #[cfg(debug_assertions)]
+        if !interrupt::interrupted() {
             let debug_cache = cli.cache.join("debug");
             drop(fs::remove_dir_all(&debug_cache));
             drop(fs::create_dir_all(&debug_cache));
             let mut debug_config = config.clone();
             debug_config.cache = debug_cache;
-            let debug_result = run(&debug_config, lints);
+            debug_config.explain_cache = None;
+            // Only one TAP stream or JSON object on stdout
+            debug_config.report &= config.output_format == cli::OutputFormat::Human;
+            debug_config.error_file = None;
+            debug_config.timings = None;
+            let debug_result = run(&mut debug_config, lints, None);
             debug_assert!(
                 match (result.as_ref(), debug_result.as_ref()) {
                     (Ok(r1), Ok(r2)) => bool::from(r1) == bool::from(r2),
@@ -367,14 +1005,459 @@ pub(crate) fn go(
     }
 }
 
-fn lint(run_cli: &cli::Run, config: &config::Config, lints: &Warns) -> Result<(), anyhow::Error> {
-    warn::check_unknown_tools(lints, &run_cli.skip_tool, &run_cli.only_tool, config)?;
-    warn::check_unlisted_config(lints, config)?;
+/// Runs the tools on files one at a time as they are saved, for `lun lsp`.
+///
+/// Nothing is printed, since stdout belongs to the client.
+#[derive(Debug)]
+pub(crate) struct Session<'a> {
+    config: Config,
+    lints: &'a Warns,
+    warm: plan::WarmStart,
+}
+
+impl<'a> Session<'a> {
+    pub(crate) fn new(
+        cli: &cli::Cli,
+        run_cli: &cli::Run,
+        config: &config::Config,
+        lints: &'a Warns,
+    ) -> Result<Self> {
+        let config_files = lint(run_cli, config, lints)?;
+        fs::create_dir_all(&cli.cache)?;
+        // Files are given to `check` one at a time
+        let mut config = mk_config(cli, run_cli, config, Some(Vec::new()), &config_files)?;
+        // Formatters would change files out from under the editor
+        config.tools.retain(|t| !t.formats);
+        config.ninja = false;
+        config.no_capture = false;
+        config.report = false;
+        config.output_format = cli::OutputFormat::Human;
+        config.error_file = None;
+        config.show_progress = exec::ProgressFormat::No;
+        config.keep_going = 0;
+        config.then = None;
+        config.r#else = None;
+        config.explain_cache = None;
+        for tool in &mut config.tools {
+            tool.no_capture = false;
+        }
+        Ok(Self {
+            config,
+            lints,
+            warm: plan::WarmStart::default(),
+        })
+    }
+
+    pub(crate) fn tools(&self) -> &[tool::Tool] {
+        &self.config.tools
+    }
+
+    /// Run the tools that match `path`, which changed since the last check
+    pub(crate) fn check(&mut self, path: &Path) -> Result<Vec<exec::Outcome>> {
+        self.warm.changed(path.to_path_buf());
+        self.config.git = git::GitContext::new(self.config.git.refs().to_vec());
+        self.config.files = vec![file::File::new(path.to_path_buf())?];
+        let (_, outcomes) = run_outcomes(&mut self.config, self.lints, Some(&mut self.warm), None)?;
+        Ok(outcomes)
+    }
+}
+
+/// The commands that `lun run` would run, for `lun plan`.
+///
+/// Nothing is written to the cache. As with `--dry-run`, the other tools are
+/// planned against the files as they are, rather than as formatted.
+pub(crate) fn plan_jobs(
+    cli: &cli::Cli,
+    run_cli: &cli::Run,
+    config: &config::Config,
+    lints: &Warns,
+) -> Result<Vec<cmd::Command>> {
+    let config_files = lint(run_cli, config, lints)?;
+    fs::create_dir_all(&cli.cache)?;
+    let mut config = mk_config(cli, run_cli, config, None, &config_files)?;
+    if matches!(config.show_progress, exec::ProgressFormat::Yes) {
+        // Clear "Collecting files"
+        eprint!("\x1b[2K\r");
+    }
+    let mut cache = if config.no_cache {
+        cache::HashCache::new(PathBuf::from("/dev/null"), 0)
+    } else {
+        cache::HashCache::from_file(&config.cache.join("cache"), config.cache_size)?
+    };
+    let mut index = if config.no_cache || !config.mtime {
+        index::Index::default()
+    } else {
+        index::Index::from_file(&config.cache.join("index"))?
+    };
+    let timings = if config.no_cache {
+        timings::Timings::default()
+    } else {
+        timings::Timings::from_file(&config.cache.join("timings"))?
+    };
+    let phases: [fn(&tool::Tool) -> bool; 2] = [|t| t.formats, |t| !t.formats];
+    let (mut all_jobs, mut sampled) = (Vec::new(), HashSet::new());
+    for include in phases {
+        let (mut jobs, _) = plan::plan(
+            &mut cache,
+            &mut index,
+            &timings,
+            &config.tools,
+            include,
+            &config.files,
+            &mut config.git,
+            config.cores,
+            config.no_batch,
+            config.mtime,
+            config.explain_cache,
+            None,
+            config.shuffle.then_some(config.seed),
+        )?;
+        if let Some(sample) = config.sample {
+            job::sample(&mut jobs, sample, config.seed, &mut sampled);
+        }
+        all_jobs.extend(jobs);
+    }
+    Ok(all_jobs)
+}
+
+/// Run the linters and record their diagnostics, for `lun baseline create`
+pub(crate) fn create_baseline(
+    cli: &cli::Cli,
+    run_cli: &cli::Run,
+    config: &config::Config,
+    lints: &Warns,
+) -> Result<()> {
+    let config_files = lint(run_cli, config, lints)?;
+    fs::create_dir_all(&cli.cache)?;
+    let mut config = mk_config(cli, run_cli, config, None, &config_files)?;
+    // Formatters don't print diagnostics
+    config.tools.retain(|t| !t.formats);
+    config.baseline = None;
+    config.ninja = false;
+    config.no_capture = false;
+    config.report = false;
+    config.error_file = None;
+    config.keep_going = 0;
+    config.then = None;
+    config.r#else = None;
+    let (_, outcomes) = run_outcomes(&mut config, lints, None, None)?;
+    if interrupt::interrupted() {
+        anyhow::bail!("Interrupted, not writing the baseline");
+    }
+    let mut entries = Vec::new();
+    for outcome in &outcomes {
+        let problems = quickfix::problems(outcome, &config.tools, None);
+        if !outcome.success && problems.is_empty() {
+            warn!(
+                "{}: failed, but `problem_matcher` found no diagnostics in its output",
+                outcome.cmd
+            );
+        }
+        entries.extend(
+            problems
+                .iter()
+                .map(|p| baseline::Entry::new(&outcome.tool, p)),
+        );
+    }
+    let n = entries.len();
+    baseline::write(Path::new(baseline::FILE), entries)?;
+    if matches!(config.show_progress, exec::ProgressFormat::Yes) {
+        eprint!("\x1b[2K\r");
+    }
+    let diagnostics = if n == 1 { "diagnostic" } else { "diagnostics" };
+    eprintln!("Recorded {n} {diagnostics} in {}", baseline::FILE);
+    Ok(())
+}
+
+/// Time each tool on all of the files, without the cache, for `lun bench`.
+///
+/// Tools run one at a time so that they don't compete for the CPU.
+pub(crate) fn time_tools(
+    cli: &cli::Cli,
+    config: &config::Config,
+    lints: &Warns,
+    iterations: NonZeroUsize,
+) -> Result<BTreeMap<String, Vec<time::Duration>>> {
+    let run_cli = <cli::Run as clap::Parser>::try_parse_from(["run", "--check", "--no-cache"])?;
+    let config_files = lint(&run_cli, config, lints)?;
+    fs::create_dir_all(&cli.cache)?;
+    let mut config = mk_config(cli, &run_cli, config, None, &config_files)?;
+    if matches!(config.show_progress, exec::ProgressFormat::Yes) {
+        // Clear "Collecting files"
+        eprint!("\x1b[2K\r");
+    }
+    config.report = false;
+    config.show_progress = exec::ProgressFormat::No;
+    config.then = None;
+    config.r#else = None;
+    let tools = std::mem::take(&mut config.tools);
+    let mut times = BTreeMap::new();
+    for tool in tools {
+        let name = tool.display_name().to_string();
+        config.tools = vec![tool];
+        for _ in 0..iterations.get() {
+            if interrupt::interrupted() {
+                return Ok(times);
+            }
+            let start = time::Instant::now();
+            let result = run(&mut config, lints, None)?;
+            if !result.ok {
+                warn!("{name} failed");
+            }
+            times
+                .entry(name.clone())
+                .or_insert_with(Vec::new)
+                .push(start.elapsed());
+        }
+    }
+    Ok(times)
+}
+
+/// The cache and index, kept in memory between runs by `lun daemon`
+#[derive(Default)]
+struct Resident {
+    cache: Option<cache::HashCache>,
+    index: Option<index::Index>,
+}
+
+/// Runs the requests of `lun run --daemon`, keeping the collected files, the
+/// cache, and the index in memory between them. Output is sent back rather
+/// than printed.
+pub(crate) struct Daemon<'a> {
+    cli: &'a cli::Cli,
+    config: &'a config::Config,
+    lints: &'a Warns,
+    /// Short-lived files, see `config::Config::transient`
+    transient: Patterns,
+    files: BTreeMap<PathBuf, file::File>,
+    /// Files were created or renamed since they were collected
+    stale: bool,
+    warm: plan::WarmStart,
+    resident: Resident,
+}
+
+impl<'a> Daemon<'a> {
+    pub(crate) fn new(
+        cli: &'a cli::Cli,
+        config: &'a config::Config,
+        lints: &'a Warns,
+    ) -> Result<Self> {
+        fs::create_dir_all(&cli.cache)?;
+        Ok(Self {
+            cli,
+            config,
+            lints,
+            transient: config.transient().context(exit::ConfigError)?,
+            files: BTreeMap::new(),
+            stale: true,
+            warm: plan::WarmStart::default(),
+            resident: Resident::default(),
+        })
+    }
+
+    /// Note a filesystem event, to walk the repository again before the next
+    /// run if files were created or renamed
+    pub(crate) fn event(&mut self, ev: &notify::Event) {
+        trace!("Filesystem event: {:?} {:?}", ev.kind, ev.paths);
+        let created = matches!(
+            ev.kind,
+            EventKind::Create(_) | EventKind::Modify(notify::event::ModifyKind::Name(_))
+        );
+        self.stale |= created && need_rerun(ev, &self.transient);
+    }
+
+    /// Collect the files again if needed, or else re-`stat` the known ones,
+    /// which is much cheaper than walking the repository
+    fn refresh(&mut self) -> Result<()> {
+        if self.stale {
+            debug!("Collecting files");
+            let mut files = file::collect_files(
+                Path::new("."),
+                &self.cli.cache,
+                exec::ProgressFormat::No,
+                self.config.follow_symlinks,
+                self.config.walk_ignore(&[])?.as_ref(),
+                &self.transient,
+            )?;
+            if self.config.skip_generated {
+                gitattributes::skip_generated(&mut files);
+            }
+            for f in &files {
+                self.warm.changed(f.path.clone());
+            }
+            self.files = files.into_iter().map(|f| (f.path.clone(), f)).collect();
+            self.stale = false;
+            return Ok(());
+        }
+        let files = std::mem::take(&mut self.files);
+        let refreshed = files
+            .into_par_iter()
+            .map(|(path, old)| {
+                let new = file::File::new(path.clone()).ok();
+                let changed = new
+                    .as_ref()
+                    .is_none_or(|new| new.size != old.size || new.mtime_stamp != old.mtime_stamp);
+                (path, new, changed)
+            })
+            .collect::<Vec<_>>();
+        for (path, new, changed) in refreshed {
+            if changed {
+                self.warm.changed(path.clone());
+            }
+            if let Some(new) = new {
+                self.files.insert(path, new);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn run(&mut self, run_cli: &cli::Run) -> Result<daemon::Reply> {
+        self.refresh()?;
+        let config_files = lint(run_cli, self.config, self.lints)?;
+        let files = resident_files_apply(run_cli, self.config.skip_generated)
+            .then(|| self.files.values().cloned().collect());
+        let mut config = mk_config(self.cli, run_cli, self.config, files, &config_files)?;
+        config.report = false;
+        config.show_progress = exec::ProgressFormat::No;
+        let (result, outcomes) = run_outcomes(
+            &mut config,
+            self.lints,
+            Some(&mut self.warm),
+            Some(&mut self.resident),
+        )?;
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let seen = diag::Seen::default();
+        // In a fixed order, like `exec::exec` prints deduplicated output
+        let mut sorted = outcomes.iter().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| (&a.tool, &a.cmd).cmp(&(&b.tool, &b.cmd)));
+        for outcome in sorted {
+            let Some(output) = outcome.output.as_ref().filter(|_| !outcome.success) else {
+                continue;
+            };
+            let group = config
+                .tools
+                .iter()
+                .find(|t| t.display_name() == outcome.tool)
+                .and_then(|t| t.dedupe_group.as_deref());
+            let (out, err) = match group {
+                Some(group) => (
+                    seen.dedupe(group, &outcome.tool, &output.stdout),
+                    seen.dedupe(group, &outcome.tool, &output.stderr),
+                ),
+                None => (output.stdout.clone(), output.stderr.clone()),
+            };
+            exec::write_failure(&mut stdout, &mut stderr, &outcome.cmd, &out, &err)?;
+        }
+        stderr.extend_from_slice(render_result(&result).as_bytes());
+        Ok(daemon::Reply {
+            ok: result.ok,
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            error: None,
+        })
+    }
+}
+
+/// Whether the files kept by `lun daemon` are the ones that `run_cli` would
+/// collect, see [`collect_files`]. Otherwise, they are collected as usual.
+fn resident_files_apply(run_cli: &cli::Run, skip_generated: bool) -> bool {
+    !run_cli.staged
+        && !run_cli.only_dirty
+        && run_cli.files_from.is_none()
+        && run_cli.walk_ignore.is_empty()
+        && (!skip_generated || !run_cli.include_generated)
+}
+
+/// Run in fix mode, then check the files that the fixes changed and the ones
+/// that still failed.
+fn fix_and_check(
+    cli: &cli::Cli,
+    run_cli: &cli::Run,
+    config: &config::Config,
+    lints: &Warns,
+    config_files: &config::ConfigFiles,
+) -> Result<RunResult> {
+    let mut fix = mk_config(cli, run_cli, config, None, config_files)?;
+    let mut check = fix.clone();
+    check.tools = filter_tools(run_cli, config, RunMode::Check, cli.log.color, config_files)?;
+    // The check reports the remaining failures, so fix as much as possible.
+    // Ninja doesn't say which commands failed, so don't use it here.
+    fix.report = false;
+    fix.keep_going = 0;
+    fix.ninja = false;
+    fix.then = None;
+    fix.r#else = None;
+    // Tools may rewrite files without changing them, so remember the contents
+    let before = fix
+        .files
+        .par_iter()
+        .cloned()
+        .map(|mut f| f.fill_content_stamp().map(|()| f))
+        .collect::<Result<Vec<_>>>()?;
+    let fixed = run(&mut fix, lints, None)?;
+
+    let mut changed = Vec::new();
+    check.files.clear();
+    for before in before {
+        // Re-collect the file so that the check re-hashes it
+        let after = file::File::new(before.path.clone())?;
+        let touched = after.size != before.size || after.mtime_stamp != before.mtime_stamp;
+        let changes = touched && {
+            let mut rehashed = after.clone();
+            rehashed.fill_content_stamp()?;
+            rehashed.content_stamp != before.content_stamp
+        };
+        if changes {
+            changed.push(after.path.clone());
+        }
+        if changes || fixed.failed_files.contains(&after.path) {
+            check.files.push(after);
+        }
+    }
+    debug!(
+        "{} files changed by fixes, {} failed",
+        changed.len(),
+        fixed.failed_files.len()
+    );
+    let result = run(&mut check, lints, None)?;
+    if !check.dry_run {
+        report_fixes(&changed, &result);
+    }
+    Ok(result)
+}
+
+/// Check for warnings, returning the config files of tools for [`mk_config`]
+fn lint(run_cli: &cli::Run, config: &config::Config, lints: &Warns) -> Result<config::ConfigFiles> {
+    warn::check_unknown_tools(
+        lints,
+        &run_cli.skip_tool,
+        &run_cli.only_tool,
+        &run_cli.fresh_tool,
+        config,
+    )?;
+    let config_files = config::ConfigFiles::default();
+    warn::check_unlisted_config(lints, config, &config_files)?;
     warn::check_no_files(lints, config)?;
-    warn::check_careful(lints, run_cli.careful, config.careful)?;
+    warn::check_undeclared_env(lints, |t| include_tool(t, run_cli), config)?;
+    warn::check_careful(
+        lints,
+        run_cli.careful.unwrap_or_default().max(config.careful),
+    )?;
     warn::check_mtime(lints, run_cli.no_mtime, config.mtime)?;
     warn::check_refs(lints, &run_cli.refs, &config.refs)?;
-    Ok(())
+    // Missing tools are reported (and skipped) once they have jobs, see
+    // `warn::check_missing_tools`
+    let installed = |t: &config::Tool| {
+        let program = t.cmd.split_whitespace().next().unwrap_or_default();
+        include_tool(t, run_cli) && cmd::on_path(program, t.cd_dir())
+    };
+    warn::check_tool_versions(lints, installed, config)?;
+    // Like missing tools, which dry runs don't skip
+    if !run_cli.dry_run {
+        let careful = run_cli.careful.unwrap_or_default().max(config.careful);
+        warn::check_version_probes(lints, installed, config, careful)?;
+    }
+    Ok(config_files)
 }
 
 fn clear_term() {
@@ -389,18 +1472,35 @@ fn watch(
     run_cli: &cli::Run,
     config: &config::Config,
     lints: &Warns,
+    config_files: &config::ConfigFiles,
 ) -> Result<bool> {
-    let mut config = mk_config(cli, run_cli, config)?;
-    run(&config, lints)?;
+    if run_cli.notify {
+        desktop::check()?;
+    }
+    let mut config_file = config.clone();
+    let mut config = mk_config(cli, run_cli, &config_file, None, config_files)?;
+    let mut warm = plan::WarmStart::default();
+    let mut notifier = desktop::Notifier::default();
+    let result = run(&mut config, lints, Some(&mut warm))?;
+    if run_cli.notify {
+        notifier.update(&result);
+    }
+    // Failures aren't cached, so these are checked again with changed files
+    let mut failed = result.failed_files;
 
-    let initial_config_hash = fs::read(&cli.config)
-        .ok()
-        .map(|contents| file::compute_hash(&contents));
+    let mut last_config_hash = config_hash(cli);
+    // Replace `lints` once the config file is reloaded
+    let mut reloaded_lints = None;
+    // Changed with keys
+    let mut current = run_cli.clone();
+    let mut tool = None;
+    let mut tools = tool_names(&config_file, run_cli);
 
     let (tx, rx) = mpsc::channel();
+    let keys_tx = tx.clone();
     let mut watcher = RecommendedWatcher::new(
         move |res| {
-            if let Err(e) = tx.send(res) {
+            if let Err(e) = tx.send(Input::Event(res)) {
                 warn!("Error sending watch event: {e}");
             }
         },
@@ -413,69 +1513,576 @@ fn watch(
         .watch(cwd, RecursiveMode::Recursive)
         .context("Failed to start watching directory")?;
 
+    // Restores the terminal when dropped
+    let raw_mode = keys::listen(move |key| keys_tx.send(Input::Key(key)).is_ok());
+    if raw_mode.is_some() {
+        info!(
+            "Press r to re-run everything, f to toggle fix mode, t to cycle through tools, q to quit"
+        );
+    }
+
     debug!("Watching for file changes...");
     let mut last_run = time::Instant::now();
+    let mut changed = Changed::default();
+    // The files of the last full collection, which changed files must be among
+    let mut known = config
+        .files
+        .iter()
+        .map(|f| f.path.clone())
+        .collect::<HashSet<_>>();
+    let mut last_collected = time::Instant::now();
+    // Run every tool on every file, ignoring the cache
+    let mut force = false;
+    // Build the tools again, after changing the config file or options
+    let mut rebuild = false;
     loop {
         let mut needed = false;
-        let ev = rx.recv().context("File watcher channel error")?;
-        needed |= process_event(ev)?;
-        while let Ok(ev) = rx.try_recv() {
-            needed |= process_event(ev)?;
+        let input = match rx.recv_timeout(time::Duration::from_millis(100)) {
+            Ok(input) => input,
+            Err(mpsc::RecvTimeoutError::Timeout) if interrupt::interrupted() => return Ok(true),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(e) => return Err(e).context("File watcher channel error"),
+        };
+        for input in std::iter::once(input).chain(rx.try_iter()) {
+            match input {
+                Input::Event(ev) => {
+                    needed |= process_event(ev, &config.transient, &mut warm, &mut changed)?;
+                }
+                Input::Key(keys::Key::Quit) => return Ok(true),
+                Input::Key(keys::Key::Rerun) => {
+                    force = true;
+                    changed.rescan = true;
+                    needed = true;
+                }
+                Input::Key(keys::Key::Fix) => {
+                    current.fix = !current.fix;
+                    rebuild = true;
+                    needed = true;
+                }
+                Input::Key(keys::Key::Tool) => {
+                    tool = keys::next_tool(&tools, tool.as_deref());
+                    current.only_tool = tool
+                        .clone()
+                        .map_or_else(|| run_cli.only_tool.clone(), |t| vec![t]);
+                    rebuild = true;
+                    needed = true;
+                }
+            }
         }
-        if needed && last_run.elapsed() > time::Duration::from_millis(50) {
+        if needed && (force || rebuild || last_run.elapsed() > time::Duration::from_millis(50)) {
             clear_term();
-            warn_if_config_changed(&cli.config, initial_config_hash);
             thread::sleep(time::Duration::from_millis(20));
-            config.files = collect_files(cli, run_cli, config.show_progress)?;
-            run(&config, lints)?;
+            config.git = git::GitContext::new(config.git.refs().to_vec());
+            let changed = std::mem::take(&mut changed);
+            let rebuild = std::mem::take(&mut rebuild);
+            let mut rebuilt = false;
+            let new_hash = config_hash(cli);
+            if new_hash != last_config_hash {
+                last_config_hash = new_hash;
+                match reload(cli, run_cli, &current) {
+                    Ok((new_config_file, new_lints, new_config)) => {
+                        info!("Reloaded the config file");
+                        config_file = new_config_file;
+                        reloaded_lints = Some(new_lints);
+                        tools = tool_names(&config_file, run_cli);
+                        config = new_config;
+                        rebuilt = true;
+                    }
+                    Err(e) => {
+                        error!("Failed to reload the config file, keeping the old one: {e:#}");
+                    }
+                }
+            }
+            if rebuild && !rebuilt {
+                // Config files may have been added or removed since
+                let config_files = config::ConfigFiles::default();
+                config = mk_config(cli, &current, &config_file, None, &config_files)?;
+                rebuilt = true;
+            }
+            if rebuilt {
+                // Tools may have changed
+                warm = plan::WarmStart::default();
+                known = config.files.iter().map(|f| f.path.clone()).collect();
+                last_collected = time::Instant::now();
+            } else if changed.rescan
+                || last_collected.elapsed() > FULL_COLLECTION_INTERVAL
+                || changed
+                    .paths
+                    .iter()
+                    .any(|p| !known.contains(p) && p.exists())
+            {
+                // E.g., new files, which may not match `--only-files` and
+                // the like, or be ignored by git
+                debug!("Collecting all files");
+                config.files = collect_files(
+                    cli,
+                    &current,
+                    &mut config.git,
+                    config.show_progress,
+                    config.follow_symlinks,
+                    config.walk_ignore.as_ref(),
+                    &config.transient,
+                    config.skip_generated,
+                )?;
+                known = config.files.iter().map(|f| f.path.clone()).collect();
+                last_collected = time::Instant::now();
+            } else {
+                debug!("Collecting {} changed files", changed.paths.len());
+                known.retain(|p| !changed.paths.contains(p) || p.exists());
+                config.files = incremental_paths(changed.paths, &known, &failed)
+                    .into_iter()
+                    .filter_map(|p| file::File::new(p).ok())
+                    .collect();
+            }
+            if current.fix != run_cli.fix || tool.is_some() {
+                info!(
+                    "Running {} in {} mode",
+                    tool.as_deref().unwrap_or("all tools"),
+                    if current.fix { "fix" } else { "check" }
+                );
+            }
+            let lints = reloaded_lints.as_ref().unwrap_or(lints);
+            let result = if std::mem::take(&mut force) {
+                // Like `--fresh-tool` for every tool
+                let mut fresh = config.clone();
+                for tool in &mut fresh.tools {
+                    tool.fresh = true;
+                }
+                run(&mut fresh, lints, Some(&mut warm))?
+            } else {
+                run(&mut config, lints, Some(&mut warm))?
+            };
+            if run_cli.notify {
+                notifier.update(&result);
+            }
+            failed = result.failed_files;
         }
         last_run = time::Instant::now();
     }
 }
 
+/// What the `--watch` loop waits for
+#[derive(Debug)]
+enum Input {
+    Event(Result<notify::Event, notify::Error>),
+    Key(keys::Key),
+}
+
+/// Names of the tools that `t` cycles through in `--watch` mode
+fn tool_names(config: &config::Config, run_cli: &cli::Run) -> Vec<String> {
+    let linters = config
+        .linter
+        .iter()
+        .map(|l| &l.tool)
+        .filter(|_| !run_cli.format);
+    linters
+        .chain(config.formatter.iter().map(|f| &f.tool))
+        .filter(|t| include_tool(t, run_cli))
+        .filter_map(|t| t.name.clone())
+        .collect()
+}
+
 fn report_result(res: &RunResult) {
-    match res {
-        RunResult::AllGood { cmds, files: 0 } => {
+    eprint!("{}", render_result(res));
+}
+
+/// The summary of a run, as printed on stderr
+#[allow(clippy::unwrap_used)] // writing to a `String`
+fn render_result(res: &RunResult) -> String {
+    for (name, tool) in &res.tools {
+        debug!(
+            "{name}: {} commands on {} files, {} failed, {:?}",
+            tool.cmds, tool.files, tool.failed, tool.duration
+        );
+    }
+    debug!(
+        "{} cache hits, {} misses in {:?}",
+        res.cache.hits(),
+        res.cache.misses,
+        res.duration
+    );
+    let mut out = String::new();
+    for path in &res.modified {
+        writeln!(out, "\x1b[2K\rmodified {}", path.display()).unwrap();
+    }
+    for (name, tool) in &res.tools {
+        if tool.sandbox_violations > 0 {
+            let cmds = if tool.sandbox_violations == 1 {
+                "command"
+            } else {
+                "commands"
+            };
+            writeln!(
+                out,
+                "\x1b[2K\rsandbox: {name} tried to write to the repository in {} {cmds}",
+                tool.sandbox_violations
+            )
+            .unwrap();
+        }
+    }
+    for (name, tool) in &res.tools {
+        if tool.problems() > 0 {
+            writeln!(out, "\x1b[2K\r{name}: {}", render_problems(tool)).unwrap();
+        }
+    }
+    if res.skipped > 0 {
+        let files = if res.skipped == 1 { "file" } else { "files" };
+        writeln!(
+            out,
+            "\x1b[2K\rskipped {} {files} that need running (--limit or --sample)",
+            res.skipped
+        )
+        .unwrap();
+    }
+    if res.formatted > 0 {
+        let files = if res.formatted == 1 { "file" } else { "files" };
+        writeln!(
+            out,
+            "\x1b[2K\rformatted {} {files}, {} modified",
+            res.formatted,
+            res.modified.len()
+        )
+        .unwrap();
+    }
+    let pairs = res.cache.hits() + res.cache.misses;
+    if let Some(percent) = (res.cache.hits() * 100).checked_div(pairs) {
+        writeln!(
+            out,
+            "\x1b[2K\rcache: {} hits, {} misses ({percent}%)",
+            res.cache.hits(),
+            res.cache.misses,
+        )
+        .unwrap();
+    }
+    if interrupt::interrupted() {
+        writeln!(
+            out,
+            "\x1b[2K\rinterrupted after {} of {} commands",
+            res.completed, res.cmds
+        )
+        .unwrap();
+        return out;
+    }
+    if !res.ok {
+        return out; // output is mirrored to std{out,err}
+    }
+    let RunResult { cmds, files, .. } = res;
+    match files {
+        0 => {
             debug_assert_eq!(*cmds, 0);
-            eprintln!("\x1b[2K\r[{cmds}/{cmds}] 0 files linted");
+            writeln!(out, "\x1b[2K\r[{cmds}/{cmds}] 0 files linted").unwrap();
         }
-        RunResult::AllGood { cmds, files: 1 } => {
-            eprintln!("\x1b[2K\r[{cmds}/{cmds}] 1 file linted");
+        1 => writeln!(out, "\x1b[2K\r[{cmds}/{cmds}] 1 file linted").unwrap(),
+        _ => writeln!(out, "\x1b[2K\r[{cmds}/{cmds}] {files} files linted").unwrap(),
+    }
+    out
+}
+
+/// E.g., `14 errors, 1 warning in 6 files`
+fn render_problems(tool: &ToolResult) -> String {
+    let plural = |n: usize, what: &str| {
+        if n == 1 {
+            format!("1 {what}")
+        } else {
+            format!("{n} {what}s")
         }
-        RunResult::AllGood { cmds, files } => {
-            eprintln!("\x1b[2K\r[{cmds}/{cmds}] {files} files linted");
+    };
+    let counts = [
+        (tool.errors, "error"),
+        (tool.warnings, "warning"),
+        (tool.notes, "note"),
+    ]
+    .into_iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, what)| plural(n, what))
+    .collect::<Vec<_>>();
+    format!(
+        "{} in {}",
+        counts.join(", "),
+        plural(tool.problem_files.len(), "file")
+    )
+}
+
+/// The summary of a run, for `--output-format json`
+fn render_json(res: &RunResult) -> String {
+    // Serializing non-UTF-8 paths fails
+    let paths = |paths: &BTreeSet<PathBuf>| {
+        paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+    };
+    let tools = res
+        .tools
+        .iter()
+        .map(|(name, tool)| {
+            let tool = serde_json::json!({
+                "commands": tool.cmds,
+                "files": tool.files,
+                "failed": tool.failed,
+                "errors": tool.errors,
+                "warnings": tool.warnings,
+                "notes": tool.notes,
+                "problem_files": paths(&tool.problem_files),
+                "seconds": tool.duration.as_secs_f64(),
+            });
+            (name.clone(), tool)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::json!({
+        "ok": res.ok,
+        "commands": res.cmds,
+        "files": res.files,
+        "modified": paths(&res.modified),
+        "cache": {
+            "hits": res.cache.hits(),
+            "misses": res.cache.misses,
+            "mtime": res.cache.mtime,
+            "content": res.cache.content,
+            "refs": res.cache.refs,
+        },
+        "tools": tools,
+    })
+    .to_string()
+}
+
+fn report_fixes(changed: &[PathBuf], res: &RunResult) {
+    let mut fixed = 0;
+    for path in changed {
+        if !res.failed_files.contains(path) {
+            eprintln!("Fixed: {}", path.display());
+            fixed += 1;
         }
-        RunResult::Errors => (), // output is mirrored to std{out,err}
     }
+    for path in &res.failed_files {
+        eprintln!("Remaining: {}", path.display());
+    }
+    let remaining = res.failed_files.len();
+    let files = |n| if n == 1 { "file" } else { "files" };
+    eprintln!(
+        "{fixed} {} fixed, {remaining} {} with remaining issues",
+        files(fixed),
+        files(remaining)
+    );
+}
+
+/// Between full collections in `--watch` mode, only the changed files are
+/// collected, see [`Changed`]
+const FULL_COLLECTION_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+/// What changed since the last run in `--watch` mode
+#[derive(Debug, Default)]
+struct Changed {
+    /// Changed paths, relative to the current directory
+    paths: HashSet<PathBuf>,
+    /// Whether the watcher missed events, so that all files need collecting
+    rescan: bool,
 }
 
-fn process_event(ev: Result<notify::Event, notify::Error>) -> Result<bool> {
+/// The files to run on in `--watch` mode when only `changed` need collecting:
+/// those that still exist, and those that failed in the last run, so that
+/// they are reported until they pass
+fn incremental_paths(
+    changed: HashSet<PathBuf>,
+    known: &HashSet<PathBuf>,
+    failed: &BTreeSet<PathBuf>,
+) -> BTreeSet<PathBuf> {
+    changed
+        .into_iter()
+        .chain(failed.iter().cloned())
+        .filter(|p| known.contains(p))
+        .collect()
+}
+
+fn process_event(
+    ev: Result<notify::Event, notify::Error>,
+    transient: &Patterns,
+    warm: &mut plan::WarmStart,
+    changed: &mut Changed,
+) -> Result<bool> {
     let ev = ev.context("File watcher error")?;
     trace!("Filesystem event: {:?} {:?}", ev.kind, ev.paths);
-    Ok(need_rerun(&ev))
+    changed.rescan |= ev.need_rescan();
+    if !matches!(ev.kind, EventKind::Access(_)) {
+        let cwd = std::env::current_dir().ok();
+        for path in &ev.paths {
+            // Watched paths may be absolute, collected files are relative
+            let path = cwd
+                .as_deref()
+                .and_then(|cwd| path.strip_prefix(cwd).ok())
+                .unwrap_or(path);
+            let path = path
+                .components()
+                .filter(|c| !matches!(c, Component::CurDir))
+                .collect::<PathBuf>();
+            if !ignored(&path, transient) {
+                changed.paths.insert(path.clone());
+            }
+            warm.changed(path);
+        }
+    }
+    Ok(need_rerun(&ev, transient))
 }
 
-fn need_rerun(event: &notify::Event) -> bool {
+fn need_rerun(event: &notify::Event, transient: &Patterns) -> bool {
     if matches!(event.kind, EventKind::Access(_)) {
         return false;
     }
+    !event.paths.iter().all(|path| ignored(path, transient))
+}
+
+/// Whether changes to `path` never need a re-run
+fn ignored(path: &Path, transient: &Patterns) -> bool {
     let ignored_prefixes = [".lun", ".git", "target"];
-    let all_paths_ignored = event.paths.iter().all(|path| {
-        ignored_prefixes.iter().any(|prefix| {
+    diff::is_copy(path)
+        || transient.is_match(path)
+        || ignored_prefixes.iter().any(|prefix| {
             path.components()
                 .any(|component| component.as_os_str() == *prefix)
         })
-    });
-    !all_paths_ignored
 }
 
-fn warn_if_config_changed(config: &Path, initial_config_hash: Option<file::Xxhash>) {
-    if let Some(initial_hash) = initial_config_hash
-        && let Ok(content) = fs::read(config)
-    {
-        let hash = file::compute_hash(&content);
-        if hash != initial_hash {
-            warn!("Config file changed! Please restart `lun`.");
+/// Hash of the config file and its overlays, to notice changes in `--watch`
+/// mode and `lun daemon`
+pub(crate) fn config_hash(cli: &cli::Cli) -> file::Xxhash {
+    let mut contents = Vec::new();
+    for path in std::iter::once(&cli.config).chain(&cli.config_overlays) {
+        contents.extend(fs::read(path).unwrap_or_default());
+        contents.push(0);
+    }
+    file::compute_hash(&contents)
+}
+
+/// Load the config file again after it changed in `--watch` mode, and collect
+/// all of the files. `current` has the options changed with keys.
+fn reload(
+    cli: &cli::Cli,
+    run_cli: &cli::Run,
+    current: &cli::Run,
+) -> Result<(config::Config, Warns, Config)> {
+    let config_file = config::Config::load(&cli.config, &cli.config_overlays)?
+        .with_context(|| format!("No config file at {}", cli.config.display()))?;
+    let lints = Warns::from_cli_and_config(&cli.warn, Some(&config_file))?;
+    let config_files = lint(run_cli, &config_file, &lints)?;
+    let config = mk_config(cli, current, &config_file, None, &config_files)?;
+    Ok((config_file, lints, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn template_quotes() {
+        let vars = [("failed", "a, b'c".to_string()), ("files", "2".to_string())];
+        expect![[r#"echo 'a, b'\''c' 2 {{other}}"#]]
+            .assert_eq(&template("echo {{failed}} {{files}} {{other}}", &vars));
+    }
+
+    #[test]
+    fn then_else_vars() {
+        let failed = || ToolResult {
+            failed: 1,
+            ..ToolResult::default()
+        };
+        let result = RunResult {
+            cmds: 3,
+            files: 2,
+            linted: 1,
+            tools: BTreeMap::from([
+                ("$(false)".to_string(), failed()),
+                ("it's".to_string(), failed()),
+                ("ok".to_string(), ToolResult::default()),
+            ]),
+            failed: vec!["lint a".to_string(), "lint b".to_string()],
+            ..RunResult::default()
+        };
+        let cmd = r#"printf '%s|' {{commands}} {{files}} {{files_linted}} {{failed}} "$LUN_FAILED" "$LUN_FAILED_TOOLS""#;
+        let output = then_else_cmd(cmd, &result).output().unwrap();
+        assert!(output.status.success());
+        expect![[r#"
+            3|2|1|$(false), it's|lint a
+            lint b|$(false)
+            it's|"#]]
+        .assert_eq(&String::from_utf8_lossy(&output.stdout));
+    }
+
+    #[test]
+    fn daemon_files() {
+        let run = |args: &[&str]| {
+            let cli = cli::Cli::try_parse_from(args).unwrap();
+            let cli::Command::Run(run) = cli.command else {
+                unreachable!()
+            };
+            run
+        };
+        assert!(resident_files_apply(&run(&["lun", "run"]), true));
+        assert!(resident_files_apply(
+            &run(&["lun", "run", "--only-files", "*.py"]),
+            true
+        ));
+        for args in [
+            &["lun", "run", "--staged"][..],
+            &["lun", "run", "--only-dirty"],
+            &["lun", "run", "--files-from", "files.txt"],
+            &["lun", "run", "--walk-ignore", "vendor"],
+            &["lun", "run", "--include-generated"],
+        ] {
+            assert!(!resident_files_apply(&run(args), true), "{args:?}");
         }
+        assert!(resident_files_apply(
+            &run(&["lun", "run", "--include-generated"]),
+            false
+        ));
+    }
+
+    #[test]
+    fn incremental_after_failure() {
+        let paths = |ps: &[&str]| ps.iter().map(PathBuf::from).collect::<HashSet<_>>();
+        let known = paths(&["a.py", "b.py", "c.py"]);
+        // `b.py` failed and wasn't touched, `gone.py` was removed
+        let failed = ["b.py", "gone.py"].into_iter().map(PathBuf::from).collect();
+        let files = incremental_paths(paths(&["a.py", "new.py"]), &known, &failed);
+        assert_eq!(
+            files.into_iter().collect::<Vec<_>>(),
+            [PathBuf::from("a.py"), PathBuf::from("b.py")]
+        );
+        let files = incremental_paths(paths(&["b.py"]), &known, &BTreeSet::new());
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn problems() {
+        let mut result = RunResult {
+            ok: false,
+            cmds: 2,
+            files: 3,
+            cache: plan::Stats {
+                mtime: 480,
+                refs: 2,
+                misses: 13,
+                ..plan::Stats::default()
+            },
+            ..RunResult::default()
+        };
+        result.tools.insert(
+            "ruff check".to_string(),
+            ToolResult {
+                cmds: 2,
+                files: 3,
+                failed: 1,
+                errors: 14,
+                warnings: 1,
+                problem_files: BTreeSet::from([PathBuf::from("a.py")]),
+                ..ToolResult::default()
+            },
+        );
+        expect!["14 errors, 1 warning in 1 file"]
+            .assert_eq(&render_problems(&result.tools["ruff check"]));
+        assert!(render_result(&result).contains("cache: 482 hits, 13 misses (97%)"));
+        expect![[r#"{"cache":{"content":0,"hits":482,"misses":13,"mtime":480,"refs":2},"commands":2,"files":3,"modified":[],"ok":false,"tools":{"ruff check":{"commands":2,"errors":14,"failed":1,"files":3,"notes":0,"problem_files":["a.py"],"seconds":0.0,"warnings":1}}}"#]]
+            .assert_eq(&render_json(&result));
     }
 }
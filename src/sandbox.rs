@@ -0,0 +1,119 @@
+//! Running tools with read-only access to the repository and no network, for
+//! `--sandbox`.
+//!
+//! Uses [bubblewrap] on Linux and `sandbox-exec` on macOS.
+//!
+//! [bubblewrap]: https://github.com/containers/bubblewrap
+
+use std::{env, path::Path, process};
+
+use anyhow::{Context as _, Result};
+
+use crate::{cmd, results};
+
+#[cfg(target_os = "macos")]
+const PROGRAM: &str = "sandbox-exec";
+#[cfg(not(target_os = "macos"))]
+const PROGRAM: &str = "bwrap";
+
+/// What tools see when they write to the repository
+#[cfg(target_os = "macos")]
+const VIOLATION: &[u8] = b"Operation not permitted";
+#[cfg(not(target_os = "macos"))]
+const VIOLATION: &[u8] = b"Read-only file system";
+
+/// Check that sandboxing is available
+pub(crate) fn check() -> Result<()> {
+    if !cfg!(any(target_os = "linux", target_os = "macos")) {
+        anyhow::bail!("`sandbox` is not supported on {}", env::consts::OS);
+    }
+    if !cmd::on_path(PROGRAM, None) {
+        anyhow::bail!("`sandbox` requires `{PROGRAM}`, which is not on the PATH");
+    }
+    Ok(())
+}
+
+/// Run `c` in the sandbox, with read-only access to the current directory
+pub(crate) fn wrap(c: &process::Command) -> Result<process::Command> {
+    let root = env::current_dir().context("Failed to get current directory")?;
+    let cwd = match c.get_current_dir() {
+        Some(dir) => root.join(dir),
+        None => root.clone(),
+    };
+    let mut sandboxed = process::Command::new(PROGRAM);
+    sandboxed.args(args(&root, &cwd));
+    sandboxed.arg(c.get_program());
+    sandboxed.args(c.get_args());
+    sandboxed.current_dir(&cwd);
+    for (key, value) in c.get_envs() {
+        match value {
+            Some(value) => sandboxed.env(key, value),
+            None => sandboxed.env_remove(key),
+        };
+    }
+    Ok(sandboxed)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn args(root: &Path, cwd: &Path) -> Vec<std::ffi::OsString> {
+    let mut args = Vec::new();
+    args.extend(["--dev-bind", "/", "/", "--ro-bind"].map(Into::into));
+    args.extend([root.as_os_str().to_owned(), root.as_os_str().to_owned()]);
+    args.extend(["--unshare-net", "--die-with-parent", "--chdir"].map(Into::into));
+    args.push(cwd.as_os_str().to_owned());
+    args.push("--".into());
+    args
+}
+
+#[cfg(target_os = "macos")]
+fn args(root: &Path, _cwd: &Path) -> Vec<std::ffi::OsString> {
+    let root = root.display().to_string().replace('"', "\\\"");
+    let profile =
+        format!("(version 1)(allow default)(deny network*)(deny file-write* (subpath \"{root}\"))");
+    vec!["-p".into(), profile.into()]
+}
+
+/// Whether a failed command's output shows that it tried to write to the
+/// repository
+pub(crate) fn violation(output: &results::Output) -> bool {
+    let contains = |haystack: &[u8]| haystack.windows(VIOLATION.len()).any(|w| w == VIOLATION);
+    contains(&output.stdout) || contains(&output.stderr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn violations() {
+        let output = |stderr: &[u8]| results::Output {
+            code: 1,
+            stdout: Vec::new(),
+            stderr: stderr.to_vec(),
+        };
+        assert!(!violation(&output(b"error: lint")));
+        let mut message = b"error: couldn't write file: ".to_vec();
+        message.extend(VIOLATION);
+        assert!(violation(&output(&message)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn wrap_command() {
+        let mut c = process::Command::new("lint");
+        c.arg("--").arg("file.py").current_dir("sub");
+        let wrapped = wrap(&c).unwrap();
+        let root = env::current_dir().unwrap();
+        let args = wrapped
+            .get_args()
+            .map(|a| {
+                a.to_string_lossy()
+                    .replace(&*root.to_string_lossy(), "ROOT")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            args.join(" "),
+            "--dev-bind / / --ro-bind ROOT ROOT --unshare-net --die-with-parent --chdir ROOT/sub -- lint -- file.py"
+        );
+    }
+}
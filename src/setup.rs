@@ -0,0 +1,136 @@
+//! `before` and `after` commands of tools, e.g., to install dependencies. They
+//! run once per run and working directory, and only if the tool has work.
+
+use std::{collections::HashSet, io, path::PathBuf, process, time};
+
+use anyhow::{Context as _, Result};
+use tracing::{debug, error};
+
+use crate::{cmd, exec, interrupt, job, results, tool};
+
+/// The distinct tools of `jobs`, by name and working directory
+fn tools(jobs: &[cmd::Command]) -> Vec<&tool::Tool> {
+    let mut seen = HashSet::new();
+    jobs.iter()
+        .map(|job| job.tool.as_ref())
+        .filter(|tool| seen.insert((tool.display_name(), tool.cd.as_deref())))
+        .collect()
+}
+
+fn run(tool: &tool::Tool, cmd: &str, report: bool) -> Result<exec::Outcome> {
+    let start = time::Instant::now();
+    let mut parts = cmd.split_whitespace();
+    let program = parts
+        .next()
+        .with_context(|| format!("{}: empty command", tool.display_name()))?;
+    let mut c = process::Command::new(program);
+    c.args(parts).stdin(process::Stdio::null());
+    if let Some(cd) = &tool.cd {
+        c.current_dir(cd);
+    }
+    let displayed = job::display_cmd(&c);
+    debug!("{displayed}: running");
+    let out = interrupt::output(&mut c)
+        .with_context(|| format!("Failed to execute command: {displayed}"))?;
+    let success = out.status.success();
+    debug!(
+        "{displayed}: {}",
+        if success { "success" } else { "failed" }
+    );
+    let output = (!success).then(|| results::Output {
+        code: out.status.code().unwrap_or(-1),
+        stdout: out.stdout,
+        stderr: out.stderr,
+    });
+    if let (true, Some(output)) = (report, &output) {
+        exec::write_failure(
+            &mut io::stdout().lock(),
+            &mut io::stderr().lock(),
+            &displayed,
+            &output.stdout,
+            &output.stderr,
+        )?;
+    }
+    Ok(exec::Outcome {
+        tool: tool.display_name().to_string(),
+        cmd: displayed,
+        files: Vec::new(),
+        cd: tool.cd.clone(),
+        modified: Vec::new(),
+        success,
+        output,
+        replayed: false,
+        sandbox_violation: false,
+        duration: start.elapsed(),
+    })
+}
+
+/// Run the `before` commands of the tools of `jobs`, and drop the jobs of
+/// tools whose `before` command failed
+pub(crate) fn before(jobs: &mut Vec<cmd::Command>, report: bool) -> Result<Vec<exec::Outcome>> {
+    let mut outcomes = Vec::new();
+    let mut failed = HashSet::<(String, Option<PathBuf>)>::new();
+    for tool in tools(jobs) {
+        let Some(before) = &tool.before else {
+            continue;
+        };
+        let outcome = run(tool, before, report)?;
+        if !outcome.success {
+            error!("`before` command of {} failed", tool.display_name());
+            failed.insert((tool.display_name().to_string(), tool.cd.clone()));
+        }
+        outcomes.push(outcome);
+    }
+    if !failed.is_empty() {
+        jobs.retain(|job| {
+            !failed.contains(&(job.tool.display_name().to_string(), job.tool.cd.clone()))
+        });
+    }
+    Ok(outcomes)
+}
+
+/// Run the `after` commands of the tools of `jobs`
+pub(crate) fn after(jobs: &[cmd::Command], report: bool) -> Result<Vec<exec::Outcome>> {
+    let mut outcomes = Vec::new();
+    for tool in tools(jobs) {
+        if let Some(after) = &tool.after {
+            outcomes.push(run(tool, after, report)?);
+        }
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use super::*;
+
+    fn job(toml: &str, cd: Option<&str>) -> cmd::Command {
+        let mut tool = tool::test_tool(toml);
+        tool.cd = cd.map(PathBuf::from);
+        cmd::Command {
+            tool: Arc::new(tool),
+            files: Vec::new(),
+            argfile: None,
+        }
+    }
+
+    #[test]
+    fn failed_before() {
+        let failing = "name = \"a\"\nbefore = \"false\"";
+        let mut jobs = vec![
+            job(failing, None),
+            job(failing, None),
+            job(failing, Some("src")),
+            job("name = \"b\"", None),
+        ];
+        let outcomes = before(&mut jobs, false).unwrap();
+        // Once per working directory
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|o| !o.success));
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].tool.display_name(), "b");
+        assert_eq!(outcomes[1].cd.as_deref(), Some(Path::new("src")));
+    }
+}
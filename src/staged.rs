@@ -1,43 +1,27 @@
-use std::{path::PathBuf, process};
+use std::path::PathBuf;
 
-use anyhow::{Context as _, Result};
-use tracing::debug;
+use anyhow::Result;
 
-use crate::file;
+use crate::{file, git};
 
-fn get_staged_files() -> Result<Vec<PathBuf>> {
-    let output = process::Command::new("git")
-        .args(["diff", "--cached", "--name-only", "--diff-filter=ACMR"])
-        .output()
-        .with_context(|| "Failed to execute git diff --cached")?;
-    if !output.status.success() {
-        return Err(anyhow::anyhow!(
-            "git diff --cached failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let files: Vec<PathBuf> = stdout
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(|p| {
-            debug!("Found staged file {p}");
-            PathBuf::from(p)
-        })
-        .collect();
-    Ok(files)
+pub(crate) fn collect_staged_files(git: &mut git::GitContext) -> Result<Vec<file::File>> {
+    existing(git.staged_files()?)
+}
+
+pub(crate) fn collect_dirty_files(git: &mut git::GitContext) -> Result<Vec<file::File>> {
+    existing(git.dirty_files()?)
 }
 
-pub(crate) fn collect_staged_files() -> Result<Vec<file::File>> {
-    let staged_paths = get_staged_files()?;
+/// Skip deleted files
+fn existing(paths: &[PathBuf]) -> Result<Vec<file::File>> {
     let mut files = Vec::new();
     let root = PathBuf::from(".");
-    for path in staged_paths {
-        let full_path = root.join(&path);
+    for path in paths {
+        let full_path = root.join(path);
         if !full_path.exists() {
             continue;
         }
-        files.push(file::File::new(path)?);
+        files.push(file::File::new(path.clone())?);
     }
     Ok(files)
 }
@@ -0,0 +1,81 @@
+//! [Test Anything Protocol] output, for `--output-format tap`.
+//!
+//! Each command is a test point, and the output of failed commands is
+//! included as diagnostic lines.
+//!
+//! [Test Anything Protocol]: https://testanything.org/
+
+use std::fmt::Write as _;
+
+use crate::{diag, exec};
+
+pub(crate) fn render(outcomes: &[exec::Outcome]) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", outcomes.len());
+    for (i, outcome) in outcomes.iter().enumerate() {
+        let status = if outcome.success { "ok" } else { "not ok" };
+        // `#` starts a directive, so it can't appear in descriptions
+        let description = outcome.cmd.replace('#', "\\#");
+        #[allow(clippy::unwrap_used)]
+        writeln!(tap, "{status} {} - {description}", i + 1).unwrap();
+        if let Some(output) = &outcome.output {
+            for stream in [&output.stdout, &output.stderr] {
+                for line in String::from_utf8_lossy(stream).lines() {
+                    // Consumers of TAP don't expect colors
+                    #[allow(clippy::unwrap_used)]
+                    writeln!(tap, "# {}", diag::strip_ansi(line)).unwrap();
+                }
+            }
+        }
+    }
+    tap
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use expect_test::expect;
+
+    use super::*;
+    use crate::results;
+
+    fn outcome(cmd: &str, output: Option<&str>) -> exec::Outcome {
+        exec::Outcome {
+            tool: "lint".to_string(),
+            cmd: cmd.to_string(),
+            files: Vec::new(),
+            cd: None,
+            modified: Vec::new(),
+            success: output.is_none(),
+            output: output.map(|o| results::Output {
+                code: 1,
+                stdout: o.as_bytes().to_vec(),
+                stderr: b"error\n".to_vec(),
+            }),
+            replayed: false,
+            sandbox_violation: false,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn render_outcomes() {
+        let outcomes = [
+            outcome("ruff check a.py", None),
+            outcome(
+                "mypy '#b.py'",
+                Some("#b.py:1: bad\n\x1b[1m#b.py\x1b[0m:2: worse"),
+            ),
+        ];
+        expect![[r#"
+            TAP version 13
+            1..2
+            ok 1 - ruff check a.py
+            not ok 2 - mypy '\#b.py'
+            # #b.py:1: bad
+            # #b.py:2: worse
+            # error
+        "#]]
+        .assert_eq(&render(&outcomes));
+    }
+}
@@ -2,7 +2,7 @@ mod warn;
 
 use crate::{
     cache::{self, CacheWriter},
-    cli, cmd, file, plan, run,
+    cli, cmd, file, git, job, plan, run,
 };
 
 use anyhow::{Context, Result};
@@ -46,6 +46,7 @@ impl TestFile {
             metadata_stamp,
             mtime_stamp,
             content_stamp,
+            binary: self.content.contains('\0').into(),
         }
     }
 }
@@ -141,9 +142,10 @@ fn process_files_section_line(scenario: &mut TestScenario, line: &str) {
             .and_then(|s| s.split("`: ").next())
             .unwrap_or("");
         let size_part = line
-            .split(": ")
-            .nth(1)
-            .and_then(|s| s.strip_suffix('b'))
+            .rsplit("`: ")
+            .next()
+            .into_iter()
+            .find_map(|s| s.strip_suffix('b'))
             .and_then(|s| s.trim().parse::<usize>().ok())
             .unwrap_or(0);
 
@@ -210,13 +212,28 @@ fn parse_test_file(path: &Path) -> Result<Vec<TestScenario>> {
                     linter: Vec::new(),
                     formatter: Vec::new(),
                     refs: Vec::new(),
-                    careful: false,
+                    cache_failures: false,
+                    cache_dir: None,
+                    arg_max: None,
+                    careful: crate::config::Careful::Off,
+                    config_hash: crate::config::ConfigHash::Content,
                     cores: None,
+                    follow_symlinks: false,
+                    fsync: false,
                     mtime: true,
                     ninja: None,
                     ignore: Vec::new(),
+                    known_tools: None,
+                    max_file_size: None,
+                    sandbox: false,
+                    strict: false,
+                    suppress: Vec::new(),
+                    cache_max_age: None,
                     cache_size: None,
                     tool: Vec::new(),
+                    skip_generated: true,
+                    transient_patterns: crate::config::default_transient_patterns(),
+                    walk: crate::config::WalkCfg::default(),
                 },
                 files,
                 expected_output: Vec::new(),
@@ -297,14 +314,13 @@ fn parse_test_file(path: &Path) -> Result<Vec<TestScenario>> {
 }
 
 fn command_to_string(cmd: &process::Command) -> String {
-    let program = cmd.get_program().to_string_lossy();
-    let args: Vec<String> = cmd
-        .get_args()
-        .map(|arg| arg.to_string_lossy().to_string())
-        .collect();
-    let cmd_str = format!("{} {}", program, args.join(" "));
+    let cmd_str = job::display_cmd(cmd);
     if let Some(cd_path) = cmd.get_current_dir() {
-        format!("cd {} && {}", cd_path.display(), cmd_str)
+        format!(
+            "cd {} && {}",
+            job::quote(&cd_path.to_string_lossy()),
+            cmd_str
+        )
     } else {
         cmd_str
     }
@@ -342,22 +358,50 @@ fn test(path: &'static str) {
             .cores
             .unwrap_or(const { NonZeroUsize::new(1).unwrap() });
         let run_mode = run::RunMode::from(run);
-        let tool =
-            scenario
-                .config
-                .linter
-                .iter()
-                .cloned()
-                .map(|t| t.into_tool(run_mode, false, scenario.color, &scenario.config.ignore))
-                .chain(
-                    scenario.config.formatter.iter().cloned().map(|t| {
-                        t.into_tool(run_mode, false, scenario.color, &scenario.config.ignore)
-                    }),
+        let mut tool = scenario
+            .config
+            .linter
+            .iter()
+            .cloned()
+            .map(|t| {
+                t.into_tool(
+                    run_mode,
+                    crate::config::StampOpts::default(),
+                    scenario.color,
+                    &scenario.config.ignore,
+                    &crate::config::ConfigFiles::default(),
+                )
+            })
+            .chain(scenario.config.formatter.iter().cloned().map(|t| {
+                t.into_tool(
+                    run_mode,
+                    crate::config::StampOpts::default(),
+                    scenario.color,
+                    &scenario.config.ignore,
+                    &crate::config::ConfigFiles::default(),
                 )
-                .collect::<Result<Vec<_>>>()
-                .unwrap();
-        let batches =
-            plan::plan(&mut cache, &tool, &files, &[], cores, run.no_batch, false).unwrap();
+            }))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        for t in &mut tool {
+            t.fresh = t.name.as_ref().is_some_and(|n| run.fresh_tool.contains(n));
+        }
+        let (batches, _stats) = plan::plan(
+            &mut cache,
+            &mut crate::index::Index::default(),
+            &crate::timings::Timings::default(),
+            &tool,
+            |_| true,
+            &files,
+            &mut git::GitContext::default(),
+            cores,
+            run.no_batch,
+            false,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
         let out = jobs_to_string(&batches);
         assert_eq!(
             out,
@@ -407,20 +451,68 @@ fn parse_test_file_debug() {
                                 ignore: [],
                                 granularity: Individual,
                                 configs: [],
+                                env: [],
+                                env_stamp: Auto,
                                 cd: None,
+                                cache_failures: None,
+                                dedupe_group: None,
+                                stdin: false,
+                                min_version: None,
+                                version: None,
+                                version_cmd: None,
+                                enabled_if: None,
+                                platforms: [],
+                                max_file_size: None,
+                                binary: false,
+                                batch_size: None,
+                                files_via: None,
+                                argfile: None,
+                                no_capture: false,
+                                absolute_paths: false,
+                                before: None,
+                                after: None,
+                                problem_matcher: None,
+                                warns: WarnCfg {
+                                    allow: [],
+                                    warn: [],
+                                    deny: [],
+                                },
                             },
                             fix: None,
                         },
                     ],
                     formatter: [],
+                    cache_max_age: None,
                     cache_size: None,
-                    careful: false,
+                    cache_failures: false,
+                    cache_dir: None,
+                    arg_max: None,
+                    careful: Off,
+                    config_hash: Content,
                     cores: None,
+                    follow_symlinks: false,
+                    fsync: false,
                     ignore: [],
+                    known_tools: None,
+                    max_file_size: None,
                     mtime: true,
                     ninja: None,
                     refs: [],
+                    sandbox: false,
+                    skip_generated: true,
+                    strict: false,
+                    suppress: [],
                     tool: [],
+                    transient_patterns: [
+                        "*.swp",
+                        "*~",
+                        "*.tmp",
+                        "**/4913",
+                        "**/.#*",
+                    ],
+                    walk: WalkCfg {
+                        ignore: [],
+                    },
                     warns: WarnCfg {
                         allow: [],
                         warn: [],
@@ -454,20 +546,68 @@ fn parse_test_file_debug() {
                                 ignore: [],
                                 granularity: Individual,
                                 configs: [],
+                                env: [],
+                                env_stamp: Auto,
                                 cd: None,
+                                cache_failures: None,
+                                dedupe_group: None,
+                                stdin: false,
+                                min_version: None,
+                                version: None,
+                                version_cmd: None,
+                                enabled_if: None,
+                                platforms: [],
+                                max_file_size: None,
+                                binary: false,
+                                batch_size: None,
+                                files_via: None,
+                                argfile: None,
+                                no_capture: false,
+                                absolute_paths: false,
+                                before: None,
+                                after: None,
+                                problem_matcher: None,
+                                warns: WarnCfg {
+                                    allow: [],
+                                    warn: [],
+                                    deny: [],
+                                },
                             },
                             fix: None,
                         },
                     ],
                     formatter: [],
+                    cache_max_age: None,
                     cache_size: None,
-                    careful: false,
+                    cache_failures: false,
+                    cache_dir: None,
+                    arg_max: None,
+                    careful: Off,
+                    config_hash: Content,
                     cores: None,
+                    follow_symlinks: false,
+                    fsync: false,
                     ignore: [],
+                    known_tools: None,
+                    max_file_size: None,
                     mtime: true,
                     ninja: None,
                     refs: [],
+                    sandbox: false,
+                    skip_generated: true,
+                    strict: false,
+                    suppress: [],
                     tool: [],
+                    transient_patterns: [
+                        "*.swp",
+                        "*~",
+                        "*.tmp",
+                        "**/4913",
+                        "**/.#*",
+                    ],
+                    walk: WalkCfg {
+                        ignore: [],
+                    },
                     warns: WarnCfg {
                         allow: [],
                         warn: [],
@@ -502,6 +642,11 @@ fn batch3() {
     test("tests/batch3.md");
 }
 
+#[test]
+fn batch_size() {
+    test("tests/batch-size.md");
+}
+
 #[test]
 fn cd() {
     test("tests/cd.md");
@@ -527,6 +672,16 @@ fn format() {
     test("tests/format.md");
 }
 
+#[test]
+fn fresh_tool() {
+    test("tests/fresh-tool.md");
+}
+
+#[test]
+fn max_file_size() {
+    test("tests/max-file-size.md");
+}
+
 #[test]
 fn no_batch() {
     test("tests/no-batch.md");
@@ -537,11 +692,21 @@ fn only_files() {
     test("tests/only-files.md");
 }
 
+#[test]
+fn quoting() {
+    test("tests/quoting.md");
+}
+
 #[test]
 fn skip_files() {
     test("tests/skip-files.md");
 }
 
+#[test]
+fn stdin() {
+    test("tests/stdin.md");
+}
+
 #[test]
 fn twice() {
     test("tests/twice.md");
@@ -1,9 +1,8 @@
 use std::path::PathBuf;
 
-use clap::Parser as _;
 use expect_test::expect;
 
-fn test(flags: &[&'static str], config: &'static str) -> Result<(), anyhow::Error> {
+fn test(flags: &[&str], config: &'static str) -> Result<(), anyhow::Error> {
     let cli = crate::cli::Cli::try_parse_from(std::iter::once("lun").chain(flags.iter().copied()))
         .map_err(|e| e.to_string())
         .unwrap();
@@ -195,3 +194,237 @@ granularity = "individual"
     let error_display = format!("{:#}", result.unwrap_err());
     expect!["found tools with empty `files` arrays and --deny=no-files"].assert_eq(&error_display);
 }
+
+#[test]
+fn tool_warns_success() {
+    test(
+        &["run", "--dry-run"],
+        r#"
+[[linter]]
+cmd = "lint --"
+files = []
+granularity = "individual"
+
+[linter.warns]
+allow = ["no-files"]
+"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn tool_warns_failure() {
+    let result = test(
+        &["--allow=no-files", "run", "--dry-run"],
+        r#"
+[[linter]]
+name = "allowed"
+cmd = "lint --"
+files = []
+
+[[linter]]
+name = "denied"
+cmd = "lint --"
+files = []
+
+[linter.warns]
+deny = ["no-files"]
+"#,
+    );
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found tools with empty `files` arrays and --deny=no-files"].assert_eq(&error_display);
+}
+
+#[test]
+fn unmatched_files_success() {
+    test(
+        &["--deny=unmatched-files", "run", "--dry-run"],
+        r#"
+[[linter]]
+cmd = "lint --"
+files = ["*"]
+"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn unmatched_files_failure() {
+    let result = test(
+        &["--deny=unmatched-files", "run", "--dry-run"],
+        r#"
+[[linter]]
+cmd = "lint --"
+files = ["*.py"]
+"#,
+    );
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found files that no tool matches and --deny=unmatched-files"]
+        .assert_eq(&error_display);
+}
+
+#[test]
+fn overlapping_tools_success() {
+    test(
+        &["--deny=overlapping-tools", "run", "--dry-run"],
+        r#"
+[[formatter]]
+cmd = "fmt1 --"
+files = ["*.rs"]
+
+[[formatter]]
+cmd = "fmt2 --"
+files = ["*.rs"]
+ignore = ["src/**"]
+"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn overlapping_tools_failure() {
+    let result = test(
+        &["--deny=overlapping-tools", "run", "--dry-run"],
+        r#"
+[[formatter]]
+cmd = "fmt1 --"
+files = ["*.rs"]
+
+[[formatter]]
+cmd = "fmt2 --"
+files = ["*.{rs,toml}"]
+"#,
+    );
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found formatters that match the same files and --deny=overlapping-tools"]
+        .assert_eq(&error_display);
+}
+
+#[test]
+fn unexpected_writes_success() {
+    test(
+        &["--deny=unexpected-writes", "run", "--dry-run"],
+        r#"
+[[linter]]
+cmd = "lint --"
+files = ["*.rs"]
+"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn unexpected_writes_failure() {
+    let lints = crate::warn::warns::Warns {
+        deny: [crate::warn::warn::Warn::UnexpectedWrites].into(),
+        ..Default::default()
+    };
+    let outcome = crate::exec::Outcome {
+        tool: "lint".to_string(),
+        cmd: "lint -- a.py".to_string(),
+        files: vec![PathBuf::from("a.py")],
+        cd: None,
+        modified: Vec::new(),
+        success: true,
+        output: None,
+        replayed: false,
+        sandbox_violation: false,
+        duration: std::time::Duration::ZERO,
+    };
+    crate::warn::check_unexpected_writes(&lints, std::slice::from_ref(&outcome), &[]).unwrap();
+    let result = crate::warn::check_unexpected_writes(&lints, &[outcome], &[PathBuf::from("a.py")]);
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found tools that changed files unexpectedly and --deny=unexpected-writes"]
+        .assert_eq(&error_display);
+}
+
+#[test]
+fn suppress_success() {
+    test(
+        &["--deny=unused-suppression", "run", "--dry-run"],
+        r#"
+suppress = ["no-files:lint"]
+
+[[linter]]
+name = "lint"
+cmd = "lint --"
+files = []
+"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn unused_suppression_failure() {
+    let result = test(
+        &["--deny=unused-suppression", "run", "--dry-run"],
+        r#"
+suppress = ["no-files:lint"]
+
+[[linter]]
+name = "lint"
+cmd = "lint --"
+files = ["*.rs"]
+"#,
+    );
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found unused suppressions and --deny=unused-suppression"].assert_eq(&error_display);
+}
+
+#[test]
+fn warnings_as_errors() {
+    let config = r#"
+[[formatter]]
+cmd = "fmt1 --"
+files = ["*.rs"]
+
+[[formatter]]
+cmd = "fmt2 --"
+files = ["*.rs"]
+"#;
+    test(&["run", "--dry-run"], config).unwrap();
+    let result = test(&["--warnings-as-errors", "run", "--dry-run"], config);
+    let err = result.unwrap_err();
+    assert!(err.downcast_ref::<crate::warn::Denied>().is_some());
+    expect!["found formatters that match the same files and --deny=overlapping-tools"]
+        .assert_eq(&format!("{err:#}"));
+}
+
+#[test]
+fn undeclared_env() {
+    // Cargo sets `CARGO_*` variables for tests
+    let config = r#"
+strict = true
+
+[[linter]]
+name = "clippy"
+cmd = "cargo clippy --"
+files = ["*.rs"]
+"#;
+    let result = test(&["run", "--dry-run"], config);
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found undeclared environment variables and --deny=undeclared-env"]
+        .assert_eq(&error_display);
+    test(&["--allow=undeclared-env", "run", "--dry-run"], config).unwrap();
+}
+
+#[test]
+fn missing_tool() {
+    let cache = tempfile::tempdir().unwrap();
+    let cache = format!("--cache={}", cache.path().display());
+    // No files to run on, so it isn't needed
+    let config = r#"
+[[linter]]
+cmd = "lun-missing-tool"
+files = ["*.lun-no-such-extension"]
+"#;
+    test(&[&cache, "run", "--no-cache"], config).unwrap();
+    let config = r#"
+[[linter]]
+cmd = "lun-missing-tool"
+files = ["Cargo.toml"]
+"#;
+    let result = test(&[&cache, "run", "--no-cache"], config);
+    let error_display = format!("{:#}", result.unwrap_err());
+    expect!["found missing tools and --deny=missing-tool"].assert_eq(&error_display);
+}
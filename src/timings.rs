@@ -0,0 +1,101 @@
+//! How long tools took on each file in previous runs, for balancing batches.
+//! See `doc/overview.md`.
+
+use std::path::Path;
+
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{aged, exec};
+
+#[derive(Debug)]
+pub(crate) struct TimingsFormat;
+
+impl aged::Format for TimingsFormat {
+    const NAME: &'static str = "timings";
+    const MAGIC: &'static [u8; 4] = b"lunt";
+    const VERSION: u16 = 1;
+    const MAX_AGE: u16 = 32;
+    /// Durations in microseconds
+    type Value = u64;
+}
+
+/// Map from hashes of (tool name, path) to durations in microseconds
+pub(crate) type Timings = aged::Store<TimingsFormat>;
+
+fn key(tool: &str, path: &Path) -> u128 {
+    let mut hasher = Xxh3::new();
+    hasher.update(tool.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    hasher.digest128()
+}
+
+impl Timings {
+    /// Microseconds that `tool` took on `path`, if known
+    pub(crate) fn get(&self, tool: &str, path: &Path) -> Option<u64> {
+        self.value(key(tool, path))
+    }
+
+    /// Record how long a command took, splitting its duration between its
+    /// files in proportion to their sizes
+    pub(crate) fn record(&mut self, outcome: &exec::Outcome, size: impl Fn(&Path) -> usize) {
+        if outcome.replayed || outcome.duration.is_zero() || outcome.files.is_empty() {
+            return;
+        }
+        let micros = u64::try_from(outcome.duration.as_micros()).unwrap_or(u64::MAX);
+        let weights = outcome
+            .files
+            .iter()
+            .map(|p| size(p) as u64 + 1)
+            .collect::<Vec<_>>();
+        let total = weights.iter().sum::<u64>();
+        for (path, weight) in outcome.files.iter().zip(weights) {
+            let sample = u64::try_from(u128::from(micros) * u128::from(weight) / u128::from(total))
+                .unwrap_or(u64::MAX);
+            let key = key(&outcome.tool, path);
+            // Average with the previous duration, to smooth out noise
+            let micros = self.value(key).map_or(sample, |old| old.midpoint(sample));
+            self.insert(key, micros);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::*;
+
+    fn outcome(files: &[&str], millis: u64) -> exec::Outcome {
+        exec::Outcome {
+            tool: "lint".to_string(),
+            cmd: "lint".to_string(),
+            files: files.iter().map(PathBuf::from).collect(),
+            cd: None,
+            modified: Vec::new(),
+            success: true,
+            output: None,
+            replayed: false,
+            sandbox_violation: false,
+            duration: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn record() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("timings");
+        let mut timings = Timings::from_file(&file).unwrap();
+        let size = |p: &Path| if p == Path::new("big.py") { 299 } else { 99 };
+        timings.record(&outcome(&["big.py", "small.py"], 400), size);
+        assert_eq!(timings.get("lint", Path::new("big.py")), Some(300_000));
+        assert_eq!(timings.get("lint", Path::new("small.py")), Some(100_000));
+        assert_eq!(timings.get("other", Path::new("small.py")), None);
+        timings.flush().unwrap();
+
+        let mut timings = Timings::from_file(&file).unwrap();
+        timings.record(&outcome(&["small.py"], 300), size);
+        assert_eq!(timings.get("lint", Path::new("small.py")), Some(200_000));
+        assert_eq!(timings.get("lint", Path::new("big.py")), Some(300_000));
+    }
+}
@@ -1,20 +1,73 @@
-use globset::GlobSet;
-
-use crate::{config::Granularity, file::Xxhash};
+use crate::{
+    config::{BatchSize, FilesVia, Granularity},
+    diag::ProblemMatcher,
+    file::Xxhash,
+    pattern::Patterns,
+};
 
 /// Hash of command, config file content, and tool version
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct Stamp(pub(crate) Xxhash);
 
+/// How to handle the output of a tool that reads files on stdin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stdin {
+    /// Report output on failure, like other tools
+    Report,
+    /// Replace the file with the output
+    Write,
+    /// Fail if the output differs from the file
+    Check,
+    /// Like `Check`, and print a diff (`--diff`)
+    Diff,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Tool {
     pub(crate) name: Option<String>,
     pub(crate) cmd: String,
-    pub(crate) files: GlobSet,
-    pub(crate) ignore: Option<GlobSet>,
+    pub(crate) files: Patterns,
+    pub(crate) ignore: Option<Patterns>,
     pub(crate) granularity: Granularity,
     pub(crate) stamp: Stamp,
     pub(crate) cd: Option<std::path::PathBuf>,
+    /// Run in the closest directory containing this file (`cd = "nearest:..."`),
+    /// see [`crate::job::by_nearest`]
+    pub(crate) cd_nearest: Option<String>,
+    /// Store and replay output of failed commands, see `doc/cache.md`
+    pub(crate) cache_failures: bool,
+    /// Ignore cache hits (`--fresh-tool`)
+    pub(crate) fresh: bool,
+    /// Format copies of the files and print diffs (`--diff`)
+    pub(crate) diff: bool,
+    /// Formats files in place, i.e., a formatter outside of check mode
+    pub(crate) formats: bool,
+    /// Report each diagnostic only once among tools in the same group
+    pub(crate) dedupe_group: Option<String>,
+    /// Pass files on stdin, one process per file
+    pub(crate) stdin: Option<Stdin>,
+    /// Run with read-only access to the repository (`--sandbox`)
+    pub(crate) sandbox: bool,
+    /// Skip files larger than this many bytes
+    pub(crate) max_file_size: Option<u64>,
+    /// Run on binary files, see [`crate::file::File::is_binary`]
+    pub(crate) binary: bool,
+    pub(crate) batch_size: BatchSize,
+    pub(crate) files_via: FilesVia,
+    /// How to name the file listing the files, with `files_via = "argfile"`,
+    /// see [`crate::cmd::ARGFILE`]
+    pub(crate) argfile: Option<String>,
+    /// Don't capture the output of this tool, as with `--no-capture`
+    pub(crate) no_capture: bool,
+    /// Pass absolute paths rather than paths relative to `cd`
+    pub(crate) absolute_paths: bool,
+    /// Commands to run once before and after the tool, see `setup.rs`
+    pub(crate) before: Option<String>,
+    pub(crate) after: Option<String>,
+    /// How to find diagnostics in the output, for `lun lsp`
+    pub(crate) problem_matcher: Option<ProblemMatcher>,
+    /// Split commands whose arguments would take more bytes than this
+    pub(crate) arg_max: usize,
 }
 
 impl Tool {
@@ -22,3 +75,25 @@ impl Tool {
         self.name.as_ref().unwrap_or(&self.cmd)
     }
 }
+
+/// A linter for tests, from the TOML of a `[[linter]]` table, where `cmd`
+/// defaults to `lint` and `files` to every file
+#[cfg(test)]
+pub(crate) fn test_tool(toml: &str) -> Tool {
+    let mut table = toml::from_str::<toml::Table>(toml).unwrap();
+    table.entry("cmd").or_insert_with(|| "lint".into());
+    table
+        .entry("files")
+        .or_insert_with(|| toml::Value::Array(vec!["*".into()]));
+    table
+        .try_into::<crate::config::Linter>()
+        .unwrap()
+        .into_tool(
+            crate::run::RunMode::Normal,
+            crate::config::StampOpts::default(),
+            crate::cli::log::Color::Never,
+            &[],
+            &crate::config::ConfigFiles::default(),
+        )
+        .unwrap()
+}
@@ -0,0 +1,128 @@
+//! Tool versions pinned by version managers ([mise] and [asdf]), for
+//! `careful` cache keys.
+//!
+//! [mise]: https://mise.jdx.dev/
+//! [asdf]: https://asdf-vm.com/
+
+use std::{fs, path::Path};
+
+use tracing::debug;
+
+/// asdf's file, also read by mise
+const TOOL_VERSIONS: &str = ".tool-versions";
+const MISE_FILES: &[&str] = &[
+    "mise.toml",
+    ".mise.toml",
+    "mise.local.toml",
+    ".mise.local.toml",
+];
+
+/// Runtimes that provide executables (e.g., `cargo clippy` depends on `rust`),
+/// under both mise's and asdf's names
+const RUNTIMES: &[(&str, &str)] = &[
+    ("bundle", "ruby"),
+    ("cargo", "rust"),
+    ("go", "go"),
+    ("go", "golang"),
+    ("gofmt", "go"),
+    ("gofmt", "golang"),
+    ("node", "node"),
+    ("node", "nodejs"),
+    ("npm", "node"),
+    ("npm", "nodejs"),
+    ("npx", "node"),
+    ("npx", "nodejs"),
+    ("python", "python"),
+    ("python3", "python"),
+    ("ruby", "ruby"),
+    ("rustfmt", "rust"),
+];
+
+/// Whether a version manager's tool `name` provides `exe`
+///
+/// mise names may have a backend and an owner, e.g., `cargo:typos-cli` or
+/// `ubi:owner/tool`.
+fn provides(name: &str, exe: &str) -> bool {
+    let base = name.rsplit([':', '/']).next().unwrap_or(name);
+    base == exe || RUNTIMES.contains(&(exe, base))
+}
+
+/// Pinned versions of the tools that provide `exe`, as `(file, tool, version)`
+pub(crate) fn pins(dir: &Path, exe: &str) -> Vec<(String, String, String)> {
+    let exe = Path::new(exe)
+        .file_name()
+        .map_or(exe.into(), |n| n.to_string_lossy());
+    let mut pins = Vec::new();
+    if let Ok(contents) = fs::read_to_string(dir.join(TOOL_VERSIONS)) {
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            if let Some((name, versions)) = line.trim().split_once(char::is_whitespace)
+                && provides(name, &exe)
+            {
+                pins.push((
+                    TOOL_VERSIONS.to_string(),
+                    name.to_string(),
+                    versions.trim().to_string(),
+                ));
+            }
+        }
+    }
+    for file in MISE_FILES {
+        let Ok(contents) = fs::read_to_string(dir.join(file)) else {
+            continue;
+        };
+        let table = match toml::from_str::<toml::Table>(&contents) {
+            Ok(table) => table,
+            Err(e) => {
+                debug!("Failed to parse {file}: {e}");
+                continue;
+            }
+        };
+        let Some(tools) = table.get("tools").and_then(toml::Value::as_table) else {
+            continue;
+        };
+        for (name, version) in tools {
+            if provides(name, &exe) {
+                pins.push(((*file).to_string(), name.clone(), version.to_string()));
+            }
+        }
+    }
+    for (file, name, version) in &pins {
+        debug!("Found {name} {version} in {file}");
+    }
+    pins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(TOOL_VERSIONS),
+            "ruff 0.6.0 # comment\nrust 1.80.0\nnodejs 20\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("mise.toml"),
+            "[tools]\n\"cargo:typos\" = \"1.2\"\nnode = { version = \"22\" }\n",
+        )
+        .unwrap();
+        let pins = |exe| {
+            pins(dir.path(), exe)
+                .into_iter()
+                .map(|(_, name, version)| format!("{name} {version}"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pins("ruff"), ["ruff 0.6.0"]);
+        assert_eq!(pins("cargo"), ["rust 1.80.0"]);
+        assert_eq!(pins("typos"), ["cargo:typos \"1.2\""]);
+        assert_eq!(
+            pins("./node_modules/.bin/npx"),
+            ["nodejs 20", "node { version = \"22\" }"]
+        );
+        assert!(pins("shellcheck").is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+//! Translating formatters to and from [treefmt] configs.
+//!
+//! [treefmt]: https://treefmt.com/
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context as _, Result};
+use tracing::warn;
+
+use crate::config::{self, Granularity};
+
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+struct Treefmt {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    excludes: Vec<String>,
+    /// Older versions of treefmt put `excludes` here
+    #[serde(default)]
+    #[serde(skip_serializing)]
+    global: Global,
+    #[serde(default)]
+    formatter: BTreeMap<String, Formatter>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct Global {
+    #[serde(default)]
+    excludes: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Formatter {
+    command: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    options: Vec<String>,
+    #[serde(default)]
+    includes: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    excludes: Vec<String>,
+}
+
+/// Formatters and global excludes from a treefmt config
+#[derive(Debug, Default)]
+pub(crate) struct Imported {
+    pub(crate) formatters: Vec<config::Formatter>,
+    pub(crate) ignore: Vec<String>,
+}
+
+pub(crate) fn load(path: &Path) -> Result<Imported> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read treefmt config: {}", path.display()))?;
+    let treefmt: Treefmt = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse treefmt config: {}", path.display()))?;
+    Ok(import(treefmt))
+}
+
+fn import(treefmt: Treefmt) -> Imported {
+    let mut ignore = treefmt.excludes;
+    ignore.extend(treefmt.global.excludes);
+    let formatters = treefmt
+        .formatter
+        .into_iter()
+        .map(|(name, f)| {
+            let mut cmd = f.command;
+            for option in f.options {
+                cmd.push(' ');
+                cmd.push_str(&option);
+            }
+            config::Formatter {
+                tool: config::Tool {
+                    name: Some(name),
+                    cmd,
+                    files: f.includes,
+                    ignore: f.excludes,
+                    granularity: Granularity::Individual,
+                    configs: Vec::new(),
+                    env: Vec::new(),
+                    env_stamp: config::EnvStamp::Auto,
+                    cd: None,
+                    cache_failures: None,
+                    dedupe_group: None,
+                    stdin: false,
+                    min_version: None,
+                    version: None,
+                    version_cmd: None,
+                    enabled_if: None,
+                    platforms: Vec::new(),
+                    max_file_size: None,
+                    binary: false,
+                    batch_size: None,
+                    files_via: None,
+                    argfile: None,
+                    no_capture: false,
+                    absolute_paths: false,
+                    before: None,
+                    after: None,
+                    problem_matcher: None,
+                    warns: config::WarnCfg::default(),
+                },
+                check: None,
+                write_back: false,
+            }
+        })
+        .collect();
+    Imported { formatters, ignore }
+}
+
+/// treefmt config with the formatters of `config`
+pub(crate) fn export(config: &config::Config) -> Result<String> {
+    let mut treefmt = Treefmt {
+        excludes: config.ignore.clone(),
+        ..Treefmt::default()
+    };
+    for formatter in &config.formatter {
+        let tool = &formatter.tool;
+        let name = tool.name.as_ref().unwrap_or(&tool.cmd);
+        // treefmt always passes files on the command line
+        if tool.granularity == Granularity::Batch || tool.stdin {
+            warn!("{name}: skipping, treefmt only runs tools on files passed as arguments");
+            continue;
+        }
+        let mut words = tool.cmd.split_whitespace().map(String::from);
+        let Some(command) = words.next() else {
+            continue;
+        };
+        treefmt.formatter.insert(
+            name.clone(),
+            Formatter {
+                command,
+                options: words.collect(),
+                includes: tool.files.clone(),
+                excludes: tool.ignore.clone(),
+            },
+        );
+    }
+    toml::to_string_pretty(&treefmt).context("Failed to serialize treefmt config")
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    const TREEFMT: &str = r#"
+excludes = ["vendor/*"]
+
+[formatter.nixfmt]
+command = "nixfmt"
+includes = ["*.nix"]
+
+[formatter.rustfmt]
+command = "rustfmt"
+options = ["--edition", "2021"]
+includes = ["*.rs"]
+excludes = ["generated/*"]
+priority = 1
+"#;
+
+    #[test]
+    fn round_trip() {
+        let imported = load_str(TREEFMT);
+        assert_eq!(imported.ignore, ["vendor/*"]);
+        let config = config::Config {
+            formatter: imported.formatters,
+            ignore: imported.ignore,
+            ..toml::from_str("").unwrap()
+        };
+        expect![[r#"
+            ignore = ["vendor/*"]
+
+            [[formatter]]
+            name = "nixfmt"
+            cmd = "nixfmt"
+            files = ["*.nix"]
+
+            [[formatter]]
+            name = "rustfmt"
+            cmd = "rustfmt --edition 2021"
+            files = ["*.rs"]
+            ignore = ["generated/*"]
+        "#]]
+        .assert_eq(&toml::to_string_pretty(&config).unwrap());
+        expect![[r#"
+            excludes = ["vendor/*"]
+
+            [formatter.nixfmt]
+            command = "nixfmt"
+            includes = ["*.nix"]
+
+            [formatter.rustfmt]
+            command = "rustfmt"
+            options = [
+                "--edition",
+                "2021",
+            ]
+            includes = ["*.rs"]
+            excludes = ["generated/*"]
+        "#]]
+        .assert_eq(&export(&config).unwrap());
+    }
+
+    #[test]
+    fn global_excludes() {
+        let imported = load_str("[global]\nexcludes = [\"*.lock\"]\n");
+        assert_eq!(imported.ignore, ["*.lock"]);
+    }
+
+    fn load_str(s: &str) -> Imported {
+        import(toml::from_str(s).unwrap())
+    }
+}
@@ -0,0 +1,165 @@
+//! Tool versions, for `careful` and version requirements.
+
+use std::{
+    collections::HashMap,
+    fs, process,
+    sync::{LazyLock, Mutex},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context as _, Result};
+use semver::{Version, VersionReq};
+use tracing::debug;
+
+use crate::{cmd, config, file};
+
+/// Result of the version command of each tool, so that each is run at most
+/// once per run
+static VERSIONS: LazyLock<Mutex<HashMap<String, Result<String, String>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The command that prints the version of `tool`: its `version_cmd`, or
+/// `PROGRAM --version`, where `PROGRAM` is the first word of its `cmd`
+pub(crate) fn command(tool: &config::Tool) -> Option<String> {
+    if let Some(cmd) = &tool.version_cmd {
+        return Some(cmd.clone());
+    }
+    let program = tool.cmd.split_whitespace().next()?;
+    Some(format!("{program} --version"))
+}
+
+/// Output of the version command of `tool`
+pub(crate) fn get(tool: &config::Tool) -> Option<String> {
+    probe(tool).ok()
+}
+
+/// Output of the version command of `tool`, or why it failed
+pub(crate) fn probe(tool: &config::Tool) -> Result<String, String> {
+    let Some(cmd) = command(tool) else {
+        return Err("empty command".to_string());
+    };
+    let mut versions = VERSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    versions
+        .entry(cmd.clone())
+        .or_insert_with(|| run(&cmd))
+        .clone()
+}
+
+fn run(cmd: &str) -> Result<String, String> {
+    let mut words = cmd.split_whitespace();
+    let program = words.next().ok_or("empty `version_cmd`")?;
+    let output = process::Command::new(program)
+        .args(words)
+        .output()
+        .map_err(|e| format!("`{cmd}` failed to start: {e}"))?;
+    if !output.status.success() {
+        debug!("Failed to get version with {cmd}: {}", output.status);
+        return Err(format!("`{cmd}` failed with {}", output.status));
+    }
+    let version_output = if !output.stdout.is_empty() {
+        String::from_utf8_lossy(&output.stdout)
+    } else {
+        String::from_utf8_lossy(&output.stderr)
+    };
+    let version = version_output.trim();
+    if version.is_empty() {
+        return Err(format!("`{cmd}` printed nothing"));
+    }
+    debug!("Version from {cmd}: {version}");
+    Ok(version.to_string())
+}
+
+/// Hash of the path, size, and modification time of the executable of `tool`,
+/// for `careful = "binary"`
+///
+/// Unlike the output of `--version`, this changes whenever the tool is rebuilt.
+/// Symlinks are followed, but shims of version managers hide the executables
+/// that they run.
+pub(crate) fn binary(tool: &config::Tool) -> Option<file::Xxhash> {
+    let program = tool.cmd.split_whitespace().next()?;
+    let path = cmd::which(program, tool.cd_dir())?;
+    let path = fs::canonicalize(&path).unwrap_or(path);
+    let metadata = fs::metadata(&path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    debug!("Executable of {program}: {}", path.display());
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    hasher.update(path.as_os_str().as_encoded_bytes());
+    hasher.update(&metadata.len().to_le_bytes());
+    hasher.update(&mtime.as_nanos().to_le_bytes());
+    Some(file::Xxhash(hasher.digest128()))
+}
+
+/// The first version number in the output of the version command
+///
+/// Missing components are zero, and suffixes (e.g., `-stable`) are ignored.
+pub(crate) fn parse(output: &str) -> Option<Version> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .find_map(|word| {
+            let word = word.strip_prefix('v').unwrap_or(word);
+            let end = word
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(word.len());
+            let mut parts = word[..end].split('.').map(str::parse::<u64>);
+            let major = parts.next()?.ok()?;
+            let minor = parts.next()?.ok()?;
+            let patch = parts.next().and_then(Result::ok).unwrap_or(0);
+            Some(Version::new(major, minor, patch))
+        })
+}
+
+/// Requirements from `min_version` and `version`
+pub(crate) fn requirements(tool: &config::Tool) -> Result<Vec<VersionReq>> {
+    let tool_name = tool.name.as_ref().unwrap_or(&tool.cmd);
+    let mut reqs = Vec::new();
+    if let Some(min) = &tool.min_version {
+        let req = VersionReq::parse(&format!(">={min}"))
+            .with_context(|| format!("Invalid `min_version` for `{tool_name}`: {min}"))?;
+        reqs.push(req);
+    }
+    if let Some(version) = &tool.version {
+        let req = VersionReq::parse(version)
+            .with_context(|| format!("Invalid `version` for `{tool_name}`: {version}"))?;
+        reqs.push(req);
+    }
+    Ok(reqs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_versions() {
+        let v = |s| parse(s).map(|v| v.to_string());
+        assert_eq!(v("ruff 0.6.9").as_deref(), Some("0.6.9"));
+        assert_eq!(
+            v("rustfmt 1.8.0-stable (2025-01-01)").as_deref(),
+            Some("1.8.0")
+        );
+        assert_eq!(
+            v("ShellCheck - shell script analysis tool\nversion: 0.10.0").as_deref(),
+            Some("0.10.0")
+        );
+        assert_eq!(v("go version go1.22.1 linux/amd64"), None);
+        assert_eq!(v("typos-cli v1.2").as_deref(), Some("1.2.0"));
+        assert_eq!(v("no version here"), None);
+    }
+
+    #[test]
+    fn probes() {
+        let tool = |s: &str| toml::from_str::<config::Tool>(s).unwrap();
+        let plain = tool("cmd = \"ruff check\"\nfiles = []");
+        assert_eq!(command(&plain).as_deref(), Some("ruff --version"));
+        let echo = tool("cmd = \"x\"\nfiles = []\nversion_cmd = \"echo x 1.2.3\"");
+        assert_eq!(probe(&echo).as_deref(), Ok("x 1.2.3"));
+        let failing = tool("cmd = \"x\"\nfiles = []\nversion_cmd = \"false\"");
+        assert!(probe(&failing).unwrap_err().contains("`false` failed"));
+        let missing = tool("cmd = \"lun-no-such-tool\"\nfiles = []");
+        assert!(probe(&missing).unwrap_err().contains("failed to start"));
+    }
+}
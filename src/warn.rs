@@ -1,12 +1,22 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic;
 
-use anyhow::bail;
+use anyhow::Context as _;
 use tracing::{error, warn};
 
+use crate::cmd;
 use crate::config;
+use crate::exec;
+use crate::exit;
+use crate::file;
 use crate::known;
+use crate::pattern::Patterns;
+use crate::tool;
+use crate::version;
 
 pub(crate) mod group;
 pub(crate) mod level;
@@ -42,10 +52,43 @@ pub(crate) fn warns(name: Option<&str>) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The error for a warning that fired at the `deny` level, see
+/// [`crate::exit::DENIED_WARNING`]
+#[derive(Debug)]
+pub(crate) struct Denied(pub(crate) String);
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Denied {}
+
+/// Report each message at its level, and fail if any of them is denied
+fn report(warn: Warn, found: &str, messages: Vec<(level::Level, String)>) -> anyhow::Result<()> {
+    let mut denied = false;
+    for (level, msg) in messages {
+        match level {
+            level::Level::Allow => {}
+            level::Level::Warn => warn!("{msg}"),
+            level::Level::Deny => {
+                error!("{msg}");
+                denied = true;
+            }
+        }
+    }
+    if denied {
+        return Err(Denied(format!("found {found} and --deny={}", warn.as_str())).into());
+    }
+    Ok(())
+}
+
 pub(crate) fn check_unknown_tools(
     lints: &Warns,
     skip_tool: &[String],
     only_tool: &[String],
+    fresh_tool: &[String],
     config: &config::Config,
 ) -> anyhow::Result<()> {
     let level = lints.level(Warn::UnknownTool);
@@ -68,18 +111,26 @@ pub(crate) fn check_unknown_tools(
 
     let mut unknown_tools = Vec::new();
 
+    let unknown =
+        |name: &str| !known_tools.contains(name) && !lints.suppressed(Warn::UnknownTool, name);
     for tool_name in skip_tool {
-        if !known_tools.contains(tool_name.as_str()) {
+        if unknown(tool_name) {
             unknown_tools.push(("--skip-tool", tool_name.clone()));
         }
     }
 
     for tool_name in only_tool {
-        if !known_tools.contains(tool_name.as_str()) {
+        if unknown(tool_name) {
             unknown_tools.push(("--only-tool", tool_name.clone()));
         }
     }
 
+    for tool_name in fresh_tool {
+        if unknown(tool_name) {
+            unknown_tools.push(("--fresh-tool", tool_name.clone()));
+        }
+    }
+
     if unknown_tools.is_empty() {
         return Ok(());
     }
@@ -95,23 +146,27 @@ pub(crate) fn check_unknown_tools(
             for (flag, tool_name) in &unknown_tools {
                 error!("unknown tool `{tool_name}` specified in {flag}");
             }
-            bail!(
+            return Err(Denied(format!(
                 "found unknown tool names and --deny={}",
                 Warn::UnknownTool.as_str()
-            );
+            ))
+            .into());
         }
     }
 
     Ok(())
 }
 
-pub(crate) fn check_unlisted_config(lints: &Warns, config: &config::Config) -> anyhow::Result<()> {
-    let level = lints.level(Warn::UnlistedConfig);
-    if matches!(level, level::Level::Allow) {
+pub(crate) fn check_unlisted_config(
+    lints: &Warns,
+    config: &config::Config,
+    config_files: &config::ConfigFiles,
+) -> anyhow::Result<()> {
+    if lints.allowed(Warn::UnlistedConfig) {
         return Ok(());
     }
 
-    let known_tools_by_name = known::known_tools_by_name();
+    let known_tools_by_name = known::Registry::load(config.known_tools.as_deref())?.tools_by_name();
     let mut unlisted_configs = Vec::new();
 
     for tool in config
@@ -123,78 +178,260 @@ pub(crate) fn check_unlisted_config(lints: &Warns, config: &config::Config) -> a
         if let Some(tool_name) = &tool.name
             && let Some(known_tool) = known_tools_by_name.get(tool_name)
         {
-            let existing_known_configs: Vec<&PathBuf> = known_tool
-                .configs
-                .iter()
-                .filter(|config_path: &&PathBuf| config_path.exists())
+            // Either may have globs or directories
+            let mut known_configs = known_tool.configs.clone();
+            known_configs.extend(known::implicit_configs(tool_name).iter().map(PathBuf::from));
+            let existing_known_configs: Vec<PathBuf> = config_files
+                .resolve(tool_name, &known_configs)?
+                .into_iter()
+                .filter(|config_path| config_path.exists())
+                .collect();
+            let tool_configs_set: HashSet<PathBuf> = config_files
+                .resolve(tool_name, &tool.configs)?
+                .into_iter()
                 .collect();
-            let tool_configs_set: HashSet<&PathBuf> = tool.configs.iter().collect();
+            let level = lints.tool_level(Warn::UnlistedConfig, Some(tool_name));
             for config_path in existing_known_configs {
-                if !tool_configs_set.contains(config_path) {
-                    unlisted_configs.push((tool_name.clone(), config_path.clone()));
+                if !tool_configs_set.contains(&config_path)
+                    && !matches!(level, level::Level::Allow)
+                    && !lints.suppressed(Warn::UnlistedConfig, tool_name)
+                {
+                    unlisted_configs.push((
+                        level,
+                        format!(
+                            "tool `{tool_name}` has unlisted config file `{}`",
+                            config_path.display()
+                        ),
+                    ));
                 }
             }
         }
     }
 
-    if unlisted_configs.is_empty() {
+    report(
+        Warn::UnlistedConfig,
+        "unlisted config files",
+        unlisted_configs,
+    )
+}
+
+pub(crate) fn check_careful(lints: &Warns, careful: config::Careful) -> anyhow::Result<()> {
+    let level = lints.level(Warn::Careful);
+    if matches!(level, level::Level::Allow) {
+        return Ok(());
+    }
+
+    if careful != config::Careful::Off {
         return Ok(());
     }
 
     match level {
         level::Level::Allow => {}
         level::Level::Warn => {
-            for (tool_name, config_path) in &unlisted_configs {
-                warn!(
-                    "tool `{tool_name}` has unlisted config file `{}`",
-                    config_path.display()
-                );
-            }
+            warn!("--careful is not set at CLI or config level");
         }
         level::Level::Deny => {
-            for (tool_name, config_path) in &unlisted_configs {
-                error!(
-                    "tool `{tool_name}` has unlisted config file `{}`",
-                    config_path.display()
-                );
-            }
-            bail!(
-                "found unlisted config files and --deny={}",
-                Warn::UnlistedConfig.as_str()
-            );
+            error!("--careful is not set at CLI or config level");
+            return Err(Denied(format!(
+                "--careful is not set and --deny={}",
+                Warn::Careful.as_str()
+            ))
+            .into());
         }
     }
 
     Ok(())
 }
 
-pub(crate) fn check_careful(
+/// Why the version of `tool` doesn't satisfy `reqs`, if it doesn't
+fn version_mismatches(tool: &config::Tool, reqs: &[semver::VersionReq]) -> Vec<String> {
+    let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
+    let output = match version::probe(tool) {
+        Ok(output) => output,
+        Err(e) => return vec![format!("can't get the version of `{tool_name}`: {e}")],
+    };
+    let Some(found) = version::parse(&output) else {
+        return vec![format!(
+            "can't find the version of `{tool_name}` in `{output}`"
+        )];
+    };
+    reqs.iter()
+        .filter(|r| !r.matches(&found))
+        .map(|req| format!("`{tool_name}` has version {found}, need {req}"))
+        .collect()
+}
+
+pub(crate) fn check_tool_versions(
     lints: &Warns,
-    careful_cli: bool,
-    careful_config: bool,
+    selected: impl Fn(&config::Tool) -> bool,
+    config: &config::Config,
 ) -> anyhow::Result<()> {
-    let level = lints.level(Warn::Careful);
-    if matches!(level, level::Level::Allow) {
+    let mut mismatches = Vec::new();
+    for tool in config
+        .linter
+        .iter()
+        .map(|l| &l.tool)
+        .chain(config.formatter.iter().map(|f| &f.tool))
+    {
+        let reqs = version::requirements(tool).context(exit::ConfigError)?;
+        let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
+        let level = lints.tool_level(Warn::ToolVersion, Some(tool_name));
+        if reqs.is_empty() || matches!(level, level::Level::Allow) {
+            continue;
+        }
+        if !selected(tool) {
+            continue;
+        }
+        let found = version_mismatches(tool, &reqs);
+        if !found.is_empty() && !lints.suppressed(Warn::ToolVersion, tool_name) {
+            mismatches.extend(found.into_iter().map(|m| (level, m)));
+        }
+    }
+
+    report(Warn::ToolVersion, "unsatisfied tool versions", mismatches)
+}
+
+/// Without a version, `careful` can't notice when a tool is upgraded
+pub(crate) fn check_version_probes(
+    lints: &Warns,
+    selected: impl Fn(&config::Tool) -> bool,
+    config: &config::Config,
+    careful: config::Careful,
+) -> anyhow::Result<()> {
+    // `careful = "binary"` doesn't need versions
+    if careful != config::Careful::Version || lints.allowed(Warn::VersionProbeFailed) {
         return Ok(());
     }
 
-    let careful = careful_cli || careful_config;
-    if careful {
+    let mut failures = Vec::new();
+    for tool in config
+        .linter
+        .iter()
+        .map(|l| &l.tool)
+        .chain(config.formatter.iter().map(|f| &f.tool))
+    {
+        let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
+        let level = lints.tool_level(Warn::VersionProbeFailed, Some(tool_name));
+        if matches!(level, level::Level::Allow) || !selected(tool) {
+            continue;
+        }
+        if let Err(e) = version::probe(tool)
+            && !lints.suppressed(Warn::VersionProbeFailed, tool_name)
+        {
+            failures.push((
+                level,
+                format!(
+                    "can't get the version of `{tool_name}` for `careful`: {e} (set `version_cmd`)"
+                ),
+            ));
+        }
+    }
+
+    report(Warn::VersionProbeFailed, "failed version probes", failures)
+}
+
+/// With `strict`, only the environment variables in `env` are part of the
+/// cache keys of tools
+pub(crate) fn check_undeclared_env(
+    lints: &Warns,
+    selected: impl Fn(&config::Tool) -> bool,
+    config: &config::Config,
+) -> anyhow::Result<()> {
+    if !config.strict || lints.allowed(Warn::UndeclaredEnv) {
         return Ok(());
     }
 
-    match level {
-        level::Level::Allow => {}
-        level::Level::Warn => {
-            warn!("--careful is not set at CLI or config level");
+    let mut undeclared = Vec::new();
+    for tool in config
+        .linter
+        .iter()
+        .map(|l| &l.tool)
+        .chain(config.formatter.iter().map(|f| &f.tool))
+    {
+        let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
+        let level = lints.tool_level(Warn::UndeclaredEnv, Some(tool_name));
+        if matches!(level, level::Level::Allow) || !selected(tool) {
+            continue;
         }
-        level::Level::Deny => {
-            error!("--careful is not set at CLI or config level");
-            bail!("--careful is not set and --deny={}", Warn::Careful.as_str());
+        let program = tool.cmd.split_whitespace().next().unwrap_or_default();
+        let mut found = config::implicit_env(program)
+            .into_iter()
+            .filter_map(|(key, _)| key.into_string().ok())
+            .filter(|key| !tool.env.contains(key))
+            .filter(|key| match &tool.env_stamp {
+                config::EnvStamp::Auto => true,
+                config::EnvStamp::Off => false,
+                config::EnvStamp::Only(names) => !names.contains(key),
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        if !found.is_empty() && !lints.suppressed(Warn::UndeclaredEnv, tool_name) {
+            undeclared.extend(found.into_iter().map(|key| {
+                (
+                    level,
+                    format!("`{key}` may affect `{tool_name}`, but isn't in its `env`"),
+                )
+            }));
         }
     }
 
-    Ok(())
+    report(
+        Warn::UndeclaredEnv,
+        "undeclared environment variables",
+        undeclared,
+    )
+}
+
+/// Returns the names of the tools of `jobs` whose executables are missing,
+/// which should be skipped. Only tools that have something to run are
+/// checked, so that, e.g., `--staged` doesn't need every tool.
+pub(crate) fn check_missing_tools(
+    lints: &Warns,
+    jobs: &[cmd::Command],
+) -> anyhow::Result<BTreeSet<String>> {
+    if lints.allowed(Warn::MissingTool) {
+        return Ok(BTreeSet::new());
+    }
+
+    let mut missing = Vec::new();
+    let mut names = BTreeSet::new();
+    for job in jobs {
+        let tool = &job.tool;
+        let program = tool.cmd.split_whitespace().next().unwrap_or_default();
+        let tool_name = tool.display_name();
+        let level = lints.tool_level(Warn::MissingTool, Some(tool_name));
+        if !matches!(level, level::Level::Allow)
+            && !names.contains(tool_name)
+            && !cmd::on_path(program, tool.cd.as_deref())
+            && !lints.suppressed(Warn::MissingTool, tool_name)
+        {
+            names.insert(tool_name.to_string());
+            missing.push((level, tool));
+        }
+    }
+
+    let message = |tool: &tool::Tool| {
+        let program = tool.cmd.split_whitespace().next().unwrap_or_default();
+        let tool_name = tool.display_name();
+        match tool.name.as_deref().and_then(known::install_hint) {
+            Some(hint) => {
+                format!(
+                    "tool `{tool_name}` needs `{program}`, which is not on the PATH (to install it: {hint})"
+                )
+            }
+            None => format!("tool `{tool_name}` needs `{program}`, which is not on the PATH"),
+        }
+    };
+    let messages = missing
+        .iter()
+        .map(|(level, tool)| match level {
+            level::Level::Deny => (*level, message(tool)),
+            _ => (*level, format!("{}, skipping it", message(tool))),
+        })
+        .collect();
+    report(Warn::MissingTool, "missing tools", messages)?;
+
+    Ok(names)
 }
 
 pub(crate) fn check_mtime(
@@ -219,7 +456,11 @@ pub(crate) fn check_mtime(
         }
         level::Level::Deny => {
             error!("mtime is enabled on CLI or config file");
-            bail!("mtime is enabled and --deny={}", Warn::Mtime.as_str());
+            return Err(Denied(format!(
+                "mtime is enabled and --deny={}",
+                Warn::Mtime.as_str()
+            ))
+            .into());
         }
     }
 
@@ -248,7 +489,7 @@ pub(crate) fn check_refs(
         }
         level::Level::Deny => {
             error!("refs is used on CLI or config file");
-            bail!("refs is used and --deny={}", Warn::Refs.as_str());
+            return Err(Denied(format!("refs is used and --deny={}", Warn::Refs.as_str())).into());
         }
     }
 
@@ -256,8 +497,7 @@ pub(crate) fn check_refs(
 }
 
 pub(crate) fn check_no_files(lints: &Warns, config: &config::Config) -> anyhow::Result<()> {
-    let level = lints.level(Warn::NoFiles);
-    if matches!(level, level::Level::Allow) {
+    if lints.allowed(Warn::NoFiles) {
         return Ok(());
     }
 
@@ -269,31 +509,307 @@ pub(crate) fn check_no_files(lints: &Warns, config: &config::Config) -> anyhow::
         .map(|l| &l.tool)
         .chain(config.formatter.iter().map(|f| &f.tool))
     {
-        if tool.files.is_empty() {
-            let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
-            no_files_tools.push(tool_name.to_string());
+        let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
+        let level = lints.tool_level(Warn::NoFiles, Some(tool_name));
+        if tool.files.is_empty()
+            && !matches!(level, level::Level::Allow)
+            && !lints.suppressed(Warn::NoFiles, tool_name)
+        {
+            no_files_tools.push((level, format!("tool `{tool_name}` has empty `files` array")));
         }
     }
 
-    if no_files_tools.is_empty() {
+    report(
+        Warn::NoFiles,
+        "tools with empty `files` arrays",
+        no_files_tools,
+    )
+}
+
+/// Number of example files to name for each unmatched extension
+const UNMATCHED_EXAMPLES: usize = 3;
+
+pub(crate) fn check_unmatched_files(
+    lints: &Warns,
+    tools: &[tool::Tool],
+    files: &[file::File],
+) -> anyhow::Result<()> {
+    let level = lints.level(Warn::UnmatchedFiles);
+    if matches!(level, level::Level::Allow) {
         return Ok(());
     }
 
+    // By extension
+    let mut unmatched = BTreeMap::<&str, Vec<&Path>>::new();
+    for f in files {
+        let Some(ext) = f.path.extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+        let matched = tools.iter().any(|t| {
+            t.files.is_match(&f.path) && t.ignore.as_ref().is_none_or(|i| !i.is_match(&f.path))
+        });
+        if !matched && !f.is_binary() {
+            unmatched.entry(ext).or_default().push(&f.path);
+        }
+    }
+
+    unmatched.retain(|ext, _| !lints.suppressed(Warn::UnmatchedFiles, ext));
+    if unmatched.is_empty() {
+        return Ok(());
+    }
+
+    let mut by_count = unmatched.into_iter().collect::<Vec<_>>();
+    by_count.sort_by_key(|(_, paths)| cmp::Reverse(paths.len()));
+    let messages = by_count.iter().map(|(ext, paths)| {
+        let examples = paths
+            .iter()
+            .take(UNMATCHED_EXAMPLES)
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let more = if paths.len() > UNMATCHED_EXAMPLES {
+            ", ..."
+        } else {
+            ""
+        };
+        format!(
+            "no tool matches {} `.{ext}` file(s), e.g., {examples}{more}",
+            paths.len()
+        )
+    });
+
     match level {
         level::Level::Allow => {}
         level::Level::Warn => {
-            for tool_name in &no_files_tools {
-                warn!("tool `{tool_name}` has empty `files` array");
+            for msg in messages {
+                warn!("{msg}");
             }
         }
         level::Level::Deny => {
-            for tool_name in &no_files_tools {
-                error!("tool `{tool_name}` has empty `files` array");
+            for msg in messages {
+                error!("{msg}");
+            }
+            return Err(Denied(format!(
+                "found files that no tool matches and --deny={}",
+                Warn::UnmatchedFiles.as_str()
+            ))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The patterns in `files` of `tool` that match `path` on their own
+fn matching_patterns(tool: &config::Tool, path: &Path) -> Vec<String> {
+    tool.files
+        .iter()
+        .filter(|p| !p.starts_with('!'))
+        .filter(|p| {
+            Patterns::new(std::slice::from_ref(*p), "files", "")
+                .is_ok_and(|pats| pats.is_match(path))
+        })
+        .map(|p| format!("`{p}`"))
+        .collect()
+}
+
+pub(crate) fn check_overlapping_tools(
+    lints: &Warns,
+    config: &config::Config,
+    files: &[file::File],
+) -> anyhow::Result<()> {
+    if lints.allowed(Warn::OverlappingTools) {
+        return Ok(());
+    }
+
+    let formatters = config
+        .formatter
+        .iter()
+        .map(|f| {
+            let (files, ignore) = config::build_tool_patterns(&f.tool, &config.ignore)?;
+            Ok((&f.tool, files, ignore))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    // By pair of indices into `formatters`
+    let mut overlaps = BTreeMap::<(usize, usize), Vec<&Path>>::new();
+    for f in files {
+        let path = f.path.as_path();
+        let matching = formatters
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, files, ignore))| {
+                files.is_match(path) && ignore.as_ref().is_none_or(|i| !i.is_match(path))
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        for (n, i) in matching.iter().enumerate() {
+            for j in &matching[n + 1..] {
+                overlaps.entry((*i, *j)).or_default().push(path);
+            }
+        }
+    }
+
+    let messages = overlaps
+        .iter()
+        .filter_map(|((i, j), paths)| {
+            let (a, b) = (formatters[*i].0, formatters[*j].0);
+            let (name_a, name_b) = (
+                a.name.as_deref().unwrap_or(&a.cmd),
+                b.name.as_deref().unwrap_or(&b.cmd),
+            );
+            // Either tool can allow the overlap
+            let level = cmp::min(
+                lints.tool_level(Warn::OverlappingTools, Some(name_a)),
+                lints.tool_level(Warn::OverlappingTools, Some(name_b)),
+            );
+            let suppressed = lints.suppressed(Warn::OverlappingTools, name_a)
+                | lints.suppressed(Warn::OverlappingTools, name_b);
+            if matches!(level, level::Level::Allow) || suppressed {
+                return None;
             }
-            bail!(
-                "found tools with empty `files` arrays and --deny={}",
-                Warn::NoFiles.as_str()
+            let example = paths[0];
+            let msg = format!(
+                "formatters `{name_a}` ({}) and `{name_b}` ({}) both match {} file(s), e.g., {}",
+                matching_patterns(a, example).join(", "),
+                matching_patterns(b, example).join(", "),
+                paths.len(),
+                example.display(),
             );
+            Some((level, msg))
+        })
+        .collect();
+
+    report(
+        Warn::OverlappingTools,
+        "formatters that match the same files",
+        messages,
+    )
+}
+
+/// Whether to re-`stat` files after tools run, see [`check_unexpected_writes`]
+pub(crate) fn check_writes(lints: &Warns) -> bool {
+    !lints.allowed(Warn::UnexpectedWrites)
+}
+
+/// `written` are the files that changed while `outcomes` ran, other than the
+/// ones that formatters changed
+pub(crate) fn check_unexpected_writes(
+    lints: &Warns,
+    outcomes: &[exec::Outcome],
+    written: &[PathBuf],
+) -> anyhow::Result<()> {
+    if lints.allowed(Warn::UnexpectedWrites) {
+        return Ok(());
+    }
+
+    // The tools that ran on each written file
+    let mut ran_on = written
+        .iter()
+        .map(|p| (p.as_path(), BTreeSet::new()))
+        .collect::<HashMap<_, _>>();
+    for outcome in outcomes {
+        for path in &outcome.files {
+            if let Some(tools) = ran_on.get_mut(path.as_path()) {
+                tools.insert(outcome.tool.as_str());
+            }
+        }
+    }
+    let mut by_tools = BTreeMap::<BTreeSet<&str>, Vec<&Path>>::new();
+    for (path, tools) in ran_on {
+        by_tools.entry(tools).or_default().push(path);
+    }
+    let mut messages = Vec::new();
+    for (tools, mut paths) in by_tools {
+        if tools
+            .iter()
+            .any(|t| lints.suppressed(Warn::UnexpectedWrites, t))
+        {
+            continue;
+        }
+        paths.sort();
+        let example = paths[0].display();
+        let n = paths.len();
+        if tools.is_empty() {
+            messages.push((
+                lints.level(Warn::UnexpectedWrites),
+                format!("{n} file(s) changed that no tool ran on, e.g., {example}"),
+            ));
+        } else {
+            // Any of the tools can allow the writes
+            let level = tools
+                .iter()
+                .map(|t| lints.tool_level(Warn::UnexpectedWrites, Some(t)))
+                .min()
+                .unwrap_or(level::Level::Allow);
+            let names = tools
+                .iter()
+                .map(|t| format!("`{t}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            messages.push((
+                level,
+                format!("{n} file(s) changed while {names} checked them, e.g., {example}"),
+            ));
+        }
+    }
+    let mut violations = BTreeMap::<&str, usize>::new();
+    for outcome in outcomes.iter().filter(|o| o.sandbox_violation) {
+        *violations.entry(outcome.tool.as_str()).or_default() += 1;
+    }
+    for (tool, n) in violations {
+        if lints.suppressed(Warn::UnexpectedWrites, tool) {
+            continue;
+        }
+        messages.push((
+            lints.tool_level(Warn::UnexpectedWrites, Some(tool)),
+            format!("`{tool}` tried to write to the repository in the sandbox in {n} command(s)"),
+        ));
+    }
+
+    report(
+        Warn::UnexpectedWrites,
+        "tools that changed files unexpectedly",
+        messages,
+    )
+}
+
+/// `dry_run`: whether tools didn't run, so that their writes weren't checked
+pub(crate) fn check_unused_suppressions(lints: &Warns, dry_run: bool) -> anyhow::Result<()> {
+    let level = lints.level(Warn::UnusedSuppression);
+    if matches!(level, level::Level::Allow) {
+        return Ok(());
+    }
+
+    let unused = lints
+        .suppressions
+        .iter()
+        .filter(|s| !s.used.load(atomic::Ordering::Relaxed) && !lints.allowed(s.warn))
+        .filter(|s| !(dry_run && s.warn == Warn::UnexpectedWrites))
+        // Only checked when tools are given on the command line
+        .filter(|s| s.warn != Warn::UnknownTool)
+        .map(|s| format!("`{}:{}`", s.warn.as_str(), s.subject))
+        .collect::<Vec<_>>();
+
+    if unused.is_empty() {
+        return Ok(());
+    }
+
+    match level {
+        level::Level::Allow => {}
+        level::Level::Warn => {
+            for s in &unused {
+                warn!("suppression {s} doesn't match any warning");
+            }
+        }
+        level::Level::Deny => {
+            for s in &unused {
+                error!("suppression {s} doesn't match any warning");
+            }
+            return Err(Denied(format!(
+                "found unused suppressions and --deny={}",
+                Warn::UnusedSuppression.as_str()
+            ))
+            .into());
         }
     }
 
@@ -317,7 +833,11 @@ pub(crate) fn check_cache_full(lints: &Warns, cache_full: bool) -> anyhow::Resul
         }
         level::Level::Deny => {
             error!("cache is full and entries are being dropped");
-            bail!("cache is full and --deny={}", Warn::CacheFull.as_str());
+            return Err(Denied(format!(
+                "cache is full and --deny={}",
+                Warn::CacheFull.as_str()
+            ))
+            .into());
         }
     }
 
@@ -354,10 +874,11 @@ pub(crate) fn check_cache_usage(
                 entries_added,
                 (entries_added * 100) / max_entries.max(1)
             );
-            bail!(
+            return Err(Denied(format!(
                 "single execution uses more than a quarter of cache size and --deny={}",
                 Warn::CacheUsage.as_str()
-            );
+            ))
+            .into());
         }
     }
 
@@ -18,6 +18,13 @@ impl Group {
                 Warn::UnknownWarning,
                 Warn::UnknownTool,
                 Warn::UnlistedConfig,
+                Warn::ToolVersion,
+                Warn::VersionProbeFailed,
+                Warn::MissingTool,
+                Warn::OverlappingTools,
+                Warn::UnexpectedWrites,
+                Warn::UnusedSuppression,
+                Warn::UndeclaredEnv,
             ],
             Group::Pedantic => &[
                 Warn::UnknownWarning,
@@ -26,6 +33,14 @@ impl Group {
                 Warn::Careful,
                 Warn::Mtime,
                 Warn::Refs,
+                Warn::ToolVersion,
+                Warn::VersionProbeFailed,
+                Warn::MissingTool,
+                Warn::UnmatchedFiles,
+                Warn::OverlappingTools,
+                Warn::UnexpectedWrites,
+                Warn::UnusedSuppression,
+                Warn::UndeclaredEnv,
             ],
         }
     }
@@ -15,6 +15,14 @@ pub(crate) enum Warn {
     NoFiles,
     CacheFull,
     CacheUsage,
+    ToolVersion,
+    VersionProbeFailed,
+    MissingTool,
+    UnmatchedFiles,
+    OverlappingTools,
+    UnexpectedWrites,
+    UnusedSuppression,
+    UndeclaredEnv,
 }
 
 impl fmt::Display for Warn {
@@ -36,6 +44,14 @@ impl Warn {
             Warn::NoFiles => level::Level::Deny,
             Warn::CacheFull => level::Level::Allow,
             Warn::CacheUsage => level::Level::Warn,
+            Warn::ToolVersion => level::Level::Deny,
+            Warn::VersionProbeFailed => level::Level::Warn,
+            Warn::MissingTool => level::Level::Deny,
+            Warn::UnmatchedFiles => level::Level::Allow,
+            Warn::OverlappingTools => level::Level::Warn,
+            Warn::UnexpectedWrites => level::Level::Allow,
+            Warn::UnusedSuppression => level::Level::Warn,
+            Warn::UndeclaredEnv => level::Level::Deny,
         }
     }
 
@@ -51,6 +67,14 @@ impl Warn {
             Warn::NoFiles => "no-files",
             Warn::CacheFull => "cache-full",
             Warn::CacheUsage => "cache-usage",
+            Warn::ToolVersion => "tool-version",
+            Warn::VersionProbeFailed => "version-probe-failed",
+            Warn::MissingTool => "missing-tool",
+            Warn::UnmatchedFiles => "unmatched-files",
+            Warn::OverlappingTools => "overlapping-tools",
+            Warn::UnexpectedWrites => "unexpected-writes",
+            Warn::UnusedSuppression => "unused-suppression",
+            Warn::UndeclaredEnv => "undeclared-env",
         }
     }
 
@@ -66,6 +90,14 @@ impl Warn {
             Warn::NoFiles => include_str!("../../doc/warns/no-files.md"),
             Warn::CacheFull => include_str!("../../doc/warns/cache-full.md"),
             Warn::CacheUsage => include_str!("../../doc/warns/cache-usage.md"),
+            Warn::ToolVersion => include_str!("../../doc/warns/tool-version.md"),
+            Warn::VersionProbeFailed => include_str!("../../doc/warns/version-probe-failed.md"),
+            Warn::MissingTool => include_str!("../../doc/warns/missing-tool.md"),
+            Warn::UnmatchedFiles => include_str!("../../doc/warns/unmatched-files.md"),
+            Warn::OverlappingTools => include_str!("../../doc/warns/overlapping-tools.md"),
+            Warn::UnexpectedWrites => include_str!("../../doc/warns/unexpected-writes.md"),
+            Warn::UnusedSuppression => include_str!("../../doc/warns/unused-suppression.md"),
+            Warn::UndeclaredEnv => include_str!("../../doc/warns/undeclared-env.md"),
         }
     }
 
@@ -81,6 +113,16 @@ impl Warn {
             Warn::NoFiles => "Tool has empty `files` array",
             Warn::CacheFull => "Cache is full and entries are being dropped",
             Warn::CacheUsage => "Single execution uses more than a quarter of the cache size",
+            Warn::ToolVersion => "Tool version does not satisfy `min_version` or `version`",
+            Warn::VersionProbeFailed => "Tool version can't be determined for `careful`",
+            Warn::MissingTool => "Tool executable is not on the `PATH`",
+            Warn::UnmatchedFiles => "Files with extensions that no tool matches",
+            Warn::OverlappingTools => "Several formatters match the same files",
+            Warn::UnexpectedWrites => "Tools changed files that they shouldn't have",
+            Warn::UnusedSuppression => "Suppressions that don't match any warning",
+            Warn::UndeclaredEnv => {
+                "Environment variables that may affect a tool aren't in its `env`, with `strict`"
+            }
         }
     }
 
@@ -96,6 +138,14 @@ impl Warn {
             Warn::NoFiles,
             Warn::CacheFull,
             Warn::CacheUsage,
+            Warn::ToolVersion,
+            Warn::VersionProbeFailed,
+            Warn::MissingTool,
+            Warn::UnmatchedFiles,
+            Warn::OverlappingTools,
+            Warn::UnexpectedWrites,
+            Warn::UnusedSuppression,
+            Warn::UndeclaredEnv,
         ]
     }
 }
@@ -114,6 +164,14 @@ impl FromStr for Warn {
             "no-files" => Ok(Warn::NoFiles),
             "cache-full" => Ok(Warn::CacheFull),
             "cache-usage" => Ok(Warn::CacheUsage),
+            "tool-version" => Ok(Warn::ToolVersion),
+            "version-probe-failed" => Ok(Warn::VersionProbeFailed),
+            "missing-tool" => Ok(Warn::MissingTool),
+            "unmatched-files" => Ok(Warn::UnmatchedFiles),
+            "overlapping-tools" => Ok(Warn::OverlappingTools),
+            "unexpected-writes" => Ok(Warn::UnexpectedWrites),
+            "unused-suppression" => Ok(Warn::UnusedSuppression),
+            "undeclared-env" => Ok(Warn::UndeclaredEnv),
             _ => Err(()),
         }
     }
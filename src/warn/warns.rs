@@ -1,18 +1,53 @@
-use std::collections::HashSet;
-use std::str::FromStr as _;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use anyhow::bail;
+use anyhow::Context as _;
 use tracing::{error, warn};
 
 use crate::cli::warn::WarnOpts;
+use crate::exit;
 use crate::warn::group;
-use crate::warn::{level, warn::Warn};
+use crate::warn::{Denied, level, warn::Warn};
 
 #[derive(Debug, Default)]
 pub(crate) struct Warns {
     pub(crate) allow: HashSet<Warn>,
     pub(crate) warn: HashSet<Warn>,
     pub(crate) deny: HashSet<Warn>,
+    /// Levels from the `warns` of tools, by tool name. These override the
+    /// global levels, including the ones given on the command line.
+    pub(crate) tools: HashMap<String, Warns>,
+    /// From `suppress` in the config file
+    pub(crate) suppressions: Vec<Suppression>,
+}
+
+/// A single warning to silence, see [`Warns::suppressed`]
+#[derive(Debug)]
+pub(crate) struct Suppression {
+    pub(crate) warn: Warn,
+    /// What the warning is about, e.g., the name of a tool
+    pub(crate) subject: String,
+    /// Whether the suppression silenced a warning
+    pub(crate) used: AtomicBool,
+}
+
+impl FromStr for Suppression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (warn, subject) = s
+            .split_once(':')
+            .with_context(|| format!("Expected `WARNING:SUBJECT` in `suppress`: `{s}`"))?;
+        let warn = Warn::from_str(warn)
+            .map_err(|()| anyhow::anyhow!("Unknown warning in `suppress`: `{warn}`"))?;
+        Ok(Self {
+            warn,
+            subject: subject.to_string(),
+            used: AtomicBool::new(false),
+        })
+    }
 }
 
 impl Warns {
@@ -34,15 +69,63 @@ impl Warns {
         self.warn.remove(&warn);
     }
 
-    pub(crate) fn level(&self, warn: Warn) -> level::Level {
+    /// The level that was set for `warn`, if any
+    fn set_level(&self, warn: Warn) -> Option<level::Level> {
         if self.allow.contains(&warn) {
-            level::Level::Allow
+            Some(level::Level::Allow)
         } else if self.warn.contains(&warn) {
-            level::Level::Warn
+            Some(level::Level::Warn)
         } else if self.deny.contains(&warn) {
-            level::Level::Deny
+            Some(level::Level::Deny)
         } else {
-            warn.default_level()
+            None
+        }
+    }
+
+    pub(crate) fn level(&self, warn: Warn) -> level::Level {
+        self.set_level(warn).unwrap_or_else(|| warn.default_level())
+    }
+
+    /// The level of `warn` for `tool` (by name), or the global level if there
+    /// is no tool
+    pub(crate) fn tool_level(&self, warn: Warn, tool: Option<&str>) -> level::Level {
+        tool.and_then(|t| self.tools.get(t))
+            .and_then(|t| t.set_level(warn))
+            .unwrap_or_else(|| self.level(warn))
+    }
+
+    /// Whether the instance of `warn` about `subject` is suppressed, which
+    /// marks the suppression as used
+    pub(crate) fn suppressed(&self, warn: Warn, subject: &str) -> bool {
+        let mut suppressed = false;
+        for s in &self.suppressions {
+            if s.warn == warn && s.subject == subject {
+                s.used.store(true, Ordering::Relaxed);
+                suppressed = true;
+            }
+        }
+        suppressed
+    }
+
+    /// Whether `warn` is allowed globally and for every tool
+    pub(crate) fn allowed(&self, warn: Warn) -> bool {
+        matches!(self.level(warn), level::Level::Allow)
+            && self.tools.values().all(|t| {
+                t.set_level(warn)
+                    .is_none_or(|l| matches!(l, level::Level::Allow))
+            })
+    }
+
+    /// Deny every warning at the `warn` level, for `--warnings-as-errors`
+    fn deny_warnings(&mut self) {
+        for &warn in Warn::all() {
+            if matches!(self.level(warn), level::Level::Warn) {
+                self.deny(warn);
+            }
+        }
+        for tool in self.tools.values_mut() {
+            let warn = mem::take(&mut tool.warn);
+            tool.deny.extend(warn);
         }
     }
 
@@ -103,6 +186,11 @@ impl Warns {
         let mut config_unknown_warns = Vec::new();
 
         if let Some(config) = config {
+            // Before the levels in the config file, which may override these
+            if config.strict {
+                warns.deny(Warn::UnlistedConfig);
+                warns.deny(Warn::UndeclaredEnv);
+            }
             config_unknown_warns.extend(warns.process_warnings(
                 &config.warns.allow,
                 &config.warns.warn,
@@ -114,6 +202,36 @@ impl Warns {
             &cli_opts.warn,
             &cli_opts.deny,
         ));
+        for tool in config.iter().flat_map(|c| {
+            c.linter
+                .iter()
+                .map(|l| &l.tool)
+                .chain(c.formatter.iter().map(|f| &f.tool))
+        }) {
+            if tool.warns == crate::config::WarnCfg::default() {
+                continue;
+            }
+            let mut tool_warns = Warns::default();
+            config_unknown_warns.extend(tool_warns.process_warnings(
+                &tool.warns.allow,
+                &tool.warns.warn,
+                &tool.warns.deny,
+            ));
+            let tool_name = tool.name.as_deref().unwrap_or(&tool.cmd);
+            warns.tools.insert(tool_name.to_string(), tool_warns);
+        }
+        if let Some(config) = config {
+            warns.suppressions = config
+                .suppress
+                .iter()
+                .map(|s| Suppression::from_str(s))
+                .collect::<Result<_, _>>()
+                .context(exit::ConfigError)?;
+        }
+
+        if cli_opts.warnings_as_errors {
+            warns.deny_warnings();
+        }
 
         let unknown_warn_level = warns.level(Warn::UnknownWarning);
         match unknown_warn_level {
@@ -134,10 +252,11 @@ impl Warns {
                     error!("unknown warning `{name}` specified in `--{level}`");
                 }
                 if !cli_unknown_warns.is_empty() {
-                    bail!(
+                    return Err(Denied(format!(
                         "found unknown warning names and --deny={}",
                         Warn::UnknownWarning.as_str()
-                    );
+                    ))
+                    .into());
                 }
             }
         }
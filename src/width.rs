@@ -0,0 +1,60 @@
+//! Display widths of text in terminals, for truncating progress lines.
+
+use std::{env, io};
+
+use unicode_segmentation::UnicodeSegmentation as _;
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::diag;
+
+/// Used when the width of the terminal can't be found
+const DEFAULT_TERM_WIDTH: usize = 80;
+
+/// The longest prefix of `s` without ANSI escape sequences that fits in
+/// `width` columns, cut between graphemes
+pub(crate) fn truncate(s: &str, width: usize) -> String {
+    let stripped = diag::strip_ansi(s);
+    let mut out = String::with_capacity(stripped.len());
+    let mut used = 0;
+    for grapheme in stripped.graphemes(true) {
+        used += grapheme.width();
+        if used > width {
+            break;
+        }
+        out.push_str(grapheme);
+    }
+    out
+}
+
+/// The width of the terminal, from `COLUMNS` or stderr. Found each time, as
+/// the terminal may be resized, e.g., during `--watch`.
+pub(crate) fn term_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&w| w > 0)
+        .or_else(|| terminal_size::terminal_size_of(io::stderr()).map(|(w, _)| usize::from(w.0)))
+        .unwrap_or(DEFAULT_TERM_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncate("ruff check a.py", 10), "ruff check");
+        assert_eq!(truncate("ruff", 10), "ruff");
+        assert_eq!(truncate("\x1b[1mruff\x1b[0m check", 6), "ruff c");
+        // Wide characters that don't fit are left out whole
+        assert_eq!(truncate("a日本", 4), "a日");
+        assert_eq!(truncate("a日本", 2), "a");
+        assert_eq!(truncate("a🦀b", 2), "a");
+        assert_eq!(truncate("a\u{1FA70}b", 3), "a\u{1FA70}");
+        // Combining marks stay with their base
+        assert_eq!(truncate("ae\u{301}b", 2), "ae\u{301}");
+        assert_eq!(truncate("क\u{94D}x", 1), "क\u{94D}");
+        // As do the parts of emoji sequences
+        assert_eq!(truncate("👩\u{200D}🔬x", 2), "👩\u{200D}🔬");
+    }
+}